@@ -0,0 +1,144 @@
+//! Parsing throughput, single-threaded and from many threads at once.
+//!
+//! Run with `cargo bench --bench parse`. Each case runs a fixed number of
+//! iterations after a warm-up, on one thread and then on each of as many
+//! threads as there are CPUs, or `SPONGY_BENCH_THREADS`. It reports the mean
+//! time per parse as seen by the thread running it, the wall time of the
+//! many-thread run divided by the parses made, which is the inverse of
+//! their throughput, and the allocations made by one `parse_with` rendering
+//! every placeholder.
+//!
+//! Parsing used to run the pest grammar on every template. Its rules are
+//! compiled in, so there was nothing to validate again per call, but each
+//! call set up a fresh parser state and tracked every character attempted,
+//! costing about 1µs plus 60ns per byte. Scanning by hand first and leaving
+//! only the rejects to pest gave, on one thread:
+//!
+//! | case  | bytes  | grammar | scanner |
+//! |-------|--------|---------|---------|
+//! | small | 16     | 3.57µs  | 0.42µs  |
+//! | text  | 3900   | 270µs   | 4.8µs   |
+//! | mixed | 37670  | 3.29ms  | 0.17ms  |
+//!
+//! With the scanner, on 8 threads sharing a single CPU, the wall time per
+//! parse stayed at that of one thread, so parses do not hold each other up
+//! on shared state, while each thread saw parses take 8 times as long, as
+//! the threads took turns on the CPU:
+//!
+//! | case  | 1 thread | 8 threads, per thread | 8 threads, wall per parse |
+//! |-------|----------|-----------------------|---------------------------|
+//! | small | 0.24µs   | 1.9µs                 | 0.25µs                    |
+//! | text  | 5.6µs    | 36µs                  | 4.6µs                     |
+//! | mixed | 0.14ms   | 1.1ms                 | 0.15ms                    |
+//!
+//! Contention between threads on separate cores has not been measured.
+//!
+//! Reserving the output from the template length, and the elements from the
+//! number of braces, took rendering the mixed case from 36 allocations down
+//! to 3, and the small one from 5.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The system allocator, counting allocations and reallocations.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Counts the allocations of rendering `s` with a mapper returning borrowed
+/// values, so that only the renderer's own allocations are counted.
+fn allocations(s: &str) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    black_box(spongy::parse_with(black_box(s), |_| Some("value")).unwrap());
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+/// Times of a run of parses.
+struct Measured {
+    /// Mean time a parse takes on the thread running it.
+    latency: Duration,
+    /// Wall time of the run divided by the number of parses, the inverse of
+    /// the throughput of all threads together.
+    wall: Duration,
+}
+
+/// Parses `s` `iterations` times on each of `threads` threads.
+fn measure(s: &str, threads: usize, iterations: u32) -> Measured {
+    for _ in 0..iterations / 10 {
+        black_box(spongy::parse(black_box(s)).unwrap());
+    }
+    let start = Instant::now();
+    let busy: Duration = thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let start = Instant::now();
+                    for _ in 0..iterations {
+                        black_box(spongy::parse(black_box(s)).unwrap());
+                    }
+                    start.elapsed()
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).sum()
+    });
+    let parses = iterations * threads as u32;
+    Measured {
+        latency: busy / parses,
+        wall: start.elapsed() / parses,
+    }
+}
+
+fn main() {
+    let small = "Hello, {{name}}!";
+    let text = "A line without any placeholder at all.\n".repeat(100);
+    let mixed: String = (0..1000)
+        .map(|i| format!("key{} = {{{{ value{} }}}} and ${{ENV{}}}\n", i, i, i))
+        .collect();
+    let threads = std::env::var("SPONGY_BENCH_THREADS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or_else(|| thread::available_parallelism().map_or(4, |n| n.get()));
+
+    for (name, s, iterations) in [
+        ("small", small, 200_000),
+        ("text", text.as_str(), 20_000),
+        ("mixed", mixed.as_str(), 200),
+    ] {
+        let single = measure(s, 1, iterations);
+        let parallel = measure(s, threads, iterations);
+        println!(
+            "{:<6} {:>7} bytes  1 thread {:>10.2?}  {} threads {:>10.2?} per parse, \
+             {:>10.2?} wall per parse  {:>3} allocations",
+            name,
+            s.len(),
+            single.latency,
+            threads,
+            parallel.latency,
+            parallel.wall,
+            allocations(s)
+        );
+    }
+}
@@ -0,0 +1,121 @@
+//! Renders the standard input with `{{secret:NAME}}` placeholders fetched
+//! from a key-value HTTP API, as `GET /v1/kv/NAME` answering with the value
+//! as the body, or 404 if there is none.
+//!
+//! Names are percent-encoded into the path, and ones with `.` or `..`
+//! segments rejected, so a template cannot send the token elsewhere on the
+//! server. Connecting and each read time out, and responses are limited to
+//! 64 KiB.
+//!
+//! ```sh
+//! KV_ADDR=127.0.0.1:8200 KV_TOKEN=... cargo run --example http_store \
+//!     --features secret-store < config.tmpl
+//! ```
+
+use std::env;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use spongy::resolve::render_with_report;
+use spongy::secret::Secrets;
+use spongy::store::{SecretStore, StoreError, StoreResolver};
+
+/// Time allowed for connecting and for each read and write.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Largest response accepted, headers included.
+const MAX_BYTES: u64 = 64 * 1024;
+
+/// `name` as a path, with anything but unreserved characters and `/`
+/// percent-encoded, or an error if it has `.` or `..` segments.
+fn encode(name: &str) -> Result<String, StoreError> {
+    if name
+        .split('/')
+        .any(|segment| segment == "." || segment == "..")
+    {
+        return Err(StoreError::Backend(format!("invalid name {:?}", name)));
+    }
+    let mut out = String::with_capacity(name.len());
+    for b in name.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                out.push(b as char)
+            }
+            b => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    Ok(out)
+}
+
+/// A store behind a plain HTTP key-value API.
+struct HttpStore {
+    addr: String,
+    token: Option<String>,
+}
+
+impl SecretStore for HttpStore {
+    fn fetch(&self, name: &str) -> Result<Option<String>, StoreError> {
+        let path = encode(name)?;
+        let addr = self
+            .addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| StoreError::Backend(format!("cannot resolve {}", self.addr)))?;
+        let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let mut request = format!("GET /v1/kv/{} HTTP/1.0\r\nHost: {}\r\n", path, self.addr);
+        if let Some(token) = &self.token {
+            request.push_str(&format!("Authorization: Bearer {}\r\n", token));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes())?;
+
+        let mut response = vec![];
+        stream.take(MAX_BYTES + 1).read_to_end(&mut response)?;
+        if response.len() as u64 > MAX_BYTES {
+            return Err(StoreError::Backend(format!(
+                "response for {} exceeds {} bytes",
+                name, MAX_BYTES
+            )));
+        }
+        let response = String::from_utf8(response)
+            .map_err(|_| StoreError::Backend("response is not UTF-8".to_owned()))?;
+        let (head, body) = response
+            .split_once("\r\n\r\n")
+            .ok_or_else(|| StoreError::Backend("malformed response".to_owned()))?;
+        let status = head.split_whitespace().nth(1).unwrap_or("");
+        match status {
+            "200" => Ok(Some(body.trim_end_matches('\n').to_owned())),
+            "404" => Ok(None),
+            status => Err(StoreError::Backend(format!("{} answered {}", name, status))),
+        }
+    }
+}
+
+fn main() -> io::Result<()> {
+    let store = HttpStore {
+        addr: env::var("KV_ADDR").unwrap_or_else(|_| "127.0.0.1:8200".to_owned()),
+        token: env::var("KV_TOKEN").ok(),
+    };
+    let secrets = Secrets::new();
+    let resolver = StoreResolver::new(store, &secrets).prefix("secret:");
+
+    let mut template = String::new();
+    io::stdin().read_to_string(&mut template)?;
+    let elements = spongy::parse(&template)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    let (output, report) = render_with_report(&elements, &resolver);
+    print!("{}", output);
+
+    for item in spongy::wrapped(&elements) {
+        if !report.errored.iter().any(|key| key == item.key()) {
+            continue;
+        }
+        if let Err(e) = resolver.try_resolve(item) {
+            eprintln!("{}: {}", item.key(), secrets.redact(&e.to_string()));
+        }
+    }
+    Ok(())
+}
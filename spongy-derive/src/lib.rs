@@ -0,0 +1,145 @@
+//! `#[derive(TemplateContext)]`, checking the fields of a context struct
+//! against the templates it is rendered with.
+//!
+//! Templates are listed with `#[template(path = "...")]`, relative to the
+//! directory of the crate's `Cargo.toml`. Each field which none of them
+//! uses, directly or through a path like `user.name`, gets a compile-time
+//! warning, and the listed files are tracked so that editing one checks the
+//! struct again:
+//!
+//! ```
+//! use spongy::lint::TemplateContext;
+//! use spongy_derive::TemplateContext;
+//!
+//! #[derive(TemplateContext)]
+//! #[template(path = "tests/templates/greeting.txt")]
+//! #[allow(deprecated)] // `email` is not used. Without this, it would warn.
+//! struct Greeting {
+//!     user: String,
+//!     email: String,
+//! }
+//!
+//! assert_eq!(Greeting::FIELDS, ["user", "email"]);
+//! assert_eq!(Greeting::UNUSED_FIELDS, ["email"]);
+//! ```
+//!
+//! Stable Rust has no way for a derive to emit warnings of its own, so they
+//! are `deprecated` warnings pointing at the field, reading "field `email`
+//! of `Greeting` is not used by any of its templates". They are silenced
+//! with `#[allow(deprecated)]` on the struct or the field, or further out.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote, quote_spanned};
+use syn::ext::IdentExt;
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Error, Fields, LitStr};
+
+#[proc_macro_derive(TemplateContext, attributes(template))]
+pub fn derive_template_context(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// The paths given with `#[template(path = "...")]`.
+fn template_paths(input: &DeriveInput) -> syn::Result<Vec<LitStr>> {
+    let mut paths = vec![];
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("template")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("path") {
+                paths.push(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `path = \"...\"`"))
+            }
+        })?;
+    }
+    if paths.is_empty() {
+        return Err(Error::new_spanned(
+            &input.ident,
+            "expected templates listed with #[template(path = \"...\")]",
+        ));
+    }
+    Ok(paths)
+}
+
+/// The `#[allow(...)]` attributes among `attrs`, which are copied onto the
+/// items carrying the warnings, as those are not inside the struct.
+fn allows(attrs: &[Attribute]) -> impl Iterator<Item = &Attribute> {
+    attrs.iter().filter(|attr| attr.path().is_ident("allow"))
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new_spanned(
+                    &data.fields,
+                    "TemplateContext needs named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "TemplateContext can only be derived for structs",
+            ))
+        }
+    };
+    let fields: Vec<_> = fields
+        .iter()
+        .filter_map(|field| Some((field.ident.as_ref()?, &field.attrs)))
+        .map(|(ident, attrs)| (ident.unraw().to_string(), ident, attrs))
+        .collect();
+
+    let root = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap_or_default());
+    let mut unused: Vec<&str> = fields.iter().map(|(name, ..)| name.as_str()).collect();
+    let mut tracked = vec![];
+    for path in template_paths(input)? {
+        let full = root.join(path.value());
+        let source = fs::read_to_string(&full)
+            .map_err(|e| Error::new(path.span(), format!("{}: {}", full.display(), e)))?;
+        unused = spongy::lint::unused_keys(&source, unused)
+            .map_err(|e| Error::new(path.span(), format!("{}: {}", full.display(), e)))?;
+        tracked.push(full.to_string_lossy().into_owned());
+    }
+
+    let context = &input.ident;
+    let warnings = fields
+        .iter()
+        .filter(|(name, ..)| unused.contains(&name.as_str()))
+        .map(|(name, ident, attrs)| {
+            let note = format!(
+                "field `{}` of `{}` is not used by any of its templates",
+                name, context
+            );
+            let warning = format_ident!("__spongy_unused_{}", name, span = ident.span());
+            let allows = allows(&input.attrs).chain(allows(attrs));
+            quote_spanned! {ident.span()=>
+                #(#allows)*
+                const _: () = {
+                    #[deprecated(note = #note)]
+                    #[allow(non_upper_case_globals)]
+                    const #warning: () = ();
+                    #warning
+                };
+            }
+        });
+    let names = fields.iter().map(|(name, ..)| name);
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::spongy::lint::TemplateContext for #context #ty_generics #where_clause {
+            const FIELDS: &'static [&'static str] = &[#(#names),*];
+            const UNUSED_FIELDS: &'static [&'static str] = &[#(#unused),*];
+        }
+
+        #(const _: &str = include_str!(#tracked);)*
+        #(#warnings)*
+    })
+}
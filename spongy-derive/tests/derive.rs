@@ -0,0 +1,34 @@
+// The fields are only there to be checked, and the unused ones meant to be
+// warned about.
+#![allow(dead_code, deprecated)]
+
+use spongy::lint::TemplateContext;
+use spongy_derive::TemplateContext;
+
+#[derive(TemplateContext)]
+#[template(path = "tests/templates/greeting.txt")]
+#[template(path = "tests/templates/footer.txt")]
+struct Page<'a, T> {
+    user: T,
+    id: u64,
+    note: &'a str,
+    r#type: String,
+}
+
+#[derive(TemplateContext)]
+#[template(
+    path = "tests/templates/greeting.txt",
+    path = "tests/templates/footer.txt"
+)]
+struct Used {
+    user: String,
+    id: u64,
+}
+
+#[test]
+fn list_unused_fields() {
+    assert_eq!(Page::<'_, ()>::FIELDS, ["user", "id", "note", "type"]);
+    // `{# note #}` is a comment, not a use.
+    assert_eq!(Page::<'_, ()>::UNUSED_FIELDS, ["note", "type"]);
+    assert!(Used::UNUSED_FIELDS.is_empty());
+}
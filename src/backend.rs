@@ -0,0 +1,147 @@
+//! Swappable parsers producing the element stream.
+//!
+//! Everything downstream of parsing works on elements and spans, so a
+//! [`ParserBackend`] can replace the built-in parser, e.g. with a dialect of
+//! its own.
+
+use pest::error::{Error, ErrorVariant};
+use pest::Position;
+
+use crate::bytes::{parse_bytes, ByteElement, ByteError};
+use crate::{parse_spanned, Element, ParseOptions, Rule, Span};
+
+pub trait ParserBackend {
+    /// Parses `s` into elements along with their spans, which must cover
+    /// `s` in order.
+    ///
+    /// Backends not based on the grammar can report errors with
+    /// [`error_at`].
+    fn parse<'e>(&self, s: &'e str) -> Result<Vec<(Element<'e>, Span)>, Error<Rule>>;
+}
+
+/// Builds a parse error with a custom message at byte `pos` of `s`.
+pub fn error_at(s: &str, pos: usize, message: String) -> Error<Rule> {
+    Error::new_from_pos(
+        ErrorVariant::CustomError { message },
+        Position::new(s, pos).unwrap_or_else(|| Position::from_start(s)),
+    )
+}
+
+/// The built-in parser with parse options, as used by
+/// [`parse_with_options`](crate::parse_with_options).
+///
+/// Despite the name, input is split by the scanner of [`crate::bytes`]; the
+/// pest grammar defines the syntax and is only run on input the scanner
+/// rejects, to describe the error.
+#[derive(Clone, Debug, Default)]
+pub struct PestBackend {
+    pub options: ParseOptions,
+}
+
+impl PestBackend {
+    pub fn new(options: ParseOptions) -> PestBackend {
+        PestBackend { options }
+    }
+}
+
+impl ParserBackend for PestBackend {
+    fn parse<'e>(&self, s: &'e str) -> Result<Vec<(Element<'e>, Span)>, Error<Rule>> {
+        parse_spanned(s, &self.options)
+    }
+}
+
+/// A hand-rolled scanner for the default grammar, without parse options.
+///
+/// It accepts the same inputs as the grammar, but reports errors with less
+/// detail.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ScannerBackend;
+
+impl ParserBackend for ScannerBackend {
+    fn parse<'e>(&self, s: &'e str) -> Result<Vec<(Element<'e>, Span)>, Error<Rule>> {
+        let elements = parse_bytes(s.as_bytes()).map_err(|e| match e {
+            ByteError::Unexpected { pos } | ByteError::TooComplex { pos } => {
+                error_at(s, pos, e.to_string())
+            }
+            ByteError::InvalidUtf8 { .. } => unreachable!("input is a str"),
+        })?;
+
+        let mut pos = 0;
+        Ok(elements
+            .into_iter()
+            .map(|el| {
+                let (el, len) = match el {
+                    // Delimiters are ASCII, so text ends on char boundaries.
+                    ByteElement::Text(t) => (Element::Text(&s[pos..pos + t.len()]), t.len()),
+                    ByteElement::Wrapped(item) => {
+                        let len = item.raw().len();
+                        (Element::Wrapped(item), len)
+                    }
+                };
+                pos += len;
+                (el, Span::new(pos - len, pos))
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backends_agree() {
+        for s in &[
+            "Hello, {name}!",
+            "{{{a}}} ${b} {{ c }} {## #} {% d %} {}",
+            "{#} {%} $ a } \\{x} \\${y} ü{ü}",
+            "",
+        ] {
+            assert_eq!(
+                ScannerBackend.parse(s).unwrap(),
+                PestBackend::default().parse(s).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn scanner_errors() {
+        let e = ScannerBackend.parse("ab {{todo..").unwrap_err();
+        assert_eq!(e.line_col, pest::error::LineColLocation::Pos((1, 4)));
+        assert!(PestBackend::default().parse("ab {{todo..").is_err());
+    }
+
+    #[test]
+    fn custom_backend() {
+        /// Treats every line starting with `@` as a placeholder.
+        struct Lines;
+
+        impl ParserBackend for Lines {
+            fn parse<'e>(&self, s: &'e str) -> Result<Vec<(Element<'e>, Span)>, Error<Rule>> {
+                let mut pos = 0;
+                let mut result = vec![];
+                for line in s.split_inclusive('\n') {
+                    let el = match line.strip_prefix('@') {
+                        Some(text) => Element::Wrapped(crate::Item::new(
+                            crate::Wrapper::Curly,
+                            text.trim_end(),
+                        )),
+                        None => Element::Text(line),
+                    };
+                    result.push((el, Span::new(pos, pos + line.len())));
+                    pos += line.len();
+                }
+                Ok(result)
+            }
+        }
+
+        let template = crate::Template::parse_with_backend("a\n@name\n", &Lines).unwrap();
+        assert_eq!(
+            template.render(|item| match item.text {
+                "name" => Some("Ann"),
+                _ => None,
+            }),
+            "a\nAnn"
+        );
+    }
+}
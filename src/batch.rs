@@ -0,0 +1,139 @@
+//! Rendering several templates against one shared context, as when
+//! materializing a directory of config files.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use pest::error::Error;
+
+use crate::resolve::{render_with_report, Resolver};
+use crate::{parse, Rule};
+
+/// What [`render_all`] does when a template fails.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OnError {
+    /// Stop at the first failing template.
+    Stop,
+    /// Render every template, collecting the failures.
+    Collect,
+}
+
+#[derive(Debug)]
+pub enum TemplateError {
+    Parse(Box<Error<Rule>>),
+    /// Resolving the placeholders with these keys failed, as reported with
+    /// [`Outcome::Errored`](crate::resolve::Outcome::Errored).
+    Errored {
+        keys: Vec<String>,
+    },
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TemplateError::Parse(e) => e.fmt(f),
+            TemplateError::Errored { keys } => {
+                write!(f, "failed to resolve {}", keys.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+impl From<Error<Rule>> for TemplateError {
+    fn from(e: Error<Rule>) -> Self {
+        TemplateError::Parse(Box::new(e))
+    }
+}
+
+/// Outputs and failures of [`render_all`], by template name.
+#[derive(Debug, Default)]
+pub struct Batch {
+    pub outputs: BTreeMap<String, String>,
+    pub errors: BTreeMap<String, TemplateError>,
+}
+
+impl Batch {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Renders each named template with `resolver`.
+///
+/// A template fails if it does not parse or one of its placeholders fails to
+/// resolve. Failed templates have no output.
+pub fn render_all<I, N, S, R>(templates: I, resolver: &R, on_error: OnError) -> Batch
+where
+    I: IntoIterator<Item = (N, S)>,
+    N: Into<String>,
+    S: AsRef<str>,
+    R: Resolver + ?Sized,
+{
+    let mut batch = Batch::default();
+    for (name, template) in templates {
+        match render_one(template.as_ref(), resolver) {
+            Ok(output) => {
+                batch.outputs.insert(name.into(), output);
+            }
+            Err(e) => {
+                batch.errors.insert(name.into(), e);
+                if on_error == OnError::Stop {
+                    break;
+                }
+            }
+        }
+    }
+    batch
+}
+
+fn render_one<R>(s: &str, resolver: &R) -> Result<String, TemplateError>
+where
+    R: Resolver + ?Sized,
+{
+    let (output, report) = render_with_report(&parse(s)?, resolver);
+    if report.errored.is_empty() {
+        Ok(output)
+    } else {
+        Err(TemplateError::Errored {
+            keys: report.errored,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolve::{Absent, ValueResolver};
+    use crate::Value;
+
+    #[test]
+    fn render_named_templates() {
+        let mut map = BTreeMap::new();
+        map.insert("host".to_owned(), Value::from("db"));
+        let root = Value::from(map);
+        let resolver = ValueResolver::new(&root).on_missing(Absent::Error);
+        let templates = vec![
+            ("a.conf", "host={{host}}"),
+            ("b.conf", "port={{port}}"),
+            ("c.conf", "{{"),
+            ("d.conf", "{{host}}:5432"),
+        ];
+
+        let batch = render_all(templates.clone(), &resolver, OnError::Collect);
+        assert!(!batch.is_ok());
+        assert_eq!(batch.outputs["a.conf"], "host=db");
+        assert_eq!(batch.outputs["d.conf"], "db:5432");
+        assert!(matches!(
+            &batch.errors["b.conf"],
+            TemplateError::Errored { keys } if keys == &["port"]
+        ));
+        assert_eq!(batch.errors["b.conf"].to_string(), "failed to resolve port");
+        assert!(matches!(batch.errors["c.conf"], TemplateError::Parse(_)));
+
+        let batch = render_all(templates, &resolver, OnError::Stop);
+        assert_eq!(batch.outputs.len(), 1);
+        assert_eq!(batch.errors.keys().collect::<Vec<_>>(), vec!["b.conf"]);
+    }
+}
@@ -0,0 +1,797 @@
+//! Command line arguments.
+
+use std::fmt;
+use std::path::PathBuf;
+
+use spongy::Wrapper;
+
+pub const USAGE: &str = "\
+Usage: spongy [render] [OPTIONS] [FILE]...
+       spongy vars [OPTIONS] [FILE]...
+       spongy check [OPTIONS] [FILE]...
+       spongy scaffold [OPTIONS] TEMPLATE_DIR OUT_DIR
+
+Renders template files, resolving placeholders from the environment.
+Files may be directories or globs like 'templates/**/*.tmpl', whose
+structure is kept below the output directory. Reads the standard input if
+no file is given.
+
+The vars command lists the variables each file uses, and the check
+command reports the files which do not parse and the variables which are
+not set, without rendering anything.
+
+The scaffold command renders every file below TEMPLATE_DIR into OUT_DIR,
+resolving placeholders in file and directory names too.
+
+Options are also read from a .spongyrc file in the current directory or
+the closest parent directory having one, holding lines like 'missing =
+warn' or 'in-place = true'. Paths in it are relative to its directory.
+Options given on the command line take precedence, except for env files,
+set variables, defaults and secrets, which add up.
+
+Options:
+  -c, --config FILE  Read options from FILE instead of .spongyrc
+      --no-config    Do not read a config file
+  -e, --env FILE     Read variables from a dotenv file
+  -j, --jobs N       Render N files at a time, or one per CPU if N is 0
+  -o, --out-dir DIR  Write each output to DIR instead of the standard output
+  -i, --in-place     Write each output back to its template file
+  -n, --dry-run      Write nothing, listing the files that would change
+      --diff         Show the changes to the files as a unified diff
+  -m, --missing POLICY
+                     What to do about unresolved variables, which are left
+                     in the output: ignore them (the default), warn about
+                     them, fail-fast before writing the first file missing
+                     any, or fail-at-end after reporting all of them and
+                     writing the files missing none. Scaffold renders the
+                     whole tree before failing either way.
+  -f, --format FORMAT
+                     Print the findings of vars and check as text (the
+                     default) or as json, one object per line with the file,
+                     line, column, wrapper, name and severity
+  -w, --wrappers LIST
+                     Only resolve placeholders of the comma-separated
+                     wrappers, like double_curly,dollar_curly
+      --set KEY=VALUE
+                     Set a variable, overriding the environment and dotenv
+                     files. Dotted keys like a.b set fields of a value
+      --set-file KEY=@FILE
+                     Set a variable to the contents of FILE
+      --set-json KEY=JSON
+                     Set a variable to a JSON value, whose fields and
+                     elements are looked up with paths like KEY.a or KEY[0]
+      --default KEY=VALUE
+                     Use VALUE for KEY if nothing else sets it
+      --secret PATTERN
+                     Redact the values of variables matching the glob
+                     PATTERN, like '*_PASSWORD', from diffs and messages
+      --interactive  Prompt on the terminal for the variables render and
+                     scaffold need which are not set, offering their
+                     defaults. Answers for secret variables and names like
+                     password or api_token are not echoed
+      --sanitize C   Replace separators in values substituted into scaffold
+                     paths with C instead of failing
+  -h, --help         Print this help
+
+Exit status:
+  0  Success
+  1  A file failed to render or to be written
+  2  Invalid arguments or dotenv files
+  3  Variables were missing with --missing fail-fast or fail-at-end, and
+     nothing else failed
+";
+
+/// What an option takes as its value.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Takes {
+    Nothing,
+    /// A path, named as in the help.
+    File(&'static str),
+    /// Some other text.
+    Text(&'static str),
+    /// One of the given words.
+    OneOf(&'static str, &'static [&'static str]),
+}
+
+/// An option as described to shells and in the man page.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct OptionSpec {
+    pub short: Option<char>,
+    pub long: &'static str,
+    pub takes: Takes,
+    /// A one line description.
+    pub help: &'static str,
+}
+
+const fn option(
+    short: Option<char>,
+    long: &'static str,
+    takes: Takes,
+    help: &'static str,
+) -> OptionSpec {
+    OptionSpec {
+        short,
+        long,
+        takes,
+        help,
+    }
+}
+
+/// The options of all commands, as listed in [`USAGE`].
+pub const OPTIONS: &[OptionSpec] = &[
+    option(
+        Some('c'),
+        "config",
+        Takes::File("FILE"),
+        "Read options from FILE instead of .spongyrc",
+    ),
+    option(
+        None,
+        "no-config",
+        Takes::Nothing,
+        "Do not read a config file",
+    ),
+    option(
+        Some('e'),
+        "env",
+        Takes::File("FILE"),
+        "Read variables from a dotenv file",
+    ),
+    option(
+        Some('j'),
+        "jobs",
+        Takes::Text("N"),
+        "Render N files at a time, or one per CPU if N is 0",
+    ),
+    option(
+        Some('o'),
+        "out-dir",
+        Takes::File("DIR"),
+        "Write each output to DIR instead of the standard output",
+    ),
+    option(
+        Some('i'),
+        "in-place",
+        Takes::Nothing,
+        "Write each output back to its template file",
+    ),
+    option(
+        Some('n'),
+        "dry-run",
+        Takes::Nothing,
+        "Write nothing, listing the files that would change",
+    ),
+    option(
+        None,
+        "diff",
+        Takes::Nothing,
+        "Show the changes to the files as a unified diff",
+    ),
+    option(
+        Some('m'),
+        "missing",
+        Takes::OneOf("POLICY", &["ignore", "warn", "fail-fast", "fail-at-end"]),
+        "What to do about unresolved variables",
+    ),
+    option(
+        Some('f'),
+        "format",
+        Takes::OneOf("FORMAT", &["text", "json"]),
+        "Print the findings of vars and check as text or json",
+    ),
+    option(
+        Some('w'),
+        "wrappers",
+        Takes::Text("LIST"),
+        "Only resolve placeholders of the comma-separated wrappers",
+    ),
+    option(
+        None,
+        "set",
+        Takes::Text("KEY=VALUE"),
+        "Set a variable, overriding the environment and dotenv files",
+    ),
+    option(
+        None,
+        "set-file",
+        Takes::Text("KEY=@FILE"),
+        "Set a variable to the contents of FILE",
+    ),
+    option(
+        None,
+        "set-json",
+        Takes::Text("KEY=JSON"),
+        "Set a variable to a JSON value",
+    ),
+    option(
+        None,
+        "default",
+        Takes::Text("KEY=VALUE"),
+        "Use VALUE for KEY if nothing else sets it",
+    ),
+    option(
+        None,
+        "secret",
+        Takes::Text("PATTERN"),
+        "Redact the values of variables matching PATTERN from diffs and messages",
+    ),
+    option(
+        None,
+        "interactive",
+        Takes::Nothing,
+        "Prompt on the terminal for the variables which are not set",
+    ),
+    option(
+        None,
+        "sanitize",
+        Takes::Text("C"),
+        "Replace separators in values substituted into scaffold paths with C",
+    ),
+    option(Some('h'), "help", Takes::Nothing, "Print this help"),
+];
+
+/// The commands listed in [`USAGE`] with their descriptions.
+pub const COMMANDS: &[(&str, &str)] = &[
+    ("render", "Render template files, the default"),
+    ("vars", "List the variables each file uses"),
+    (
+        "check",
+        "Report files which do not parse and variables which are not set",
+    ),
+    (
+        "scaffold",
+        "Render a template directory into an output directory",
+    ),
+];
+
+/// Exit statuses with their meanings, as listed in [`USAGE`].
+pub const EXIT_STATUSES: &[(i32, &str)] = &[
+    (0, "Success"),
+    (EXIT_ERROR, "A file failed to render or to be written"),
+    (EXIT_USAGE, "Invalid arguments or dotenv files"),
+    (
+        EXIT_MISSING,
+        "Variables were missing with --missing fail-fast or fail-at-end, and nothing else failed",
+    ),
+];
+
+/// Exit status for files failing to render or to be written.
+pub const EXIT_ERROR: i32 = 1;
+/// Exit status for invalid arguments or dotenv files.
+pub const EXIT_USAGE: i32 = 2;
+/// Exit status for missing variables under a failing [`Missing`] policy.
+pub const EXIT_MISSING: i32 = 3;
+
+/// A value given with `--set`, `--set-file` or `--set-json`.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Set {
+    Text(String),
+    File(PathBuf),
+    Json(String),
+}
+
+/// Shells to generate completions for.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// How vars and check print their findings.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+/// What to do about unresolved variables.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Missing {
+    Ignore,
+    Warn,
+    FailFast,
+    FailAtEnd,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Command {
+    /// Render the given files.
+    Render,
+    /// List the variables of the given files.
+    Vars,
+    /// Check the given files without rendering them.
+    Check,
+    Scaffold {
+        template_dir: PathBuf,
+        out_dir: PathBuf,
+    },
+    /// Print a completion script, a hidden command for packaging.
+    Completions(Shell),
+    /// Print the man page, a hidden command for packaging.
+    Man,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Args {
+    pub command: Command,
+    pub config: Option<PathBuf>,
+    pub no_config: bool,
+    pub files: Vec<PathBuf>,
+    pub env_files: Vec<PathBuf>,
+    /// Variables set on the command line by key, in order.
+    pub sets: Vec<(String, Set)>,
+    /// Values for variables nothing else sets, by key.
+    pub defaults: Vec<(String, String)>,
+    pub interactive: bool,
+    /// Glob patterns of the variables whose values are redacted.
+    pub secrets: Vec<String>,
+    pub jobs: usize,
+    pub out_dir: Option<PathBuf>,
+    pub in_place: bool,
+    pub dry_run: bool,
+    pub diff: bool,
+    pub missing: Missing,
+    pub format: Format,
+    /// The wrappers to resolve, or `None` for all of them.
+    pub wrappers: Option<Vec<Wrapper>>,
+    pub sanitize: Option<char>,
+    pub help: bool,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Args {
+            command: Command::Render,
+            config: None,
+            no_config: false,
+            files: vec![],
+            env_files: vec![],
+            sets: vec![],
+            defaults: vec![],
+            interactive: false,
+            secrets: vec![],
+            jobs: 1,
+            out_dir: None,
+            in_place: false,
+            dry_run: false,
+            diff: false,
+            missing: Missing::Ignore,
+            format: Format::Text,
+            wrappers: None,
+            sanitize: None,
+            help: false,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ArgsError {
+    /// The option needs a value but none was given.
+    MissingValue(String),
+    InvalidValue {
+        option: String,
+        value: String,
+    },
+    UnknownOption(String),
+    /// The positional arguments do not fit the command.
+    Usage(&'static str),
+}
+
+impl fmt::Display for ArgsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArgsError::MissingValue(option) => write!(f, "{} needs a value", option),
+            ArgsError::InvalidValue { option, value } => {
+                write!(f, "invalid value {:?} for {}", value, option)
+            }
+            ArgsError::UnknownOption(option) => write!(f, "unknown option {}", option),
+            ArgsError::Usage(message) => f.write_str(message),
+        }
+    }
+}
+
+impl Args {
+    #[cfg(test)]
+    pub fn parse<I>(args: I) -> Result<Args, ArgsError>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        Ok(Args::parse_given(args)?.0)
+    }
+
+    /// Parses `args`, also returning the long names of the options given,
+    /// for [config files](crate::config) not to override them.
+    pub fn parse_given<I>(args: I) -> Result<(Args, Vec<String>), ArgsError>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let (mut result, given) = Args::parse_options(args)?;
+        result.detect_command()?;
+        result.validate()?;
+        Ok((result, given))
+    }
+
+    /// Parses options and positional arguments, without looking for a
+    /// command.
+    pub fn parse_options<I>(args: I) -> Result<(Args, Vec<String>), ArgsError>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut result = Args::default();
+        let mut given = vec![];
+        let mut args = args.into_iter();
+        let mut options = true;
+
+        while let Some(arg) = args.next() {
+            if !options || arg == "-" || !arg.starts_with('-') {
+                result.files.push(PathBuf::from(arg));
+                continue;
+            }
+            if arg == "--" {
+                options = false;
+                continue;
+            }
+
+            // `--name=value` is the same as `--name value`.
+            let (option, inline) = match arg.split_once('=') {
+                Some((option, value)) if option.starts_with("--") => {
+                    (option.to_owned(), Some(value.to_owned()))
+                }
+                _ => (arg, None),
+            };
+            let long = match option.strip_prefix("--") {
+                Some(long) => long,
+                None => OPTIONS
+                    .iter()
+                    .find(|spec| {
+                        spec.short
+                            .is_some_and(|short| option[1..] == *short.to_string())
+                    })
+                    .map_or("", |spec| spec.long),
+            }
+            .to_owned();
+            let mut value = || {
+                inline
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| ArgsError::MissingValue(option.clone()))
+            };
+            let invalid = |value: String| ArgsError::InvalidValue {
+                option: option.clone(),
+                value,
+            };
+            match long.as_str() {
+                "help" => result.help = true,
+                "config" => result.config = Some(PathBuf::from(value()?)),
+                "no-config" => result.no_config = true,
+                "env" => result.env_files.push(PathBuf::from(value()?)),
+                "set" | "set-file" | "set-json" | "default" => {
+                    let assignment = value()?;
+                    let (key, value) = match assignment.split_once('=') {
+                        Some((key, value)) if !key.trim().is_empty() => (key.trim(), value),
+                        _ => return Err(invalid(assignment)),
+                    };
+                    let set = match long.as_str() {
+                        "default" => {
+                            result.defaults.push((key.to_owned(), value.to_owned()));
+                            continue;
+                        }
+                        "set" => Set::Text(value.to_owned()),
+                        "set-file" => Set::File(value.strip_prefix('@').unwrap_or(value).into()),
+                        _ => Set::Json(value.to_owned()),
+                    };
+                    result.sets.push((key.to_owned(), set));
+                }
+                "jobs" => {
+                    let jobs = value()?;
+                    result.jobs = jobs.parse().map_err(|_| invalid(jobs))?;
+                }
+                "out-dir" => result.out_dir = Some(PathBuf::from(value()?)),
+                "in-place" => result.in_place = true,
+                "interactive" => result.interactive = true,
+                "secret" => result.secrets.push(value()?),
+                "dry-run" => result.dry_run = true,
+                "diff" => result.diff = true,
+                "wrappers" => {
+                    let wrappers = value()?;
+                    let parsed = wrappers
+                        .split(',')
+                        .map(|name| name.trim().parse())
+                        .collect::<Result<Vec<Wrapper>, _>>();
+                    result.wrappers = Some(parsed.map_err(|_| invalid(wrappers))?);
+                }
+                "format" => {
+                    let format = value()?;
+                    result.format = match format.as_str() {
+                        "text" => Format::Text,
+                        "json" => Format::Json,
+                        _ => return Err(invalid(format)),
+                    };
+                }
+                "missing" => {
+                    let policy = value()?;
+                    result.missing = match policy.as_str() {
+                        "ignore" => Missing::Ignore,
+                        "warn" => Missing::Warn,
+                        "fail-fast" => Missing::FailFast,
+                        "fail-at-end" => Missing::FailAtEnd,
+                        _ => return Err(invalid(policy)),
+                    };
+                }
+                "sanitize" => {
+                    let c = value()?;
+                    let mut chars = c.chars();
+                    result.sanitize = match (chars.next(), chars.next()) {
+                        (Some(c), None) => Some(c),
+                        _ => return Err(invalid(c)),
+                    };
+                }
+                _ => return Err(ArgsError::UnknownOption(option)),
+            }
+            given.push(long);
+        }
+        Ok((result, given))
+    }
+
+    /// Takes the command from the first positional argument.
+    fn detect_command(&mut self) -> Result<(), ArgsError> {
+        let command = self.files.first().and_then(|first| first.to_str());
+        self.command = match command {
+            Some("scaffold") => match &self.files[1..] {
+                [template_dir, out_dir] => Command::Scaffold {
+                    template_dir: template_dir.clone(),
+                    out_dir: out_dir.clone(),
+                },
+                _ => return Err(ArgsError::Usage("scaffold needs TEMPLATE_DIR and OUT_DIR")),
+            },
+            Some("vars") => Command::Vars,
+            Some("check") => Command::Check,
+            Some("completions") => match self.files.get(1).and_then(|shell| shell.to_str()) {
+                Some("bash") => Command::Completions(Shell::Bash),
+                Some("zsh") => Command::Completions(Shell::Zsh),
+                Some("fish") => Command::Completions(Shell::Fish),
+                _ => return Err(ArgsError::Usage("completions needs bash, zsh or fish")),
+            },
+            Some("man") => Command::Man,
+            _ => Command::Render,
+        };
+        match self.command {
+            Command::Render if command == Some("render") => {
+                self.files.remove(0);
+            }
+            Command::Render => {}
+            Command::Vars | Command::Check => {
+                self.files.remove(0);
+            }
+            Command::Completions(_) | Command::Man | Command::Scaffold { .. } => self.files.clear(),
+        }
+        Ok(())
+    }
+
+    /// Checks that the options fit together and with the command.
+    pub fn validate(&self) -> Result<(), ArgsError> {
+        match self.command {
+            Command::Vars | Command::Check
+                if self.dry_run || self.diff || self.in_place || self.out_dir.is_some() =>
+            {
+                return Err(ArgsError::Usage("vars and check do not write files"));
+            }
+            Command::Scaffold { .. } if self.dry_run || self.diff || self.in_place => {
+                return Err(ArgsError::Usage(
+                    "scaffold does not support --dry-run, --diff or --in-place",
+                ));
+            }
+            _ => {}
+        }
+        if self.format != Format::Text && !matches!(self.command, Command::Vars | Command::Check) {
+            return Err(ArgsError::Usage("--format only applies to vars and check"));
+        }
+
+        if self.in_place && self.out_dir.is_some() {
+            return Err(ArgsError::Usage("--in-place and --out-dir conflict"));
+        }
+        if (self.dry_run || self.diff) && !self.in_place && self.out_dir.is_none() {
+            return Err(ArgsError::Usage(
+                "--dry-run and --diff need --out-dir or --in-place",
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, ArgsError> {
+        Args::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parse_args() {
+        assert_eq!(parse(&[]).unwrap(), Args::default());
+
+        let args = parse(&["scaffold", "-e", "c.env", "tpl", "--sanitize", "_", "out"]).unwrap();
+        assert_eq!(args.sanitize, Some('_'));
+        assert_eq!(
+            args.command,
+            Command::Scaffold {
+                template_dir: "tpl".into(),
+                out_dir: "out".into()
+            }
+        );
+        assert!(args.files.is_empty());
+        assert_eq!(
+            parse(&["scaffold", "tpl"]),
+            Err(ArgsError::Usage("scaffold needs TEMPLATE_DIR and OUT_DIR"))
+        );
+
+        let args = parse(&[
+            "a.txt",
+            "-j",
+            "4",
+            "--env=.env",
+            "--out-dir",
+            "out",
+            "-",
+            "--",
+            "-b",
+        ])
+        .unwrap();
+        assert_eq!(
+            args,
+            Args {
+                command: Command::Render,
+                files: vec!["a.txt".into(), "-".into(), "-b".into()],
+                env_files: vec![".env".into()],
+                jobs: 4,
+                out_dir: Some("out".into()),
+                sanitize: None,
+                ..Default::default()
+            }
+        );
+
+        assert_eq!(
+            parse(&["--missing=fail-at-end"]).unwrap().missing,
+            Missing::FailAtEnd
+        );
+        assert_eq!(
+            parse(&["-m", "fail"]),
+            Err(ArgsError::InvalidValue {
+                option: "-m".to_owned(),
+                value: "fail".to_owned()
+            })
+        );
+
+        assert_eq!(
+            parse(&["completions", "zsh"]).unwrap().command,
+            Command::Completions(Shell::Zsh)
+        );
+        assert_eq!(parse(&["man"]).unwrap().command, Command::Man);
+        assert_eq!(
+            parse(&["completions", "csh"]),
+            Err(ArgsError::Usage("completions needs bash, zsh or fish"))
+        );
+
+        let (args, given) = Args::parse_given(
+            ["-w", "curly, dollar_curly", "--no-config", "x"]
+                .iter()
+                .map(|arg| arg.to_string()),
+        )
+        .unwrap();
+        assert_eq!(
+            args.wrappers,
+            Some(vec![Wrapper::Curly, Wrapper::DollarCurly])
+        );
+        assert!(args.no_config);
+        assert_eq!(given, vec!["wrappers", "no-config"]);
+        assert!(parse(&["--wrappers=curly,nope"]).is_err());
+
+        let args = parse(&["check", "--format", "json", "a.txt"]).unwrap();
+        assert_eq!((args.command, args.format), (Command::Check, Format::Json));
+        assert_eq!(args.files, vec![PathBuf::from("a.txt")]);
+        assert_eq!(parse(&["vars"]).unwrap().command, Command::Vars);
+        assert_eq!(
+            parse(&[
+                "--set",
+                "a.b=x=y",
+                "--set-file",
+                "k=@v.txt",
+                "--set-json=j={\"a\":1}"
+            ])
+            .unwrap()
+            .sets,
+            vec![
+                ("a.b".to_owned(), Set::Text("x=y".to_owned())),
+                ("k".to_owned(), Set::File("v.txt".into())),
+                ("j".to_owned(), Set::Json("{\"a\":1}".to_owned())),
+            ]
+        );
+        assert!(parse(&["--set", "=x"]).is_err());
+        let args = parse(&["--interactive", "--default", "port=80"]).unwrap();
+        assert!(args.interactive);
+        assert_eq!(
+            parse(&["--secret", "*_KEY", "--secret=token"])
+                .unwrap()
+                .secrets,
+            vec!["*_KEY", "token"]
+        );
+        assert_eq!(args.defaults, vec![("port".to_owned(), "80".to_owned())]);
+        assert_eq!(
+            parse(&["-f", "json", "a.txt"]),
+            Err(ArgsError::Usage("--format only applies to vars and check"))
+        );
+        assert_eq!(
+            parse(&["vars", "-o", "out"]),
+            Err(ArgsError::Usage("vars and check do not write files"))
+        );
+
+        let args = parse(&["render", "-n", "--diff", "-i", "a.txt"]).unwrap();
+        assert!(args.dry_run && args.diff && args.in_place);
+        assert_eq!(args.files, vec![PathBuf::from("a.txt")]);
+        assert_eq!(
+            parse(&["--diff", "a.txt"]),
+            Err(ArgsError::Usage(
+                "--dry-run and --diff need --out-dir or --in-place"
+            ))
+        );
+        assert_eq!(
+            parse(&["-i", "-o", "out"]),
+            Err(ArgsError::Usage("--in-place and --out-dir conflict"))
+        );
+
+        assert_eq!(
+            parse(&["-j"]),
+            Err(ArgsError::MissingValue("-j".to_owned()))
+        );
+        assert_eq!(
+            parse(&["--jobs=x"]),
+            Err(ArgsError::InvalidValue {
+                option: "--jobs".to_owned(),
+                value: "x".to_owned()
+            })
+        );
+        assert_eq!(
+            parse(&["--nope"]),
+            Err(ArgsError::UnknownOption("--nope".to_owned()))
+        );
+    }
+
+    #[test]
+    fn options_are_described() {
+        for spec in OPTIONS {
+            let long = format!("--{}", spec.long);
+            let listed = match spec.short {
+                Some(short) => format!("-{}, {}", short, long),
+                None => format!("    {}", long),
+            };
+            assert!(USAGE.contains(&listed), "{} is not listed", long);
+
+            let mut args = vec![long];
+            match spec.takes {
+                Takes::Nothing => {}
+                Takes::File(_) => args.push("x".to_owned()),
+                Takes::Text(_) if spec.long == "wrappers" => args.push("curly".to_owned()),
+                Takes::Text(_) if spec.long.starts_with("set") || spec.long == "default" => {
+                    args.push("k=1".to_owned())
+                }
+                Takes::Text(_) => args.push("1".to_owned()),
+                Takes::OneOf(_, values) => args.push(values[0].to_owned()),
+            }
+            if spec.long == "format" {
+                args.insert(0, "vars".to_owned());
+            }
+            if spec.long == "dry-run" || spec.long == "diff" {
+                args.push("-i".to_owned());
+            }
+            assert!(Args::parse(args).is_ok(), "--{} does not parse", spec.long);
+        }
+        for (command, _) in COMMANDS {
+            assert!(USAGE.contains(command));
+        }
+        for (code, _) in EXIT_STATUSES {
+            assert!(USAGE.contains(&format!("  {}  ", code)));
+        }
+    }
+}
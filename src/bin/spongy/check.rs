@@ -0,0 +1,215 @@
+//! Findings of the vars and check commands.
+
+use std::fmt::Write;
+
+use pest::error::LineColLocation;
+use spongy::{filters, lint, parse_spanned, Element, ParseOptions, Resolver, Wrapper};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+/// Something found at a position of a file, counting lines and columns
+/// from 1.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Finding {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    /// The wrapper of the placeholder, if the finding is about one.
+    pub wrapper: Option<Wrapper>,
+    /// The variable the finding is about, or the error message.
+    pub name: String,
+    pub severity: Severity,
+}
+
+impl Finding {
+    /// Formats the finding as `file:line:column: severity: name`.
+    pub fn text(&self) -> String {
+        format!(
+            "{}:{}:{}: {}: {}",
+            self.file,
+            self.line,
+            self.column,
+            self.severity.name(),
+            self.name
+        )
+    }
+
+    /// Formats the finding as a single line JSON object.
+    pub fn json(&self) -> String {
+        let wrapper = match self.wrapper {
+            Some(wrapper) => json_string(wrapper.name()),
+            None => "null".to_owned(),
+        };
+        format!(
+            "{{\"file\":{},\"line\":{},\"column\":{},\"wrapper\":{},\"name\":{},\"severity\":{}}}",
+            json_string(&self.file),
+            self.line,
+            self.column,
+            wrapper,
+            json_string(&self.name),
+            json_string(self.severity.name())
+        )
+    }
+}
+
+/// Quotes `s` as a JSON string.
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// The line and column of byte offset `pos` in `s`, counting characters.
+fn line_col(s: &str, pos: usize) -> (usize, usize) {
+    let before = &s[..pos];
+    let line = before.matches('\n').count() + 1;
+    let start = before.rfind('\n').map_or(0, |i| i + 1);
+    (line, before[start..].chars().count() + 1)
+}
+
+/// Finds the variables of the template `s` at their first occurrence, as
+/// [`Severity::Info`] findings. With a resolver, only the variables it does
+/// not resolve are reported, with the given severity. A template which does
+/// not parse gives a single [`Severity::Error`]. Placeholders of wrappers
+/// not `enabled` are skipped.
+pub fn findings<R, E>(
+    file: &str,
+    s: &str,
+    resolver: Option<(&R, Severity)>,
+    enabled: E,
+) -> Vec<Finding>
+where
+    R: Resolver + ?Sized,
+    E: Fn(Wrapper) -> bool,
+{
+    let elements = match parse_spanned(s, &ParseOptions::default()) {
+        Ok(elements) => elements,
+        Err(e) => {
+            let (line, column) = match e.line_col {
+                LineColLocation::Pos(pos) | LineColLocation::Span(pos, _) => pos,
+            };
+            return vec![Finding {
+                file: file.to_owned(),
+                line,
+                column,
+                wrapper: None,
+                name: e.variant.message().into_owned(),
+                severity: Severity::Error,
+            }];
+        }
+    };
+
+    let plain: Vec<Element> = elements.iter().map(|(el, _)| el.clone()).collect();
+    let mut result = vec![];
+    for variable in lint::variables(&plain) {
+        if variable.is_empty() {
+            continue;
+        }
+        let (item, span) = elements
+            .iter()
+            .find_map(|(el, span)| match el {
+                Element::Wrapped(item)
+                    if !matches!(item.wrapper, Wrapper::CurlyHash | Wrapper::CurlyPercent)
+                        && filters::pipeline(item.text).0 == variable =>
+                {
+                    Some((item, span))
+                }
+                _ => None,
+            })
+            .expect("variables come from the elements");
+        if !enabled(item.wrapper) {
+            continue;
+        }
+        let severity = match resolver {
+            None => Severity::Info,
+            Some((resolver, _)) if resolver.resolve(item).is_some() => continue,
+            Some((_, severity)) => severity,
+        };
+        let (line, column) = line_col(s, span.start);
+        result.push(Finding {
+            file: file.to_owned(),
+            line,
+            column,
+            wrapper: Some(item.wrapper),
+            name: variable.to_owned(),
+            severity,
+        });
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::Context;
+    use spongy::Item;
+
+    #[test]
+    fn find_variables() {
+        let s = "{# y #}a {{ x }}\n  é{y|upper} {{x}} {}";
+        let found = findings::<Context, _>("t.txt", s, None, |_| true);
+        assert_eq!(
+            found.iter().map(Finding::text).collect::<Vec<_>>(),
+            vec!["t.txt:1:10: info: x", "t.txt:2:4: info: y"]
+        );
+        assert_eq!(
+            found[1].json(),
+            r#"{"file":"t.txt","line":2,"column":4,"wrapper":"curly","name":"y","severity":"info"}"#
+        );
+
+        let resolver = |item: &Item| match item.key() {
+            "x" => Some("1"),
+            _ => None,
+        };
+        let found = findings("t", s, Some((&resolver, Severity::Warning)), |_| true);
+        assert_eq!(
+            findings("t", s, Some((&resolver, Severity::Warning)), |w| {
+                w != Wrapper::Curly
+            }),
+            vec![]
+        );
+        assert_eq!(
+            found.iter().map(Finding::text).collect::<Vec<_>>(),
+            vec!["t:2:4: warning: y"]
+        );
+
+        let found = findings(
+            "a\"b",
+            "x\n {{",
+            Some((&resolver, Severity::Warning)),
+            |_| true,
+        );
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].line, found[0].severity), (2, Severity::Error));
+        assert!(found[0].json().starts_with(r#"{"file":"a\"b","line":2,"#));
+    }
+}
@@ -0,0 +1,286 @@
+//! Config files holding default options.
+//!
+//! A config file has one option per line, named like the long command line
+//! option, as `name = value`. Options without a value are set with `name`
+//! or `name = true`. Empty lines and lines starting with `#` are ignored.
+//!
+//! ```text
+//! # Shared by everyone rendering the deployment templates.
+//! env = deploy.env
+//! missing = fail-at-end
+//! out-dir = out
+//! ```
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::args::{Args, ArgsError, Command, Takes, OPTIONS};
+
+/// The name of discovered config files.
+pub const FILE_NAME: &str = ".spongyrc";
+
+/// Options only applying to the render command.
+const OUTPUT_OPTIONS: &[&str] = &["out-dir", "in-place", "dry-run", "diff"];
+/// Options only applying to the vars and check commands.
+const REPORT_OPTIONS: &[&str] = &["format"];
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    /// Line `line` of the config file at `path` is invalid.
+    Line {
+        path: PathBuf,
+        line: usize,
+        error: ArgsError,
+    },
+    /// The options do not fit together once the config file is applied.
+    Args(ArgsError),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            ConfigError::Line { path, line, error } => {
+                write!(f, "{}:{}: {}", path.display(), line, error)
+            }
+            ConfigError::Args(error) => error.fmt(f),
+        }
+    }
+}
+
+/// Finds the config file in `dir` or the closest of its ancestors.
+pub fn discover(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(FILE_NAME))
+        .find(|path| path.is_file())
+}
+
+/// Options read from a config file.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Config {
+    args: Args,
+    given: Vec<String>,
+}
+
+impl Config {
+    /// Parses the config file contents `s`, taking paths as relative to
+    /// `dir`.
+    pub fn parse(s: &str, dir: &Path) -> Result<Config, (usize, ArgsError)> {
+        let mut args = vec![];
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = match line.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim())),
+                None => (line, None),
+            };
+            let spec = OPTIONS
+                .iter()
+                .find(|spec| spec.long == name && !matches!(name, "help" | "config" | "no-config"))
+                .ok_or_else(|| (i + 1, ArgsError::UnknownOption(name.to_owned())))?;
+
+            let arg = match (spec.takes, value) {
+                (Takes::Nothing, None) | (Takes::Nothing, Some("true")) => format!("--{}", name),
+                (Takes::Nothing, Some("false")) => continue,
+                (Takes::Nothing, Some(value)) => {
+                    let error = ArgsError::InvalidValue {
+                        option: name.to_owned(),
+                        value: value.to_owned(),
+                    };
+                    return Err((i + 1, error));
+                }
+                (Takes::File(_), Some(value)) => {
+                    format!("--{}={}", name, dir.join(value).display())
+                }
+                (_, Some(value)) if name == "set-file" => match value.split_once('=') {
+                    Some((key, path)) => {
+                        let path = path.strip_prefix('@').unwrap_or(path);
+                        format!("--{}={}={}", name, key, dir.join(path).display())
+                    }
+                    None => format!("--{}={}", name, value),
+                },
+                (_, Some(value)) => format!("--{}={}", name, value),
+                (_, None) => return Err((i + 1, ArgsError::MissingValue(name.to_owned()))),
+            };
+            Args::parse_options(vec![arg.clone()]).map_err(|error| (i + 1, error))?;
+            args.push(arg);
+        }
+
+        let (args, given) = Args::parse_options(args).expect("every line parses");
+        Ok(Config { args, given })
+    }
+
+    /// Reads the config file at `path`.
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let s = fs::read_to_string(path).map_err(|error| ConfigError::Io {
+            path: path.to_owned(),
+            error,
+        })?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        Config::parse(&s, dir).map_err(|(line, error)| ConfigError::Line {
+            path: path.to_owned(),
+            line,
+            error,
+        })
+    }
+
+    /// Sets the options of `args` not in `given` from the config, apart
+    /// from those not applying to its command. Env files, variables set,
+    /// defaults and secrets of the config come before those of `args`, which
+    /// override them.
+    pub fn apply(&self, args: &mut Args, given: &[String]) {
+        let config = &self.args;
+        for name in &self.given {
+            let applies = match args.command {
+                Command::Render => !REPORT_OPTIONS.contains(&name.as_str()),
+                Command::Vars | Command::Check => !OUTPUT_OPTIONS.contains(&name.as_str()),
+                _ => {
+                    !OUTPUT_OPTIONS.contains(&name.as_str())
+                        && !REPORT_OPTIONS.contains(&name.as_str())
+                }
+            };
+            if !applies || (name != "env" && given.contains(name)) {
+                continue;
+            }
+            match name.as_str() {
+                "env" | "set" | "set-file" | "set-json" | "default" | "secret" => {}
+                "interactive" => args.interactive = config.interactive,
+                "jobs" => args.jobs = config.jobs,
+                "out-dir" => args.out_dir = config.out_dir.clone(),
+                "in-place" => args.in_place = config.in_place,
+                "dry-run" => args.dry_run = config.dry_run,
+                "diff" => args.diff = config.diff,
+                "missing" => args.missing = config.missing,
+                "format" => args.format = config.format,
+                "wrappers" => args.wrappers = config.wrappers.clone(),
+                "sanitize" => args.sanitize = config.sanitize,
+                _ => unreachable!("the name of an option allowed in a config file"),
+            }
+        }
+        let mut env_files = config.env_files.clone();
+        env_files.append(&mut args.env_files);
+        args.env_files = env_files;
+        let mut sets = config.sets.clone();
+        sets.append(&mut args.sets);
+        args.sets = sets;
+        let mut defaults = config.defaults.clone();
+        defaults.append(&mut args.defaults);
+        args.defaults = defaults;
+        let mut secrets = config.secrets.clone();
+        secrets.append(&mut args.secrets);
+        args.secrets = secrets;
+    }
+}
+
+/// Applies the config file named by `args`, or else the one discovered from
+/// `dir`, to `args`, unless `--no-config` is given.
+pub fn configure(mut args: Args, given: &[String], dir: &Path) -> Result<Args, ConfigError> {
+    if args.no_config {
+        return Ok(args);
+    }
+    let path = match args.config.clone().or_else(|| discover(dir)) {
+        Some(path) => path,
+        None => return Ok(args),
+    };
+    Config::load(&path)?.apply(&mut args, given);
+    args.validate().map_err(ConfigError::Args)?;
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::{Missing, Set};
+
+    fn given(args: &[&str]) -> (Args, Vec<String>) {
+        Args::parse_given(args.iter().map(|arg| arg.to_string())).unwrap()
+    }
+
+    #[test]
+    fn parse_config() {
+        let s = "# defaults\nenv = a.env\n\nmissing = warn\njobs=4\nout-dir = out\ndry-run\ndiff = false\n";
+        let config = Config::parse(s, Path::new("conf")).unwrap();
+        assert_eq!(
+            config.given,
+            vec!["env", "missing", "jobs", "out-dir", "dry-run"]
+        );
+
+        let (mut args, cli) = given(&["-j", "2", "-e", "b.env", "a.txt"]);
+        config.apply(&mut args, &cli);
+        assert_eq!(args.jobs, 2);
+        assert_eq!(args.missing, Missing::Warn);
+        assert_eq!(args.out_dir, Some(PathBuf::from("conf/out")));
+        assert!(args.dry_run && !args.diff);
+        assert_eq!(
+            args.env_files,
+            vec![PathBuf::from("conf/a.env"), PathBuf::from("b.env")]
+        );
+        assert!(args.validate().is_ok());
+
+        let (mut args, cli) = given(&["check"]);
+        config.apply(&mut args, &cli);
+        assert_eq!((args.out_dir, args.dry_run), (None, false));
+
+        assert!(matches!(
+            Config::parse("missing = warn\nnope = 1", Path::new("")),
+            Err((2, ArgsError::UnknownOption(_)))
+        ));
+        assert!(matches!(
+            Config::parse("diff = yes", Path::new("")),
+            Err((1, ArgsError::InvalidValue { .. }))
+        ));
+        let config = Config::parse(
+            "set-file = k=@v.txt\nset = a=1\ndefault = d=x\ninteractive",
+            Path::new("conf"),
+        )
+        .unwrap();
+        let (mut args, cli) = given(&["--set", "a=2"]);
+        config.apply(&mut args, &cli);
+        assert_eq!(
+            args.sets,
+            vec![
+                ("k".to_owned(), Set::File("conf/v.txt".into())),
+                ("a".to_owned(), Set::Text("1".to_owned())),
+                ("a".to_owned(), Set::Text("2".to_owned())),
+            ]
+        );
+        assert_eq!(args.defaults, vec![("d".to_owned(), "x".to_owned())]);
+        assert!(args.interactive);
+
+        assert!(matches!(
+            Config::parse("\njobs = x", Path::new("")),
+            Err((2, ArgsError::InvalidValue { .. }))
+        ));
+        assert!(matches!(
+            Config::parse("help", Path::new("")),
+            Err((1, ArgsError::UnknownOption(_)))
+        ));
+    }
+
+    #[test]
+    fn discover_config() {
+        let root = std::env::temp_dir().join(format!("spongy-config-{}", std::process::id()));
+        let dir = root.join("a/b");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(root.join(FILE_NAME), "missing = fail-fast\n").unwrap();
+        assert_eq!(discover(&dir), Some(root.join(FILE_NAME)));
+
+        let (args, cli) = given(&["x"]);
+        let args = configure(args, &cli, &dir).unwrap();
+        assert_eq!(args.missing, Missing::FailFast);
+        let (args, cli) = given(&["--no-config"]);
+        assert_eq!(
+            configure(args, &cli, &dir).unwrap().missing,
+            Missing::Ignore
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+}
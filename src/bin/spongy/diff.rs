@@ -0,0 +1,186 @@
+//! Unified diffs of rendered outputs against the files they would replace.
+
+use std::fmt::Write;
+
+/// Lines of context around each change.
+pub const CONTEXT: usize = 3;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Edit {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Computes the shortest edit script turning `a` into `b` with Myers'
+/// algorithm.
+fn edits(a: &[&str], b: &[&str]) -> Vec<Edit> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let offset = n + m + 1;
+    let index = |k: isize| (k + offset) as usize;
+    let mut v = vec![0isize; 2 * offset as usize + 1];
+    let mut trace = vec![];
+
+    'search: for d in 0..=n + m {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+                v[index(k + 1)]
+            } else {
+                v[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index(k)] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    let mut result = vec![];
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[index(prev_k)];
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            result.push(Edit::Equal);
+        }
+        if d > 0 {
+            if x == prev_x {
+                y -= 1;
+                result.push(Edit::Insert);
+            } else {
+                x -= 1;
+                result.push(Edit::Delete);
+            }
+        }
+    }
+    result.reverse();
+    result
+}
+
+/// Writes a line of a hunk, flagging a missing final newline.
+fn push_line(out: &mut String, marker: char, line: &str) {
+    out.push(marker);
+    out.push_str(line);
+    if !line.ends_with('\n') {
+        out.push_str("\n\\ No newline at end of file\n");
+    }
+}
+
+/// The range of a hunk as shown in its header.
+fn range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        len => format!("{},{}", start + 1, len),
+    }
+}
+
+/// Formats the changes from `old` to `new` as a unified diff with
+/// [`CONTEXT`] lines of context, or returns `None` if they are the same.
+pub fn unified(old: &str, new: &str, old_name: &str, new_name: &str) -> Option<String> {
+    if old == new {
+        return None;
+    }
+    let a: Vec<&str> = old.split_inclusive('\n').collect();
+    let b: Vec<&str> = new.split_inclusive('\n').collect();
+    let edits = edits(&a, &b);
+
+    // Positions in `a` and `b` before each edit.
+    let mut positions = Vec::with_capacity(edits.len() + 1);
+    let (mut i, mut j) = (0, 0);
+    for edit in &edits {
+        positions.push((i, j));
+        match edit {
+            Edit::Equal => {
+                i += 1;
+                j += 1;
+            }
+            Edit::Delete => i += 1,
+            Edit::Insert => j += 1,
+        }
+    }
+    positions.push((i, j));
+
+    let changes: Vec<usize> = (0..edits.len())
+        .filter(|&e| edits[e] != Edit::Equal)
+        .collect();
+    let mut hunks: Vec<(usize, usize)> = vec![];
+    for &e in &changes {
+        let start = e.saturating_sub(CONTEXT);
+        let end = (e + 1 + CONTEXT).min(edits.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut out = String::new();
+    let _ = writeln!(out, "--- {}\n+++ {}", old_name, new_name);
+    for (start, end) in hunks {
+        let (a_start, b_start) = positions[start];
+        let (a_end, b_end) = positions[end];
+        let _ = writeln!(
+            out,
+            "@@ -{} +{} @@",
+            range(a_start, a_end - a_start),
+            range(b_start, b_end - b_start)
+        );
+        for e in start..end {
+            let (i, j) = positions[e];
+            match edits[e] {
+                Edit::Equal => push_line(&mut out, ' ', a[i]),
+                Edit::Delete => push_line(&mut out, '-', a[i]),
+                Edit::Insert => push_line(&mut out, '+', b[j]),
+            }
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_lines() {
+        assert_eq!(unified("a\n", "a\n", "x", "y"), None);
+        assert_eq!(
+            unified("a\nb\nc\n", "a\nB\nc\nd", "old", "new").unwrap(),
+            "--- old\n+++ new\n@@ -1,3 +1,4 @@\n a\n-b\n+B\n c\n+d\n\\ No newline at end of file\n"
+        );
+        assert_eq!(
+            unified("", "x\n", "/dev/null", "new").unwrap(),
+            "--- /dev/null\n+++ new\n@@ -0,0 +1 @@\n+x\n"
+        );
+
+        let old: String = (1..=20).map(|i| format!("{}\n", i)).collect();
+        let new: String = (1..=20)
+            .filter(|&i| i != 18)
+            .map(|i| match i {
+                2 => "two\n".to_owned(),
+                i => format!("{}\n", i),
+            })
+            .collect();
+        assert_eq!(
+            unified(&old, &new, "a", "b").unwrap(),
+            "--- a\n+++ b\n\
+             @@ -1,5 +1,5 @@\n 1\n-2\n+two\n 3\n 4\n 5\n\
+             @@ -15,6 +15,5 @@\n 15\n 16\n 17\n-18\n 19\n 20\n"
+        );
+    }
+}
@@ -0,0 +1,257 @@
+//! Shell completions and the man page, generated from the option table.
+
+use std::fmt::Write;
+
+use crate::args::{OptionSpec, Shell, Takes, COMMANDS, EXIT_STATUSES, OPTIONS};
+
+/// The option as written on the command line, `-e, --env` or `--diff`.
+fn flags(spec: &OptionSpec) -> String {
+    match spec.short {
+        Some(short) => format!("-{}, --{}", short, spec.long),
+        None => format!("--{}", spec.long),
+    }
+}
+
+fn command_names() -> String {
+    COMMANDS
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The completion script for `shell`.
+pub fn completions(shell: Shell) -> String {
+    match shell {
+        Shell::Bash => bash(),
+        Shell::Zsh => zsh(),
+        Shell::Fish => fish(),
+    }
+}
+
+fn bash() -> String {
+    let mut cases = String::new();
+    for spec in OPTIONS {
+        let patterns = match spec.short {
+            Some(short) => format!("-{}|--{}", short, spec.long),
+            None => format!("--{}", spec.long),
+        };
+        let reply = match spec.takes {
+            Takes::Nothing => continue,
+            Takes::File(_) => "COMPREPLY=($(compgen -f -- \"$cur\"))".to_owned(),
+            Takes::Text(_) => "COMPREPLY=()".to_owned(),
+            Takes::OneOf(_, values) => format!(
+                "COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+                values.join(" ")
+            ),
+        };
+        let _ = writeln!(
+            cases,
+            "        {})\n            {}\n            return ;;",
+            patterns, reply
+        );
+    }
+    let options: Vec<String> = OPTIONS
+        .iter()
+        .flat_map(|spec| {
+            spec.short
+                .map(|short| format!("-{}", short))
+                .into_iter()
+                .chain(Some(format!("--{}", spec.long)))
+        })
+        .collect();
+
+    format!(
+        r#"_spongy() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}"
+    local prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+    case "$prev" in
+{cases}    esac
+    if [[ "$cur" == -* ]]; then
+        COMPREPLY=($(compgen -W "{options}" -- "$cur"))
+        return
+    fi
+    COMPREPLY=($(compgen -f -- "$cur"))
+    if [[ $COMP_CWORD -eq 1 ]]; then
+        COMPREPLY+=($(compgen -W "{commands}" -- "$cur"))
+    fi
+}}
+complete -o filenames -F _spongy spongy
+"#,
+        cases = cases,
+        options = options.join(" "),
+        commands = command_names(),
+    )
+}
+
+/// Escapes the description of a zsh `_arguments` spec.
+fn zsh_escape(s: &str) -> String {
+    s.replace('\'', "'\\''")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+        .replace(':', "\\:")
+}
+
+fn zsh() -> String {
+    let mut out = String::from("#compdef spongy\n\n_arguments -s \\\n");
+    for spec in OPTIONS {
+        let names = match spec.short {
+            Some(short) => format!(
+                "'(-{} --{})'{{-{},--{}}}",
+                short, spec.long, short, spec.long
+            ),
+            None => format!("--{}", spec.long),
+        };
+        let action = match spec.takes {
+            Takes::Nothing => String::new(),
+            Takes::File(name) => format!(":{}:_files", name),
+            Takes::Text(name) => format!(":{}: ", name),
+            Takes::OneOf(name, values) => format!(":{}:({})", name, values.join(" ")),
+        };
+        let _ = writeln!(out, "  {}'[{}]{}' \\", names, zsh_escape(spec.help), action);
+    }
+    let _ = writeln!(
+        out,
+        "  '1: :{{_alternative \"commands:command:({})\" \"files:file:_files\"}}' \\",
+        command_names()
+    );
+    out.push_str("  '*:file:_files'\n");
+    out
+}
+
+/// Quotes `s` for fish.
+fn fish_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn fish() -> String {
+    let mut out = String::new();
+    for (name, help) in COMMANDS {
+        let _ = writeln!(
+            out,
+            "complete -c spongy -n __fish_use_subcommand -a {} -d {}",
+            name,
+            fish_quote(help)
+        );
+    }
+    for spec in OPTIONS {
+        let mut line = String::from("complete -c spongy");
+        if let Some(short) = spec.short {
+            let _ = write!(line, " -s {}", short);
+        }
+        let _ = write!(line, " -l {}", spec.long);
+        match spec.takes {
+            Takes::Nothing => {}
+            Takes::File(_) => line.push_str(" -r -F"),
+            Takes::Text(_) => line.push_str(" -x"),
+            Takes::OneOf(_, values) => {
+                let _ = write!(line, " -x -a {}", fish_quote(&values.join(" ")));
+            }
+        }
+        let _ = writeln!(out, "{} -d {}", line, fish_quote(spec.help));
+    }
+    out
+}
+
+/// Escapes `s` for roff, also keeping lines from starting a request.
+fn roff(s: &str) -> String {
+    let escaped = s.replace('\\', "\\e").replace('-', "\\-");
+    match escaped.chars().next() {
+        Some('.') | Some('\'') => format!("\\&{}", escaped),
+        _ => escaped,
+    }
+}
+
+/// The man page in roff format.
+pub fn man() -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        ".TH SPONGY 1 \"\" \"spongy {}\" \"User Commands\"",
+        env!("CARGO_PKG_VERSION")
+    );
+    out.push_str(".SH NAME\nspongy \\- render templates with placeholders\n");
+    out.push_str(
+        ".SH SYNOPSIS\n\
+         .B spongy\n\
+         [\\fBrender\\fR] [\\fIOPTIONS\\fR] [\\fIFILE\\fR]...\n\
+         .br\n\
+         .B spongy\n\
+         \\fBvars\\fR|\\fBcheck\\fR [\\fIOPTIONS\\fR] [\\fIFILE\\fR]...\n\
+         .br\n\
+         .B spongy scaffold\n\
+         [\\fIOPTIONS\\fR] \\fITEMPLATE_DIR\\fR \\fIOUT_DIR\\fR\n",
+    );
+    out.push_str(
+        ".SH DESCRIPTION\n\
+         Renders template files, resolving placeholders from the environment\n\
+         and dotenv files. Files may be directories or globs, whose structure\n\
+         is kept below the output directory. Reads the standard input if no\n\
+         file is given.\n",
+    );
+
+    out.push_str(".SH COMMANDS\n");
+    for (name, help) in COMMANDS {
+        let _ = writeln!(out, ".TP\n\\fB{}\\fR\n{}", name, roff(help));
+    }
+
+    out.push_str(".SH OPTIONS\n");
+    for spec in OPTIONS {
+        let mut flags = roff(&flags(spec))
+            .split(", ")
+            .map(|flag| format!("\\fB{}\\fR", flag))
+            .collect::<Vec<_>>()
+            .join(", ");
+        match spec.takes {
+            Takes::Nothing => {}
+            Takes::File(name) | Takes::Text(name) | Takes::OneOf(name, _) => {
+                let _ = write!(flags, " \\fI{}\\fR", name);
+            }
+        }
+        let mut help = roff(spec.help);
+        if let Takes::OneOf(_, values) = spec.takes {
+            let _ = write!(help, ", one of {}", roff(&values.join(", ")));
+        }
+        let _ = writeln!(out, ".TP\n{}\n{}", flags, help);
+    }
+
+    out.push_str(".SH EXIT STATUS\n");
+    for (code, meaning) in EXIT_STATUSES {
+        let _ = writeln!(out, ".TP\n{}\n{}", code, roff(meaning));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generate_completions() {
+        let bash = completions(Shell::Bash);
+        assert!(bash.contains("        -m|--missing)\n            COMPREPLY=($(compgen -W \"ignore warn fail-fast fail-at-end\" -- \"$cur\"))\n"));
+        assert!(bash.contains("-e --env -j --jobs"));
+        assert!(bash.ends_with("complete -o filenames -F _spongy spongy\n"));
+
+        let zsh = completions(Shell::Zsh);
+        assert!(zsh.starts_with("#compdef spongy\n"));
+        assert!(zsh.contains(
+            "  '(-e --env)'{-e,--env}'[Read variables from a dotenv file]:FILE:_files' \\\n"
+        ));
+        assert!(zsh.contains("  --diff'[Show the changes to the files as a unified diff]' \\\n"));
+
+        let fish = completions(Shell::Fish);
+        assert!(fish.contains("complete -c spongy -s f -l format -x -a 'text json' -d "));
+        assert!(fish.contains("complete -c spongy -n __fish_use_subcommand -a vars -d 'List the variables each file uses'\n"));
+    }
+
+    #[test]
+    fn generate_man_page() {
+        let man = man();
+        assert!(man.starts_with(".TH SPONGY 1 "));
+        assert!(man.contains(".TP\n\\fB\\-o\\fR, \\fB\\-\\-out\\-dir\\fR \\fIDIR\\fR\n"));
+        assert!(man.contains("\n.TP\n3\nVariables were missing with \\-\\-missing"));
+        assert!(!man.lines().any(|line| line.starts_with('\'')));
+        assert_eq!(roff(".x a-b \\"), "\\&.x a\\-b \\e");
+    }
+}
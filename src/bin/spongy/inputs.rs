@@ -0,0 +1,149 @@
+//! Expanding file, directory and glob arguments into input files.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use spongy::path::matches_glob;
+
+/// A file to render.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Input {
+    pub path: PathBuf,
+    /// Where the output goes below the output directory, or `None` for the
+    /// standard input, whose output always goes to the standard output.
+    pub relative: Option<PathBuf>,
+}
+
+fn is_glob(component: &str) -> bool {
+    component.contains(['*', '?'])
+}
+
+/// Expands `args` into the files they name. Directories stand for all files
+/// below them and globs for the files they match, both keeping their
+/// structure relative to the directory or the part of the glob before the
+/// first wildcard.
+///
+/// Arguments failing to expand are reported as errors, leaving the others.
+pub fn expand(args: &[PathBuf]) -> (Vec<Input>, Vec<(PathBuf, io::Error)>) {
+    let mut inputs = vec![];
+    let mut errors = vec![];
+    for arg in args {
+        match expand_one(arg) {
+            Ok(expanded) => inputs.extend(expanded),
+            Err(e) => errors.push((arg.clone(), e)),
+        }
+    }
+    (inputs, errors)
+}
+
+fn expand_one(arg: &Path) -> io::Result<Vec<Input>> {
+    if arg == Path::new("-") {
+        return Ok(vec![Input {
+            path: arg.to_owned(),
+            relative: None,
+        }]);
+    }
+
+    let components: Vec<String> = arg
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    let base_len = match components.iter().position(|c| is_glob(c)) {
+        Some(len) => len,
+        None if arg.is_dir() => components.len(),
+        None => {
+            return Ok(vec![Input {
+                path: arg.to_owned(),
+                relative: arg.file_name().map(PathBuf::from),
+            }])
+        }
+    };
+
+    let base: PathBuf = components[..base_len].iter().collect();
+    let base = if base_len == 0 {
+        PathBuf::from(".")
+    } else {
+        base
+    };
+    let pattern = match components[base_len..].join("/") {
+        pattern if pattern.is_empty() => "**".to_owned(),
+        pattern => pattern,
+    };
+
+    let mut files = vec![];
+    walk(&base, Path::new(""), &mut files)?;
+    let inputs: Vec<Input> = files
+        .into_iter()
+        .filter(|relative| matches_glob(&pattern, &slashed(relative)))
+        .map(|relative| Input {
+            path: base.join(&relative),
+            relative: Some(relative),
+        })
+        .collect();
+    if inputs.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "no files match"));
+    }
+    Ok(inputs)
+}
+
+/// `path` with `/` separators, for matching against globs.
+fn slashed(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Collects the files below `root.join(dir)` relative to `root`, sorted.
+fn walk(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries = fs::read_dir(root.join(dir))?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let relative = dir.join(entry.file_name());
+        if entry.path().is_dir() {
+            walk(root, &relative, files)?;
+        } else {
+            files.push(relative);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_dirs_and_globs() {
+        let root = std::env::temp_dir().join(format!("spongy-inputs-{}", std::process::id()));
+        for file in ["a.tmpl", "b.txt", "sub/c.tmpl", "sub/deep/d.tmpl"] {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+
+        let relatives = |arg: PathBuf| -> Vec<String> {
+            let (inputs, errors) = expand(&[arg]);
+            assert!(errors.is_empty());
+            inputs
+                .iter()
+                .map(|input| slashed(input.relative.as_ref().unwrap()))
+                .collect()
+        };
+        assert_eq!(
+            relatives(root.join("**/*.tmpl")),
+            vec!["a.tmpl", "sub/c.tmpl", "sub/deep/d.tmpl"]
+        );
+        assert_eq!(relatives(root.join("sub/*.tmpl")), vec!["c.tmpl"]);
+        assert_eq!(relatives(root.join("sub")), vec!["c.tmpl", "deep/d.tmpl"]);
+        assert_eq!(relatives(root.join("b.txt")), vec!["b.txt"]);
+
+        let (inputs, errors) = expand(&[root.join("*.nope"), root.join("missing/*"), "-".into()]);
+        assert_eq!(inputs.len(), 1);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].1.to_string(), "no files match");
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,246 @@
+//! A small JSON parser for values given on the command line.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use spongy::Value;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct JsonError {
+    /// Byte offset of the problem.
+    pub pos: usize,
+    pub message: &'static str,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid JSON at byte {}: {}", self.pos, self.message)
+    }
+}
+
+/// Parses the JSON document `s`. Integers which fit an `i64` become
+/// [`Value::Int`], other numbers [`Value::Float`].
+pub fn parse(s: &str) -> Result<Value, JsonError> {
+    let mut parser = Parser { s, pos: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos < s.len() {
+        return Err(parser.error("trailing characters"));
+    }
+    Ok(value)
+}
+
+struct Parser<'s> {
+    s: &'s str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &'static str) -> JsonError {
+        JsonError {
+            pos: self.pos,
+            message,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.s.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        let found = self.s[self.pos..].starts_with(token);
+        if found {
+            self.pos += token.len();
+        }
+        found
+    }
+
+    fn value(&mut self) -> Result<Value, JsonError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => self.string().map(Value::Str),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ if self.eat("true") => Ok(Value::Bool(true)),
+            _ if self.eat("false") => Ok(Value::Bool(false)),
+            _ if self.eat("null") => Ok(Value::Null),
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, JsonError> {
+        self.pos += 1;
+        let mut map = BTreeMap::new();
+        self.skip_whitespace();
+        if self.eat("}") {
+            return Ok(Value::Map(map));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected a key"));
+            }
+            let key = self.string()?;
+            self.skip_whitespace();
+            if !self.eat(":") {
+                return Err(self.error("expected `:`"));
+            }
+            map.insert(key, self.value()?);
+            self.skip_whitespace();
+            if self.eat("}") {
+                return Ok(Value::Map(map));
+            }
+            if !self.eat(",") {
+                return Err(self.error("expected `,` or `}`"));
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, JsonError> {
+        self.pos += 1;
+        let mut list = vec![];
+        self.skip_whitespace();
+        if self.eat("]") {
+            return Ok(Value::List(list));
+        }
+        loop {
+            list.push(self.value()?);
+            self.skip_whitespace();
+            if self.eat("]") {
+                return Ok(Value::List(list));
+            }
+            if !self.eat(",") {
+                return Err(self.error("expected `,` or `]`"));
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, JsonError> {
+        let digits = self
+            .s
+            .get(self.pos..self.pos + 4)
+            .ok_or_else(|| self.error("expected 4 hex digits"))?;
+        let code =
+            u32::from_str_radix(digits, 16).map_err(|_| self.error("expected 4 hex digits"))?;
+        self.pos += 4;
+        Ok(code)
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            let c = self.s[self.pos..]
+                .chars()
+                .next()
+                .ok_or_else(|| self.error("unterminated string"))?;
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(out),
+                '\\' => {
+                    let escape = self
+                        .peek()
+                        .ok_or_else(|| self.error("unterminated string"))?;
+                    self.pos += 1;
+                    out.push(match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let mut code = self.hex4()?;
+                            if (0xd800..0xdc00).contains(&code) && self.eat("\\u") {
+                                let low = self.hex4()?;
+                                code = 0x10000
+                                    + ((code - 0xd800) << 10)
+                                    + (low.wrapping_sub(0xdc00) & 0x3ff);
+                            }
+                            char::from_u32(code).ok_or_else(|| self.error("invalid code point"))?
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    });
+                }
+                c if (c as u32) < 0x20 => return Err(self.error("control character in string")),
+                c => out.push(c),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, JsonError> {
+        let start = self.pos;
+        self.eat("-");
+        let digits = |parser: &mut Parser| {
+            let from = parser.pos;
+            while let Some(b'0'..=b'9') = parser.peek() {
+                parser.pos += 1;
+            }
+            parser.pos > from
+        };
+        if !digits(self) {
+            return Err(self.error("expected digits"));
+        }
+        let mut float = false;
+        if self.eat(".") {
+            float = true;
+            if !digits(self) {
+                return Err(self.error("expected digits"));
+            }
+        }
+        if let Some(b'e' | b'E') = self.peek() {
+            float = true;
+            self.pos += 1;
+            if let Some(b'+' | b'-') = self.peek() {
+                self.pos += 1;
+            }
+            if !digits(self) {
+                return Err(self.error("expected digits"));
+            }
+        }
+
+        let text = &self.s[start..self.pos];
+        match text.parse::<i64>() {
+            Ok(i) if !float => Ok(Value::Int(i)),
+            _ => text
+                .parse::<f64>()
+                .map(Value::Float)
+                .map_err(|_| self.error("invalid number")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_json() {
+        let value = parse(r#" {"a": [1, -2.5e1, true, null], "b": {"c": "x\"é😀"}} "#).unwrap();
+        assert_eq!(value.lookup("a[0]"), Some(&Value::Int(1)));
+        assert_eq!(value.lookup("a[1]"), Some(&Value::Float(-25.0)));
+        assert_eq!(value.lookup("a[2]"), Some(&Value::Bool(true)));
+        assert_eq!(value.lookup("a[3]"), Some(&Value::Null));
+        assert_eq!(value.lookup("b.c"), Some(&Value::from("x\"é😀")));
+        assert_eq!(parse("[]").unwrap(), Value::List(vec![]));
+        assert_eq!(
+            parse("12345678901234567890").unwrap(),
+            Value::Float(12345678901234567890.0)
+        );
+
+        assert_eq!(parse("{\"a\" 1}").unwrap_err().pos, 5);
+        assert!(parse("[1,]").is_err());
+        assert!(parse("\"a").is_err());
+        assert!(parse("1 2").is_err());
+        assert!(parse("-").is_err());
+    }
+}
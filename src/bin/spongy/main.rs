@@ -0,0 +1,309 @@
+//! Command line interface rendering template files.
+
+mod args;
+mod check;
+mod config;
+mod diff;
+mod generate;
+mod inputs;
+mod json;
+mod prompt;
+mod render;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+
+use spongy::dotenv;
+use spongy::path::PathPolicy;
+use spongy::scaffold::{is_binary, Scaffold};
+use spongy::secret::Secrets;
+use spongy::Value;
+
+use crate::args::{
+    Args, Command, Format, Missing, Set, EXIT_ERROR, EXIT_MISSING, EXIT_USAGE, USAGE,
+};
+use crate::check::{findings, Severity};
+use crate::inputs::{expand, Input};
+use crate::prompt::Prompt;
+use crate::render::{read, render_all, Context, Recording};
+
+fn main() {
+    let (args, given) = match Args::parse_given(std::env::args().skip(1)) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("spongy: {}\n\n{}", e, USAGE);
+            process::exit(EXIT_USAGE);
+        }
+    };
+    if args.help {
+        print!("{}", USAGE);
+        return;
+    }
+    let dir = std::env::current_dir().unwrap_or_default();
+    match config::configure(args, &given, &dir) {
+        Ok(args) => process::exit(run(args)),
+        Err(e) => {
+            eprintln!("spongy: {}", e);
+            process::exit(EXIT_USAGE);
+        }
+    }
+}
+
+/// Renders the files named by `args`, returning the exit code.
+fn run(mut args: Args) -> i32 {
+    let mut vars = HashMap::new();
+    for path in &args.env_files {
+        match dotenv::load(path) {
+            Ok(loaded) => vars.extend(loaded),
+            Err(e) => {
+                eprintln!("spongy: {}: {}", path.display(), e);
+                return EXIT_USAGE;
+            }
+        }
+    }
+    match args.command {
+        Command::Completions(shell) => {
+            print!("{}", generate::completions(shell));
+            return 0;
+        }
+        Command::Man => {
+            print!("{}", generate::man());
+            return 0;
+        }
+        _ => {}
+    }
+
+    let prompt = match args.command {
+        Command::Render | Command::Scaffold { .. } if args.interactive => {
+            match Prompt::terminal() {
+                Ok(prompt) => Some(prompt),
+                Err(e) => {
+                    eprintln!("spongy: --interactive needs a terminal: {}", e);
+                    return EXIT_USAGE;
+                }
+            }
+        }
+        _ => None,
+    };
+    let mut context = Context::new(vars)
+        .defaults(args.defaults.iter().cloned().collect())
+        .prompt(prompt)
+        .secrets(
+            args.secrets
+                .iter()
+                .fold(Secrets::new(), |secrets, pattern| {
+                    secrets.mark(pattern.as_str())
+                }),
+        )
+        .wrappers(args.wrappers.clone());
+    for (key, set) in &args.sets {
+        let value = match set {
+            Set::Text(text) => Ok(Value::from(text.as_str())),
+            Set::File(path) => fs::read_to_string(path)
+                .map(Value::Str)
+                .map_err(|e| format!("{}: {}", path.display(), e)),
+            Set::Json(json) => json::parse(json).map_err(|e| e.to_string()),
+        };
+        match value {
+            Ok(value) => context.set(key, value),
+            Err(e) => {
+                eprintln!("spongy: {}: {}", key, e);
+                return EXIT_USAGE;
+            }
+        }
+    }
+    if let Command::Scaffold {
+        template_dir,
+        out_dir,
+    } = &args.command
+    {
+        let policy = args
+            .sanitize
+            .map_or(PathPolicy::Reject, PathPolicy::Sanitize);
+        let scaffold = Scaffold::new(template_dir, out_dir).path_policy(policy);
+        let recording = Recording::new(&context);
+        if let Err(e) = scaffold.render(&recording) {
+            eprintln!("spongy: {}", context.redact(&e.to_string()));
+            return EXIT_ERROR;
+        }
+        let missing = recording.missing();
+        if args.missing != Missing::Ignore {
+            for key in &missing {
+                eprintln!("spongy: missing variable {}", key);
+            }
+        }
+        return match args.missing {
+            Missing::FailFast | Missing::FailAtEnd if !missing.is_empty() => EXIT_MISSING,
+            _ => 0,
+        };
+    }
+
+    if args.files.is_empty() {
+        args.files.push("-".into());
+    }
+
+    let mut code = 0;
+    let mut missing = false;
+    let (inputs, errors) = expand(&args.files);
+    for (arg, e) in errors {
+        eprintln!("spongy: {}: {}", arg.display(), e);
+        code = EXIT_ERROR;
+    }
+
+    if let Command::Vars | Command::Check = args.command {
+        let reported = report(&args, &inputs, &context);
+        return if code == 0 { reported } else { code };
+    }
+
+    let paths: Vec<_> = inputs.iter().map(|input| &input.path).collect();
+    let results = render_all(&paths, args.jobs, |path| context.render_file(path));
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    for (input, result) in inputs.iter().zip(results) {
+        let path = &input.path;
+        let output = match result {
+            Ok(rendered) => {
+                if args.missing != Missing::Ignore {
+                    for key in &rendered.missing {
+                        eprintln!("spongy: {}: missing variable {}", path.display(), key);
+                    }
+                }
+                if !rendered.missing.is_empty() {
+                    match args.missing {
+                        Missing::FailFast => return EXIT_MISSING,
+                        Missing::FailAtEnd => {
+                            missing = true;
+                            continue;
+                        }
+                        Missing::Ignore | Missing::Warn => {}
+                    }
+                }
+                rendered.output
+            }
+            Err(e) => {
+                let message = format!("{}: {}", path.display(), e);
+                eprintln!("spongy: {}", context.redact(&message));
+                code = EXIT_ERROR;
+                continue;
+            }
+        };
+        let target = if args.in_place {
+            Some(path.clone()).filter(|path| path != Path::new("-"))
+        } else {
+            args.out_dir
+                .as_ref()
+                .zip(input.relative.as_ref())
+                .map(|(dir, relative)| dir.join(relative))
+        };
+        let written = match target {
+            Some(target) => update(&target, &output, &args, &context, &mut stdout),
+            None => stdout.write_all(&output),
+        };
+        if let Err(e) = written {
+            eprintln!("spongy: {}: {}", path.display(), e);
+            code = EXIT_ERROR;
+        }
+    }
+    match code {
+        0 if missing => EXIT_MISSING,
+        code => code,
+    }
+}
+
+/// Writes `output` to `target`, first reporting how the file changes with
+/// `--dry-run` or `--diff`, with the secrets of `context` redacted. With
+/// `--dry-run`, nothing is written.
+fn update<W: Write>(
+    target: &Path,
+    output: &[u8],
+    args: &Args,
+    context: &Context,
+    out: &mut W,
+) -> io::Result<()> {
+    if args.dry_run || args.diff {
+        let old = match fs::read(target) {
+            Ok(old) => Some(old),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        let name = target.display().to_string();
+        let changed = old.as_deref() != Some(output);
+        if changed && !args.diff {
+            writeln!(out, "{}", name)?;
+        } else if changed {
+            let old_text = old.as_deref().map(std::str::from_utf8).transpose();
+            match (old_text, std::str::from_utf8(output)) {
+                (Ok(old_text), Ok(new_text)) => {
+                    let old_name = if old_text.is_some() {
+                        name.as_str()
+                    } else {
+                        "/dev/null"
+                    };
+                    if let Some(diff) =
+                        diff::unified(old_text.unwrap_or(""), new_text, old_name, &name)
+                    {
+                        out.write_all(context.redact(&diff).as_bytes())?;
+                    }
+                }
+                _ => writeln!(out, "Binary files {} differ", name)?,
+            }
+        }
+        if args.dry_run {
+            return Ok(());
+        }
+    }
+
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(target, output)
+}
+
+/// Prints the findings of the vars or check command about `inputs`,
+/// returning the exit code.
+fn report(args: &Args, inputs: &[Input], context: &Context) -> i32 {
+    let severity = match args.missing {
+        Missing::FailFast | Missing::FailAtEnd => Severity::Error,
+        Missing::Ignore | Missing::Warn => Severity::Warning,
+    };
+    let resolver = match args.command {
+        Command::Check => Some((context, severity)),
+        _ => None,
+    };
+
+    let mut code = 0;
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    for input in inputs {
+        let file = input.path.display().to_string();
+        let s = match read(&input.path) {
+            Ok(bytes) if is_binary(&bytes) => continue,
+            Ok(bytes) => String::from_utf8(bytes).expect("binary files are skipped"),
+            Err(e) => {
+                eprintln!("spongy: {}: {}", file, e);
+                code = EXIT_ERROR;
+                continue;
+            }
+        };
+        for finding in findings(&file, &s, resolver, |wrapper| context.enabled(wrapper)) {
+            code = match finding.severity {
+                Severity::Error if finding.wrapper.is_none() => EXIT_ERROR,
+                Severity::Error if code == 0 => EXIT_MISSING,
+                _ => code,
+            };
+            let line = match args.format {
+                Format::Text => finding.text(),
+                Format::Json => finding.json(),
+            };
+            if let Err(e) = writeln!(stdout, "{}", line) {
+                eprintln!("spongy: {}", e);
+                return EXIT_ERROR;
+            }
+        }
+    }
+    code
+}
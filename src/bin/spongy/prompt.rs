@@ -0,0 +1,175 @@
+//! Prompting for variables on the terminal with `--interactive`.
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::sync::Mutex;
+
+/// Words marking a variable name as secret, compared to the words of the
+/// name, so that `DB_PASSWORD` and `apiToken` are secret but `monkey` is not.
+const SECRET_WORDS: &[&str] = &[
+    "password",
+    "passwd",
+    "pass",
+    "secret",
+    "token",
+    "key",
+    "credential",
+    "credentials",
+    "private",
+];
+
+/// Whether the variable `name` looks like it holds a secret.
+pub fn is_secret(name: &str) -> bool {
+    spongy::filters::snake(name)
+        .split('_')
+        .any(|word| SECRET_WORDS.contains(&word))
+}
+
+struct State {
+    input: Box<dyn BufRead + Send>,
+    output: Box<dyn Write + Send>,
+    /// The terminal, for turning off its echo while reading secrets.
+    tty: Option<File>,
+    /// Answers by key, `None` for those left empty without a default.
+    answers: HashMap<String, Option<String>>,
+}
+
+/// Asks for variables which are not set, once for each key.
+pub struct Prompt {
+    state: Mutex<State>,
+}
+
+impl Prompt {
+    /// Reads answers from `input`, writing the questions to `output`.
+    pub fn new(input: Box<dyn BufRead + Send>, output: Box<dyn Write + Send>) -> Prompt {
+        Prompt {
+            state: Mutex::new(State {
+                input,
+                output,
+                tty: None,
+                answers: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Prompts on the controlling terminal, which leaves the standard input
+    /// free for templates. Answers for secret variables are not echoed.
+    pub fn terminal() -> io::Result<Prompt> {
+        let tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+        let prompt = Prompt::new(
+            Box::new(BufReader::new(tty.try_clone()?)),
+            Box::new(tty.try_clone()?),
+        );
+        prompt.state.lock().unwrap().tty = Some(tty);
+        Ok(prompt)
+    }
+
+    /// Asks for the variable `key`, offering `default` as the answer to an
+    /// empty line, without echoing the answer or the default if `secret`.
+    /// Returns `None` if left empty without a default, or if the prompt
+    /// fails.
+    pub fn ask(&self, key: &str, default: Option<&str>, secret: bool) -> Option<String> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(answer) = state.answers.get(key) {
+            return answer.clone();
+        }
+        let answer = state.read(key, default, secret).ok().flatten();
+        let answer = answer
+            .filter(|answer| !answer.is_empty())
+            .or_else(|| default.map(str::to_owned));
+        state.answers.insert(key.to_owned(), answer.clone());
+        answer
+    }
+}
+
+impl State {
+    /// Reads a line for `key`, or `None` at the end of the input.
+    fn read(
+        &mut self,
+        key: &str,
+        default: Option<&str>,
+        secret: bool,
+    ) -> io::Result<Option<String>> {
+        match (default, secret) {
+            (Some(_), true) => write!(self.output, "{} [hidden default]: ", key)?,
+            (Some(default), false) => write!(self.output, "{} [{}]: ", key, default)?,
+            (None, _) => write!(self.output, "{}: ", key)?,
+        }
+        self.output.flush()?;
+
+        let hide = secret && self.tty.is_some();
+        if hide {
+            self.echo(false);
+        }
+        let mut line = String::new();
+        let read = self.input.read_line(&mut line);
+        if hide {
+            self.echo(true);
+            writeln!(self.output)?;
+        }
+        if read? == 0 {
+            return Ok(None);
+        }
+        let line = line.strip_suffix('\n').unwrap_or(&line);
+        Ok(Some(line.strip_suffix('\r').unwrap_or(line).to_owned()))
+    }
+
+    /// Turns the echo of the terminal on or off, doing nothing where `stty`
+    /// is not available.
+    fn echo(&self, on: bool) {
+        #[cfg(unix)]
+        if let Some(tty) = self.tty.as_ref().and_then(|tty| tty.try_clone().ok()) {
+            let _ = std::process::Command::new("stty")
+                .arg(if on { "echo" } else { "-echo" })
+                .stdin(tty)
+                .status();
+        }
+        #[cfg(not(unix))]
+        let _ = on;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::sync::Arc;
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn secret_names() {
+        assert!(is_secret("DB_PASSWORD"));
+        assert!(is_secret("apiToken"));
+        assert!(is_secret("ssh.private_key"));
+        assert!(!is_secret("monkey"));
+        assert!(!is_secret("name"));
+    }
+
+    #[test]
+    fn ask_once() {
+        let output = Shared::default();
+        let prompt = Prompt::new(Box::new(Cursor::new("x\n\r\n\n")), Box::new(output.clone()));
+        assert_eq!(prompt.ask("name", None, false), Some("x".to_owned()));
+        assert_eq!(prompt.ask("name", None, false), Some("x".to_owned()));
+        assert_eq!(prompt.ask("port", Some("80"), false), Some("80".to_owned()));
+        assert_eq!(prompt.ask("token", Some("t"), true), Some("t".to_owned()));
+        assert_eq!(prompt.ask("other", None, false), None);
+        assert_eq!(
+            String::from_utf8(output.0.lock().unwrap().clone()).unwrap(),
+            "name: port [80]: token [hidden default]: other: "
+        );
+    }
+}
@@ -0,0 +1,350 @@
+//! Rendering input files, possibly several at a time.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use spongy::scaffold::{is_binary, BINARY_EXTENSIONS};
+use spongy::secret::Secrets;
+
+use crate::prompt::{is_secret, Prompt};
+use spongy::{parse, render, Item, Resolver, Value, Wrapper};
+
+/// The output of a template, along with the variables it left unresolved.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Rendered {
+    pub output: Vec<u8>,
+    /// Distinct keys of the unresolved placeholders, in order of appearance.
+    pub missing: Vec<String>,
+}
+
+pub type FileResult = Result<Rendered, Box<dyn Error + Send + Sync>>;
+
+/// Whether an unresolved `item` counts as a missing variable. Comments,
+/// statements and empty placeholders do not.
+fn is_variable(item: &Item) -> bool {
+    !matches!(item.wrapper, Wrapper::CurlyHash | Wrapper::CurlyPercent) && !item.key().is_empty()
+}
+
+/// Resolves placeholders by their [key](Item::key) from the environment,
+/// falling back to `vars`, then to the prompt and the defaults.
+pub struct Context {
+    vars: HashMap<String, String>,
+    /// Variables set on the command line, taking precedence over the others.
+    overrides: Value,
+    defaults: HashMap<String, String>,
+    prompt: Option<Prompt>,
+    secrets: Secrets,
+    wrappers: Option<Vec<Wrapper>>,
+}
+
+impl Context {
+    pub fn new(vars: HashMap<String, String>) -> Context {
+        Context {
+            vars,
+            overrides: Value::Map(BTreeMap::new()),
+            defaults: HashMap::new(),
+            prompt: None,
+            secrets: Secrets::new(),
+            wrappers: None,
+        }
+    }
+
+    /// Sets the variable `key`, overriding the environment and `vars`. A
+    /// dotted key like `a.b` sets the field `b` of `a`, making `a` a map if
+    /// it is not one.
+    pub fn set(&mut self, key: &str, value: Value) {
+        let mut target = &mut self.overrides;
+        let mut segments = key.split('.').peekable();
+        while let Some(segment) = segments.next() {
+            let map = match target {
+                Value::Map(map) => map,
+                other => {
+                    *other = Value::Map(BTreeMap::new());
+                    match other {
+                        Value::Map(map) => map,
+                        _ => unreachable!(),
+                    }
+                }
+            };
+            if segments.peek().is_none() {
+                map.insert(segment.to_owned(), value);
+                return;
+            }
+            target = map.entry(segment.to_owned()).or_default();
+        }
+    }
+
+    /// Uses `defaults` for the variables nothing else sets.
+    pub fn defaults(mut self, defaults: HashMap<String, String>) -> Self {
+        self.defaults = defaults;
+        self
+    }
+
+    /// Asks `prompt` for the variables which are not set, offering their
+    /// defaults.
+    pub fn prompt(mut self, prompt: Option<Prompt>) -> Self {
+        self.prompt = prompt;
+        self
+    }
+
+    /// Remembers the values of the variables `secrets` marks, for
+    /// [`redact`](Context::redact).
+    pub fn secrets(mut self, secrets: Secrets) -> Self {
+        self.secrets = secrets;
+        self
+    }
+
+    /// Replaces the values resolved so far for secret variables in `s`.
+    pub fn redact<'s>(&self, s: &'s str) -> Cow<'s, str> {
+        self.secrets.redact(s)
+    }
+
+    /// Only resolves placeholders of `wrappers`, if given, leaving the others
+    /// as they are.
+    pub fn wrappers(mut self, wrappers: Option<Vec<Wrapper>>) -> Self {
+        self.wrappers = wrappers;
+        self
+    }
+
+    /// Whether placeholders of `wrapper` are resolved.
+    pub fn enabled(&self, wrapper: Wrapper) -> bool {
+        self.wrappers
+            .as_ref()
+            .is_none_or(|wrappers| wrappers.contains(&wrapper))
+    }
+
+    /// Renders the template `s`, looking each placeholder up once.
+    pub fn render(&self, s: &str) -> FileResult {
+        let elements = parse(s)?;
+        let recording = Recording::new(self);
+        let output = render(&elements, |item| recording.resolve(item)).into_bytes();
+        Ok(Rendered {
+            output,
+            missing: recording.missing(),
+        })
+    }
+
+    /// Renders the file at `path`, or the standard input for `-`. Binary
+    /// files are passed through untouched.
+    pub fn render_file(&self, path: &Path) -> FileResult {
+        let bytes = read(path)?;
+        let ext = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase());
+        if ext.is_some_and(|ext| BINARY_EXTENSIONS.contains(&ext.as_str())) || is_binary(&bytes) {
+            return Ok(Rendered {
+                output: bytes,
+                missing: vec![],
+            });
+        }
+        self.render(&String::from_utf8(bytes)?)
+    }
+}
+
+/// Reads the file at `path`, or the standard input for `-`.
+pub fn read(path: &Path) -> io::Result<Vec<u8>> {
+    if path == Path::new("-") {
+        let mut bytes = vec![];
+        io::stdin().read_to_end(&mut bytes)?;
+        Ok(bytes)
+    } else {
+        fs::read(path)
+    }
+}
+
+impl Resolver for Context {
+    fn resolve<'r>(&'r self, item: &Item) -> Option<Cow<'r, str>> {
+        let value = self.lookup(item);
+        if let Some(value) = &value {
+            self.secrets.record(item, value);
+        }
+        value
+    }
+}
+
+impl Context {
+    fn lookup(&self, item: &Item) -> Option<Cow<'_, str>> {
+        let key = item.key();
+        if key.is_empty() || !self.enabled(item.wrapper) {
+            return None;
+        }
+        if let Some(value) = self.overrides.lookup(key) {
+            return Some(Cow::Owned(value.to_text().into_owned()));
+        }
+        if let Ok(value) = env::var(key) {
+            return Some(Cow::Owned(value));
+        }
+        if let Some(value) = self.vars.get(key) {
+            return Some(Cow::Borrowed(value));
+        }
+        let default = self.defaults.get(key).map(String::as_str);
+        match &self.prompt {
+            Some(prompt) => {
+                let secret = is_secret(key) || self.secrets.is_secret(key);
+                prompt.ask(key, default, secret).map(Cow::Owned)
+            }
+            None => default.map(Cow::Borrowed),
+        }
+    }
+}
+
+/// Resolves like a [`Context`], recording the missing variables, for
+/// rendering through the library.
+pub struct Recording<'c> {
+    context: &'c Context,
+    missing: Mutex<Vec<String>>,
+}
+
+impl<'c> Recording<'c> {
+    pub fn new(context: &'c Context) -> Recording<'c> {
+        Recording {
+            context,
+            missing: Mutex::new(vec![]),
+        }
+    }
+
+    /// Distinct missing variables, in the order they were looked up.
+    pub fn missing(&self) -> Vec<String> {
+        self.missing.lock().unwrap().clone()
+    }
+}
+
+impl Resolver for Recording<'_> {
+    fn resolve<'r>(&'r self, item: &Item) -> Option<Cow<'r, str>> {
+        let value = self.context.resolve(item);
+        if value.is_none() && is_variable(item) && self.context.enabled(item.wrapper) {
+            let mut missing = self.missing.lock().unwrap();
+            if !missing.iter().any(|m| m == item.key()) {
+                missing.push(item.key().to_owned());
+            }
+        }
+        value
+    }
+}
+
+/// Applies `render` to every path on `jobs` threads, returning the results
+/// in the order of `paths`.
+pub fn render_all<P, F, T>(paths: &[P], jobs: usize, render: F) -> Vec<T>
+where
+    P: AsRef<Path> + Sync,
+    F: Fn(&Path) -> T + Sync,
+    T: Send,
+{
+    let jobs = match jobs {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        jobs => jobs,
+    };
+    if jobs == 1 || paths.len() <= 1 {
+        return paths.iter().map(|path| render(path.as_ref())).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let results: Vec<Mutex<Option<T>>> = paths.iter().map(|_| Mutex::new(None)).collect();
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(paths.len()) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                match paths.get(i) {
+                    Some(path) => *results[i].lock().unwrap() = Some(render(path.as_ref())),
+                    None => break,
+                }
+            });
+        }
+    });
+    results
+        .into_iter()
+        .map(|result| result.into_inner().unwrap().unwrap())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_in_order() {
+        let paths: Vec<String> = (0..20).map(|i| i.to_string()).collect();
+        let render = |path: &Path| -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+            let i: u64 = path.to_str().unwrap().parse().unwrap();
+            thread::sleep(std::time::Duration::from_millis(20 - i));
+            if i == 3 {
+                Err("three".into())
+            } else {
+                Ok(format!("<{}>", i).into_bytes())
+            }
+        };
+        for jobs in [0, 1, 4] {
+            let results = render_all(&paths, jobs, render);
+            assert_eq!(results.len(), 20);
+            assert_eq!(results[0].as_ref().unwrap(), b"<0>");
+            assert_eq!(results[3].as_ref().unwrap_err().to_string(), "three");
+            assert_eq!(results[19].as_ref().unwrap(), b"<19>");
+        }
+    }
+
+    #[test]
+    fn render_with_vars() {
+        let mut vars = HashMap::new();
+        vars.insert("SPONGY_CLI_NAME".to_owned(), "world".to_owned());
+        let context = Context::new(vars);
+        let rendered = context
+            .render("Hello, {{ SPONGY_CLI_NAME }}! {x} {# c #} {x} {}")
+            .unwrap();
+        assert_eq!(rendered.output, b"Hello, world! {x} {# c #} {x} {}");
+        assert_eq!(rendered.missing, vec!["x"]);
+        assert!(context.render("{{").is_err());
+
+        let mut context = context;
+        context.set("SPONGY_CLI_NAME", Value::from("set"));
+        context.set("s.b", Value::Int(1));
+        context.set("s.c.d", Value::from("x"));
+        assert_eq!(
+            context
+                .render("{{SPONGY_CLI_NAME}} {s.b} {s.c.d} {s}")
+                .unwrap()
+                .output,
+            b"set 1 x b: 1, c: d: x"
+        );
+        context.set("s.b.e", Value::Null);
+        assert_eq!(context.render("{s.b}").unwrap().output, b"e: ");
+
+        let context = context.wrappers(Some(vec![Wrapper::Curly]));
+        let rendered = context.render("{{SPONGY_CLI_NAME}} {x}").unwrap();
+        assert_eq!(rendered.output, b"{{SPONGY_CLI_NAME}} {x}");
+        assert_eq!(rendered.missing, vec!["x"]);
+
+        let context = context
+            .defaults(vec![("a".to_owned(), "d".to_owned())].into_iter().collect())
+            .wrappers(None);
+        assert_eq!(context.render("{a}").unwrap().output, b"d");
+        let context = context.prompt(Some(Prompt::new(
+            Box::new(io::Cursor::new("\nb\n")),
+            Box::new(io::sink()),
+        )));
+        let rendered = context.render("{a}{b}{c}{b}").unwrap();
+        assert_eq!(rendered.output, b"db{c}b");
+        assert_eq!(rendered.missing, vec!["c"]);
+        let context = context
+            .prompt(None)
+            .defaults(HashMap::new())
+            .secrets(Secrets::new().mark("SPONGY_CLI_*"));
+        context.render("{{SPONGY_CLI_NAME}}").unwrap();
+        assert_eq!(context.redact("a set"), "a [redacted]");
+
+        let recording = Recording::new(&context);
+        assert_eq!(
+            render(&parse("{a}{b}{a}{# c #}").unwrap(), |item| recording
+                .resolve(item)),
+            "{a}{b}{a}{# c #}"
+        );
+        assert_eq!(recording.missing(), vec!["a", "b"]);
+    }
+}
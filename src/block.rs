@@ -0,0 +1,284 @@
+//! Block helpers in the style of Handlebars, like
+//! `{{#each items}}...{{/each}}`.
+//!
+//! Templates have no block syntax yet. This module is the extension point
+//! blocks will be rendered through: a [`BlockHelper`] receives the parameters
+//! of the opening tag, evaluated against the context, and the [`Body`] of
+//! the block, which it may render any number of times with a context of its
+//! choosing. [`Each`], [`With`] and [`If`] are helpers like those of
+//! Handlebars; others are registered in [`BlockHelpers`] the same way.
+//!
+//! ```
+//! use spongy::block::{BlockHelper, BlockHelpers, Body, Each};
+//! use spongy::Value;
+//!
+//! /// `{{#repeat 3}}...{{/repeat}}`
+//! struct Repeat;
+//!
+//! impl BlockHelper for Repeat {
+//!     fn name(&self) -> &str {
+//!         "repeat"
+//!     }
+//!
+//!     fn render(&self, params: &[Value], context: &Value, body: &Body) -> Result<String, String> {
+//!         let times = params.first().and_then(Value::as_i64).ok_or("expected a count")?;
+//!         (0..times).map(|_| body.render(context)).collect()
+//!     }
+//! }
+//!
+//! let helpers = BlockHelpers::new().register(Each).register(Repeat);
+//! let body = |context: &Value| Ok(format!("<{}>", context));
+//! let items = Value::List(vec![Value::from("a"), Value::from("b")]);
+//! let each = helpers.render("each", &[items], &Value::Null, &Body::new(&body));
+//! assert_eq!(each.unwrap(), "<a><b>");
+//! let context = Value::from("x");
+//! let repeat = helpers.render("repeat", &[Value::Int(2)], &context, &Body::new(&body));
+//! assert_eq!(repeat.unwrap(), "<x><x>");
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::Value;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum BlockError {
+    /// No helper registered as `name`.
+    Unknown { name: String },
+    /// The helper `name` failed with `message`.
+    Helper { name: String, message: String },
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BlockError::Unknown { name } => write!(f, "unknown block helper `{}`", name),
+            BlockError::Helper { name, message } => {
+                write!(f, "block helper `{}`: {}", name, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BlockError {}
+
+/// Renders a part of a block with a context.
+pub type Render<'b> = dyn Fn(&Value) -> Result<String, String> + 'b;
+
+/// The template enclosed by a block, and the part after its `{{else}}`, if
+/// any.
+pub struct Body<'b> {
+    main: &'b Render<'b>,
+    inverse: Option<&'b Render<'b>>,
+}
+
+impl<'b> Body<'b> {
+    pub fn new(main: &'b Render<'b>) -> Body<'b> {
+        Body {
+            main,
+            inverse: None,
+        }
+    }
+
+    /// Adds the part after `{{else}}`.
+    pub fn inverse(mut self, inverse: &'b Render<'b>) -> Self {
+        self.inverse = Some(inverse);
+        self
+    }
+
+    /// Renders the block with `context`.
+    pub fn render(&self, context: &Value) -> Result<String, String> {
+        (self.main)(context)
+    }
+
+    /// Renders the part after `{{else}}` with `context`, or nothing if the
+    /// block has none.
+    pub fn render_inverse(&self, context: &Value) -> Result<String, String> {
+        match self.inverse {
+            Some(inverse) => inverse(context),
+            None => Ok(String::new()),
+        }
+    }
+}
+
+/// A block construct, like `{{#each}}` or a custom one.
+pub trait BlockHelper {
+    /// The name opening the block, e.g. `each` for `{{#each items}}`.
+    fn name(&self) -> &str;
+
+    /// Renders a block opened with `params` in `context`.
+    fn render(&self, params: &[Value], context: &Value, body: &Body) -> Result<String, String>;
+}
+
+/// `{{#each list}}`: renders the body with each element of a list or each
+/// value of a map, or the inverse if there are none.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Each;
+
+impl BlockHelper for Each {
+    fn name(&self) -> &str {
+        "each"
+    }
+
+    fn render(&self, params: &[Value], context: &Value, body: &Body) -> Result<String, String> {
+        let values: Vec<&Value> = match params {
+            [Value::List(list)] => list.iter().collect(),
+            [Value::Map(map)] => map.values().collect(),
+            [Value::Null] => vec![],
+            [value] => return Err(format!("cannot iterate over a {}", value.type_name())),
+            _ => return Err("expected one parameter".to_owned()),
+        };
+        if values.is_empty() {
+            return body.render_inverse(context);
+        }
+        values.into_iter().map(|value| body.render(value)).collect()
+    }
+}
+
+/// `{{#with value}}`: renders the body with the value as context, or the
+/// inverse if it is null.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct With;
+
+impl BlockHelper for With {
+    fn name(&self) -> &str {
+        "with"
+    }
+
+    fn render(&self, params: &[Value], context: &Value, body: &Body) -> Result<String, String> {
+        match params {
+            [Value::Null] => body.render_inverse(context),
+            [value] => body.render(value),
+            _ => Err("expected one parameter".to_owned()),
+        }
+    }
+}
+
+/// `{{#if condition}}`: renders the body if the condition is
+/// [truthy](Value::is_truthy), and the inverse otherwise.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct If;
+
+impl BlockHelper for If {
+    fn name(&self) -> &str {
+        "if"
+    }
+
+    fn render(&self, params: &[Value], context: &Value, body: &Body) -> Result<String, String> {
+        match params {
+            [value] if value.is_truthy() => body.render(context),
+            [_] => body.render_inverse(context),
+            _ => Err("expected one parameter".to_owned()),
+        }
+    }
+}
+
+/// Block helpers by name.
+#[derive(Default)]
+pub struct BlockHelpers<'h> {
+    helpers: HashMap<String, Box<dyn BlockHelper + 'h>>,
+}
+
+impl<'h> BlockHelpers<'h> {
+    pub fn new() -> BlockHelpers<'h> {
+        BlockHelpers::default()
+    }
+
+    /// Registers `helper` under its name, replacing any helper of that name.
+    pub fn register<H: BlockHelper + 'h>(mut self, helper: H) -> Self {
+        self.helpers
+            .insert(helper.name().to_owned(), Box::new(helper));
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&(dyn BlockHelper + 'h)> {
+        self.helpers.get(name).map(|helper| &**helper)
+    }
+
+    /// Renders a block of the helper `name`.
+    pub fn render(
+        &self,
+        name: &str,
+        params: &[Value],
+        context: &Value,
+        body: &Body,
+    ) -> Result<String, BlockError> {
+        let helper = self.get(name).ok_or_else(|| BlockError::Unknown {
+            name: name.to_owned(),
+        })?;
+        helper
+            .render(params, context, body)
+            .map_err(|message| BlockError::Helper {
+                name: name.to_owned(),
+                message,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn render_builtin_blocks() {
+        let helpers = BlockHelpers::new()
+            .register(Each)
+            .register(With)
+            .register(If);
+        let main = |context: &Value| Ok(format!("[{}]", context));
+        let inverse = |_: &Value| Ok("none".to_owned());
+        let body = Body::new(&main).inverse(&inverse);
+        let render =
+            |name, param: Value| helpers.render(name, &[param], &Value::from("ctx"), &body);
+
+        let list = Value::List(vec![Value::Int(1), Value::Int(2)]);
+        assert_eq!(render("each", list).unwrap(), "[1][2]");
+        let mut map = BTreeMap::new();
+        map.insert("a".to_owned(), Value::Bool(true));
+        assert_eq!(render("each", Value::Map(map)).unwrap(), "[true]");
+        assert_eq!(render("each", Value::List(vec![])).unwrap(), "none");
+        assert_eq!(render("with", Value::from("x")).unwrap(), "[x]");
+        assert_eq!(render("with", Value::Null).unwrap(), "none");
+        assert_eq!(render("if", Value::Int(1)).unwrap(), "[ctx]");
+        assert_eq!(render("if", Value::from("")).unwrap(), "none");
+        assert_eq!(
+            helpers.render("if", &[], &Value::Null, &Body::new(&main)),
+            Err(BlockError::Helper {
+                name: "if".to_owned(),
+                message: "expected one parameter".to_owned(),
+            })
+        );
+        assert_eq!(
+            render("each", Value::Int(3)).unwrap_err().to_string(),
+            "block helper `each`: cannot iterate over a int"
+        );
+        assert_eq!(
+            render("unless", Value::Null),
+            Err(BlockError::Unknown {
+                name: "unless".to_owned()
+            })
+        );
+        assert_eq!(
+            helpers.render("if", &[Value::Null], &Value::Null, &Body::new(&main)),
+            Ok(String::new())
+        );
+    }
+
+    #[test]
+    fn propagate_body_errors() {
+        let helpers = BlockHelpers::new().register(Each);
+        let main = |context: &Value| match context {
+            Value::Int(2) => Err("two".to_owned()),
+            context => Ok(context.to_string()),
+        };
+        let list = Value::List(vec![Value::Int(1), Value::Int(2), Value::Int(3)]);
+        assert_eq!(
+            helpers.render("each", &[list], &Value::Null, &Body::new(&main)),
+            Err(BlockError::Helper {
+                name: "each".to_owned(),
+                message: "two".to_owned(),
+            })
+        );
+    }
+}
@@ -0,0 +1,188 @@
+//! Building templates in code rather than formatting and parsing strings.
+//!
+//! [`TemplateBuilder`] produces the elements [`parse`](crate::parse) would
+//! produce from its [source](TemplateBuilder::source), escaping text so that
+//! it cannot be taken for placeholders:
+//!
+//! ```
+//! use spongy::{parse, TemplateBuilder, Wrapper};
+//!
+//! let builder = TemplateBuilder::new()
+//!     .text("Hello, ")
+//!     .placeholder(Wrapper::Curly, "name")
+//!     .text("! {not a placeholder}");
+//! let source = builder.source().unwrap();
+//! assert_eq!(source, "Hello, {name}! \\{not a placeholder}");
+//!
+//! let elements = builder.build().unwrap();
+//! let parsed: Vec<_> = parse(&source).unwrap().iter().map(Into::into).collect();
+//! assert_eq!(elements, parsed);
+//! ```
+
+use std::fmt;
+
+use crate::{escape, parse_with_options, unparse, Element, OwnedElement, OwnedItem, ParseOptions};
+use crate::{Item, Wrapper};
+
+/// A placeholder whose text would not parse back into the same placeholder,
+/// such as `{a}b}`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct BuildError {
+    /// Index of the placeholder among the built elements.
+    pub index: usize,
+    /// The placeholder in its source form.
+    pub placeholder: String,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "placeholder `{}` at element {} does not parse back",
+            self.placeholder, self.index
+        )
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// Whether `item` alone parses back into itself.
+fn parses_back(item: &Item) -> bool {
+    let options = ParseOptions {
+        dollar_paren: item.wrapper == Wrapper::DollarParen,
+        ..Default::default()
+    };
+    let source = item.raw();
+    parse_with_options(&source, &options)
+        .is_ok_and(|elements| elements == [Element::Wrapped(item.clone())])
+}
+
+/// A template assembled from text and placeholders.
+///
+/// Consecutive text is merged into one element, as the parser does. Its
+/// source parses back into the built elements with the default
+/// [`ParseOptions`], or with [`dollar_paren`](ParseOptions::dollar_paren) if
+/// it contains [`Wrapper::DollarParen`] placeholders, in which case text
+/// must not contain `$(` either.
+#[derive(Clone, Debug, Default)]
+pub struct TemplateBuilder {
+    elements: Vec<OwnedElement>,
+    error: Option<BuildError>,
+}
+
+impl TemplateBuilder {
+    pub fn new() -> TemplateBuilder {
+        TemplateBuilder::default()
+    }
+
+    /// Appends `text`, [escaped](escape) so that the source parses back into
+    /// the same elements.
+    ///
+    /// As with any escape, the backslashes stay in the text and so in
+    /// rendered output: `text("{a}")` renders as `\{a}`.
+    pub fn text(mut self, text: &str) -> Self {
+        let text = escape(text);
+        match self.elements.last_mut() {
+            Some(OwnedElement::Text(last)) => last.push_str(&text),
+            _ if text.is_empty() => {}
+            _ => self.elements.push(OwnedElement::Text(text.into_owned())),
+        }
+        self
+    }
+
+    /// Appends a placeholder of `wrapper` around `text`.
+    ///
+    /// Text which would end the placeholder early or not parse at all makes
+    /// [`build`](TemplateBuilder::build) fail.
+    pub fn placeholder<T: Into<String>>(mut self, wrapper: Wrapper, text: T) -> Self {
+        let item = OwnedItem::new(wrapper, text);
+        if self.error.is_none() && !parses_back(&item.as_item()) {
+            self.error = Some(BuildError {
+                index: self.elements.len(),
+                placeholder: item.to_string(),
+            });
+        }
+        // A `$` right before the placeholder would change its wrapper, or
+        // escape it for `$(`.
+        if let Some(OwnedElement::Text(last)) = self.elements.last_mut() {
+            let prefix = item.wrapper.get_prefix();
+            if last.ends_with('$') && (prefix.starts_with('{') || prefix.starts_with('$')) {
+                last.insert(last.len() - 1, '\\');
+            }
+        }
+        self.elements.push(OwnedElement::Wrapped(item));
+        self
+    }
+
+    /// The elements built, or the first placeholder that would not parse
+    /// back.
+    pub fn build(self) -> Result<Vec<OwnedElement>, BuildError> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.elements),
+        }
+    }
+
+    /// The template in its source form, as [`unparse`] writes the built
+    /// elements.
+    pub fn source(&self) -> Result<String, BuildError> {
+        if let Some(error) = &self.error {
+            return Err(error.clone());
+        }
+        let elements: Vec<Element> = self.elements.iter().map(|el| el.as_element()).collect();
+        Ok(unparse(&elements))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn build_as_parsed() {
+        let builder = TemplateBuilder::new()
+            .text("a {b} \\ $")
+            .placeholder(Wrapper::Curly, "c")
+            .text("")
+            .text("$")
+            .placeholder(Wrapper::DoubleCurly, " d | upper ")
+            .placeholder(Wrapper::CurlyHash, "")
+            .text("}")
+            .placeholder(Wrapper::DollarCurly, "e")
+            .placeholder(Wrapper::TripleCurly, "f")
+            .text("} #} %}");
+        let source = builder.source().unwrap();
+        assert_eq!(
+            source,
+            "a \\{b} \\\\ \\${c}\\${{ d | upper }}{##}}${e}{{{f}}}} #} %}"
+        );
+
+        let elements = builder.build().unwrap();
+        assert_eq!(elements.len(), 9);
+        let parsed: Vec<OwnedElement> = parse(&source).unwrap().iter().map(Into::into).collect();
+        assert_eq!(elements, parsed);
+    }
+
+    #[test]
+    fn reject_unparsable_placeholders() {
+        let builder = TemplateBuilder::new()
+            .text("a")
+            .placeholder(Wrapper::Curly, "b}c")
+            .placeholder(Wrapper::DoubleCurly, "{d}");
+        let error = BuildError {
+            index: 1,
+            placeholder: "{b}c}".to_owned(),
+        };
+        assert_eq!(builder.source(), Err(error.clone()));
+        assert_eq!(builder.build(), Err(error));
+        assert!(TemplateBuilder::new()
+            .placeholder(Wrapper::DollarParen, "a)")
+            .build()
+            .is_err());
+        assert!(TemplateBuilder::new()
+            .placeholder(Wrapper::DollarParen, "A")
+            .build()
+            .is_ok());
+    }
+}
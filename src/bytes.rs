@@ -0,0 +1,495 @@
+//! Parsing of byte input which is not necessarily valid UTF-8.
+//!
+//! The grammar is the same as for [`parse`](crate::parse): text is emitted as
+//! raw byte slices, while placeholder contents must be valid UTF-8 so they can
+//! be handed to the usual mappers as [`Item`]s.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::{self, Utf8Error};
+
+use crate::{Item, Wrapper};
+
+#[derive(PartialEq, Debug)]
+pub enum ByteElement<'a> {
+    Text(&'a [u8]),
+    Wrapped(Item<'a>),
+}
+
+#[derive(PartialEq, Debug)]
+pub enum ByteError {
+    /// No text or placeholder can be matched at `pos`, e.g. an unterminated
+    /// `{{` or a trailing `\`.
+    Unexpected { pos: usize },
+    /// The placeholder content starting at `pos` is not valid UTF-8.
+    InvalidUtf8 { pos: usize, error: Utf8Error },
+    /// Matching placeholders took more work than any input should need, so
+    /// scanning stopped at `pos`.
+    TooComplex { pos: usize },
+}
+
+impl fmt::Display for ByteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ByteError::Unexpected { pos } => write!(f, "unexpected input at byte {}", pos),
+            ByteError::InvalidUtf8 { pos, error } => {
+                write!(f, "invalid placeholder at byte {}: {}", pos, error)
+            }
+            ByteError::TooComplex { pos } => write!(f, "too complex to scan at byte {}", pos),
+        }
+    }
+}
+
+impl std::error::Error for ByteError {}
+
+/// Delimiters in the order the grammar tries them, with the bytes not allowed
+/// inside each wrapper.
+const WRAPPERS: [(Wrapper, &[u8]); 6] = [
+    (Wrapper::TripleCurly, b"{}"),
+    (Wrapper::DollarCurly, b"{}"),
+    (Wrapper::DoubleCurly, b"{}"),
+    (Wrapper::CurlyHash, b"{}#"),
+    (Wrapper::CurlyPercent, b"{}%"),
+    (Wrapper::Curly, b"{}"),
+];
+
+/// Where the content of a wrapper ends.
+#[derive(Clone, Copy)]
+enum End {
+    At(usize),
+    /// The content cannot be part of the wrapper.
+    Invalid,
+    /// The content runs to the end of the input.
+    Open,
+}
+
+/// Finds the first `}` in `input` not closing a `{` before it, as `${ }`
+/// allows balanced braces in its content.
+fn balanced_end(input: &[u8]) -> End {
+    let mut depth = 0;
+    for (i, &b) in input.iter().enumerate() {
+        match b {
+            b'{' => depth += 1,
+            b'}' if depth == 0 => return End::At(i),
+            b'}' => depth -= 1,
+            _ => {}
+        }
+    }
+    End::Open
+}
+
+/// Finds the `#}` closing a comment whose content starts `input`, skipping
+/// nested comments.
+fn comment_end(input: &[u8]) -> End {
+    let mut depth = 0;
+    let mut pos = 0;
+    while pos < input.len() {
+        match &input[pos..] {
+            [b'{', b'#', ..] => {
+                depth += 1;
+                pos += 2;
+            }
+            [b'#', b'}', ..] if depth == 0 => return End::At(pos),
+            [b'#', b'}', ..] => {
+                depth -= 1;
+                pos += 2;
+            }
+            // A lone `{`, `#` or `}` at the end may still become a pair.
+            [_] => return End::Open,
+            [b'{', ..] | [b'}', ..] | [b'#', ..] => return End::Invalid,
+            _ => pos += 1,
+        }
+    }
+    End::Open
+}
+
+/// Outcome of matching a wrapper at the start of some input.
+pub(crate) enum WrapperMatch {
+    /// The wrapper, the range of its content and the matched length.
+    Matched(Wrapper, usize, usize, usize),
+    /// More input could still make a wrapper match.
+    Incomplete,
+    Failed,
+}
+
+/// Matches a wrapper at the start of `input`, which is the whole rest of the
+/// template if `complete`, or may be followed by more input otherwise.
+///
+/// Wrappers are tried in order, so a match is only reported once any wrapper
+/// tried before it cannot match whatever follows.
+pub(crate) fn match_wrapper_partial(input: &[u8], complete: bool) -> WrapperMatch {
+    for &(wrapper, excluded) in &WRAPPERS {
+        let prefix = wrapper.get_prefix().as_bytes();
+        let suffix = wrapper.get_suffix().as_bytes();
+        if !input.starts_with(prefix) {
+            if !complete && prefix.starts_with(input) {
+                return WrapperMatch::Incomplete;
+            }
+            continue;
+        }
+
+        let start = prefix.len();
+        let content = &input[start..];
+        let end = if wrapper == Wrapper::DollarCurly {
+            balanced_end(content)
+        } else if wrapper == Wrapper::CurlyHash {
+            match comment_end(content) {
+                End::Open if complete => End::Invalid,
+                end => end,
+            }
+        } else {
+            content
+                .iter()
+                .position(|b| excluded.contains(b))
+                .map_or(End::Open, End::At)
+        };
+        let end = match end {
+            End::At(p) => start + p,
+            End::Invalid => continue,
+            End::Open if complete => continue,
+            End::Open => return WrapperMatch::Incomplete,
+        };
+        let rest = &input[end..];
+        if rest.starts_with(suffix) {
+            return WrapperMatch::Matched(wrapper, start, end, end + suffix.len());
+        }
+        if !complete && suffix.starts_with(rest) {
+            return WrapperMatch::Incomplete;
+        }
+    }
+    WrapperMatch::Failed
+}
+
+/// Work allowed per byte of input for matching wrappers with a [`Scanner`],
+/// a few times what it needs at most.
+const WORK_PER_BYTE: usize = 16;
+
+/// Matches wrappers anywhere in an input in linear time overall.
+///
+/// [`match_wrapper_partial`] scans `${` and `{#` contents up to their end,
+/// which for unclosed ones is the end of the input, and trying it at every
+/// position of a broken template, as lenient parsing does, would take
+/// quadratic time. The scanner remembers where the braces and comments it
+/// went through end instead, so that every byte is scanned a bounded number
+/// of times. As a safeguard, it stops matching once it has done more work
+/// than that bound.
+pub(crate) struct Scanner<'i> {
+    input: &'i [u8],
+    /// Ends of the braces seen so far, by the position of their `{`.
+    braces: HashMap<usize, End>,
+    /// Ends of the comment contents seen so far, by the position of their
+    /// `{#`.
+    comments: HashMap<usize, End>,
+    budget: usize,
+}
+
+impl<'i> Scanner<'i> {
+    pub(crate) fn new(input: &'i [u8]) -> Scanner<'i> {
+        Scanner {
+            input,
+            braces: HashMap::new(),
+            comments: HashMap::new(),
+            budget: WORK_PER_BYTE * input.len() + 64,
+        }
+    }
+
+    /// Whether matching stopped because the work limit was reached.
+    pub(crate) fn exhausted(&self) -> bool {
+        self.budget == 0
+    }
+
+    fn spend(&mut self, work: usize) {
+        self.budget = self.budget.saturating_sub(work);
+    }
+
+    /// Finds the `}` closing the `{` at `open`, if any.
+    fn brace_end(&mut self, open: usize) -> End {
+        // Most contents have no braces, and end with the match.
+        let content = &self.input[open + 1..];
+        let first = content.iter().position(|&b| b == b'{' || b == b'}');
+        self.spend(first.map_or(content.len(), |p| p + 1));
+        if let Some(p) = first.filter(|&p| content[p] == b'}') {
+            return End::At(open + 1 + p);
+        }
+        if let Some(&end) = self.braces.get(&open) {
+            return end;
+        }
+
+        let mut open_braces = vec![open];
+        let mut pos = open + 1;
+        while let Some(&innermost) = open_braces.last() {
+            self.spend(1);
+            match self.input.get(pos) {
+                None => break,
+                Some(b'{') => match self.braces.get(&pos) {
+                    Some(&End::At(close)) => pos = close + 1,
+                    Some(_) => break,
+                    None => {
+                        open_braces.push(pos);
+                        pos += 1;
+                    }
+                },
+                Some(b'}') => {
+                    self.braces.insert(innermost, End::At(pos));
+                    open_braces.pop();
+                    pos += 1;
+                }
+                Some(_) => pos += 1,
+            }
+        }
+        // Braces left open run to the end of the input.
+        for open in open_braces {
+            self.braces.insert(open, End::Open);
+        }
+        self.braces[&open]
+    }
+
+    /// Finds the `#}` closing the comment at `open`, skipping nested ones.
+    fn comment_end(&mut self, open: usize) -> End {
+        let content = &self.input[open + 2..];
+        let first = content.iter().position(|b| b"{}#".contains(b));
+        self.spend(first.map_or(content.len(), |p| p + 1));
+        if let Some(p) = first.filter(|&p| content[p..].starts_with(b"#}")) {
+            return End::At(open + 2 + p);
+        }
+        if let Some(&end) = self.comments.get(&open) {
+            return end;
+        }
+
+        let mut open_comments = vec![open];
+        let mut pos = open + 2;
+        let mut unclosed = End::Open;
+        while let Some(&innermost) = open_comments.last() {
+            self.spend(1);
+            match &self.input[pos.min(self.input.len())..] {
+                [] => break,
+                [b'{', b'#', ..] => match self.comments.get(&pos) {
+                    Some(&End::At(close)) => pos = close + 2,
+                    Some(&end) => {
+                        unclosed = end;
+                        break;
+                    }
+                    None => {
+                        open_comments.push(pos);
+                        pos += 2;
+                    }
+                },
+                [b'#', b'}', ..] => {
+                    self.comments.insert(innermost, End::At(pos));
+                    open_comments.pop();
+                    pos += 2;
+                }
+                [b'{', ..] | [b'}', ..] | [b'#', ..] => {
+                    unclosed = End::Invalid;
+                    break;
+                }
+                _ => pos += 1,
+            }
+        }
+        // A comment cannot close if one nested in it does not.
+        for open in open_comments {
+            self.comments.insert(open, unclosed);
+        }
+        self.comments[&open]
+    }
+
+    /// Matches a wrapper at `pos`, returning it along with the range of its
+    /// content and the matched length, relative to `pos`.
+    pub(crate) fn match_at(&mut self, pos: usize) -> Option<(Wrapper, usize, usize, usize)> {
+        self.spend(1);
+        if self.exhausted() {
+            return None;
+        }
+        let input = &self.input[pos..];
+        for &(wrapper, excluded) in &WRAPPERS {
+            let prefix = wrapper.get_prefix().as_bytes();
+            let suffix = wrapper.get_suffix().as_bytes();
+            if !input.starts_with(prefix) {
+                continue;
+            }
+
+            let start = prefix.len();
+            let end = match wrapper {
+                Wrapper::DollarCurly => self.brace_end(pos + 1),
+                Wrapper::CurlyHash => self.comment_end(pos),
+                _ => {
+                    let found = input[start..].iter().position(|b| excluded.contains(b));
+                    self.spend(found.map_or(input.len() - start, |p| p + 1));
+                    found.map_or(End::Open, |p| End::At(pos + start + p))
+                }
+            };
+            let end = match end {
+                End::At(end) => end - pos,
+                End::Invalid | End::Open => continue,
+            };
+            if input[end..].starts_with(suffix) {
+                return Some((wrapper, start, end, end + suffix.len()));
+            }
+        }
+        None
+    }
+}
+
+/// Returns the length of the text run at the start of `input`, or `None` if
+/// a trailing escape has nothing to escape.
+pub(crate) fn match_text(input: &[u8]) -> Option<usize> {
+    let mut pos = 0;
+    while pos < input.len() {
+        match input[pos] {
+            b'{' => break,
+            b'$' if input.get(pos + 1) == Some(&b'{') => break,
+            b'\\' if pos + 1 == input.len() => return None,
+            b'\\' => pos += 2,
+            _ => pos += 1,
+        }
+    }
+    Some(pos)
+}
+
+pub fn parse_bytes<'e>(s: &'e [u8]) -> Result<Vec<ByteElement<'e>>, ByteError> {
+    let mut result = vec![];
+    let mut pos = 0;
+    let mut scanner = Scanner::new(s);
+
+    while pos < s.len() {
+        let rest = &s[pos..];
+        match match_text(rest) {
+            Some(0) => {}
+            Some(len) => {
+                result.push(ByteElement::Text(&rest[..len]));
+                pos += len;
+                continue;
+            }
+            None => return Err(ByteError::Unexpected { pos: s.len() }),
+        }
+
+        let (wrapper, start, end, len) = match scanner.match_at(pos) {
+            Some(matched) => matched,
+            None if scanner.exhausted() => return Err(ByteError::TooComplex { pos }),
+            None => return Err(ByteError::Unexpected { pos }),
+        };
+        let text = str::from_utf8(&rest[start..end]).map_err(|error| ByteError::InvalidUtf8 {
+            pos: pos + start,
+            error,
+        })?;
+        let source = str::from_utf8(&rest[..len]).unwrap();
+        result.push(ByteElement::Wrapped(Item::with_source(
+            wrapper, text, source,
+        )));
+        pos += len;
+    }
+
+    Ok(result)
+}
+
+/// Renders parsed byte elements like [`render`](crate::render).
+pub fn render_bytes<M, V>(elements: &[ByteElement], mapper: M) -> Vec<u8>
+where
+    M: Fn(&Item) -> Option<V>,
+    V: AsRef<str>,
+{
+    let mut result = vec![];
+    for el in elements {
+        match el {
+            ByteElement::Text(t) => result.extend_from_slice(t),
+            ByteElement::Wrapped(item) => match mapper(item) {
+                Some(value) => result.extend_from_slice(value.as_ref().as_bytes()),
+                None => {
+                    let mut source = String::new();
+                    item.push_source(&mut source);
+                    result.extend_from_slice(source.as_bytes());
+                }
+            },
+        }
+    }
+    result
+}
+
+pub fn parse_bytes_with<M, V>(s: &[u8], mapper: M) -> Result<Vec<u8>, ByteError>
+where
+    M: Fn(&Item) -> Option<V>,
+    V: AsRef<str>,
+{
+    Ok(render_bytes(&parse_bytes(s)?, mapper))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, Element};
+
+    fn same_as_str(s: &str) {
+        let expected: Vec<ByteElement> = parse(s)
+            .unwrap()
+            .into_iter()
+            .map(|el| match el {
+                Element::Text(t) => ByteElement::Text(t.as_bytes()),
+                Element::Wrapped(item) => ByteElement::Wrapped(item),
+                Element::Escaped { .. } => unreachable!(),
+            })
+            .collect();
+        assert_eq!(parse_bytes(s.as_bytes()).unwrap(), expected);
+    }
+
+    #[test]
+    fn parse_bytes_like_str() {
+        same_as_str("Hello, {name}!");
+        same_as_str("{{{a}}} ${b} {{ c }} {## #} {% d %} {}");
+        same_as_str("{#} {%} $ a } \\{x} \\${y}");
+        same_as_str("${lookup({a = {b = 1}}, \"a\")} ${}}");
+        same_as_str("{# a {# b {##} #} c #}{#}#} x {##}");
+    }
+
+    #[test]
+    fn scan_within_budget() {
+        let s = format!("{}{{a}}", "${{#".repeat(1000));
+        let mut scanner = Scanner::new(s.as_bytes());
+        for pos in (0..4000).step_by(2) {
+            assert!(scanner.match_at(pos).is_none());
+        }
+        assert_eq!(scanner.match_at(4000), Some((Wrapper::Curly, 1, 2, 3)));
+        assert!(!scanner.exhausted());
+
+        scanner.budget = 1;
+        assert!(scanner.match_at(4000).is_none());
+        assert!(scanner.exhausted());
+        assert_eq!(
+            parse_bytes(s.as_bytes()),
+            Err(ByteError::Unexpected { pos: 0 })
+        );
+    }
+
+    #[test]
+    fn parse_non_utf8() {
+        assert_eq!(
+            parse_bytes(b"\xff\xfe{name}\x80").unwrap(),
+            vec![
+                ByteElement::Text(b"\xff\xfe"),
+                ByteElement::Wrapped(Item::new(Wrapper::Curly, "name")),
+                ByteElement::Text(b"\x80"),
+            ]
+        );
+
+        assert_eq!(
+            parse_bytes_with(b"\xff${a} ${b}", |item| match item.text {
+                "a" => Some("A".to_owned()),
+                _ => None,
+            })
+            .unwrap(),
+            b"\xffA ${b}"
+        );
+    }
+
+    #[test]
+    fn parse_bytes_broken() {
+        assert_eq!(
+            parse_bytes(b"ab {{todo.."),
+            Err(ByteError::Unexpected { pos: 3 })
+        );
+        assert_eq!(parse_bytes(b"ab\\"), Err(ByteError::Unexpected { pos: 3 }));
+        assert!(matches!(
+            parse_bytes(b"{\xff}"),
+            Err(ByteError::InvalidUtf8 { pos: 1, .. })
+        ));
+    }
+}
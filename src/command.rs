@@ -0,0 +1,342 @@
+//! Substituting the output of commands, such as `git describe`.
+//!
+//! Running commands from templates is opt-in twice: behind the `command`
+//! feature, and for each command, which [`CommandResolver::allow`] binds to a
+//! name with all its arguments. Templates only ever pick a name, so they
+//! cannot run anything else or pass arguments of their own.
+//!
+//! ```no_run
+//! use spongy::command::CommandResolver;
+//! use spongy::{parse_with, Resolver};
+//!
+//! let resolver = CommandResolver::new()
+//!     .allow("version", &["git", "describe", "--tags"])
+//!     .allow("today", &["date", "+%F"]);
+//! let banner = parse_with("Built {{version}} on {{today}}", |item| resolver.resolve(item));
+//! ```
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::resolve::{Outcome, Resolver};
+use crate::{filters, Item};
+
+/// Longest part of the standard error kept for a [`CommandError::Failed`].
+const MAX_STDERR_BYTES: usize = 4096;
+
+#[derive(Debug)]
+pub enum CommandError {
+    /// The command could not be run.
+    Io(io::Error),
+    /// The command exited unsuccessfully, writing `stderr`.
+    Failed { status: ExitStatus, stderr: String },
+    /// The command did not exit in time and was killed.
+    Timeout,
+    /// The command wrote more than the limit.
+    TooLarge { limit: usize },
+    /// The command wrote something other than UTF-8.
+    InvalidUtf8,
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandError::Io(e) => e.fmt(f),
+            CommandError::Failed { status, stderr } if stderr.is_empty() => status.fmt(f),
+            CommandError::Failed { status, stderr } => write!(f, "{}: {}", status, stderr),
+            CommandError::Timeout => f.write_str("timed out"),
+            CommandError::TooLarge { limit } => write!(f, "output is larger than {} bytes", limit),
+            CommandError::InvalidUtf8 => f.write_str("output is not UTF-8"),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+impl From<io::Error> for CommandError {
+    fn from(e: io::Error) -> Self {
+        CommandError::Io(e)
+    }
+}
+
+/// Reads at most `limit + 1` bytes of `reader` on a thread, sending them
+/// once it is closed.
+fn read_on_thread<R>(reader: Option<R>, limit: usize) -> mpsc::Receiver<io::Result<Vec<u8>>>
+where
+    R: Read + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let read = || {
+            let mut bytes = vec![];
+            if let Some(reader) = reader {
+                let mut limited = reader.take(limit as u64 + 1);
+                limited.read_to_end(&mut bytes)?;
+                // Drain the rest so the command does not block on a full pipe.
+                io::copy(&mut limited.into_inner(), &mut io::sink())?;
+            }
+            Ok(bytes)
+        };
+        // The receiver is gone if the command timed out.
+        let _ = sender.send(read());
+    });
+    receiver
+}
+
+/// Kills `child` along with the processes it started in its process group.
+fn kill(child: &mut Child) {
+    #[cfg(unix)]
+    // SAFETY: `kill` has no memory safety requirements. The group is the
+    // child's own, created when spawning it.
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+    let _ = child.kill();
+}
+
+/// Waits for `child` until `deadline`, killing it if it is not done by then.
+fn wait_until(child: &mut Child, deadline: Instant) -> Result<ExitStatus, CommandError> {
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        if Instant::now() >= deadline {
+            kill(child);
+            let _ = child.wait();
+            return Err(CommandError::Timeout);
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+/// Receives what a reader of `child` read, killing the processes of its
+/// group if it is not closed by `deadline`, as when the command left a
+/// process running in the background with the pipe open.
+fn receive(
+    reader: &mpsc::Receiver<io::Result<Vec<u8>>>,
+    child: &mut Child,
+    deadline: Instant,
+) -> Result<Vec<u8>, CommandError> {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    match reader.recv_timeout(remaining) {
+        Ok(read) => Ok(read?),
+        Err(mpsc::RecvTimeoutError::Timeout) => {
+            kill(child);
+            Err(CommandError::Timeout)
+        }
+        Err(mpsc::RecvTimeoutError::Disconnected) => panic!("reader panicked"),
+    }
+}
+
+/// Resolves placeholders naming an allowed command to its standard output,
+/// minus one trailing newline.
+///
+/// Commands run once per name for the lifetime of the resolver, with the
+/// standard input closed, and fail if they exit unsuccessfully, take longer
+/// than the [timeout](CommandResolver::timeout) or write more than
+/// [`max_bytes`](CommandResolver::max_bytes). The timeout covers reading
+/// the output until every process holding it open closes it. On Unix,
+/// commands run in a process group of their own, which is killed as a
+/// whole on timeout, background processes included; they do not receive
+/// signals like `Ctrl-C` sent to the terminal's foreground group. Placeholders of other names
+/// are left unresolved, and failing ones reported as [`Outcome::Errored`].
+pub struct CommandResolver {
+    commands: HashMap<String, Vec<String>>,
+    current_dir: Option<PathBuf>,
+    timeout: Duration,
+    max_bytes: usize,
+    cache: Mutex<HashMap<String, String>>,
+}
+
+impl Default for CommandResolver {
+    fn default() -> Self {
+        CommandResolver {
+            commands: HashMap::new(),
+            current_dir: None,
+            timeout: Duration::from_secs(10),
+            max_bytes: 64 * 1024,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl CommandResolver {
+    /// A resolver allowing no commands, with a timeout of 10 seconds and
+    /// outputs of up to 64 KiB.
+    pub fn new() -> CommandResolver {
+        CommandResolver::default()
+    }
+
+    /// Resolves the placeholder `name` by running `argv`, a program followed
+    /// by its arguments, which are passed as they are without a shell.
+    ///
+    /// # Panics
+    ///
+    /// If `argv` is empty.
+    pub fn allow<S: AsRef<str>>(mut self, name: &str, argv: &[S]) -> Self {
+        assert!(!argv.is_empty(), "a command needs a program");
+        let argv = argv.iter().map(|arg| arg.as_ref().to_owned()).collect();
+        self.commands.insert(name.to_owned(), argv);
+        self
+    }
+
+    /// Runs the commands in `dir` instead of the current directory.
+    pub fn current_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.current_dir = Some(dir.into());
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Whether the placeholder `item` names an allowed command.
+    pub fn allows(&self, item: &Item) -> bool {
+        self.commands.contains_key(filters::pipeline(item.key()).0)
+    }
+
+    /// Resolves `item`, failing if its command does.
+    pub fn try_resolve(&self, item: &Item) -> Result<Option<String>, CommandError> {
+        let name = filters::pipeline(item.key()).0;
+        let argv = match self.commands.get(name) {
+            Some(argv) => argv,
+            None => return Ok(None),
+        };
+        if let Some(output) = self.cache.lock().unwrap().get(name) {
+            return Ok(Some(output.clone()));
+        }
+
+        let output = self.run(argv)?;
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(name.to_owned(), output.clone());
+        Ok(Some(output))
+    }
+
+    fn run(&self, argv: &[String]) -> Result<String, CommandError> {
+        let mut command = Command::new(&argv[0]);
+        command
+            .args(&argv[1..])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Some(dir) = &self.current_dir {
+            command.current_dir(dir);
+        }
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+        let deadline = Instant::now() + self.timeout;
+        let mut child = command.spawn()?;
+        let stdout = read_on_thread(child.stdout.take(), self.max_bytes);
+        let stderr = read_on_thread(child.stderr.take(), MAX_STDERR_BYTES);
+        let status = wait_until(&mut child, deadline)?;
+        let stdout = receive(&stdout, &mut child, deadline)?;
+        let stderr = receive(&stderr, &mut child, deadline)?;
+
+        if !status.success() {
+            let stderr = String::from_utf8_lossy(&stderr);
+            return Err(CommandError::Failed {
+                status,
+                stderr: stderr.trim_end().to_owned(),
+            });
+        }
+        if stdout.len() > self.max_bytes {
+            return Err(CommandError::TooLarge {
+                limit: self.max_bytes,
+            });
+        }
+        let stdout = String::from_utf8(stdout).map_err(|_| CommandError::InvalidUtf8)?;
+        let output = stdout.strip_suffix('\n').unwrap_or(&stdout);
+        Ok(output.strip_suffix('\r').unwrap_or(output).to_owned())
+    }
+}
+
+impl Resolver for CommandResolver {
+    fn resolve<'r>(&'r self, item: &Item) -> Option<Cow<'r, str>> {
+        self.try_resolve(item).ok().flatten().map(Cow::Owned)
+    }
+
+    fn resolve_with_outcome<'r>(&'r self, item: &Item) -> (Option<Cow<'r, str>>, Outcome) {
+        match self.try_resolve(item) {
+            Ok(Some(value)) => (Some(Cow::Owned(value)), Outcome::Resolved),
+            Ok(None) => (None, Outcome::Unresolved),
+            Err(_) => (None, Outcome::Errored),
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::resolve::render_with_report;
+    use crate::{parse, Wrapper};
+
+    #[test]
+    fn run_allowed_commands() {
+        let resolver = CommandResolver::new()
+            .allow("greeting", &["echo", "hello"])
+            .allow("here", &["pwd"])
+            .allow("fails", &["sh", "-c", "echo oops >&2; exit 3"])
+            .current_dir("/");
+        let (result, report) = render_with_report(
+            &parse("{{greeting | upper}} {{here}} {{echo hi}} {{fails}} {{greeting}}").unwrap(),
+            &resolver,
+        );
+        assert_eq!(result, "hello / {{echo hi}} {{fails}} hello");
+        assert_eq!(report.errored, vec!["fails"]);
+        assert_eq!(report.unresolved, vec!["echo hi"]);
+
+        let error = resolver
+            .try_resolve(&Item::new(Wrapper::DoubleCurly, "fails"))
+            .unwrap_err();
+        assert!(error.to_string().ends_with(": oops"), "{}", error);
+        assert!(!resolver.allows(&Item::new(Wrapper::DoubleCurly, "rm")));
+    }
+
+    #[test]
+    fn bound_commands() {
+        let resolver = CommandResolver::new()
+            .allow("slow", &["sleep", "5"])
+            .allow("background", &["sh", "-c", "sleep 5 & echo hi"])
+            .allow("long", &["sh", "-c", "yes | head -c 100000"])
+            .allow("missing", &["spongy-no-such-command"])
+            .timeout(Duration::from_millis(100))
+            .max_bytes(10);
+        let item = |key| Item::new(Wrapper::Curly, key);
+        let started = Instant::now();
+        assert!(matches!(
+            resolver.try_resolve(&item("slow")),
+            Err(CommandError::Timeout)
+        ));
+        assert!(started.elapsed() < Duration::from_secs(2));
+        let started = Instant::now();
+        assert!(matches!(
+            resolver.try_resolve(&item("background")),
+            Err(CommandError::Timeout)
+        ));
+        assert!(started.elapsed() < Duration::from_secs(2));
+        let resolver = resolver.timeout(Duration::from_secs(5));
+        assert!(matches!(
+            resolver.try_resolve(&item("long")),
+            Err(CommandError::TooLarge { limit: 10 })
+        ));
+        assert!(matches!(
+            resolver.try_resolve(&item("missing")),
+            Err(CommandError::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,264 @@
+//! Variable interpolation with the semantics of Docker Compose files.
+//!
+//! Compose files are not brace templates, so this module has its own
+//! scanner, which only gives meaning to `$`:
+//!
+//! - `$VAR` and `${VAR}` substitute the variable, or an empty string if it
+//!   is unset.
+//! - `${VAR:-default}` substitutes `default` if `VAR` is unset or empty,
+//!   `${VAR-default}` only if it is unset.
+//! - `${VAR:?message}` fails if `VAR` is unset or empty, `${VAR?message}`
+//!   only if it is unset.
+//! - `${VAR:+other}` substitutes `other` if `VAR` is set and not empty,
+//!   `${VAR+other}` if it is set.
+//! - `$$` is a literal `$`.
+//!
+//! Defaults and other values may contain interpolations themselves, which
+//! are only expanded if used.
+
+use std::env;
+use std::fmt;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ComposeError {
+    /// The interpolation at byte `pos` is malformed or unterminated.
+    Syntax { pos: usize },
+    /// The required variable `name` is missing, as described by `message`.
+    Required { name: String, message: String },
+}
+
+impl fmt::Display for ComposeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ComposeError::Syntax { pos } => write!(f, "invalid interpolation at byte {}", pos),
+            ComposeError::Required { name, message } if message.is_empty() => {
+                write!(f, "required variable {} is missing a value", name)
+            }
+            ComposeError::Required { name, message } => {
+                write!(
+                    f,
+                    "required variable {} is missing a value: {}",
+                    name, message
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ComposeError {}
+
+fn name_len(s: &str) -> usize {
+    let mut chars = s.char_indices();
+    match chars.next() {
+        Some((_, c)) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return 0,
+    }
+    chars
+        .find(|&(_, c)| !(c.is_ascii_alphanumeric() || c == '_'))
+        .map_or(s.len(), |(i, _)| i)
+}
+
+/// Finds the `}` closing a braced interpolation whose argument starts `s`.
+fn argument_end(s: &str) -> Option<usize> {
+    let bytes = s.as_bytes();
+    let mut depth = 0;
+    let mut pos = 0;
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'$' if bytes.get(pos + 1) == Some(&b'$') => pos += 1,
+            b'$' if bytes.get(pos + 1) == Some(&b'{') => {
+                depth += 1;
+                pos += 1;
+            }
+            b'}' if depth == 0 => return Some(pos),
+            b'}' => depth -= 1,
+            _ => {}
+        }
+        pos += 1;
+    }
+    None
+}
+
+struct Interpolator<L> {
+    lookup: L,
+}
+
+impl<L, V> Interpolator<L>
+where
+    L: Fn(&str) -> Option<V>,
+    V: AsRef<str>,
+{
+    /// Expands `s`, which starts at byte `offset` of the whole input.
+    fn expand(&self, s: &str, offset: usize, out: &mut String) -> Result<(), ComposeError> {
+        let mut pos = 0;
+        while let Some(dollar) = s[pos..].find('$').map(|i| pos + i) {
+            out.push_str(&s[pos..dollar]);
+            let rest = &s[dollar + 1..];
+            pos = if rest.starts_with('$') {
+                out.push('$');
+                dollar + 2
+            } else if let Some(braced) = rest.strip_prefix('{') {
+                let start = dollar + 2;
+                let len =
+                    self.braced(braced, offset + start, out)
+                        .ok_or(ComposeError::Syntax {
+                            pos: offset + dollar,
+                        })??;
+                start + len + 1
+            } else {
+                let len = name_len(rest);
+                if len == 0 {
+                    out.push('$');
+                } else if let Some(value) = (self.lookup)(&rest[..len]) {
+                    out.push_str(value.as_ref());
+                }
+                dollar + 1 + len
+            };
+        }
+        out.push_str(&s[pos..]);
+        Ok(())
+    }
+
+    /// Expands the braced interpolation whose content starts `s`, returning
+    /// the length of the content, or `None` if it is malformed.
+    fn braced(
+        &self,
+        s: &str,
+        offset: usize,
+        out: &mut String,
+    ) -> Option<Result<usize, ComposeError>> {
+        let len = name_len(s);
+        if len == 0 {
+            return None;
+        }
+        let name = &s[..len];
+        let value = (self.lookup)(name);
+        let set = value.is_some();
+        let non_empty = value.as_ref().is_some_and(|v| !v.as_ref().is_empty());
+
+        let rest = &s[len..];
+        if rest.starts_with('}') {
+            out.push_str(value.as_ref().map_or("", |v| v.as_ref()));
+            return Some(Ok(len));
+        }
+
+        let op_len = if rest.starts_with(':') { 2 } else { 1 };
+        let op = rest.get(..op_len)?;
+        let start = len + op_len;
+        let end = start + argument_end(&s[start..])?;
+        let argument = &s[start..end];
+        let expand = |out: &mut String| self.expand(argument, offset + start, out);
+
+        let result = match op {
+            ":-" | "-" if (op == "-" && set) || non_empty => {
+                out.push_str(value.as_ref().unwrap().as_ref());
+                Ok(())
+            }
+            ":-" | "-" => expand(out),
+            ":?" | "?" if (op == "?" && set) || non_empty => {
+                out.push_str(value.as_ref().unwrap().as_ref());
+                Ok(())
+            }
+            ":?" | "?" => {
+                let mut message = String::new();
+                expand(&mut message).and(Err(ComposeError::Required {
+                    name: name.to_owned(),
+                    message,
+                }))
+            }
+            ":+" | "+" if (op == "+" && set) || non_empty => expand(out),
+            ":+" | "+" => Ok(()),
+            _ => return None,
+        };
+        Some(result.map(|()| end))
+    }
+}
+
+/// Interpolates `s`, looking variables up with `lookup`.
+pub fn interpolate<L, V>(s: &str, lookup: L) -> Result<String, ComposeError>
+where
+    L: Fn(&str) -> Option<V>,
+    V: AsRef<str>,
+{
+    let mut result = String::with_capacity(s.len());
+    Interpolator { lookup }.expand(s, 0, &mut result)?;
+    Ok(result)
+}
+
+/// Interpolates `s` with variables from the process environment.
+pub fn interpolate_env(s: &str) -> Result<String, ComposeError> {
+    interpolate(s, |name| env::var(name).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<&'static str> {
+        match name {
+            "SET" => Some("value"),
+            "EMPTY" => Some(""),
+            "NESTED" => Some("NESTED"),
+            _ => None,
+        }
+    }
+
+    fn expand(s: &str) -> Result<String, ComposeError> {
+        interpolate(s, lookup)
+    }
+
+    #[test]
+    fn substitute_variables() {
+        assert_eq!(
+            expand("$SET ${SET} $UNSET. ${EMPTY}").unwrap(),
+            "value value . "
+        );
+        assert_eq!(expand("$$SET $${SET} $ 5$").unwrap(), "$SET ${SET} $ 5$");
+        assert_eq!(expand("a: {b: $SET}").unwrap(), "a: {b: value}");
+    }
+
+    #[test]
+    fn default_values() {
+        assert_eq!(expand("${UNSET:-d} ${UNSET-d}").unwrap(), "d d");
+        assert_eq!(expand("${EMPTY:-d} [${EMPTY-d}]").unwrap(), "d []");
+        assert_eq!(expand("${SET:-d} ${SET-d}").unwrap(), "value value");
+        assert_eq!(
+            expand("${UNSET:-${EMPTY:-$$x ${SET}}}").unwrap(),
+            "$x value"
+        );
+        assert_eq!(expand("${SET:-${UNSET:?unused}}").unwrap(), "value");
+    }
+
+    #[test]
+    fn alternative_values() {
+        assert_eq!(expand("[${SET:+alt}] [${EMPTY:+alt}]").unwrap(), "[alt] []");
+        assert_eq!(expand("[${EMPTY+alt}] [${UNSET+alt}]").unwrap(), "[alt] []");
+    }
+
+    #[test]
+    fn required_values() {
+        assert_eq!(expand("${SET:?} ${EMPTY?}").unwrap(), "value ");
+        assert_eq!(
+            expand("${EMPTY:?need ${NESTED}}"),
+            Err(ComposeError::Required {
+                name: "EMPTY".to_owned(),
+                message: "need NESTED".to_owned()
+            })
+        );
+        assert_eq!(
+            expand("${UNSET?}").unwrap_err().to_string(),
+            "required variable UNSET is missing a value"
+        );
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(expand("a ${SET"), Err(ComposeError::Syntax { pos: 2 }));
+        assert_eq!(expand("${}"), Err(ComposeError::Syntax { pos: 0 }));
+        assert_eq!(expand("${SET*x}"), Err(ComposeError::Syntax { pos: 0 }));
+        assert_eq!(
+            expand("${UNSET:-${}}"),
+            Err(ComposeError::Syntax { pos: 9 })
+        );
+    }
+}
@@ -0,0 +1,116 @@
+//! Rendering with a context threaded through the resolver.
+
+use pest::error::Error;
+
+use crate::{parse_spanned, Element, Item, Location, ParseOptions, Rule, Warning};
+
+/// State passed by reference to the mapper of [`parse_with_context`].
+///
+/// Besides the user `state`, it accumulates warnings raised by the mapper and
+/// records which placeholders were resolved.
+#[derive(Debug, Default)]
+pub struct RenderContext<S = ()> {
+    pub state: S,
+    pub warnings: Vec<Warning>,
+    /// Texts of the resolved placeholders, in order of appearance.
+    pub resolved: Vec<String>,
+    /// Texts of the placeholders left as-is, in order of appearance.
+    pub unresolved: Vec<String>,
+    location: Option<Location>,
+}
+
+impl<S> RenderContext<S> {
+    pub fn new(state: S) -> RenderContext<S> {
+        RenderContext {
+            state,
+            warnings: vec![],
+            resolved: vec![],
+            unresolved: vec![],
+            location: None,
+        }
+    }
+
+    /// Location of the item being resolved, if any.
+    pub fn location(&self) -> Option<Location> {
+        self.location
+    }
+
+    /// Records a warning at the item being resolved.
+    pub fn warn<T: Into<String>>(&mut self, message: T) {
+        self.warnings.push(Warning {
+            pos: self.location.map_or(0, |location| location.span.start),
+            message: message.into(),
+        });
+    }
+}
+
+/// Like [`parse_with`](crate::parse_with), but the mapper also receives
+/// `context`.
+pub fn parse_with_context<S, M, V>(
+    s: &str,
+    context: &mut RenderContext<S>,
+    mapper: M,
+) -> Result<String, Error<Rule>>
+where
+    M: Fn(&Item, &mut RenderContext<S>) -> Option<V>,
+    V: AsRef<str>,
+{
+    let mut result = String::new();
+    for (index, (el, span)) in parse_spanned(s, &ParseOptions::default())?
+        .iter()
+        .enumerate()
+    {
+        match el {
+            Element::Text(t) | Element::Escaped { text: t, .. } => result.push_str(t),
+            Element::Wrapped(item) => {
+                context.location = Some(Location { index, span: *span });
+                match mapper(item, context) {
+                    Some(value) => {
+                        result.push_str(value.as_ref());
+                        context.resolved.push(item.text.to_owned());
+                    }
+                    None => {
+                        item.push_source(&mut result);
+                        context.unresolved.push(item.text.to_owned());
+                    }
+                }
+            }
+        }
+    }
+    context.location = None;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_with_context() {
+        let mut context = RenderContext::new(0);
+        let formatted = parse_with_context("{a} {b} {a} {c}", &mut context, |item, ctx| {
+            ctx.state += 1;
+            match item.text {
+                "a" => Some("A"),
+                "c" => {
+                    ctx.warn("c is deprecated");
+                    Some("C")
+                }
+                _ => None,
+            }
+        });
+
+        assert_eq!(formatted.unwrap(), "A {b} A C");
+        assert_eq!(context.state, 4);
+        assert_eq!(context.resolved, vec!["a", "a", "c"]);
+        assert_eq!(context.unresolved, vec!["b"]);
+        assert_eq!(
+            context.warnings,
+            vec![Warning {
+                pos: 12,
+                message: "c is deprecated".to_owned()
+            }]
+        );
+        assert_eq!(context.location(), None);
+    }
+}
@@ -0,0 +1,177 @@
+//! Placeholder syntaxes recognized outside the grammar, as enabled in
+//! [`ParseOptions`].
+
+use std::ops::Range;
+
+use crate::bytes::Scanner;
+use crate::{ParseOptions, Verbatim, Wrapper};
+
+/// A span found by [`dialect_ranges`].
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) enum Found {
+    /// A placeholder, with the range of its content.
+    Item(Range<usize>),
+    /// An escape which stands for the text in the given range.
+    Escape(Range<usize>),
+    /// Text the grammar would otherwise interpret.
+    Text,
+}
+
+/// Matches `$(...)` at the start of `s`, returning the length of the
+/// content.
+fn dollar_paren(s: &str) -> Option<usize> {
+    let content = s.strip_prefix("$(")?;
+    let end = content.find(['(', ')', '{', '}'])?;
+    if content[end..].starts_with(')') {
+        Some(end)
+    } else {
+        None
+    }
+}
+
+/// Matches `$${...}` at `pos`, returning the length of the `${...}` it
+/// stands for.
+fn escaped_dollar_curly(scanner: &mut Scanner, s: &str, pos: usize) -> Option<usize> {
+    if !s[pos..].starts_with('$') {
+        return None;
+    }
+    match scanner.match_at(pos + 1)? {
+        (Wrapper::DollarCurly, _, _, len) => Some(len),
+        _ => None,
+    }
+}
+
+/// Returns the sorted, disjoint ranges of `s` matched by the enabled
+/// dialects.
+///
+/// Placeholders and escapes of the template itself are skipped.
+pub(crate) fn dialect_ranges(s: &str, options: &ParseOptions) -> Vec<(Range<usize>, Found)> {
+    let mut result = vec![];
+    let custom_escape = options.escape != '\\';
+    let delimiters: Vec<(&str, &str)> = options
+        .verbatim
+        .iter()
+        .filter_map(|verbatim| match verbatim {
+            Verbatim::Delimiters(prefix, suffix) if !prefix.is_empty() => {
+                Some((prefix.as_str(), suffix.as_str()))
+            }
+            _ => None,
+        })
+        .collect();
+    if !options.dollar_paren && !options.dollar_escape && !custom_escape && delimiters.is_empty() {
+        return result;
+    }
+
+    let mut scanner = Scanner::new(s.as_bytes());
+    // Where each suffix was last found, so that prefixes without one only
+    // search for it once.
+    let mut suffixes: Vec<Option<Option<usize>>> = vec![None; delimiters.len()];
+    let mut pos = 0;
+    while let Some(c) = s[pos..].chars().next() {
+        let next = pos + c.len_utf8();
+        let rest = &s[pos..];
+        let mut find_suffix = |i: usize, from: usize, suffix: &str| match suffixes[i] {
+            Some(Some(at)) if at >= from => Some(at),
+            Some(None) => None,
+            _ => {
+                let at = s[from..].find(suffix).map(|at| from + at);
+                suffixes[i] = Some(at);
+                at
+            }
+        };
+        if c == options.escape {
+            let end = next + s[next..].chars().next().map_or(0, char::len_utf8);
+            if custom_escape {
+                result.push((pos..end, Found::Text));
+            }
+            pos = end;
+        } else if c == '\\' && custom_escape {
+            // Not an escape, but the grammar would take it as one.
+            result.push((pos..next, Found::Text));
+            pos = next;
+        } else if let Some((start, end, len)) =
+            delimiters
+                .iter()
+                .enumerate()
+                .find_map(|(i, &(prefix, suffix))| {
+                    rest.strip_prefix(prefix)?;
+                    let end = find_suffix(i, pos + prefix.len(), suffix)? - pos - prefix.len();
+                    Some((
+                        prefix.len(),
+                        prefix.len() + end,
+                        prefix.len() + end + suffix.len(),
+                    ))
+                })
+        {
+            result.push((pos..pos + len, Found::Escape(pos + start..pos + end)));
+            pos += len;
+        } else if options.dollar_paren && rest.starts_with("$$(") {
+            // `$$(VAR)` stands for a literal `$(VAR)`.
+            result.push((pos..pos + 2, Found::Escape(pos + 1..pos + 2)));
+            pos += 3;
+        } else if let Some(len) =
+            escaped_dollar_curly(&mut scanner, s, pos).filter(|_| options.dollar_escape)
+        {
+            result.push((pos..pos + 1 + len, Found::Escape(pos + 1..pos + 1 + len)));
+            pos += 1 + len;
+        } else if let Some(len) = dollar_paren(rest).filter(|_| options.dollar_paren) {
+            result.push((pos..pos + len + 3, Found::Item(pos + 2..pos + 2 + len)));
+            pos += len + 3;
+        } else if let Some((_, _, _, len)) = scanner.match_at(pos) {
+            pos += len;
+        } else {
+            pos = next;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_dollar_parens() {
+        let options = ParseOptions {
+            dollar_paren: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            dialect_ranges("a $(B) $$(C) \\$(D) {$(E)} $(F $(G(", &options),
+            vec![(2..6, Found::Item(4..5)), (7..9, Found::Escape(8..9)),]
+        );
+        assert!(dialect_ranges("$(A) $${A} \\{", &ParseOptions::default()).is_empty());
+
+        let options = ParseOptions {
+            dollar_escape: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            dialect_ranges("$${a} $(b) $${c $$$${d{e}}", &options),
+            vec![(0..5, Found::Escape(1..5)), (18..26, Found::Escape(19..26))]
+        );
+
+        let options = ParseOptions {
+            verbatim: vec![Verbatim::Delimiters("{=".to_owned(), "=}".to_owned())],
+            ..Default::default()
+        };
+        assert_eq!(
+            dialect_ranges("{= {x} =} {{=}} {=x", &options),
+            vec![(0..9, Found::Escape(2..7))]
+        );
+
+        let options = ParseOptions {
+            escape: '`',
+            ..Default::default()
+        };
+        assert_eq!(
+            dialect_ranges("\\{a} `{b} {`} ``ü`", &options),
+            vec![
+                (0..1, Found::Text),
+                (5..7, Found::Text),
+                (14..16, Found::Text),
+                (18..19, Found::Text),
+            ]
+        );
+    }
+}
@@ -0,0 +1,955 @@
+//! Expressions inside placeholders, evaluated against structured values.
+//!
+//! Supported are the conditional form `cond ? then : else`, arithmetic with
+//! `+`, `-`, `*`, `/` and `%`, comparisons with `==`, `!=`, `<`, `>`, `<=`
+//! and `>=`, and `and`, `or` and `not`, over string literals in double
+//! quotes, numbers, `true`, `false`, `null`, parentheses and
+//! [paths](crate::Value::lookup) into the context, e.g.
+//! `{is_admin ? "Admin" : "User"}` or `{{ price * quantity }}`.
+//!
+//! Integer arithmetic stays integral unless a division has a remainder. `+`
+//! also concatenates strings. Numbers compare by value regardless of type,
+//! strings lexicographically. Conditions use [truthiness](Value::is_truthy),
+//! and like in Python, `a or b` evaluates to `a` if it is true and to `b`
+//! otherwise, so `{{ name or "anonymous" }}` gives a fallback.
+//!
+//! Calls like `{{ upper(name) }}` dispatch to [`Functions`] registered with
+//! the evaluator.
+//!
+//! Evaluating expressions from untrusted templates can be limited with a
+//! [`Budget`]. Parsing them is limited by nesting: expressions nested more
+//! than [`MAX_DEPTH`] levels deep, counting operands chained by operators,
+//! are syntax errors.
+
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::{Item, Resolver, Value};
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ExprError {
+    /// The expression is malformed at byte `pos`.
+    Syntax { pos: usize, message: String },
+    /// No value at `path`.
+    Unknown { path: String },
+    /// No function registered as `name`.
+    UnknownFunction { name: String },
+    /// The function `name` failed with `message`.
+    Call { name: String, message: String },
+    /// An operator was applied to values it does not support.
+    Type { message: String },
+    /// Evaluation took more steps or time than its [`Budget`] allows.
+    BudgetExceeded,
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExprError::Syntax { pos, message } => write!(f, "{} at byte {}", message, pos),
+            ExprError::Unknown { path } => write!(f, "unknown variable {:?}", path),
+            ExprError::UnknownFunction { name } => write!(f, "unknown function {:?}", name),
+            ExprError::Call { name, message } => write!(f, "{}(): {}", name, message),
+            ExprError::Type { message } => f.write_str(message),
+            ExprError::BudgetExceeded => f.write_str("evaluation budget exceeded"),
+        }
+    }
+}
+
+impl std::error::Error for ExprError {}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token<'a> {
+    Literal(Value),
+    Path(&'a str),
+    Punct(&'a str),
+}
+
+/// Punctuation, longest first so `<=` is not read as `<`.
+const PUNCTS: [&str; 16] = [
+    "==", "!=", "<=", ">=", "<", ">", "?", ":", "(", ")", ",", "+", "-", "*", "/", "%",
+];
+
+fn is_path_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '.' | '[')
+}
+
+/// Length of the path at the start of `s`. Inside brackets, `*` is a
+/// wildcard rather than multiplication.
+fn path_len(s: &str) -> usize {
+    let mut in_brackets = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            '[' => in_brackets = true,
+            ']' if in_brackets => in_brackets = false,
+            '*' if in_brackets => {}
+            c if is_path_char(c) => {}
+            _ => return i,
+        }
+    }
+    s.len()
+}
+
+/// How deeply expressions may nest, so that parsing and evaluating them
+/// cannot overflow the stack.
+pub const MAX_DEPTH: usize = 128;
+
+fn syntax<T>(pos: usize, message: &str) -> Result<T, ExprError> {
+    Err(ExprError::Syntax {
+        pos,
+        message: message.to_owned(),
+    })
+}
+
+/// Splits `s` into tokens along with their byte offsets.
+fn tokenize(s: &str) -> Result<Vec<(Token<'_>, usize)>, ExprError> {
+    let mut tokens = vec![];
+    let mut pos = 0;
+    while pos < s.len() {
+        let rest = &s[pos..];
+        let c = rest.chars().next().unwrap();
+        if c.is_whitespace() {
+            pos += c.len_utf8();
+            continue;
+        }
+
+        let (token, len) = if c == '"' {
+            let mut value = String::new();
+            let mut chars = rest.char_indices().skip(1);
+            let len = loop {
+                match chars.next() {
+                    Some((i, '"')) => break i + 1,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, c)) => value.push(c),
+                        None => return syntax(pos, "unterminated string"),
+                    },
+                    Some((_, c)) => value.push(c),
+                    None => return syntax(pos, "unterminated string"),
+                }
+            };
+            (Token::Literal(Value::Str(value)), len)
+        } else if let Some(punct) = PUNCTS.iter().find(|p| rest.starts_with(**p)) {
+            (Token::Punct(punct), punct.len())
+        } else if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(rest.len());
+            let number = &rest[..len];
+            let value = match number.parse::<i64>() {
+                Ok(i) => Value::Int(i),
+                Err(_) => match number.parse::<f64>() {
+                    Ok(f) => Value::Float(f),
+                    Err(_) => return syntax(pos, "invalid number"),
+                },
+            };
+            (Token::Literal(value), len)
+        } else if is_path_char(c) {
+            let len = path_len(rest);
+            let token = match &rest[..len] {
+                "true" => Token::Literal(Value::Bool(true)),
+                "false" => Token::Literal(Value::Bool(false)),
+                "null" => Token::Literal(Value::Null),
+                "and" => Token::Punct("and"),
+                "or" => Token::Punct("or"),
+                "not" => Token::Punct("not"),
+                path => Token::Path(path),
+            };
+            (token, len)
+        } else {
+            return syntax(pos, "unexpected character");
+        };
+        tokens.push((token, pos));
+        pos += len;
+    }
+    Ok(tokens)
+}
+
+/// A parsed expression.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Expr {
+    Literal(Value),
+    Path(String),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Call(String, Vec<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl BinaryOp {
+    fn from_punct(punct: &str) -> Option<BinaryOp> {
+        match punct {
+            "or" => Some(BinaryOp::Or),
+            "and" => Some(BinaryOp::And),
+            "==" => Some(BinaryOp::Eq),
+            "!=" => Some(BinaryOp::Ne),
+            "<" => Some(BinaryOp::Lt),
+            ">" => Some(BinaryOp::Gt),
+            "<=" => Some(BinaryOp::Le),
+            ">=" => Some(BinaryOp::Ge),
+            "+" => Some(BinaryOp::Add),
+            "-" => Some(BinaryOp::Sub),
+            "*" => Some(BinaryOp::Mul),
+            "/" => Some(BinaryOp::Div),
+            "%" => Some(BinaryOp::Rem),
+            _ => None,
+        }
+    }
+
+    /// Binding strength, higher binds tighter.
+    fn precedence(self) -> u8 {
+        match self {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            BinaryOp::Eq
+            | BinaryOp::Ne
+            | BinaryOp::Lt
+            | BinaryOp::Gt
+            | BinaryOp::Le
+            | BinaryOp::Ge => COMPARISON,
+            BinaryOp::Add | BinaryOp::Sub => 4,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => 5,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Or => "or",
+            BinaryOp::And => "and",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Gt => ">",
+            BinaryOp::Le => "<=",
+            BinaryOp::Ge => ">=",
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Rem => "%",
+        }
+    }
+
+    fn apply(self, a: Value, b: Value) -> Result<Value, ExprError> {
+        match self {
+            BinaryOp::Or if a.is_truthy() => Ok(a),
+            BinaryOp::And if !a.is_truthy() => Ok(a),
+            BinaryOp::Or | BinaryOp::And => Ok(b),
+            BinaryOp::Eq => Ok(Value::Bool(equal(&a, &b))),
+            BinaryOp::Ne => Ok(Value::Bool(!equal(&a, &b))),
+            BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Le | BinaryOp::Ge => {
+                let ordering = compare(&a, &b).ok_or_else(|| ExprError::Type {
+                    message: format!(
+                        "cannot compare {} and {} with `{}`",
+                        a.type_name(),
+                        b.type_name(),
+                        self.symbol()
+                    ),
+                })?;
+                Ok(Value::Bool(match self {
+                    BinaryOp::Lt => ordering == Ordering::Less,
+                    BinaryOp::Gt => ordering == Ordering::Greater,
+                    BinaryOp::Le => ordering != Ordering::Greater,
+                    _ => ordering != Ordering::Less,
+                }))
+            }
+            _ => self.arithmetic(a, b),
+        }
+    }
+
+    fn arithmetic(self, a: Value, b: Value) -> Result<Value, ExprError> {
+        let mismatch = |a: &Value, b: &Value| ExprError::Type {
+            message: format!(
+                "cannot apply `{}` to {} and {}",
+                self.symbol(),
+                a.type_name(),
+                b.type_name()
+            ),
+        };
+        let overflow = || ExprError::Type {
+            message: format!("overflow in `{}`", self.symbol()),
+        };
+
+        match (self, &a, &b) {
+            (BinaryOp::Add, Value::Str(x), Value::Str(y)) => Ok(Value::Str(format!("{}{}", x, y))),
+            (_, Value::Int(_), Value::Int(0)) if matches!(self, BinaryOp::Div | BinaryOp::Rem) => {
+                Err(ExprError::Type {
+                    message: "division by zero".to_owned(),
+                })
+            }
+            (_, &Value::Int(x), &Value::Int(y)) => match self {
+                BinaryOp::Add => x.checked_add(y).map(Value::Int).ok_or_else(overflow),
+                BinaryOp::Sub => x.checked_sub(y).map(Value::Int).ok_or_else(overflow),
+                BinaryOp::Mul => x.checked_mul(y).map(Value::Int).ok_or_else(overflow),
+                BinaryOp::Rem => x.checked_rem(y).map(Value::Int).ok_or_else(overflow),
+                BinaryOp::Div if x % y == 0 => {
+                    x.checked_div(y).map(Value::Int).ok_or_else(overflow)
+                }
+                BinaryOp::Div => Ok(Value::Float(x as f64 / y as f64)),
+                _ => unreachable!("not an arithmetic operator"),
+            },
+            _ => match (a.as_f64(), b.as_f64()) {
+                (Some(x), Some(y)) => Ok(Value::Float(match self {
+                    BinaryOp::Add => x + y,
+                    BinaryOp::Sub => x - y,
+                    BinaryOp::Mul => x * y,
+                    BinaryOp::Div => x / y,
+                    BinaryOp::Rem => x % y,
+                    _ => unreachable!("not an arithmetic operator"),
+                })),
+                _ => Err(mismatch(&a, &b)),
+            },
+        }
+    }
+}
+
+/// Precedence of comparisons, which is also the operand of `not`.
+const COMPARISON: u8 = 3;
+
+/// Equality, with integers and floats comparing by value.
+fn equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Int(_), Value::Float(_)) | (Value::Float(_), Value::Int(_)) => {
+            a.as_f64() == b.as_f64()
+        }
+        _ => a == b,
+    }
+}
+
+/// Ordering of numbers and of strings, `None` for other values.
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Int(x), Value::Int(y)) => Some(x.cmp(y)),
+        (Value::Str(x), Value::Str(y)) => Some(x.cmp(y)),
+        _ => a.as_f64()?.partial_cmp(&b.as_f64()?),
+    }
+}
+
+struct Parser<'a> {
+    tokens: Vec<(Token<'a>, usize)>,
+    pos: usize,
+    len: usize,
+    /// Nesting of the expression being parsed.
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token<'a>> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.len, |&(_, pos)| pos)
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        if self.peek() == Some(&Token::Punct(punct)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Enters a nested expression, which is left by decrementing `depth`.
+    fn enter(&mut self) -> Result<(), ExprError> {
+        if self.depth == MAX_DEPTH {
+            return syntax(self.offset(), "expression nested too deeply");
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), ExprError> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            syntax(self.offset(), &format!("expected `{}`", punct))
+        }
+    }
+
+    fn conditional(&mut self) -> Result<Expr, ExprError> {
+        let condition = self.binary(1)?;
+        if !self.eat("?") {
+            return Ok(condition);
+        }
+        self.enter()?;
+        let then = self.conditional()?;
+        self.expect(":")?;
+        let otherwise = self.conditional()?;
+        self.depth -= 1;
+        Ok(Expr::Conditional(
+            Box::new(condition),
+            Box::new(then),
+            Box::new(otherwise),
+        ))
+    }
+
+    /// The binary operator at the current token, if it binds at least as
+    /// tightly as `min`.
+    fn operator(&self, min: u8) -> Option<BinaryOp> {
+        match self.peek() {
+            Some(Token::Punct(punct)) => {
+                BinaryOp::from_punct(punct).filter(|op| op.precedence() >= min)
+            }
+            _ => None,
+        }
+    }
+
+    /// Parses operators binding at least as tightly as `min`.
+    fn binary(&mut self, min: u8) -> Result<Expr, ExprError> {
+        let mut lhs = self.unary()?;
+        // Each operator nests the operands before it one level deeper.
+        let depth = self.depth;
+        while let Some(op) = self.operator(min) {
+            self.enter()?;
+            self.pos += 1;
+            let rhs = self.binary(op.precedence() + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        self.depth = depth;
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ExprError> {
+        self.enter()?;
+        let expr = if self.eat("not") {
+            Expr::Not(Box::new(self.binary(COMPARISON)?))
+        } else if self.eat("-") {
+            Expr::Neg(Box::new(self.unary()?))
+        } else {
+            self.primary()?
+        };
+        self.depth -= 1;
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr, ExprError> {
+        let offset = self.offset();
+        let token = match self.tokens.get(self.pos) {
+            Some((token, _)) => token.clone(),
+            None => return syntax(offset, "expected a value"),
+        };
+        self.pos += 1;
+        match token {
+            Token::Literal(value) => Ok(Expr::Literal(value)),
+            Token::Path(name) if self.eat("(") => {
+                if !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                    return syntax(offset, "invalid function name");
+                }
+                let mut args = vec![];
+                if !self.eat(")") {
+                    loop {
+                        args.push(self.conditional()?);
+                        if !self.eat(",") {
+                            break;
+                        }
+                    }
+                    self.expect(")")?;
+                }
+                Ok(Expr::Call(name.to_owned(), args))
+            }
+            Token::Path(path) => Ok(Expr::Path(path.to_owned())),
+            Token::Punct("(") => {
+                let expr = self.conditional()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Token::Punct(_) => syntax(offset, "expected a value"),
+        }
+    }
+}
+
+impl Expr {
+    pub fn parse(s: &str) -> Result<Expr, ExprError> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+            len: s.len(),
+            depth: 0,
+        };
+        let expr = parser.conditional()?;
+        if parser.pos < parser.tokens.len() {
+            return syntax(parser.offset(), "unexpected token");
+        }
+        Ok(expr)
+    }
+
+    /// Evaluates the expression, looking paths up in `root`.
+    pub fn eval(&self, root: &Value) -> Result<Value, ExprError> {
+        self.eval_with(root, &Functions::new())
+    }
+
+    /// Evaluates the expression, looking paths up in `root` and calling
+    /// `functions`.
+    pub fn eval_with(&self, root: &Value, functions: &Functions) -> Result<Value, ExprError> {
+        self.eval_within(root, functions, &Budget::default())
+    }
+
+    /// Evaluates the expression like [`eval_with`](Expr::eval_with), failing
+    /// with [`ExprError::BudgetExceeded`] once `budget` is used up.
+    pub fn eval_within(
+        &self,
+        root: &Value,
+        functions: &Functions,
+        budget: &Budget,
+    ) -> Result<Value, ExprError> {
+        Evaluator {
+            root,
+            functions,
+            steps: budget.steps,
+            deadline: budget.timeout.map(|timeout| Instant::now() + timeout),
+        }
+        .eval(self)
+    }
+}
+
+/// Limits on evaluating an expression, to keep untrusted templates from
+/// tying up the renderer. Both are unlimited by default.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Budget {
+    /// Maximum number of subexpressions evaluated.
+    pub steps: Option<usize>,
+    /// Maximum wall-clock time. It is checked between steps, so a slow
+    /// function call is not interrupted.
+    pub timeout: Option<Duration>,
+}
+
+struct Evaluator<'a, 'f> {
+    root: &'a Value,
+    functions: &'a Functions<'f>,
+    /// Steps left, if limited.
+    steps: Option<usize>,
+    deadline: Option<Instant>,
+}
+
+impl Evaluator<'_, '_> {
+    /// Accounts for one evaluation step.
+    fn step(&mut self) -> Result<(), ExprError> {
+        if let Some(steps) = &mut self.steps {
+            *steps = steps.checked_sub(1).ok_or(ExprError::BudgetExceeded)?;
+        }
+        match self.deadline {
+            Some(deadline) if Instant::now() > deadline => Err(ExprError::BudgetExceeded),
+            _ => Ok(()),
+        }
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<Value, ExprError> {
+        self.step()?;
+        match expr {
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::Path(path) => self
+                .root
+                .lookup(path)
+                .cloned()
+                .ok_or_else(|| ExprError::Unknown { path: path.clone() }),
+            Expr::Conditional(condition, then, otherwise) => {
+                if self.eval(condition)?.is_truthy() {
+                    self.eval(then)
+                } else {
+                    self.eval(otherwise)
+                }
+            }
+            Expr::Neg(expr) => match self.eval(expr)? {
+                Value::Int(i) => i.checked_neg().map(Value::Int).ok_or(ExprError::Type {
+                    message: "overflow in `-`".to_owned(),
+                }),
+                Value::Float(f) => Ok(Value::Float(-f)),
+                value => Err(ExprError::Type {
+                    message: format!("cannot negate {}", value.type_name()),
+                }),
+            },
+            Expr::Not(expr) => Ok(Value::Bool(!self.eval(expr)?.is_truthy())),
+            Expr::Binary(op @ BinaryOp::Or, a, b) | Expr::Binary(op @ BinaryOp::And, a, b) => {
+                // Short-circuit, so `b` may be missing if it is not needed.
+                let a = self.eval(a)?;
+                if a.is_truthy() == (*op == BinaryOp::Or) {
+                    Ok(a)
+                } else {
+                    self.eval(b)
+                }
+            }
+            Expr::Call(name, args) => {
+                let function = self
+                    .functions
+                    .get(name)
+                    .ok_or_else(|| ExprError::UnknownFunction { name: name.clone() })?;
+                let args = args
+                    .iter()
+                    .map(|arg| self.eval(arg))
+                    .collect::<Result<Vec<Value>, ExprError>>()?;
+                function(&args).map_err(|message| ExprError::Call {
+                    name: name.clone(),
+                    message,
+                })
+            }
+            Expr::Binary(op, a, b) => {
+                let a = self.eval(a)?;
+                op.apply(a, self.eval(b)?)
+            }
+        }
+    }
+}
+
+/// A function callable from expressions.
+pub type Function<'f> = Box<dyn Fn(&[Value]) -> Result<Value, String> + 'f>;
+
+/// Functions callable from expressions by name, like `{ now() }` or
+/// `{{ upper(name) }}`.
+#[derive(Default)]
+pub struct Functions<'f> {
+    functions: HashMap<String, Function<'f>>,
+}
+
+impl<'f> Functions<'f> {
+    pub fn new() -> Functions<'f> {
+        Functions::default()
+    }
+
+    /// Registers `function` as `name`, replacing any function of that name.
+    pub fn register<N, F>(mut self, name: N, function: F) -> Self
+    where
+        N: Into<String>,
+        F: Fn(&[Value]) -> Result<Value, String> + 'f,
+    {
+        self.functions.insert(name.into(), Box::new(function));
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Function<'f>> {
+        self.functions.get(name)
+    }
+}
+
+/// Parses and evaluates `s` against `root`.
+pub fn eval(s: &str, root: &Value) -> Result<Value, ExprError> {
+    Expr::parse(s)?.eval(root)
+}
+
+/// Resolves placeholders by evaluating their contents as expressions against
+/// a structured value. Placeholders which fail to evaluate are left
+/// unresolved.
+pub struct ExprResolver<'v> {
+    root: &'v Value,
+    functions: Functions<'v>,
+    precision: Option<usize>,
+    budget: Budget,
+}
+
+impl<'v> ExprResolver<'v> {
+    pub fn new(root: &'v Value) -> ExprResolver<'v> {
+        ExprResolver {
+            root,
+            functions: Functions::new(),
+            precision: None,
+            budget: Budget::default(),
+        }
+    }
+
+    pub fn functions(mut self, functions: Functions<'v>) -> Self {
+        self.functions = functions;
+        self
+    }
+
+    /// Number of decimals to render floats with. By default they are
+    /// rendered in their shortest exact form.
+    pub fn precision(mut self, precision: Option<usize>) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Limits the evaluation of each placeholder. Those exceeding the budget
+    /// are left unresolved.
+    pub fn budget(mut self, budget: Budget) -> Self {
+        self.budget = budget;
+        self
+    }
+}
+
+impl Resolver for ExprResolver<'_> {
+    fn resolve<'r>(&'r self, item: &Item) -> Option<Cow<'r, str>> {
+        let value = Expr::parse(item.key())
+            .and_then(|expr| expr.eval_within(self.root, &self.functions, &self.budget))
+            .ok()?;
+        Some(Cow::Owned(match (value, self.precision) {
+            (Value::Float(f), Some(precision)) => format!("{:.*}", precision, f),
+            (value, _) => value.to_text().into_owned(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_with;
+    use std::collections::BTreeMap;
+
+    fn context() -> Value {
+        let mut map = BTreeMap::new();
+        map.insert("is_admin".to_owned(), Value::from(true));
+        map.insert("count".to_owned(), Value::from(0));
+        map.insert("name".to_owned(), Value::from("Ann"));
+        map.insert("price".to_owned(), Value::from(2.5));
+        map.insert("quantity".to_owned(), Value::from(4));
+        map.insert("items".to_owned(), Value::from(vec![3, 4]));
+        Value::from(map)
+    }
+
+    #[test]
+    fn eval_conditional() {
+        let root = context();
+        assert_eq!(
+            eval(r#"is_admin ? "Admin" : "User""#, &root),
+            Ok(Value::from("Admin"))
+        );
+        assert_eq!(eval("count ? 1 : 2.5", &root), Ok(Value::from(2.5)));
+        assert_eq!(
+            eval(r#"null ? "a" : count ? "b" : "c""#, &root),
+            Ok(Value::from("c"))
+        );
+        assert_eq!(
+            eval(r#"(name ? "\"x\"" : false)"#, &root),
+            Ok(Value::from("\"x\""))
+        );
+        assert_eq!(
+            eval("other ? 1 : 2", &root),
+            Err(ExprError::Unknown {
+                path: "other".to_owned()
+            })
+        );
+    }
+
+    #[test]
+    fn eval_arithmetic() {
+        let root = context();
+        let ok = |s| eval(s, &root).unwrap();
+        assert_eq!(ok("1 + 2 * 3"), Value::from(7));
+        assert_eq!(ok("(1 + 2) * 3"), Value::from(9));
+        assert_eq!(ok("10 - 4 - 3"), Value::from(3));
+        assert_eq!(ok("7 / 2"), Value::from(3.5));
+        assert_eq!(ok("8 / 2"), Value::from(4));
+        assert_eq!(ok("-7 % 3"), Value::from(-1));
+        assert_eq!(ok("price*quantity"), Value::from(10.0));
+        assert_eq!(ok("items[1] - items[0]"), Value::from(1));
+        assert_eq!(ok(r#"name + "!""#), Value::from("Ann!"));
+        assert_eq!(ok("quantity - 4 ? 1 : 2"), Value::from(2));
+        assert!(matches!(eval("1 / 0", &root), Err(ExprError::Type { .. })));
+        assert!(matches!(
+            eval("name * 2", &root),
+            Err(ExprError::Type { .. })
+        ));
+        assert!(matches!(
+            eval("9223372036854775807 + 1", &root),
+            Err(ExprError::Type { .. })
+        ));
+    }
+
+    #[test]
+    fn eval_logic() {
+        let root = context();
+        let ok = |s| eval(s, &root).unwrap();
+        assert_eq!(ok("quantity > 0 ? 1 : 2"), Value::from(1));
+        assert_eq!(ok("price * 2 == 5"), Value::from(true));
+        assert_eq!(ok("quantity != 4.0"), Value::from(false));
+        assert_eq!(ok(r#"name < "Bob" and count <= 0"#), Value::from(true));
+        assert_eq!(ok("1 >= 2 or not is_admin"), Value::from(false));
+        assert_eq!(ok("not count == 1"), Value::from(true));
+        assert_eq!(ok("not (count or null)"), Value::from(true));
+        assert_eq!(ok(r#"count or "none""#), Value::from("none"));
+        assert_eq!(ok(r#"name and "set""#), Value::from("set"));
+        assert_eq!(ok("is_admin or missing"), Value::from(true));
+        assert_eq!(ok("items == items"), Value::from(true));
+        assert_eq!(ok(r#"1 == "1""#), Value::from(false));
+        assert!(matches!(
+            eval(r#"1 < "1""#, &root),
+            Err(ExprError::Type { .. })
+        ));
+        assert!(matches!(
+            eval("count and missing", &root),
+            Ok(Value::Int(0))
+        ));
+        assert!(matches!(
+            eval("is_admin and missing", &root),
+            Err(ExprError::Unknown { .. })
+        ));
+    }
+
+    #[test]
+    fn call_functions() {
+        let root = context();
+        let functions = Functions::new()
+            .register("now", |_| Ok(Value::from(1700000000)))
+            .register("upper", |args| match args {
+                [Value::Str(s)] => Ok(Value::from(s.to_uppercase())),
+                _ => Err("expected a string".to_owned()),
+            })
+            .register("max", |args| {
+                args.iter()
+                    .filter_map(Value::as_i64)
+                    .max()
+                    .map(Value::from)
+                    .ok_or_else(|| "no integers".to_owned())
+            });
+        let eval = |s| Expr::parse(s).unwrap().eval_with(&root, &functions);
+
+        assert_eq!(eval("now()"), Ok(Value::from(1700000000)));
+        assert_eq!(eval("upper(name)"), Ok(Value::from("ANN")));
+        assert_eq!(eval("max(1, quantity * 2, (3))"), Ok(Value::from(8)));
+        assert_eq!(
+            eval("upper(1)"),
+            Err(ExprError::Call {
+                name: "upper".to_owned(),
+                message: "expected a string".to_owned()
+            })
+        );
+        assert_eq!(
+            eval("uuid()"),
+            Err(ExprError::UnknownFunction {
+                name: "uuid".to_owned()
+            })
+        );
+        assert!(matches!(
+            Expr::parse("a.b()"),
+            Err(ExprError::Syntax { pos: 0, .. })
+        ));
+        assert!(matches!(
+            Expr::parse("f(1,)"),
+            Err(ExprError::Syntax { pos: 4, .. })
+        ));
+
+        let resolver = ExprResolver::new(&root).functions(functions);
+        assert_eq!(
+            parse_with("{ now() } {{ upper(name) }} {{ lower(name) }}", |item| {
+                resolver.resolve(item)
+            })
+            .unwrap(),
+            "1700000000 ANN {{ lower(name) }}"
+        );
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert!(matches!(
+            Expr::parse("a ? b"),
+            Err(ExprError::Syntax { pos: 5, .. })
+        ));
+        assert!(matches!(
+            Expr::parse("\"abc"),
+            Err(ExprError::Syntax { pos: 0, .. })
+        ));
+        assert!(matches!(
+            Expr::parse("a b"),
+            Err(ExprError::Syntax { pos: 2, .. })
+        ));
+        assert!(matches!(Expr::parse(""), Err(ExprError::Syntax { .. })));
+    }
+
+    #[test]
+    fn eval_within_budget() {
+        let root = context();
+        let functions = Functions::new().register("slow", |_| {
+            std::thread::sleep(Duration::from_millis(20));
+            Ok(Value::Null)
+        });
+        let expr = Expr::parse("1 + 2 * 3").unwrap();
+        let budget = |steps| Budget {
+            steps: Some(steps),
+            timeout: None,
+        };
+        assert_eq!(
+            expr.eval_within(&root, &functions, &budget(5)),
+            Ok(Value::Int(7))
+        );
+        assert_eq!(
+            expr.eval_within(&root, &functions, &budget(4)),
+            Err(ExprError::BudgetExceeded)
+        );
+
+        let budget = Budget {
+            steps: None,
+            timeout: Some(Duration::from_millis(5)),
+        };
+        let expr = Expr::parse("slow() or slow()").unwrap();
+        assert_eq!(
+            expr.eval_within(&root, &functions, &budget),
+            Err(ExprError::BudgetExceeded)
+        );
+
+        let resolver = ExprResolver::new(&root).budget(Budget {
+            steps: Some(3),
+            timeout: None,
+        });
+        assert_eq!(
+            parse_with("{{ 1 + 2 }} {{ 1 + 2 + 3 }}", |item| resolver.resolve(item)).unwrap(),
+            "3 {{ 1 + 2 + 3 }}"
+        );
+    }
+
+    #[test]
+    fn reject_deep_nesting() {
+        for unit in &["(", "-", "not ", "1 ? 1 : ", "f(", "1 + "] {
+            let s = unit.repeat(100_000) + "1";
+            match Expr::parse(&s) {
+                Err(ExprError::Syntax { message, .. }) => {
+                    assert_eq!(message, "expression nested too deeply", "{}", unit)
+                }
+                result => panic!("{}: {:?}", unit, result),
+            }
+        }
+        let s = format!("{}1{}", "(".repeat(100), ")".repeat(100));
+        assert_eq!(Expr::parse(&s), Ok(Expr::Literal(Value::Int(1))));
+
+        let root = context();
+        let resolver = ExprResolver::new(&root);
+        let item = Item::new(crate::Wrapper::Curly, &s);
+        assert_eq!(resolver.resolve(&item).as_deref(), Some("1"));
+        let deep = format!("{}1{}", "(".repeat(100_000), ")".repeat(100_000));
+        assert_eq!(
+            resolver.resolve(&Item::new(crate::Wrapper::Curly, &deep)),
+            None
+        );
+    }
+
+    #[test]
+    fn resolve_expressions() {
+        let root = context();
+        let resolver = ExprResolver::new(&root);
+        assert_eq!(
+            parse_with(
+                r#"{name}: {is_admin ? "Admin" : "User"} {x ? 1 : 2} {a b}"#,
+                |item| resolver.resolve(item)
+            )
+            .unwrap(),
+            "Ann: Admin {x ? 1 : 2} {a b}"
+        );
+
+        let resolver = ExprResolver::new(&root).precision(Some(2));
+        assert_eq!(
+            parse_with("{{ price * quantity }} {{ quantity * 2 }}", |item| {
+                resolver.resolve(item)
+            })
+            .unwrap(),
+            "10.00 8"
+        );
+    }
+}
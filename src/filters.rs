@@ -0,0 +1,283 @@
+//! Built-in string filters.
+//!
+//! Filters are plain `fn(&str) -> String` transforms. They can be called
+//! directly, looked up by name with [`builtin`], or chained inside a
+//! placeholder as a pipeline, e.g. `{{ name | snake }}`, by wrapping a
+//! mapper with [`with_filters`].
+//!
+//! Some filters take arguments, written as a call in a pipeline like
+//! `{{ name | truncate(8) }}`. With the `unicode` feature, these are
+//! `truncate(n)` and `pad(n)`, which count grapheme clusters.
+
+use std::borrow::Cow;
+
+#[cfg(feature = "unicode")]
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::Item;
+
+/// A string transform applicable to a resolved value.
+pub type Filter = fn(&str) -> String;
+
+/// Separator between the key and the filters of a pipeline.
+pub const PIPE: char = '|';
+
+/// Splits `s` into words for case conversion.
+///
+/// Word boundaries are non-alphanumeric characters, a lowercase letter or
+/// digit followed by an uppercase letter (`fooBar`), and the last capital of
+/// an acronym followed by a lowercase letter (`HTTPServer`).
+fn words(s: &str) -> Vec<&str> {
+    let mut words = vec![];
+    let chars: Vec<(usize, char)> = s.char_indices().collect();
+    let mut start: Option<usize> = None;
+
+    for (i, &(pos, c)) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if let Some(st) = start.take() {
+                words.push(&s[st..pos]);
+            }
+            continue;
+        }
+
+        if let Some(st) = start {
+            let prev = chars[i - 1].1;
+            let next = chars.get(i + 1).map(|&(_, c)| c);
+            let boundary = c.is_uppercase()
+                && (prev.is_lowercase()
+                    || prev.is_numeric()
+                    || (prev.is_uppercase() && next.is_some_and(char::is_lowercase)));
+            if boundary {
+                words.push(&s[st..pos]);
+                start = Some(pos);
+            }
+        } else {
+            start = Some(pos);
+        }
+    }
+
+    if let Some(st) = start {
+        words.push(&s[st..]);
+    }
+
+    words
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first
+            .to_uppercase()
+            .chain(chars.flat_map(char::to_lowercase))
+            .collect(),
+        None => String::new(),
+    }
+}
+
+/// `fooBar baz` -> `foo_bar_baz`
+pub fn snake(s: &str) -> String {
+    words(s)
+        .iter()
+        .map(|w| w.to_lowercase())
+        .collect::<Vec<String>>()
+        .join("_")
+}
+
+/// `foo_bar baz` -> `fooBarBaz`
+pub fn camel(s: &str) -> String {
+    words(s)
+        .iter()
+        .enumerate()
+        .map(|(i, w)| {
+            if i == 0 {
+                w.to_lowercase()
+            } else {
+                capitalize(w)
+            }
+        })
+        .collect()
+}
+
+/// `fooBar baz` -> `foo-bar-baz`
+pub fn kebab(s: &str) -> String {
+    words(s)
+        .iter()
+        .map(|w| w.to_lowercase())
+        .collect::<Vec<String>>()
+        .join("-")
+}
+
+pub fn upper(s: &str) -> String {
+    s.to_uppercase()
+}
+
+pub fn lower(s: &str) -> String {
+    s.to_lowercase()
+}
+
+/// Keeps the first `n` grapheme clusters of `s`, so combining sequences and
+/// emoji are never cut in half.
+#[cfg(feature = "unicode")]
+pub fn truncate(s: &str, n: usize) -> String {
+    match s.grapheme_indices(true).nth(n) {
+        Some((end, _)) => s[..end].to_owned(),
+        None => s.to_owned(),
+    }
+}
+
+/// Pads `s` with spaces on the right to `n` grapheme clusters.
+#[cfg(feature = "unicode")]
+pub fn pad(s: &str, n: usize) -> String {
+    let len = s.graphemes(true).count();
+    let mut result = s.to_owned();
+    result.extend(std::iter::repeat_n(' ', n.saturating_sub(len)));
+    result
+}
+
+/// Splits a filter call like `truncate(8)` into its name and arguments.
+fn call(filter: &str) -> Option<(&str, Vec<&str>)> {
+    let (name, args) = filter.split_at(filter.find('(')?);
+    let args = args.strip_prefix('(')?.strip_suffix(')')?;
+    let args = if args.trim().is_empty() {
+        vec![]
+    } else {
+        args.split(',').map(str::trim).collect()
+    };
+    Some((name.trim(), args))
+}
+
+/// Applies the built-in filter taking arguments named `name`.
+#[cfg_attr(not(feature = "unicode"), allow(unused_variables))]
+fn apply_call(value: &str, name: &str, args: &[&str]) -> Option<String> {
+    match (name, args) {
+        #[cfg(feature = "unicode")]
+        ("truncate", [n]) => Some(truncate(value, n.parse().ok()?)),
+        #[cfg(feature = "unicode")]
+        ("pad", [n]) => Some(pad(value, n.parse().ok()?)),
+        _ => None,
+    }
+}
+
+/// Looks up a built-in filter by name.
+pub fn builtin(name: &str) -> Option<Filter> {
+    match name {
+        "snake" => Some(snake),
+        "camel" => Some(camel),
+        "kebab" => Some(kebab),
+        "upper" => Some(upper),
+        "lower" => Some(lower),
+        _ => None,
+    }
+}
+
+/// Splits a placeholder text into its key and filter names.
+///
+/// `" name | upper "` gives `("name", ["upper"])`.
+pub fn pipeline(text: &str) -> (&str, Vec<&str>) {
+    let mut parts = text.split(PIPE).map(str::trim);
+    let key = parts.next().unwrap_or("");
+    (key, parts.collect())
+}
+
+/// Applies the named filters to `value` in order.
+///
+/// Returns `None` if any filter is unknown or called with invalid arguments.
+pub fn apply(value: &str, names: &[&str]) -> Option<String> {
+    names
+        .iter()
+        .try_fold(value.to_owned(), |value, name| match call(name) {
+            Some((name, args)) => apply_call(&value, name, &args),
+            None => builtin(name).map(|filter| filter(&value)),
+        })
+}
+
+/// Wraps `mapper` so placeholders may carry a filter pipeline.
+///
+/// The mapper is called with the bare key; its result is passed through the
+/// filters. Placeholders without a pipeline are passed on untouched, and those
+/// naming an unknown filter are left unresolved.
+pub fn with_filters<M, V>(mapper: M) -> impl Fn(&Item) -> Option<Cow<'static, str>>
+where
+    M: Fn(&Item) -> Option<V>,
+    V: Into<Cow<'static, str>>,
+{
+    move |item: &Item| {
+        if !item.text.contains(PIPE) {
+            return mapper(item).map(Into::into);
+        }
+
+        let (key, names) = pipeline(item.text);
+        let value = mapper(&Item {
+            text: key,
+            ..item.clone()
+        })?
+        .into();
+        apply(&value, &names).map(Cow::Owned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_with, Wrapper};
+
+    #[test]
+    fn case_conversion() {
+        assert_eq!(snake("fooBar baz"), "foo_bar_baz");
+        assert_eq!(snake("HTTPServer"), "http_server");
+        assert_eq!(snake("user-id2Name"), "user_id2_name");
+        assert_eq!(camel("foo_bar baz"), "fooBarBaz");
+        assert_eq!(camel("HTTP_SERVER"), "httpServer");
+        assert_eq!(kebab("FooBar_baz"), "foo-bar-baz");
+        assert_eq!(upper("fooBar"), "FOOBAR");
+        assert_eq!(lower("FooBar"), "foobar");
+        assert_eq!(snake(""), "");
+    }
+
+    #[test]
+    fn split_pipeline() {
+        assert_eq!(
+            pipeline(" name | upper | snake "),
+            ("name", vec!["upper", "snake"])
+        );
+        assert_eq!(pipeline("name"), ("name", vec![]));
+        assert_eq!(
+            apply("fooBar", &["snake", "upper"]),
+            Some("FOO_BAR".to_owned())
+        );
+        assert_eq!(apply("fooBar", &["nope"]), None);
+        assert_eq!(apply("fooBar", &["upper()"]), None);
+    }
+
+    #[cfg(feature = "unicode")]
+    #[test]
+    fn grapheme_filters() {
+        let s = "e\u{301}🇯🇵x";
+        assert_eq!(truncate(s, 2), "e\u{301}🇯🇵");
+        assert_eq!(truncate(s, 0), "");
+        assert_eq!(truncate(s, 9), s);
+        assert_eq!(pad("e\u{301}", 3), "e\u{301}  ");
+        assert_eq!(pad("abcd", 3), "abcd");
+        assert_eq!(
+            apply("👍🏽ab", &["truncate(2)", "pad( 4 )", "upper"]),
+            Some("👍🏽A  ".to_owned())
+        );
+        assert_eq!(apply("ab", &["truncate(x)"]), None);
+        assert_eq!(apply("ab", &["pad(1, 2)"]), None);
+    }
+
+    #[test]
+    fn format_with_filters() {
+        let formatted = parse_with(
+            "{name | snake}, {name | kebab | upper}, {name}, {name | nope}",
+            with_filters(|item| match (item.wrapper, item.text) {
+                (Wrapper::Curly, "name") => Some("MyStruct".to_owned()),
+                _ => None,
+            }),
+        );
+        assert_eq!(
+            formatted.unwrap(),
+            "my_struct, MY-STRUCT, MyStruct, {name | nope}"
+        );
+    }
+}
@@ -0,0 +1,207 @@
+//! Fill, alignment and width of substituted values, like `{name:<20}`.
+//!
+//! The spec after the last `:` of a placeholder is `[[fill]align][width]`,
+//! where `align` is `<` (left), `>` (right) or `^` (center), as in Rust's
+//! `format!`. Without an alignment, values are left-aligned. Width counts
+//! chars, or grapheme clusters with the `unicode` feature.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(feature = "unicode")]
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::Item;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Align {
+    #[default]
+    Left,
+    Right,
+    Center,
+}
+
+impl Align {
+    fn from_char(c: char) -> Option<Align> {
+        match c {
+            '<' => Some(Align::Left),
+            '>' => Some(Align::Right),
+            '^' => Some(Align::Center),
+            _ => None,
+        }
+    }
+
+    fn as_char(self) -> char {
+        match self {
+            Align::Left => '<',
+            Align::Right => '>',
+            Align::Center => '^',
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FormatSpec {
+    pub fill: char,
+    pub align: Align,
+    pub width: usize,
+}
+
+impl Default for FormatSpec {
+    fn default() -> Self {
+        FormatSpec {
+            fill: ' ',
+            align: Align::Left,
+            width: 0,
+        }
+    }
+}
+
+fn width(s: &str) -> usize {
+    #[cfg(feature = "unicode")]
+    return s.graphemes(true).count();
+    #[cfg(not(feature = "unicode"))]
+    return s.chars().count();
+}
+
+impl FormatSpec {
+    /// Pads `value` to the width of the spec. Longer values are kept whole.
+    pub fn apply<'v>(&self, value: &'v str) -> Cow<'v, str> {
+        let len = width(value);
+        if len >= self.width {
+            return Cow::Borrowed(value);
+        }
+
+        let padding = self.width - len;
+        let (before, after) = match self.align {
+            Align::Left => (0, padding),
+            Align::Right => (padding, 0),
+            Align::Center => (padding / 2, padding - padding / 2),
+        };
+        let mut result = String::with_capacity(value.len() + padding * self.fill.len_utf8());
+        result.extend(std::iter::repeat_n(self.fill, before));
+        result.push_str(value);
+        result.extend(std::iter::repeat_n(self.fill, after));
+        Cow::Owned(result)
+    }
+}
+
+impl fmt::Display for FormatSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}{}", self.fill, self.align.as_char(), self.width)
+    }
+}
+
+impl FromStr for FormatSpec {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut spec = FormatSpec::default();
+        let mut chars = s.chars();
+        let rest = match (chars.next(), chars.next()) {
+            (Some(fill), Some(c)) if Align::from_char(c).is_some() => {
+                spec.fill = fill;
+                spec.align = Align::from_char(c).unwrap();
+                chars.as_str()
+            }
+            (Some(c), _) if Align::from_char(c).is_some() => {
+                spec.align = Align::from_char(c).unwrap();
+                &s[1..]
+            }
+            _ => s,
+        };
+        if rest.is_empty() || !rest.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(());
+        }
+        spec.width = rest.parse().map_err(|_| ())?;
+        Ok(spec)
+    }
+}
+
+/// Splits a placeholder text into its name and format spec.
+///
+/// `"name:<20"` gives `("name", Some(..))`, while a suffix which is not a
+/// spec is kept as part of the name.
+pub fn split(text: &str) -> (&str, Option<FormatSpec>) {
+    if let Some(colon) = text.rfind(':') {
+        if let Ok(spec) = text[colon + 1..].trim().parse() {
+            return (text[..colon].trim(), Some(spec));
+        }
+    }
+    (text.trim(), None)
+}
+
+/// Wraps `mapper` so placeholders may carry a format spec.
+///
+/// The mapper is called with the name before the spec; its result is padded
+/// as the spec says. Placeholders without a spec are passed on untouched.
+pub fn with_format<M, V>(mapper: M) -> impl Fn(&Item) -> Option<Cow<'static, str>>
+where
+    M: Fn(&Item) -> Option<V>,
+    V: Into<Cow<'static, str>>,
+{
+    move |item: &Item| match split(item.text) {
+        (key, Some(spec)) => {
+            let value = mapper(&Item {
+                text: key,
+                ..item.clone()
+            })?
+            .into();
+            Some(Cow::Owned(spec.apply(&value).into_owned()))
+        }
+        (_, None) => mapper(item).map(Into::into),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_with;
+
+    #[test]
+    fn parse_specs() {
+        let spec = |fill, align, width| FormatSpec { fill, align, width };
+        assert_eq!("20".parse(), Ok(spec(' ', Align::Left, 20)));
+        assert_eq!(">5".parse(), Ok(spec(' ', Align::Right, 5)));
+        assert_eq!("*^7".parse(), Ok(spec('*', Align::Center, 7)));
+        assert_eq!("<<3".parse(), Ok(spec('<', Align::Left, 3)));
+        assert_eq!("".parse::<FormatSpec>(), Err(()));
+        assert_eq!("<".parse::<FormatSpec>(), Err(()));
+        assert_eq!("int".parse::<FormatSpec>(), Err(()));
+        assert_eq!("+5".parse::<FormatSpec>(), Err(()));
+        assert_eq!(spec('*', Align::Center, 7).to_string(), "*^7");
+
+        assert_eq!(split("name:<20").0, "name");
+        assert_eq!(split(" a:b ").0, "a:b");
+        assert_eq!(split("a:b:>2").0, "a:b");
+    }
+
+    #[test]
+    fn pad_values() {
+        let apply = |spec: &str, value| {
+            spec.parse::<FormatSpec>()
+                .unwrap()
+                .apply(value)
+                .into_owned()
+        };
+        assert_eq!(apply("6", "ab"), "ab    ");
+        assert_eq!(apply(">6", "ab"), "    ab");
+        assert_eq!(apply("-^7", "ab"), "--ab---");
+        assert_eq!(apply("ü>3", "é"), "üüé");
+        assert_eq!(apply("2", "abc"), "abc");
+    }
+
+    #[test]
+    fn format_report() {
+        let formatted = parse_with(
+            "|{name:<6}|{count:>4}|{other:3}|{name}|",
+            with_format(|item| match item.text {
+                "name" => Some("disk"),
+                "count" => Some("42"),
+                _ => None,
+            }),
+        );
+        assert_eq!(formatted.unwrap(), "|disk  |  42|{other:3}|disk|");
+    }
+}
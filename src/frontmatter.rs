@@ -0,0 +1,281 @@
+//! Templates starting with a frontmatter block, as in static site
+//! generators.
+//!
+//! A block fenced by `---` lines holds YAML and one fenced by `+++` lines
+//! TOML. Only flat `key: value` or `key = value` pairs of scalars are
+//! understood, which is what frontmatter mostly consists of; nested
+//! structures are reported as errors.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use pest::error::Error;
+
+use crate::resolve::{Resolver, ValueResolver};
+use crate::{parse, render, Item, Rule, Value};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+    Yaml,
+    Toml,
+}
+
+impl Format {
+    fn fence(self) -> &'static str {
+        match self {
+            Format::Yaml => "---",
+            Format::Toml => "+++",
+        }
+    }
+}
+
+/// A template split into its frontmatter and body.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Frontmatter<'s> {
+    pub format: Format,
+    /// The whole block, fences included.
+    pub block: &'s str,
+    /// The content between the fences.
+    pub content: &'s str,
+    pub body: &'s str,
+}
+
+#[derive(Debug)]
+pub enum FrontmatterError {
+    Parse(Box<Error<Rule>>),
+    /// Line `line` of the frontmatter content, counting from 1, is not
+    /// understood.
+    Syntax {
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for FrontmatterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrontmatterError::Parse(e) => e.fmt(f),
+            FrontmatterError::Syntax { line, message } => {
+                write!(f, "frontmatter line {}: {}", line, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FrontmatterError {}
+
+impl From<Error<Rule>> for FrontmatterError {
+    fn from(e: Error<Rule>) -> Self {
+        FrontmatterError::Parse(Box::new(e))
+    }
+}
+
+/// Splits the frontmatter off `s`, if it starts with one.
+pub fn split(s: &str) -> Option<Frontmatter<'_>> {
+    let format = [Format::Yaml, Format::Toml]
+        .iter()
+        .copied()
+        .find(|format| first_line(s) == Some(format.fence()))?;
+    let content_start = s.find('\n')? + 1;
+
+    let mut pos = content_start;
+    while pos < s.len() {
+        let line_end = s[pos..].find('\n').map_or(s.len(), |i| pos + i + 1);
+        if first_line(&s[pos..]) == Some(format.fence()) {
+            return Some(Frontmatter {
+                format,
+                block: &s[..line_end],
+                content: &s[content_start..pos],
+                body: &s[line_end..],
+            });
+        }
+        pos = line_end;
+    }
+    None
+}
+
+/// The first line of `s` without its line ending, if `s` has one.
+fn first_line(s: &str) -> Option<&str> {
+    let line = s.split('\n').next()?;
+    Some(line.strip_suffix('\r').unwrap_or(line))
+}
+
+impl Frontmatter<'_> {
+    /// Reads the key-value pairs of the frontmatter into a map.
+    pub fn values(&self) -> Result<Value, FrontmatterError> {
+        let separator = match self.format {
+            Format::Yaml => ':',
+            Format::Toml => '=',
+        };
+        let mut map = BTreeMap::new();
+        for (i, line) in self.content.lines().enumerate() {
+            let syntax = |message: &str| FrontmatterError::Syntax {
+                line: i + 1,
+                message: message.to_owned(),
+            };
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            if line.starts_with([' ', '\t']) || trimmed.starts_with(['-', '[']) {
+                return Err(syntax("nested values are not supported"));
+            }
+            let (key, value) = trimmed
+                .split_once(separator)
+                .ok_or_else(|| syntax("expected a key-value pair"))?;
+            let key = key.trim().trim_matches('"');
+            if key.is_empty() {
+                return Err(syntax("empty key"));
+            }
+            map.insert(key.to_owned(), scalar(value.trim()));
+        }
+        Ok(Value::Map(map))
+    }
+}
+
+fn scalar(s: &str) -> Value {
+    let quoted = [('"', '"'), ('\'', '\'')]
+        .iter()
+        .find_map(|&(open, close)| s.strip_prefix(open)?.strip_suffix(close));
+    if let Some(s) = quoted {
+        return Value::from(s);
+    }
+    match s {
+        "" | "~" | "null" => Value::Null,
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ => s
+            .parse()
+            .map(Value::Int)
+            .or_else(|_| s.parse().map(Value::Float))
+            .unwrap_or_else(|_| Value::from(s)),
+    }
+}
+
+/// How [`render_with_frontmatter`] treats the frontmatter.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct FrontmatterOptions {
+    /// Resolve placeholders the resolver leaves unresolved from the
+    /// frontmatter values.
+    pub as_context: bool,
+    /// Render the frontmatter as well and keep it in the output. Otherwise
+    /// only the body is output.
+    pub keep: bool,
+}
+
+/// Renders `s` with `resolver`, treating a leading frontmatter block as
+/// `options` say. Without a frontmatter, the whole of `s` is the body.
+pub fn render_with_frontmatter<R>(
+    s: &str,
+    resolver: &R,
+    options: FrontmatterOptions,
+) -> Result<String, FrontmatterError>
+where
+    R: Resolver + ?Sized,
+{
+    let frontmatter = split(s);
+    let context = match frontmatter {
+        Some(frontmatter) if options.as_context => frontmatter.values()?,
+        _ => Value::Null,
+    };
+    let fallback = ValueResolver::new(&context);
+    let mapper = |item: &Item| -> Option<Cow<str>> {
+        resolver.resolve(item).or_else(|| fallback.resolve(item))
+    };
+
+    match frontmatter {
+        Some(frontmatter) if options.keep => {
+            let mut result = render(&parse(frontmatter.block)?, mapper);
+            result.push_str(&render(&parse(frontmatter.body)?, mapper));
+            Ok(result)
+        }
+        Some(frontmatter) => Ok(render(&parse(frontmatter.body)?, mapper)),
+        None => Ok(render(&parse(s)?, mapper)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_frontmatter() {
+        let s = "---\r\ntitle: Hi\n---\r\nbody";
+        let frontmatter = split(s).unwrap();
+        assert_eq!(frontmatter.format, Format::Yaml);
+        assert_eq!(frontmatter.content, "title: Hi\n");
+        assert_eq!(frontmatter.block, "---\r\ntitle: Hi\n---\r\n");
+        assert_eq!(frontmatter.body, "body");
+
+        let frontmatter = split("+++\n+++").unwrap();
+        assert_eq!(
+            (frontmatter.format, frontmatter.content),
+            (Format::Toml, "")
+        );
+        assert_eq!(split("---\ntitle: Hi\n"), None);
+        assert_eq!(split("--- \n---\n"), None);
+        assert_eq!(split("body\n---\n---\n"), None);
+    }
+
+    #[test]
+    fn read_values() {
+        let frontmatter = split("---\ntitle: \"Hi: there\"\n# c\n\ndraft: false\nn: 3\nx: 1.5\ny:\nz: plain text\n---\n").unwrap();
+        let values = frontmatter.values().unwrap();
+        assert_eq!(values.lookup("title"), Some(&Value::from("Hi: there")));
+        assert_eq!(values.lookup("draft"), Some(&Value::Bool(false)));
+        assert_eq!(values.lookup("n"), Some(&Value::Int(3)));
+        assert_eq!(values.lookup("x"), Some(&Value::Float(1.5)));
+        assert_eq!(values.lookup("y"), Some(&Value::Null));
+        assert_eq!(values.lookup("z"), Some(&Value::from("plain text")));
+
+        let values = split("+++\ntitle = 'Hi'\nweight = 10\n+++\n")
+            .unwrap()
+            .values()
+            .unwrap();
+        assert_eq!(values.lookup("title"), Some(&Value::from("Hi")));
+        assert_eq!(values.lookup("weight"), Some(&Value::Int(10)));
+
+        let e = split("---\na: 1\ntags:\n  - x\n---\n")
+            .unwrap()
+            .values()
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "frontmatter line 3: nested values are not supported"
+        );
+        assert!(split("+++\n[extra]\n+++\n").unwrap().values().is_err());
+    }
+
+    #[test]
+    fn render_body() {
+        let s = "---\ntitle: Hi\nsite: {{site}}\n---\n# {{title}} on {{site}}";
+        let resolver = |item: &Item| match item.text {
+            "site" => Some("example.com"),
+            _ => None,
+        };
+        let render = |options| render_with_frontmatter(s, &resolver, options).unwrap();
+        assert_eq!(
+            render(FrontmatterOptions::default()),
+            "# {{title}} on example.com"
+        );
+        assert_eq!(
+            render(FrontmatterOptions {
+                as_context: true,
+                keep: false
+            }),
+            "# Hi on example.com"
+        );
+        assert_eq!(
+            render(FrontmatterOptions {
+                as_context: true,
+                keep: true
+            }),
+            "---\ntitle: Hi\nsite: example.com\n---\n# Hi on example.com"
+        );
+        assert_eq!(
+            render_with_frontmatter("{{site}}", &resolver, FrontmatterOptions::default()).unwrap(),
+            "example.com"
+        );
+    }
+}
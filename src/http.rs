@@ -0,0 +1,439 @@
+//! Fetching placeholder values from an HTTP service.
+//!
+//! [`HttpResolver`] fills a URL template like `http://kv.internal/kv/{key}`
+//! with the key of each placeholder and substitutes the body of the
+//! response, minus one trailing newline. A 404 leaves the placeholder
+//! unresolved. Only plain `http` URLs are supported, so the service should
+//! be reachable over a trusted network.
+//!
+//! Keys may contain `/` to reach nested paths, but not `.` or `..` segments,
+//! so a template cannot make requests outside the path of the URL template
+//! with the configured headers attached.
+//!
+//! Lookups are bounded by a [timeout](HttpResolver::timeout), retried on
+//! connection failures and 5xx responses, and fail on bodies over a
+//! [size limit](HttpResolver::max_bytes). Wrap the resolver in
+//! [`Memoized`](crate::resolve::Memoized) to fetch repeated keys once.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::resolve::{Outcome, Resolver};
+use crate::{filters, Item};
+
+/// Placeholder of a URL template replaced with the key.
+pub const KEY: &str = "{key}";
+
+/// Longest response head read before the body.
+const MAX_HEAD_BYTES: usize = 16 * 1024;
+
+#[derive(Debug)]
+pub enum HttpError {
+    /// The URL template is not a `http://host[:port]/path` URL with a
+    /// [`KEY`] in its path.
+    InvalidUrl(String),
+    /// The key has a `.` or `..` path segment.
+    InvalidKey(String),
+    Io(io::Error),
+    /// The service answered with an unexpected status.
+    Status(u16),
+    /// The response body is longer than the limit.
+    TooLarge {
+        limit: usize,
+    },
+    /// The response is not a valid HTTP response with a UTF-8 body.
+    Malformed(&'static str),
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HttpError::InvalidUrl(url) => write!(f, "invalid URL template {:?}", url),
+            HttpError::InvalidKey(key) => write!(f, "invalid key {:?}", key),
+            HttpError::Io(e) => e.fmt(f),
+            HttpError::Status(status) => write!(f, "unexpected status {}", status),
+            HttpError::TooLarge { limit } => {
+                write!(f, "response body is larger than {} bytes", limit)
+            }
+            HttpError::Malformed(message) => write!(f, "malformed response: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+impl From<io::Error> for HttpError {
+    fn from(e: io::Error) -> Self {
+        HttpError::Io(e)
+    }
+}
+
+/// Percent-encodes `key` for a URL path, keeping unreserved characters and
+/// `/`, and rejecting dot segments.
+fn encode(key: &str) -> Result<String, HttpError> {
+    if key
+        .split('/')
+        .any(|segment| segment == "." || segment == "..")
+    {
+        return Err(HttpError::InvalidKey(key.to_owned()));
+    }
+    let mut out = String::with_capacity(key.len());
+    for b in key.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                out.push(b as char)
+            }
+            b => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    Ok(out)
+}
+
+/// Time left until `deadline`, or an error once it has passed.
+fn remaining(deadline: Instant) -> io::Result<Duration> {
+    deadline
+        .checked_duration_since(Instant::now())
+        .filter(|left| !left.is_zero())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::TimedOut, "lookup timed out"))
+}
+
+/// Finds `needle` in `haystack`.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Resolves placeholders with `GET` requests to a URL template.
+#[derive(Clone, Debug)]
+pub struct HttpResolver {
+    host: String,
+    port: u16,
+    /// The path and query, containing [`KEY`].
+    path: String,
+    headers: Vec<(String, String)>,
+    timeout: Duration,
+    retries: u32,
+    retry_delay: Duration,
+    max_bytes: usize,
+}
+
+impl HttpResolver {
+    /// Resolves from `url`, a template like `http://localhost:8080/kv/{key}`.
+    ///
+    /// Lookups time out after 5 seconds, are retried twice starting after
+    /// 100 milliseconds, and accept bodies up to 64 KiB.
+    pub fn new(url: &str) -> Result<HttpResolver, HttpError> {
+        let invalid = || HttpError::InvalidUrl(url.to_owned());
+        let rest = url.strip_prefix("http://").ok_or_else(invalid)?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => rest.split_at(i),
+            None => return Err(invalid()),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| invalid())?),
+            None => (authority, 80),
+        };
+        if host.is_empty() || !path.contains(KEY) {
+            return Err(invalid());
+        }
+        Ok(HttpResolver {
+            host: host.to_owned(),
+            port,
+            path: path.to_owned(),
+            headers: vec![],
+            timeout: Duration::from_secs(5),
+            retries: 2,
+            retry_delay: Duration::from_millis(100),
+            max_bytes: 64 * 1024,
+        })
+    }
+
+    /// Sends the header `name: value` with every request, e.g. a token.
+    pub fn header<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Time allowed for each attempt at a lookup, from connecting until the
+    /// whole response is read.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Tries failed lookups `retries` more times, waiting `delay` before the
+    /// first retry and twice as long before each further one.
+    pub fn retries(mut self, retries: u32, delay: Duration) -> Self {
+        self.retries = retries;
+        self.retry_delay = delay;
+        self
+    }
+
+    /// Fails on response bodies longer than `max_bytes`.
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Fetches the value for `key`, or `None` if the service answers 404.
+    pub fn fetch(&self, key: &str) -> Result<Option<String>, HttpError> {
+        let mut delay = self.retry_delay;
+        let mut attempt = 0;
+        loop {
+            match self.fetch_once(key) {
+                Err(HttpError::Io(_)) | Err(HttpError::Status(500..=599))
+                    if attempt < self.retries =>
+                {
+                    thread::sleep(delay);
+                    delay *= 2;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn fetch_once(&self, key: &str) -> Result<Option<String>, HttpError> {
+        let path = self.path.replace(KEY, &encode(key)?);
+        let deadline = Instant::now() + self.timeout;
+        let addrs = (self.host.as_str(), self.port).to_socket_addrs()?;
+        let mut stream = None;
+        let mut error = io::Error::new(io::ErrorKind::NotFound, "no address for host");
+        for addr in addrs {
+            match TcpStream::connect_timeout(&addr, remaining(deadline)?) {
+                Ok(connected) => {
+                    stream = Some(connected);
+                    break;
+                }
+                Err(e) => error = e,
+            }
+        }
+        let mut stream = stream.ok_or(error)?;
+        stream.set_write_timeout(Some(remaining(deadline)?))?;
+
+        let mut request = format!(
+            "GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n",
+            path, self.host
+        );
+        for (name, value) in &self.headers {
+            request.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes())?;
+
+        // Reads up to one byte over the limit, each with the time left, so
+        // a server sending slowly cannot hold the lookup open.
+        let limit = MAX_HEAD_BYTES + self.max_bytes;
+        let mut response = vec![];
+        let mut buf = [0; 8192];
+        while response.len() <= limit {
+            stream.set_read_timeout(Some(remaining(deadline)?))?;
+            let len = (limit + 1 - response.len()).min(buf.len());
+            match stream.read(&mut buf[..len]) {
+                Ok(0) => break,
+                Ok(n) => response.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let head_end = find(&response, b"\r\n\r\n").ok_or({
+            if response.len() > limit {
+                HttpError::Malformed("response head too long")
+            } else {
+                HttpError::Malformed("incomplete response head")
+            }
+        })?;
+        let head = std::str::from_utf8(&response[..head_end])
+            .map_err(|_| HttpError::Malformed("response head is not UTF-8"))?;
+        let body = &response[head_end + 4..];
+
+        let status = head
+            .split_whitespace()
+            .nth(1)
+            .and_then(|status| status.parse::<u16>().ok())
+            .ok_or(HttpError::Malformed("no status"))?;
+        if head.lines().skip(1).any(|line| {
+            let (name, value) = line.split_once(':').unwrap_or((line, ""));
+            name.eq_ignore_ascii_case("transfer-encoding")
+                && !value.trim().eq_ignore_ascii_case("identity")
+        }) {
+            return Err(HttpError::Malformed("transfer encodings are not supported"));
+        }
+        match status {
+            200..=299 => {}
+            404 => return Ok(None),
+            status => return Err(HttpError::Status(status)),
+        }
+        if body.len() > self.max_bytes {
+            return Err(HttpError::TooLarge {
+                limit: self.max_bytes,
+            });
+        }
+
+        let body =
+            std::str::from_utf8(body).map_err(|_| HttpError::Malformed("body is not UTF-8"))?;
+        let body = body.strip_suffix('\n').unwrap_or(body);
+        Ok(Some(body.strip_suffix('\r').unwrap_or(body).to_owned()))
+    }
+
+    /// Resolves `item` by its [key](Item::key) without filters.
+    pub fn try_resolve(&self, item: &Item) -> Result<Option<String>, HttpError> {
+        match filters::pipeline(item.key()).0 {
+            "" => Ok(None),
+            key => self.fetch(key),
+        }
+    }
+}
+
+impl Resolver for HttpResolver {
+    fn resolve<'r>(&'r self, item: &Item) -> Option<Cow<'r, str>> {
+        self.try_resolve(item).ok().flatten().map(Cow::Owned)
+    }
+
+    fn resolve_with_outcome<'r>(&'r self, item: &Item) -> (Option<Cow<'r, str>>, Outcome) {
+        match self.try_resolve(item) {
+            Ok(Some(value)) => (Some(Cow::Owned(value)), Outcome::Resolved),
+            Ok(None) => (None, Outcome::Unresolved),
+            Err(_) => (None, Outcome::Errored),
+        }
+    }
+}
+
+#[cfg(feature = "secret-store")]
+impl crate::store::SecretStore for HttpResolver {
+    fn fetch(&self, name: &str) -> Result<Option<String>, crate::store::StoreError> {
+        HttpResolver::fetch(self, name).map_err(|e| match e {
+            HttpError::Io(e) => crate::store::StoreError::Io(e),
+            e => crate::store::StoreError::Backend(e.to_string()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_with;
+    use std::net::TcpListener;
+
+    /// Serves `responses` in order, one per connection, returning the URL
+    /// template and the handle yielding the requests received.
+    fn serve(responses: Vec<&'static str>) -> (String, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/kv/{{key}}?v=1", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let mut requests = vec![];
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = vec![0; 4096];
+                let n = stream.read(&mut request).unwrap();
+                requests.push(String::from_utf8_lossy(&request[..n]).into_owned());
+                match response {
+                    "hang" => thread::sleep(Duration::from_millis(200)),
+                    // One byte at a time, each well within the timeout.
+                    "drip" => {
+                        for b in b"HTTP/1.0 200 OK\r\n\r\nvalue".iter() {
+                            thread::sleep(Duration::from_millis(10));
+                            if stream.write_all(&[*b]).is_err() {
+                                break;
+                            }
+                        }
+                    }
+                    response => stream.write_all(response.as_bytes()).unwrap(),
+                }
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn parse_url_templates() {
+        let resolver = HttpResolver::new("http://kv.internal/{key}").unwrap();
+        assert_eq!((resolver.host.as_str(), resolver.port), ("kv.internal", 80));
+        assert!(HttpResolver::new("http://localhost:1/a/{key}").is_ok());
+        for url in &[
+            "https://kv/{key}",
+            "http://kv",
+            "http:///{key}",
+            "http://kv:x/{key}",
+            "http://kv/a",
+        ] {
+            assert!(matches!(
+                HttpResolver::new(url),
+                Err(HttpError::InvalidUrl(_))
+            ));
+        }
+        assert_eq!(
+            encode("db/pass word&é.%2e").unwrap(),
+            "db/pass%20word%26%C3%A9.%252e"
+        );
+        for key in &["..", "../admin/keys", "a/../../other", "a/./b", "a/."] {
+            assert!(matches!(encode(key), Err(HttpError::InvalidKey(_))));
+        }
+        assert!(encode("a..b/.c/d.").is_ok());
+    }
+
+    #[test]
+    fn fetch_values() {
+        let (url, server) = serve(vec![
+            "HTTP/1.0 200 OK\r\nContent-Type: text/plain\r\n\r\nhunter2\n",
+            "HTTP/1.0 404 Not Found\r\n\r\n",
+            "HTTP/1.0 503 Unavailable\r\n\r\n",
+            "HTTP/1.0 200 OK\r\n\r\nlater",
+            "HTTP/1.0 403 Forbidden\r\n\r\n",
+        ]);
+        let resolver = HttpResolver::new(&url)
+            .unwrap()
+            .header("Authorization", "Bearer t")
+            .retries(1, Duration::from_millis(1));
+        assert_eq!(
+            parse_with("{{db pw | upper}} {{missing}} {{retried}}", |item| {
+                resolver.resolve(item)
+            })
+            .unwrap(),
+            "hunter2 {{missing}} later"
+        );
+        assert!(matches!(
+            resolver.fetch("denied"),
+            Err(HttpError::Status(403))
+        ));
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with("GET /kv/db%20pw?v=1 HTTP/1.0\r\n"));
+        assert!(requests[0].contains("\r\nAuthorization: Bearer t\r\n"));
+        assert!(requests[3].starts_with("GET /kv/retried?v=1 "));
+    }
+
+    #[test]
+    fn bound_lookups() {
+        let (url, server) = serve(vec![
+            "HTTP/1.0 200 OK\r\n\r\n0123456789",
+            "hang",
+            "drip",
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n1\r\nx\r\n0\r\n\r\n",
+        ]);
+        let resolver = HttpResolver::new(&url)
+            .unwrap()
+            .max_bytes(4)
+            .timeout(Duration::from_millis(50))
+            .retries(0, Duration::ZERO);
+        assert!(matches!(
+            resolver.fetch("a"),
+            Err(HttpError::TooLarge { limit: 4 })
+        ));
+        assert!(matches!(resolver.fetch("b"), Err(HttpError::Io(_))));
+        let start = Instant::now();
+        assert!(matches!(resolver.fetch("c"), Err(HttpError::Io(_))));
+        assert!(start.elapsed() < Duration::from_millis(150));
+        assert!(matches!(
+            resolver.fetch("../keys"),
+            Err(HttpError::InvalidKey(_))
+        ));
+        let resolver = resolver.timeout(Duration::from_secs(5));
+        assert!(matches!(resolver.fetch("d"), Err(HttpError::Malformed(_))));
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,407 @@
+//! A vector keeping its first few elements inline, with the
+//! `inline-elements` feature.
+//!
+//! Most templates parse into a handful of elements, so with the feature,
+//! [`Elements`](crate::Elements) is an [`InlineVec`] holding up to
+//! [`INLINE_ELEMENTS`](crate::INLINE_ELEMENTS) of them without allocating.
+//! It derefs to a slice like a `Vec` and moves its elements to the heap once
+//! they outgrow the inline storage.
+//!
+//! ```
+//! use spongy::inline::InlineVec;
+//!
+//! let mut v: InlineVec<u32, 2> = InlineVec::new();
+//! v.push(1);
+//! v.push(2);
+//! assert!(!v.spilled());
+//! v.push(3);
+//! assert!(v.spilled());
+//! assert_eq!(v, [1, 2, 3]);
+//! assert_eq!(v.iter().sum::<u32>(), 6);
+//! ```
+
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
+use std::mem::{self, MaybeUninit};
+use std::ops::{Deref, DerefMut};
+use std::ptr;
+use std::slice;
+use std::vec;
+
+enum Storage<T, const N: usize> {
+    /// The first `len` items are initialized.
+    Inline {
+        items: [MaybeUninit<T>; N],
+        len: usize,
+    },
+    Heap(Vec<T>),
+}
+
+/// A vector storing up to `N` items inline.
+pub struct InlineVec<T, const N: usize> {
+    storage: Storage<T, N>,
+}
+
+impl<T, const N: usize> InlineVec<T, N> {
+    pub fn new() -> InlineVec<T, N> {
+        InlineVec {
+            storage: Storage::Inline {
+                items: std::array::from_fn(|_| MaybeUninit::uninit()),
+                len: 0,
+            },
+        }
+    }
+
+    /// A vector with room for `capacity` items, on the heap if they would
+    /// not fit inline.
+    pub fn with_capacity(capacity: usize) -> InlineVec<T, N> {
+        if capacity <= N {
+            InlineVec::new()
+        } else {
+            Vec::with_capacity(capacity).into()
+        }
+    }
+
+    /// Makes room for `additional` more items, moving them to the heap if
+    /// they would not fit inline.
+    pub fn reserve(&mut self, additional: usize) {
+        match &mut self.storage {
+            Storage::Inline { len, .. } if *len + additional <= N => {}
+            Storage::Inline { .. } => {
+                let mut heap = Vec::with_capacity(self.len() + additional);
+                heap.extend(mem::take(self));
+                *self = heap.into();
+            }
+            Storage::Heap(heap) => heap.reserve(additional),
+        }
+    }
+
+    /// Whether the items have moved to the heap.
+    pub fn spilled(&self) -> bool {
+        matches!(self.storage, Storage::Heap(_))
+    }
+
+    pub fn push(&mut self, item: T) {
+        match &mut self.storage {
+            Storage::Inline { items, len } if *len < N => {
+                items[*len] = MaybeUninit::new(item);
+                *len += 1;
+            }
+            Storage::Inline { items, len } => {
+                let mut heap = Vec::with_capacity(N * 2 + 1);
+                // SAFETY: the first `len` items are initialized, and are
+                // forgotten by setting `len` to 0 before the storage is
+                // replaced.
+                let used = mem::take(len);
+                heap.extend(
+                    items[..used]
+                        .iter()
+                        .map(|item| unsafe { item.assume_init_read() }),
+                );
+                heap.push(item);
+                self.storage = Storage::Heap(heap);
+            }
+            Storage::Heap(heap) => heap.push(item),
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        match &mut self.storage {
+            Storage::Inline { len: 0, .. } => None,
+            Storage::Inline { items, len } => {
+                *len -= 1;
+                // SAFETY: the item was initialized, and is now past `len`.
+                Some(unsafe { items[*len].assume_init_read() })
+            }
+            Storage::Heap(heap) => heap.pop(),
+        }
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        match &self.storage {
+            // SAFETY: the first `len` items are initialized, and
+            // `MaybeUninit<T>` has the layout of `T`.
+            Storage::Inline { items, len } => unsafe {
+                slice::from_raw_parts(items.as_ptr() as *const T, *len)
+            },
+            Storage::Heap(heap) => heap,
+        }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        match &mut self.storage {
+            // SAFETY: as for `as_slice`.
+            Storage::Inline { items, len } => unsafe {
+                slice::from_raw_parts_mut(items.as_mut_ptr() as *mut T, *len)
+            },
+            Storage::Heap(heap) => heap,
+        }
+    }
+
+    /// Moves the items into a `Vec`, which only allocates if they are inline.
+    pub fn into_vec(self) -> Vec<T> {
+        match self.into_storage() {
+            Storage::Heap(heap) => heap,
+            storage => IntoIter::new(storage).collect(),
+        }
+    }
+
+    /// Takes the storage, leaving nothing for `Drop` to drop.
+    fn into_storage(mut self) -> Storage<T, N> {
+        mem::replace(&mut self.storage, Storage::Heap(Vec::new()))
+    }
+}
+
+impl<T, const N: usize> Drop for InlineVec<T, N> {
+    fn drop(&mut self) {
+        if let Storage::Inline { .. } = self.storage {
+            // SAFETY: the items are initialized and dropped only here.
+            unsafe { ptr::drop_in_place(self.as_mut_slice()) };
+        }
+    }
+}
+
+impl<T, const N: usize> Default for InlineVec<T, N> {
+    fn default() -> Self {
+        InlineVec::new()
+    }
+}
+
+impl<T, const N: usize> Deref for InlineVec<T, N> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T, const N: usize> DerefMut for InlineVec<T, N> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+}
+
+impl<T: Clone, const N: usize> Clone for InlineVec<T, N> {
+    fn clone(&self) -> Self {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<T: fmt::Debug, const N: usize> fmt::Debug for InlineVec<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.as_slice().fmt(f)
+    }
+}
+
+impl<T: Hash, const N: usize> Hash for InlineVec<T, N> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_slice().hash(state)
+    }
+}
+
+impl<T: PartialEq<U>, U, const N: usize, const M: usize> PartialEq<InlineVec<U, M>>
+    for InlineVec<T, N>
+{
+    fn eq(&self, other: &InlineVec<U, M>) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl<T: Eq, const N: usize> Eq for InlineVec<T, N> {}
+
+impl<T: PartialEq<U>, U, const N: usize> PartialEq<Vec<U>> for InlineVec<T, N> {
+    fn eq(&self, other: &Vec<U>) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl<T: PartialEq<U>, U, const N: usize> PartialEq<[U]> for InlineVec<T, N> {
+    fn eq(&self, other: &[U]) -> bool {
+        self.as_slice() == other
+    }
+}
+
+impl<T: PartialEq<U>, U, const N: usize, const M: usize> PartialEq<[U; M]> for InlineVec<T, N> {
+    fn eq(&self, other: &[U; M]) -> bool {
+        self.as_slice() == other
+    }
+}
+
+impl<T, const N: usize> Extend<T> for InlineVec<T, N> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for item in iter {
+            self.push(item);
+        }
+    }
+}
+
+impl<T, const N: usize> FromIterator<T> for InlineVec<T, N> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut result = InlineVec::new();
+        result.extend(iter);
+        result
+    }
+}
+
+impl<T, const N: usize> From<Vec<T>> for InlineVec<T, N> {
+    fn from(vec: Vec<T>) -> Self {
+        InlineVec {
+            storage: Storage::Heap(vec),
+        }
+    }
+}
+
+impl<T, const N: usize> From<InlineVec<T, N>> for Vec<T> {
+    fn from(vec: InlineVec<T, N>) -> Self {
+        vec.into_vec()
+    }
+}
+
+/// Iterator returned by [`InlineVec::into_iter`].
+pub struct IntoIter<T, const N: usize> {
+    inner: Inner<T, N>,
+}
+
+enum Inner<T, const N: usize> {
+    /// Items from `pos` up to `len` are initialized and not yet returned.
+    Inline {
+        items: [MaybeUninit<T>; N],
+        pos: usize,
+        len: usize,
+    },
+    Heap(vec::IntoIter<T>),
+}
+
+impl<T, const N: usize> IntoIter<T, N> {
+    fn new(storage: Storage<T, N>) -> IntoIter<T, N> {
+        let inner = match storage {
+            Storage::Inline { items, len } => Inner::Inline { items, pos: 0, len },
+            Storage::Heap(heap) => Inner::Heap(heap.into_iter()),
+        };
+        IntoIter { inner }
+    }
+}
+
+impl<T, const N: usize> Iterator for IntoIter<T, N> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        match &mut self.inner {
+            Inner::Inline { items, pos, len } if *pos < *len => {
+                *pos += 1;
+                // SAFETY: the item is initialized, and is not read again now
+                // that `pos` is past it.
+                Some(unsafe { items[*pos - 1].assume_init_read() })
+            }
+            Inner::Inline { .. } => None,
+            Inner::Heap(heap) => heap.next(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = match &self.inner {
+            Inner::Inline { pos, len, .. } => len - pos,
+            Inner::Heap(heap) => heap.len(),
+        };
+        (len, Some(len))
+    }
+}
+
+impl<T, const N: usize> ExactSizeIterator for IntoIter<T, N> {}
+
+impl<T, const N: usize> Drop for IntoIter<T, N> {
+    fn drop(&mut self) {
+        if let Inner::Inline { items, pos, len } = &mut self.inner {
+            for item in &mut items[*pos..*len] {
+                // SAFETY: the items not yet returned are initialized.
+                unsafe { item.assume_init_drop() };
+            }
+        }
+    }
+}
+
+impl<T, const N: usize> IntoIterator for InlineVec<T, N> {
+    type Item = T;
+    type IntoIter = IntoIter<T, N>;
+
+    fn into_iter(self) -> IntoIter<T, N> {
+        IntoIter::new(self.into_storage())
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a InlineVec<T, N> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> slice::Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a mut InlineVec<T, N> {
+    type Item = &'a mut T;
+    type IntoIter = slice::IterMut<'a, T>;
+
+    fn into_iter(self) -> slice::IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn spill_to_the_heap() {
+        let mut v: InlineVec<String, 2> = InlineVec::with_capacity(2);
+        v.push("a".to_owned());
+        v.push("b".to_owned());
+        assert!(!v.spilled());
+        assert_eq!(v, ["a", "b"]);
+        v.push("c".to_owned());
+        assert!(v.spilled());
+        assert_eq!(v.clone(), vec!["a", "b", "c"]);
+        assert_eq!(v.pop().as_deref(), Some("c"));
+        v[0].push('!');
+        assert_eq!(v.into_vec(), ["a!", "b"]);
+
+        let mut v: InlineVec<u8, 4> = (0..3).collect();
+        v.reserve(1);
+        assert!(!v.spilled());
+        v.reserve(2);
+        assert!(v.spilled());
+        assert_eq!(v, [0, 1, 2]);
+        assert!(InlineVec::<u8, 4>::with_capacity(5).spilled());
+        assert_eq!(format!("{:?}", v), "[0, 1, 2]");
+
+        assert!(!crate::parse("Hello, {name}!").unwrap().spilled());
+        assert!(crate::parse(&"{a} ".repeat(5)).unwrap().spilled());
+    }
+
+    #[test]
+    fn drop_every_item_once() {
+        let item = Rc::new(());
+        let filled = |n: usize| -> InlineVec<Rc<()>, 3> { (0..n).map(|_| item.clone()).collect() };
+
+        for n in 0..6 {
+            drop(filled(n));
+            assert_eq!(Rc::strong_count(&item), 1);
+
+            let mut iter = filled(n).into_iter();
+            iter.next();
+            assert_eq!(iter.len(), n.saturating_sub(1));
+            drop(iter);
+            assert_eq!(Rc::strong_count(&item), 1);
+
+            assert_eq!(filled(n).into_iter().count(), n);
+            assert_eq!(Vec::from(filled(n)).len(), n);
+            let mut v = filled(n);
+            v.pop();
+            v.reserve(3);
+            drop(v);
+            assert_eq!(Rc::strong_count(&item), 1);
+        }
+    }
+}
@@ -1,13 +1,75 @@
 #[macro_use]
 extern crate pest_derive;
 
-use pest::{error::Error, Parser};
+use std::borrow::{Borrow, Cow};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::str::FromStr;
+
+use pest::error::{Error, InputLocation};
+use pest::{Parser, Position};
+
+use crate::dialect::Found;
+
+pub mod backend;
+pub mod batch;
+pub mod block;
+pub mod builder;
+pub mod bytes;
+#[cfg(feature = "command")]
+pub mod command;
+pub mod compose;
+pub mod context;
+mod dialect;
+pub mod dotenv;
+pub mod expr;
+pub mod filters;
+pub mod format;
+pub mod frontmatter;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(feature = "inline-elements")]
+pub mod inline;
+pub mod lint;
+mod literal;
+pub mod manifest;
+#[cfg(all(feature = "mmap", unix))]
+pub mod mmap;
+pub mod normalize;
+mod owned;
+pub mod path;
+pub mod policy;
+pub mod quote;
+pub mod resolve;
+pub mod scaffold;
+#[cfg(feature = "serde")]
+pub mod schema;
+pub mod secret;
+pub mod stats;
+#[cfg(feature = "secret-store")]
+pub mod store;
+pub mod template;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod tokenizer;
+pub mod types;
+pub mod value;
+
+pub use builder::TemplateBuilder;
+pub use context::RenderContext;
+pub use owned::{OwnedElement, OwnedItem};
+pub use quote::QuoteRule;
+pub use resolve::{RenderReport, Resolver};
+pub use template::Template;
+pub use types::TypeHint;
+pub use value::Value;
 
 #[derive(Parser)]
 #[grammar = "spec.pest"]
 struct IdentParser;
 
-#[derive(PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum Wrapper {
     TripleCurly,
     DoubleCurly,
@@ -15,9 +77,45 @@ pub enum Wrapper {
     DollarCurly,
     CurlyHash,
     CurlyPercent,
+    /// Kubernetes-style `$(VAR)`, only recognized with
+    /// [`ParseOptions::dollar_paren`].
+    DollarParen,
 }
 
 impl Wrapper {
+    /// All wrappers, in the order the grammar tries them, followed by those
+    /// recognized outside the grammar.
+    pub const ALL: [Wrapper; 7] = [
+        Wrapper::TripleCurly,
+        Wrapper::DollarCurly,
+        Wrapper::DoubleCurly,
+        Wrapper::CurlyHash,
+        Wrapper::CurlyPercent,
+        Wrapper::Curly,
+        Wrapper::DollarParen,
+    ];
+
+    /// Looks a wrapper up by its delimiters, e.g. `("{{", "}}")`.
+    pub fn from_delimiters(prefix: &str, suffix: &str) -> Option<Wrapper> {
+        Wrapper::ALL
+            .iter()
+            .copied()
+            .find(|w| w.get_prefix() == prefix && w.get_suffix() == suffix)
+    }
+
+    /// The snake case name of the wrapper, e.g. `double_curly`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Wrapper::TripleCurly => "triple_curly",
+            Wrapper::DoubleCurly => "double_curly",
+            Wrapper::Curly => "curly",
+            Wrapper::DollarCurly => "dollar_curly",
+            Wrapper::CurlyHash => "curly_hash",
+            Wrapper::CurlyPercent => "curly_percent",
+            Wrapper::DollarParen => "dollar_paren",
+        }
+    }
+
     fn get_prefix(&self) -> &'static str {
         match self {
             Wrapper::TripleCurly => "{{{",
@@ -26,6 +124,7 @@ impl Wrapper {
             Wrapper::DollarCurly => "${",
             Wrapper::CurlyHash => "{#",
             Wrapper::CurlyPercent => "{%",
+            Wrapper::DollarParen => "$(",
         }
     }
 
@@ -37,93 +136,1129 @@ impl Wrapper {
             Wrapper::DollarCurly => "}",
             Wrapper::CurlyHash => "#}",
             Wrapper::CurlyPercent => "%}",
+            Wrapper::DollarParen => ")",
         }
     }
 }
 
-#[derive(PartialEq, Debug)]
+/// Shows the delimiters, e.g. `{% %}`.
+impl fmt::Display for Wrapper {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.get_prefix(), self.get_suffix())
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ParseWrapperError(String);
+
+impl fmt::Display for ParseWrapperError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown wrapper {:?}", self.0)
+    }
+}
+
+impl std::error::Error for ParseWrapperError {}
+
+/// Parses a wrapper from its [name](Wrapper::name) in snake, kebab or camel
+/// case, or from its delimiters with or without a space, like `{% %}`.
+impl FromStr for Wrapper {
+    type Err = ParseWrapperError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name: String = s
+            .chars()
+            .filter(|&c| c != '_' && c != '-')
+            .collect::<String>()
+            .to_lowercase();
+        let delimiters: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+
+        Wrapper::ALL
+            .iter()
+            .copied()
+            .find(|w| {
+                w.name().replace('_', "") == name
+                    || format!("{}{}", w.get_prefix(), w.get_suffix()) == delimiters
+            })
+            .ok_or_else(|| ParseWrapperError(s.to_owned()))
+    }
+}
+
+/// A placeholder.
+///
+/// Items produced by the parser remember the exact slice they were matched
+/// from, so writing them back never diverges from the input. That slice is
+/// not part of the item's identity: items compare equal by wrapper and text.
+#[derive(Clone, Debug)]
 pub struct Item<'a> {
     pub wrapper: Wrapper,
     pub text: &'a str,
+    source: Option<&'a str>,
+}
+
+impl PartialEq for Item<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.wrapper == other.wrapper && self.text == other.text
+    }
+}
+
+impl Eq for Item<'_> {}
+
+impl Hash for Item<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.wrapper.hash(state);
+        self.text.hash(state);
+    }
 }
 
 impl<'a> Item<'a> {
     fn new(wrapper: Wrapper, text: &'a str) -> Item<'a> {
-        Item { wrapper, text }
+        Item {
+            wrapper,
+            text,
+            source: None,
+        }
+    }
+
+    /// Creates an item matched from `source`, delimiters included.
+    fn with_source(wrapper: Wrapper, text: &'a str, source: &'a str) -> Item<'a> {
+        Item {
+            wrapper,
+            text,
+            source: Some(source),
+        }
+    }
+
+    /// The full source text of the item, e.g. `{{ user.name }}`.
+    ///
+    /// For items not produced by the parser, it is rebuilt from the wrapper
+    /// and text.
+    pub fn raw(&self) -> Cow<'a, str> {
+        match self.source {
+            Some(source) => Cow::Borrowed(source),
+            None => {
+                let mut raw = String::new();
+                self.push_source(&mut raw);
+                Cow::Owned(raw)
+            }
+        }
+    }
+
+    /// The item text without surrounding whitespace, e.g. `user.name`.
+    pub fn key(&self) -> &'a str {
+        self.text.trim()
+    }
+
+    /// The type annotation of the item, as in `{age:int}`.
+    pub fn type_hint(&self) -> Option<TypeHint> {
+        types::split(self.text).1
+    }
+
+    /// Length of the item in its source form.
+    fn source_len(&self) -> usize {
+        match self.source {
+            Some(source) => source.len(),
+            None => {
+                self.wrapper.get_prefix().len() + self.text.len() + self.wrapper.get_suffix().len()
+            }
+        }
+    }
+
+    /// Writes the item back in its source form.
+    fn push_source(&self, out: &mut String) {
+        match self.source {
+            Some(source) => out.push_str(source),
+            None => {
+                out.push_str(self.wrapper.get_prefix());
+                out.push_str(self.text);
+                out.push_str(self.wrapper.get_suffix());
+            }
+        }
+    }
+}
+
+/// Byte range of an element in the parsed input.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+impl From<Range<usize>> for Span {
+    fn from(range: Range<usize>) -> Self {
+        Span::new(range.start, range.end)
+    }
+}
+
+impl From<Span> for Range<usize> {
+    fn from(span: Span) -> Self {
+        span.start..span.end
+    }
+}
+
+/// Shows the source form, e.g. `{{ user.name }}`.
+impl fmt::Display for Item<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.raw())
     }
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub enum Element<'a> {
     Text(&'a str),
     Wrapped(Item<'a>),
+    /// Text written in an escaped form, like a literal `${x}` written as
+    /// `$${x}` with [`ParseOptions::dollar_escape`]. It renders as `text`
+    /// and unparses as `source`.
+    Escaped {
+        text: &'a str,
+        source: &'a str,
+    },
 }
 
-pub fn parse<'e>(s: &'e str) -> Result<Vec<Element<'e>>, Error<Rule>> {
-    let pairs = IdentParser::parse(Rule::expression, s)?;
-
-    let result: Vec<Element<'e>> = pairs
-        .take_while(|pair| pair.as_rule() != Rule::EOI)
-        .map(|pair| match pair.as_rule() {
-            Rule::text => Element::Text(pair.into_inner().next().unwrap().as_str()),
-            Rule::triple_curly => Element::Wrapped(Item::new(
-                Wrapper::TripleCurly,
-                pair.into_inner().next().unwrap().as_str(),
-            )),
-            Rule::double_curly => Element::Wrapped(Item::new(
-                Wrapper::DoubleCurly,
-                pair.into_inner().next().unwrap().as_str(),
-            )),
-            Rule::curly => Element::Wrapped(Item::new(
-                Wrapper::Curly,
-                pair.into_inner().next().unwrap().as_str(),
-            )),
-            Rule::dollar_curly => Element::Wrapped(Item::new(
-                Wrapper::DollarCurly,
-                pair.into_inner().next().unwrap().as_str(),
-            )),
-            Rule::curly_hash => Element::Wrapped(Item::new(
-                Wrapper::CurlyHash,
-                pair.into_inner().next().unwrap().as_str(),
-            )),
-            Rule::curly_percent => Element::Wrapped(Item::new(
-                Wrapper::CurlyPercent,
-                pair.into_inner().next().unwrap().as_str(),
-            )),
-            Rule::EOI => Element::Text(""),
-            _ => unreachable!(),
-        })
-        .collect();
+/// Shows the source form, so displaying all parsed elements in order gives
+/// back the input.
+impl fmt::Display for Element<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Element::Text(t) => f.write_str(t),
+            Element::Wrapped(item) => item.fmt(f),
+            Element::Escaped { source, .. } => f.write_str(source),
+        }
+    }
+}
+
+/// Options controlling how a template is parsed.
+#[derive(Clone, Debug)]
+pub struct ParseOptions {
+    /// Trim leading and trailing whitespace inside placeholders, so that
+    /// `{{ user.name }}` and `{{user.name}}` yield the same item text.
+    pub trim: bool,
+    /// Quoted spans in which placeholders are not recognized, e.g.
+    /// [`QuoteRule::c_style`] for JavaScript sources.
+    pub quotes: Vec<QuoteRule>,
+    /// Treat the braces of a bare `{ ... }` block as text when its balanced
+    /// content looks like a JSON object or code block, i.e. it is blank or
+    /// contains a `:` or a newline. Placeholders inside the block are still
+    /// recognized.
+    pub literal_blocks: bool,
+    /// Treat the input as Markdown, leaving fenced code blocks and inline
+    /// code spans as text, so that code samples are never substituted.
+    pub markdown: bool,
+    /// Leave everything between a `spongy:off` marker and the next
+    /// `spongy:on` marker as text, `true` by default. Markers may sit in a
+    /// comment of the host language, like `# spongy:off`, or in a
+    /// `{# spongy:off #}` comment, and are kept in the output. A region
+    /// without an end marker extends to the end of the input.
+    pub region_markers: bool,
+    /// Treat delimiters which do not start a complete placeholder, like a
+    /// stray `${` or an unterminated `{%`, as text instead of failing. Each
+    /// one is reported as a [`Warning`].
+    pub lenient: bool,
+    /// Recognize Kubernetes-style `$(VAR)` placeholders, as
+    /// [`Wrapper::DollarParen`]. Their content may not contain parentheses
+    /// or braces, and `$$(VAR)` stands for a literal `$(VAR)`.
+    pub dollar_paren: bool,
+    /// Treat `$${...}` as text standing for a literal `${...}`, as in
+    /// Compose files and Makefiles. It is parsed as an [`Element::Escaped`].
+    pub dollar_escape: bool,
+    /// Character suppressing the interpretation of the character after it,
+    /// `\\` by default. Escapes are kept in the text elements, so rendering
+    /// writes them back verbatim. With another escape character, a `\\` is
+    /// plain text, and so is a trailing escape character.
+    pub escape: char,
+    /// Placeholders whose content is written out as text instead of being
+    /// resolved.
+    pub verbatim: Vec<Verbatim>,
+    /// Reject placeholders whose content, apart from surrounding whitespace,
+    /// is not a dotted path of letters, digits and `_`, like `user.name`.
+    /// This catches typos like `{user name}` at parse time, but also
+    /// disallows filters and type hints. Comments and statements are exempt,
+    /// and empty placeholders are allowed.
+    pub strict_identifiers: bool,
+    /// How to treat placeholders with nothing but whitespace inside, like
+    /// `{}` or `{{ }}`, which are usually authoring mistakes. Empty comments
+    /// are always allowed.
+    pub empty_placeholders: EmptyPlaceholders,
+    /// Drop the first newline after a `{% %}` statement or `{# #}` comment,
+    /// so tags on their own line leave no blank line behind.
+    pub trim_blocks: bool,
+    /// Strip the spaces and tabs before a `{% %}` statement or `{# #}`
+    /// comment standing alone on its line, so indenting tags does not indent
+    /// the output.
+    pub lstrip_blocks: bool,
+    /// Keep a single newline ending the input, `true` by default. Without it,
+    /// rendered output does not end with the template's trailing newline.
+    pub keep_trailing_newline: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            trim: false,
+            quotes: vec![],
+            literal_blocks: false,
+            markdown: false,
+            region_markers: true,
+            lenient: false,
+            dollar_paren: false,
+            dollar_escape: false,
+            escape: '\\',
+            verbatim: vec![],
+            strict_identifiers: false,
+            empty_placeholders: EmptyPlaceholders::Allow,
+            trim_blocks: false,
+            lstrip_blocks: false,
+            keep_trailing_newline: true,
+        }
+    }
+}
+
+/// How to treat empty placeholders, see
+/// [`ParseOptions::empty_placeholders`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EmptyPlaceholders {
+    /// Parse them as items with empty text.
+    Allow,
+    /// Parse them as items, reporting a [`Warning`] for each.
+    Warn,
+    /// Fail to parse.
+    Reject,
+}
+
+/// Delimiters whose content is taken verbatim, see
+/// [`ParseOptions::verbatim`].
+///
+/// Verbatim content is parsed as an [`Element::Escaped`], so it is never
+/// passed to a mapper and rendered exactly as written, without the
+/// delimiters, while [`unparse`] writes the delimiters back.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Verbatim {
+    /// Placeholders of the given wrapper.
+    Wrapper(Wrapper),
+    /// A custom pair of delimiters like `{=` and `=}`, recognized before any
+    /// wrapper. The content ends at the first suffix.
+    Delimiters(String, String),
+}
+
+/// A problem in the input that did not prevent parsing.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Warning {
+    /// Byte offset of the problem in the input.
+    pub pos: usize,
+    pub message: String,
+}
+
+/// How many elements parse results hold without allocating, with the
+/// `inline-elements` feature.
+pub const INLINE_ELEMENTS: usize = 8;
+
+/// Storage of parse results.
+///
+/// This is a `Vec`, or with the `inline-elements` feature an `InlineVec` of
+/// the `inline` module keeping up to [`INLINE_ELEMENTS`] elements inline.
+/// Code naming this rather than either type builds with or without the
+/// feature; it derefs to a slice either way.
+#[cfg(not(feature = "inline-elements"))]
+pub type Elements<'e> = Vec<Element<'e>>;
+#[cfg(feature = "inline-elements")]
+pub type Elements<'e> = inline::InlineVec<Element<'e>, INLINE_ELEMENTS>;
+
+/// Elements along with their spans while parsing, kept like [`Elements`].
+#[cfg(not(feature = "inline-elements"))]
+type Spanned<'e> = Vec<(Element<'e>, Span)>;
+#[cfg(feature = "inline-elements")]
+type Spanned<'e> = inline::InlineVec<(Element<'e>, Span), INLINE_ELEMENTS>;
+
+pub fn parse<'e>(s: &'e str) -> Result<Elements<'e>, Error<Rule>> {
+    parse_with_options(s, &ParseOptions::default())
+}
+
+pub fn parse_with_options<'e>(
+    s: &'e str,
+    options: &ParseOptions,
+) -> Result<Elements<'e>, Error<Rule>> {
+    Ok(parse_with_warnings(s, options)?.0)
+}
+
+/// Parses `s` like [`parse_with_options`], also returning the warnings
+/// collected on the way, such as delimiters skipped in lenient mode.
+pub fn parse_with_warnings<'e>(
+    s: &'e str,
+    options: &ParseOptions,
+) -> Result<(Elements<'e>, Vec<Warning>), Error<Rule>> {
+    let parsed = Parsed::new(s, options)?;
+    let elements = parsed.elements.into_iter().map(|(el, _)| el).collect();
+    Ok((elements, parsed.warnings))
+}
+
+/// Parses `s` like [`parse_with_options`], keeping the span of each element.
+pub fn parse_spanned<'e>(
+    s: &'e str,
+    options: &ParseOptions,
+) -> Result<Vec<(Element<'e>, Span)>, Error<Rule>> {
+    Ok(Parsed::new(s, options)?.elements.into_iter().collect())
+}
+
+/// Elements of a whole input along with their spans.
+struct Parsed<'e> {
+    source: &'e str,
+    elements: Spanned<'e>,
+    warnings: Vec<Warning>,
+}
+
+impl<'e> Parsed<'e> {
+    fn new(s: &'e str, options: &ParseOptions) -> Result<Parsed<'e>, Error<Rule>> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("parse", bytes = s.len()).entered();
+
+        let mut parsed = Parsed {
+            source: s,
+            elements: Spanned::new(),
+            warnings: vec![],
+        };
+        let mut pos = 0;
+
+        for literal in literal::literal_ranges(s, options) {
+            parsed.parse_dialects(pos..literal.start, options)?;
+            parsed.push_text(literal.clone());
+            pos = literal.end;
+        }
+        parsed.parse_dialects(pos..s.len(), options)?;
+        parsed.strip_whitespace(options);
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            elements = parsed.elements.len(),
+            warnings = parsed.warnings.len(),
+            "parsed template"
+        );
+        Ok(parsed)
+    }
+
+    /// Strips the whitespace around tags and at the end of the input as
+    /// configured by `options`.
+    ///
+    /// Spans are left unchanged, so like escapes, stripped text elements
+    /// render shorter than their source. Text elements stripped down to
+    /// nothing are removed and their span added to the previous element.
+    fn strip_whitespace(&mut self, options: &ParseOptions) {
+        let mut emptied = vec![];
+        if options.lstrip_blocks {
+            for i in 1..self.elements.len() {
+                if self.standalone(i) && self.strip_text(i - 1, strip_indent) {
+                    emptied.push(i - 1);
+                }
+            }
+        }
+        if options.trim_blocks {
+            for i in 1..self.elements.len() {
+                if is_block(&self.elements[i - 1].0) && self.strip_text(i, strip_leading_newline) {
+                    emptied.push(i);
+                }
+            }
+        }
+        if !options.keep_trailing_newline {
+            let last = self.elements.len().saturating_sub(1);
+            if self.strip_text(last, strip_trailing_newline) {
+                emptied.push(last);
+            }
+        }
+        if emptied.is_empty() {
+            return;
+        }
+
+        let mut elements = Spanned::with_capacity(self.elements.len());
+        let mut start = None;
+        for (i, (el, mut span)) in std::mem::take(&mut self.elements).into_iter().enumerate() {
+            if emptied.contains(&i) {
+                match elements.last_mut() {
+                    Some((_, last)) => last.end = span.end,
+                    None => start = Some(span.start),
+                }
+                continue;
+            }
+            span.start = start.take().unwrap_or(span.start);
+            elements.push((el, span));
+        }
+        self.elements = elements;
+    }
+
+    /// Whether the element at `index` is a tag with nothing but spaces and
+    /// tabs around it on its line.
+    fn standalone(&self, index: usize) -> bool {
+        if !is_block(&self.elements[index].0) {
+            return false;
+        }
+        let before = match &self.elements[index - 1].0 {
+            Element::Text(text) if index == 1 || text.contains('\n') => text,
+            _ => return false,
+        };
+        if strip_indent(before).is_none() {
+            return false;
+        }
+        match self.elements.get(index + 1) {
+            None => true,
+            Some((Element::Text(after), _)) => {
+                let after = after.trim_start_matches([' ', '\t']);
+                after.is_empty() && index + 2 == self.elements.len()
+                    || strip_leading_newline(after).is_some()
+            }
+            Some(_) => false,
+        }
+    }
+
+    /// Replaces the text element at `index` with the result of `strip`, if
+    /// any. Returns whether the text was stripped to nothing.
+    fn strip_text<F>(&mut self, index: usize, strip: F) -> bool
+    where
+        F: for<'t> Fn(&'t str) -> Option<&'t str>,
+    {
+        if let Some((Element::Text(text), _)) = self.elements.get_mut(index) {
+            if let Some(stripped) = strip(text) {
+                *text = stripped;
+                return stripped.is_empty();
+            }
+        }
+        false
+    }
+
+    /// Appends `source[range]` as text, extending a directly preceding text
+    /// element unless it was substituted for an escape.
+    fn push_text(&mut self, range: Range<usize>) {
+        if let Some((Element::Text(text), last)) = self.elements.last_mut() {
+            if last.end == range.start && text.len() == last.len() {
+                last.end = range.end;
+                *text = &self.source[last.start..last.end];
+                return;
+            }
+        }
+        self.elements
+            .push((Element::Text(&self.source[range.clone()]), range.into()));
+    }
+
+    /// Appends the item at `source[range]`, whose content is at
+    /// `source[content]`.
+    fn push_item(
+        &mut self,
+        wrapper: Wrapper,
+        range: Range<usize>,
+        content: Range<usize>,
+        options: &ParseOptions,
+    ) -> Result<(), Error<Rule>> {
+        let mut text = &self.source[content.clone()];
+        if options.verbatim.contains(&Verbatim::Wrapper(wrapper)) {
+            let source = &self.source[range.clone()];
+            self.elements
+                .push((Element::Escaped { text, source }, range.into()));
+            return Ok(());
+        }
+        if options.strict_identifiers {
+            check_identifier(self.source, wrapper, content)?;
+        }
+        if text.trim().is_empty() && wrapper != Wrapper::CurlyHash {
+            let message = format!("empty placeholder `{}`", &self.source[range.clone()]);
+            match options.empty_placeholders {
+                EmptyPlaceholders::Allow => {}
+                EmptyPlaceholders::Warn => self.warnings.push(Warning {
+                    pos: range.start,
+                    message,
+                }),
+                EmptyPlaceholders::Reject => {
+                    return Err(backend::error_at(self.source, range.start, message))
+                }
+            }
+        }
+        if options.trim {
+            text = text.trim();
+        }
+        let source = &self.source[range.clone()];
+        self.elements.push((
+            Element::Wrapped(Item::with_source(wrapper, text, source)),
+            range.into(),
+        ));
+        Ok(())
+    }
+
+    /// Parses `source[range]`, splitting off the placeholders of enabled
+    /// dialects before handing the rest to the grammar.
+    fn parse_dialects(
+        &mut self,
+        range: Range<usize>,
+        options: &ParseOptions,
+    ) -> Result<(), Error<Rule>> {
+        let offset = range.start;
+        let mut pos = offset;
+        for (found, kind) in dialect::dialect_ranges(&self.source[range.clone()], options) {
+            let found = offset + found.start..offset + found.end;
+            self.parse_range(pos..found.start, options)?;
+            match kind {
+                Found::Item(content) => {
+                    let content = offset + content.start..offset + content.end;
+                    self.push_item(Wrapper::DollarParen, found.clone(), content, options)?;
+                }
+                Found::Text => self.push_text(found.clone()),
+                Found::Escape(text) => {
+                    let text = &self.source[offset + text.start..offset + text.end];
+                    let source = &self.source[found.clone()];
+                    self.elements
+                        .push((Element::Escaped { text, source }, found.clone().into()));
+                }
+            }
+            pos = found.end;
+        }
+        self.parse_range(pos..range.end, options)
+    }
+
+    /// Parses `source[range]` with the grammar, reporting errors against the
+    /// whole source.
+    fn parse_range(
+        &mut self,
+        range: Range<usize>,
+        options: &ParseOptions,
+    ) -> Result<(), Error<Rule>> {
+        if range.is_empty() {
+            return Ok(());
+        }
 
+        let s = self.source;
+        let offset = range.start;
+        let scanned = match scan(&s[range.clone()], options.lenient) {
+            Ok(scanned) => scanned,
+            Err(Unscanned::TooComplex(pos)) => {
+                return Err(backend::error_at(
+                    s,
+                    offset + pos,
+                    "template too complex to parse".to_owned(),
+                ))
+            }
+            Err(Unscanned::Unmatched) => {
+                // The grammar rejects the same inputs, describing why.
+                return Err(match IdentParser::parse(Rule::expression, &s[range]) {
+                    Err(e) => relocate(e, s, offset),
+                    Ok(_) => backend::error_at(s, offset, "unexpected input".to_owned()),
+                });
+            }
+        };
+
+        self.elements.reserve(scanned.len());
+        for token in scanned {
+            match token {
+                Scanned::Text(text) => self.push_text(offset + text.start..offset + text.end),
+                Scanned::Stray(stray) => {
+                    self.warnings.push(Warning {
+                        pos: offset + stray.start,
+                        message: format!(
+                            "unmatched `{}` treated as text",
+                            &s[offset + stray.start..offset + stray.end]
+                        ),
+                    });
+                    self.push_text(offset + stray.start..offset + stray.end);
+                }
+                Scanned::Wrapped(wrapper, all, content) => self.push_item(
+                    wrapper,
+                    offset + all.start..offset + all.end,
+                    offset + content.start..offset + content.end,
+                    options,
+                )?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+enum Scanned {
+    Text(Range<usize>),
+    /// A delimiter lenient parsing takes as text.
+    Stray(Range<usize>),
+    /// A placeholder with its whole range and that of its content.
+    Wrapped(Wrapper, Range<usize>, Range<usize>),
+}
+
+enum Unscanned {
+    /// Something does not match the grammar.
+    Unmatched,
+    /// The work limit of the [`Scanner`](bytes::Scanner) was reached at the
+    /// position.
+    TooComplex(usize),
+}
+
+/// Splits `s` with the [`Scanner`](bytes::Scanner), which accepts the same
+/// inputs as the grammar in linear time, taking unmatched delimiters as
+/// text if `lenient`.
+///
+/// Driving pest costs a state setup per call and bookkeeping per character,
+/// which made up nearly all of the parse time; see `benches/parse.rs`. It
+/// also tries every wrapper to the end of the input at each unmatched
+/// delimiter, so recovering from them takes quadratic time. Pest is only
+/// left to describe errors.
+fn scan(s: &str, lenient: bool) -> Result<Vec<Scanned>, Unscanned> {
+    let input = s.as_bytes();
+    let mut scanner = bytes::Scanner::new(input);
+    // Every placeholder starts with a brace and may be followed by text.
+    let braces = s.matches('{').count();
+    let mut result = Vec::with_capacity(2 * braces + 1);
+    let mut pos = 0;
+    while pos < input.len() {
+        let rest = &input[pos..];
+        let len = match bytes::match_text(rest) {
+            Some(len) => len,
+            // A trailing `\` escaping nothing.
+            None if lenient => {
+                if rest.len() > 1 {
+                    result.push(Scanned::Text(pos..input.len() - 1));
+                }
+                result.push(Scanned::Stray(input.len() - 1..input.len()));
+                break;
+            }
+            None => return Err(Unscanned::Unmatched),
+        };
+        if len > 0 {
+            result.push(Scanned::Text(pos..pos + len));
+            pos += len;
+            continue;
+        }
+        match scanner.match_at(pos) {
+            Some((wrapper, start, end, len)) => {
+                result.push(Scanned::Wrapped(
+                    wrapper,
+                    pos..pos + len,
+                    pos + start..pos + end,
+                ));
+                pos += len;
+            }
+            None if scanner.exhausted() => return Err(Unscanned::TooComplex(pos)),
+            None if lenient => {
+                let len = if rest.starts_with(b"${") { 2 } else { 1 };
+                result.push(Scanned::Stray(pos..pos + len));
+                pos += len;
+            }
+            None => return Err(Unscanned::Unmatched),
+        }
+    }
     Ok(result)
 }
 
-pub fn parse_with<M>(s: &str, mapper: M) -> Result<String, Error<Rule>>
+fn strip_trailing_newline(text: &str) -> Option<&str> {
+    text.strip_suffix("\r\n")
+        .or_else(|| text.strip_suffix('\n'))
+}
+
+/// Strips the spaces and tabs ending the last line of `text`, if there is
+/// nothing else on it.
+fn strip_indent(text: &str) -> Option<&str> {
+    let line_start = text.rfind('\n').map_or(0, |pos| pos + 1);
+    if text[line_start..]
+        .trim_start_matches([' ', '\t'])
+        .is_empty()
+    {
+        Some(&text[..line_start])
+    } else {
+        None
+    }
+}
+
+fn strip_leading_newline(text: &str) -> Option<&str> {
+    text.strip_prefix("\r\n")
+        .or_else(|| text.strip_prefix('\n'))
+}
+
+/// Whether `el` is a tag affected by [`ParseOptions::trim_blocks`] and
+/// [`ParseOptions::lstrip_blocks`].
+fn is_block(el: &Element) -> bool {
+    match el {
+        Element::Wrapped(item) => {
+            matches!(item.wrapper, Wrapper::CurlyPercent | Wrapper::CurlyHash)
+        }
+        Element::Text(_) | Element::Escaped { .. } => false,
+    }
+}
+
+/// Checks that the placeholder content at `s[content]` is an identifier as
+/// required by [`ParseOptions::strict_identifiers`].
+fn check_identifier(s: &str, wrapper: Wrapper, content: Range<usize>) -> Result<(), Error<Rule>> {
+    if let Wrapper::CurlyHash | Wrapper::CurlyPercent = wrapper {
+        return Ok(());
+    }
+
+    let text = &s[content.clone()];
+    let key = text.trim();
+    if key.is_empty() {
+        return Ok(());
+    }
+    let start = content.start + (text.len() - text.trim_start().len());
+    let mut segment_start = true;
+    for (i, c) in key.char_indices() {
+        let message = match c {
+            '.' if segment_start => "empty path segment",
+            '.' => {
+                segment_start = true;
+                continue;
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                segment_start = false;
+                continue;
+            }
+            _ => "invalid character",
+        };
+        return Err(backend::error_at(
+            s,
+            start + i,
+            format!("{} {:?} in placeholder `{}`", message, c, key),
+        ));
+    }
+    if segment_start {
+        return Err(backend::error_at(
+            s,
+            start + key.len() - 1,
+            format!("empty path segment in placeholder `{}`", key),
+        ));
+    }
+    Ok(())
+}
+
+/// Moves an error found in a slice starting at `offset` onto the whole input.
+fn relocate(e: Error<Rule>, s: &str, offset: usize) -> Error<Rule> {
+    if offset == 0 {
+        return e;
+    }
+
+    match e.location {
+        InputLocation::Pos(pos) => {
+            Error::new_from_pos(e.variant, Position::new(s, offset + pos).unwrap())
+        }
+        InputLocation::Span((start, end)) => Error::new_from_span(
+            e.variant,
+            pest::Span::new(s, offset + start, offset + end).unwrap(),
+        ),
+    }
+}
+
+/// Renders parsed elements, substituting items the mapper resolves and
+/// writing the others back in their source form.
+///
+/// The mapper may return any string-like value, so constant or borrowed
+/// substitutions like `&'static str` or `Cow<str>` need no allocation.
+pub fn render<M, V>(elements: &[Element], mapper: M) -> String
 where
-    M: Fn(&Item) -> Option<String>,
+    M: Fn(&Item) -> Option<V>,
+    V: AsRef<str>,
 {
-    let result = parse(s)?
+    render_iter(elements, mapper, estimate_len(elements))
+}
+
+/// Length of `elements` rendered without substitutions, which is where
+/// output buffers start.
+fn estimate_len(elements: &[Element]) -> usize {
+    elements
         .iter()
-        .map(|el: &Element| -> String {
-            match el {
-                Element::Text(t) => t.to_owned().to_owned(),
-                Element::Wrapped(item) => mapper(item).unwrap_or(format!(
-                    "{}{}{}",
-                    item.wrapper.get_prefix(),
-                    item.text,
-                    item.wrapper.get_suffix()
-                )),
-            }
+        .map(|el| match el {
+            Element::Text(t) => t.len(),
+            Element::Wrapped(item) => item.source_len(),
+            Element::Escaped { source, .. } => source.len(),
         })
-        .collect::<Vec<String>>()
-        .join("");
+        .sum()
+}
+
+pub(crate) fn render_iter<'e, I, M, V>(elements: I, mapper: M, capacity: usize) -> String
+where
+    I: IntoIterator,
+    I::Item: Borrow<Element<'e>>,
+    M: Fn(&Item) -> Option<V>,
+    V: AsRef<str>,
+{
+    render_traced_iter(elements, mapper, |_, _, _| (), capacity)
+}
+
+/// Renders like [`render`], calling `trace` for every element with the
+/// value it resolved to, if any, and the byte offset in the output it was
+/// written at.
+///
+/// Text elements are traced with their text as value. This allows audit logs
+/// or debugging overlays without reimplementing the render loop.
+pub fn render_traced<M, V, T>(elements: &[Element], mapper: M, trace: T) -> String
+where
+    M: Fn(&Item) -> Option<V>,
+    V: AsRef<str>,
+    T: FnMut(&Element, Option<&str>, usize),
+{
+    render_traced_iter(elements, mapper, trace, estimate_len(elements))
+}
+
+/// Renders into a buffer reserving `capacity` bytes up front.
+pub(crate) fn render_traced_iter<'e, I, M, V, T>(
+    elements: I,
+    mapper: M,
+    mut trace: T,
+    capacity: usize,
+) -> String
+where
+    I: IntoIterator,
+    I::Item: Borrow<Element<'e>>,
+    M: Fn(&Item) -> Option<V>,
+    V: AsRef<str>,
+    T: FnMut(&Element, Option<&str>, usize),
+{
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!("render").entered();
+    #[cfg(feature = "tracing")]
+    let (mut count, mut unresolved) = (0, 0);
+
+    let mut result = String::with_capacity(capacity);
+    for el in elements {
+        let el = el.borrow();
+        #[cfg(feature = "tracing")]
+        {
+            count += 1;
+        }
+        let offset = result.len();
+        match el {
+            Element::Text(text) | Element::Escaped { text, .. } => {
+                result.push_str(text);
+                trace(el, Some(text), offset);
+            }
+            Element::Wrapped(item) => match mapper(item) {
+                Some(value) => {
+                    result.push_str(value.as_ref());
+                    trace(el, Some(value.as_ref()), offset);
+                }
+                None => {
+                    #[cfg(feature = "tracing")]
+                    {
+                        unresolved += 1;
+                        tracing::debug!(placeholder = %item, offset, "unresolved placeholder");
+                    }
+                    item.push_source(&mut result);
+                    trace(el, None, offset);
+                }
+            },
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    tracing::debug!(
+        elements = count,
+        unresolved,
+        bytes = result.len(),
+        "rendered template"
+    );
+    result
+}
+
+pub fn parse_with<M, V>(s: &str, mapper: M) -> Result<String, Error<Rule>>
+where
+    M: Fn(&Item) -> Option<V>,
+    V: AsRef<str>,
+{
+    // Rendering straight from the spanned elements saves collecting them
+    // again, and the source length is the output length without
+    // substitutions.
+    let elements = parse_spanned(s, &ParseOptions::default())?;
+    Ok(render_iter(
+        elements.iter().map(|(el, _)| el),
+        mapper,
+        s.len(),
+    ))
+}
+
+/// Writes `elements` back in their source form.
+///
+/// This undoes parsing exactly: `unparse(&parse(s)?) == s` for every input
+/// `s` that parses, which the `testing` module checks on generated inputs. The
+/// same holds with any [`ParseOptions`] except those rewriting the input,
+/// namely [`trim_blocks`](ParseOptions::trim_blocks),
+/// [`lstrip_blocks`](ParseOptions::lstrip_blocks) and disabling
+/// [`keep_trailing_newline`](ParseOptions::keep_trailing_newline).
+pub fn unparse(elements: &[Element]) -> String {
+    let mut result = String::with_capacity(estimate_len(elements));
+    for el in elements {
+        match el {
+            Element::Text(t) | Element::Escaped { source: t, .. } => result.push_str(t),
+            Element::Wrapped(item) => item.push_source(&mut result),
+        }
+    }
+    result
+}
+
+/// Iterates over the items among `elements`, skipping text.
+pub fn wrapped<'r, 'e>(elements: &'r [Element<'e>]) -> impl Iterator<Item = &'r Item<'e>> {
+    elements.iter().filter_map(|el| match el {
+        Element::Wrapped(item) => Some(item),
+        Element::Text(_) | Element::Escaped { .. } => None,
+    })
+}
+
+/// Consuming variant of [`wrapped`].
+pub fn into_wrapped<'e, I>(elements: I) -> impl Iterator<Item = Item<'e>>
+where
+    I: IntoIterator<Item = Element<'e>>,
+{
+    elements.into_iter().filter_map(|el| match el {
+        Element::Wrapped(item) => Some(item),
+        Element::Text(_) | Element::Escaped { .. } => None,
+    })
+}
+
+/// Escapes `s` with backslashes so that it parses as a single text element.
+pub fn escape(s: &str) -> Cow<'_, str> {
+    if !s.contains(['{', '\\']) {
+        return Cow::Borrowed(s);
+    }
+
+    let mut result = String::with_capacity(s.len() + 1);
+    for c in s.chars() {
+        if c == '{' || c == '\\' {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    Cow::Owned(result)
+}
+
+/// Substitutes the placeholders the mapper resolves and keeps the others,
+/// producing a template that can be rendered again later.
+///
+/// Substituted values are [escaped](escape) so they cannot introduce
+/// placeholders or break the residual template. Like any escape, the inserted
+/// backslashes are kept verbatim by the final [`render`].
+pub fn render_partial<M, V>(s: &str, mapper: M) -> Result<String, Error<Rule>>
+where
+    M: Fn(&Item) -> Option<V>,
+    V: AsRef<str>,
+{
+    Ok(render(&parse(s)?, |item| {
+        mapper(item).map(|value| escape(value.as_ref()).into_owned())
+    }))
+}
+
+/// Where an item sits in the parsed input, as passed to the mapper of
+/// [`parse_with_ctx`].
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Location {
+    /// Index of the element among all parsed elements, text included.
+    pub index: usize,
+    pub span: Span,
+}
+
+/// Like [`parse_with`], but the mapper also receives the [`Location`] of
+/// each item.
+pub fn parse_with_ctx<M, V>(s: &str, mapper: M) -> Result<String, Error<Rule>>
+where
+    M: Fn(&Item, &Location) -> Option<V>,
+    V: AsRef<str>,
+{
+    let mut result = String::new();
+    for (index, (el, span)) in parse_spanned(s, &ParseOptions::default())?
+        .iter()
+        .enumerate()
+    {
+        match el {
+            Element::Text(t) | Element::Escaped { text: t, .. } => result.push_str(t),
+            Element::Wrapped(item) => match mapper(item, &Location { index, span: *span }) {
+                Some(value) => result.push_str(value.as_ref()),
+                None => item.push_source(&mut result),
+            },
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scan_like_grammar() {
+        for s in &[
+            "Hello, {name}!",
+            "{{{a}}} ${b} {{ c }} {## #} {% d %} {}",
+            "{#} {%} $ a } \\{x} \\${y} \\\r\n ü{ü} \\ü",
+            "${lookup({a = {b = 1}}, \"a\")} ${}}",
+            "{# a {# b {##} #} c #}{#}#} x {##}",
+            "{# a} {%#%} {{a}}} }",
+        ] {
+            let pairs = IdentParser::parse(Rule::expression, s).unwrap();
+            let grammar: Vec<_> = pairs
+                .take_while(|pair| pair.as_rule() != Rule::EOI)
+                .map(|pair| {
+                    let all = pair.as_span().start()..pair.as_span().end();
+                    let content = pair.into_inner().next().unwrap().as_span();
+                    (all, content.start()..content.end())
+                })
+                .collect();
+            let scanned: Vec<_> = scan(s, false)
+                .unwrap_or_else(|_| panic!("{}", s))
+                .into_iter()
+                .map(|token| match token {
+                    Scanned::Text(text) => (text.clone(), text),
+                    Scanned::Wrapped(_, all, content) => (all, content),
+                    Scanned::Stray(_) => unreachable!(),
+                })
+                .collect();
+            assert_eq!(scanned, grammar, "{}", s);
+        }
+
+        for s in &["{{a}", "a {", "${a", "a\\", "{# {a} #}"] {
+            assert!(matches!(scan(s, false), Err(Unscanned::Unmatched)), "{}", s);
+            assert!(IdentParser::parse(Rule::expression, s).is_err(), "{}", s);
+        }
+    }
 
-    Ok(result)
-}
+    #[test]
+    fn parse_adversarial_in_linear_time() {
+        let lenient = ParseOptions {
+            lenient: true,
+            ..Default::default()
+        };
+        let n = 5_000;
+        for s in &[
+            "{".repeat(n),
+            "${".repeat(n),
+            "{#".repeat(n),
+            "${a".repeat(n),
+            "{{{a}}".repeat(n),
+            "{%{#{{${".repeat(n),
+            format!("${}", "{".repeat(n)),
+            format!("{}{}", "{#".repeat(n), "#}".repeat(n - 1)),
+            format!("${}{}", "{".repeat(n), "}".repeat(n)),
+        ] {
+            // Running out of the linear work limit would be an error.
+            let (elements, _) = parse_with_warnings(s, &lenient).unwrap();
+            assert_eq!(render(&elements, |_| None::<&str>), *s);
+            assert!(parse(s).is_err() || s.starts_with("${{"));
+        }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        let (elements, warnings) = parse_with_warnings("${ {#{#a#} {x} {{y}", &lenient).unwrap();
+        assert_eq!(
+            elements,
+            vec![
+                Element::Text("${ {#"),
+                Element::Wrapped(Item::new(Wrapper::CurlyHash, "a")),
+                Element::Text(" "),
+                Element::Wrapped(Item::new(Wrapper::Curly, "x")),
+                Element::Text(" {"),
+                Element::Wrapped(Item::new(Wrapper::Curly, "y")),
+            ]
+        );
+        assert_eq!(
+            warnings.iter().map(|w| w.pos).collect::<Vec<_>>(),
+            vec![0, 3, 15]
+        );
+    }
 
     #[test]
     fn parse_curly() {
@@ -220,11 +1355,11 @@ mod tests {
             "{{greeting}}, {name}! by {hidden}",
             |item: &Item| -> Option<String> {
                 match item.wrapper {
-                    Wrapper::Curly => match item.text.as_ref() {
+                    Wrapper::Curly => match item.text {
                         "name" => Some("world".to_owned()),
                         _ => None,
                     },
-                    Wrapper::DoubleCurly => match item.text.as_ref() {
+                    Wrapper::DoubleCurly => match item.text {
                         "greeting" => Some("Hello".to_owned()),
                         _ => None,
                     },
@@ -234,4 +1369,757 @@ mod tests {
         );
         assert_eq!(parsed.unwrap(), "Hello, world! by {hidden}");
     }
+
+    #[test]
+    fn parse_trimmed() {
+        let options = ParseOptions {
+            trim: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_with_options("{{ user.name }} {{user.name}} { \n}", &options).unwrap(),
+            vec![
+                Element::Wrapped(Item::new(Wrapper::DoubleCurly, "user.name")),
+                Element::Text(" "),
+                Element::Wrapped(Item::new(Wrapper::DoubleCurly, "user.name")),
+                Element::Text(" "),
+                Element::Wrapped(Item::new(Wrapper::Curly, "")),
+            ]
+        );
+
+        let elements = parse_with_options("Hello, {{ name }}!", &options).unwrap();
+        assert_eq!(
+            render(&elements, |item| match item.text {
+                "name" => Some("world".to_owned()),
+                _ => None,
+            }),
+            "Hello, world!"
+        );
+    }
+
+    #[test]
+    fn parse_quoted() {
+        let options = ParseOptions {
+            quotes: QuoteRule::c_style(),
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_with_options(r#"f("{a}", '{b}', "\"{c}") + {d}"#, &options).unwrap(),
+            vec![
+                Element::Text(r#"f("{a}", '{b}', "\"{c}") + "#),
+                Element::Wrapped(Item::new(Wrapper::Curly, "d")),
+            ]
+        );
+
+        assert_eq!(
+            parse_with_options(r#"{% if a == "x" %}"{b}"#, &options).unwrap(),
+            vec![
+                Element::Wrapped(Item::new(Wrapper::CurlyPercent, r#" if a == "x" "#)),
+                Element::Text("\""),
+                Element::Wrapped(Item::new(Wrapper::Curly, "b")),
+            ]
+        );
+
+        let error = parse_with_options("'{' {{todo..", &options).unwrap_err();
+        assert_eq!(error.location, InputLocation::Pos(4));
+    }
+
+    #[test]
+    fn parse_markdown() {
+        let options = ParseOptions {
+            markdown: true,
+            ..Default::default()
+        };
+        let s = "# {title}\n\nUse `{name}` like:\n\n```rust\nformat!(\"{x}\");\n```\n{end}";
+        assert_eq!(
+            parse_with_options(s, &options).unwrap(),
+            vec![
+                Element::Text("# "),
+                Element::Wrapped(Item::new(Wrapper::Curly, "title")),
+                Element::Text("\n\nUse `{name}` like:\n\n```rust\nformat!(\"{x}\");\n```\n"),
+                Element::Wrapped(Item::new(Wrapper::Curly, "end")),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_region_markers() {
+        let s = "{a}\n<!-- spongy:off -->\n{b} ${c}\n<!-- spongy:on -->\n{d}";
+        let elements = parse(s).unwrap();
+        assert_eq!(
+            elements,
+            vec![
+                Element::Wrapped(Item::new(Wrapper::Curly, "a")),
+                Element::Text("\n<!-- spongy:off -->\n{b} ${c}\n<!-- spongy:on -->\n"),
+                Element::Wrapped(Item::new(Wrapper::Curly, "d")),
+            ]
+        );
+        assert_eq!(
+            elements.iter().map(ToString::to_string).collect::<String>(),
+            s
+        );
+
+        assert_eq!(
+            parse("{# spongy:off #}{a}{# spongy:on #}").unwrap(),
+            vec![
+                Element::Wrapped(Item::new(Wrapper::CurlyHash, " spongy:off ")),
+                Element::Text("{a}"),
+                Element::Wrapped(Item::new(Wrapper::CurlyHash, " spongy:on ")),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_literal_blocks() {
+        let options = ParseOptions {
+            literal_blocks: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_with_options(r#"{"a": {"b": "{c}"}, "d": {}}"#, &options).unwrap(),
+            vec![
+                Element::Text(r#"{"a": {"b": ""#),
+                Element::Wrapped(Item::new(Wrapper::Curly, "c")),
+                Element::Text(r#""}, "d": {}}"#),
+            ]
+        );
+
+        assert_eq!(
+            parse_with_options("function f() {\n  return {{x}};\n}", &options).unwrap(),
+            vec![
+                Element::Text("function f() {\n  return "),
+                Element::Wrapped(Item::new(Wrapper::DoubleCurly, "x")),
+                Element::Text(";\n}"),
+            ]
+        );
+
+        assert!(parse(r#"{"a": {"b": 1}}"#).is_err());
+    }
+
+    #[test]
+    fn parse_dollar_escape() {
+        let options = ParseOptions {
+            dollar_escape: true,
+            ..Default::default()
+        };
+        let s = "echo $${HOME} ${HOME} $$$${HOME}";
+        let elements = parse_with_options(s, &options).unwrap();
+        assert_eq!(
+            elements,
+            vec![
+                Element::Text("echo "),
+                Element::Escaped {
+                    text: "${HOME}",
+                    source: "$${HOME}"
+                },
+                Element::Text(" "),
+                Element::Wrapped(Item::new(Wrapper::DollarCurly, "HOME")),
+                Element::Text(" $$"),
+                Element::Escaped {
+                    text: "${HOME}",
+                    source: "$${HOME}"
+                },
+            ]
+        );
+        assert_eq!(render(&elements, |_| Some("~")), "echo ${HOME} ~ $$${HOME}");
+        assert_eq!(unparse(&elements), s);
+        assert_eq!(
+            parse_with_options("$${", &options),
+            parse_with_options("$${", &ParseOptions::default())
+        );
+    }
+
+    #[test]
+    fn parse_custom_escape() {
+        let options = ParseOptions {
+            escape: '^',
+            ..Default::default()
+        };
+        let s = r"C:\{dir}\^{x} ^^{y}^";
+        let elements = parse_with_options(s, &options).unwrap();
+        assert_eq!(
+            elements,
+            vec![
+                Element::Text("C:\\"),
+                Element::Wrapped(Item::new(Wrapper::Curly, "dir")),
+                Element::Text(r"\^{x} ^^"),
+                Element::Wrapped(Item::new(Wrapper::Curly, "y")),
+                Element::Text("^"),
+            ]
+        );
+        assert_eq!(render(&elements, |_| Some("v")), r"C:\v\^{x} ^^v^");
+    }
+
+    #[test]
+    fn parse_verbatim() {
+        let options = ParseOptions {
+            verbatim: vec![
+                Verbatim::Wrapper(Wrapper::TripleCurly),
+                Verbatim::Delimiters("{=".to_owned(), "=}".to_owned()),
+            ],
+            ..Default::default()
+        };
+        let s = "{{{ raw }}} {= {{x}} =}{{x}}";
+        let elements = parse_with_options(s, &options).unwrap();
+        assert_eq!(
+            elements,
+            vec![
+                Element::Escaped {
+                    text: " raw ",
+                    source: "{{{ raw }}}"
+                },
+                Element::Text(" "),
+                Element::Escaped {
+                    text: " {{x}} ",
+                    source: "{= {{x}} =}"
+                },
+                Element::Wrapped(Item::new(Wrapper::DoubleCurly, "x")),
+            ]
+        );
+        assert_eq!(render(&elements, |_| Some("v")), " raw   {{x}} v");
+
+        // Rewriting a template keeps verbatim content out of reach of the
+        // mapper when it is parsed again.
+        let mut rewritten = elements.clone();
+        rewritten[3] = Element::Wrapped(Item::new(Wrapper::DoubleCurly, "y"));
+        let rewritten = unparse(&rewritten);
+        assert_eq!(rewritten, "{{{ raw }}} {= {{x}} =}{{y}}");
+        let reparsed = parse_with_options(&rewritten, &options).unwrap();
+        assert_eq!(reparsed[..3], elements[..3]);
+        assert_eq!(render(&reparsed, |_| Some("v")), " raw   {{x}} v");
+        testing::check_round_trip(s, &options).unwrap();
+    }
+
+    #[test]
+    fn parse_multi_line() {
+        let content = "\n  if user.admin\r\n    and user.active\n";
+        for &wrapper in Wrapper::ALL.iter() {
+            let source = format!(
+                "a {}{}{} b",
+                wrapper.get_prefix(),
+                content,
+                wrapper.get_suffix()
+            );
+            let options = ParseOptions {
+                dollar_paren: wrapper == Wrapper::DollarParen,
+                trim: true,
+                ..Default::default()
+            };
+            let elements = parse_with_options(&source, &options).unwrap();
+            assert_eq!(
+                elements,
+                vec![
+                    Element::Text("a "),
+                    Element::Wrapped(Item::new(wrapper, content.trim())),
+                    Element::Text(" b"),
+                ],
+                "{:?}",
+                wrapper
+            );
+            assert_eq!(render(&elements, |_| None::<&str>), source);
+
+            if wrapper != Wrapper::DollarParen {
+                assert_eq!(
+                    bytes::parse_bytes(source.as_bytes()).unwrap().len(),
+                    3,
+                    "{:?}",
+                    wrapper
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn unparse_generated() {
+        let mut generator = testing::Generator::new(7);
+        let lenient = ParseOptions {
+            lenient: true,
+            ..Default::default()
+        };
+        let trim = ParseOptions {
+            trim: true,
+            markdown: true,
+            ..Default::default()
+        };
+        let dialects = ParseOptions {
+            dollar_escape: true,
+            dollar_paren: true,
+            verbatim: vec![
+                Verbatim::Wrapper(Wrapper::TripleCurly),
+                Verbatim::Delimiters("{=".to_owned(), "=}".to_owned()),
+            ],
+            ..Default::default()
+        };
+        for s in &["a $${b} c", "a $$(B) c", "a {= {x} =} c"] {
+            testing::check_round_trip(s, &dialects).unwrap();
+        }
+        let mut accepted = 0;
+        for _ in 0..2000 {
+            let s = generator.template(12);
+            testing::check_round_trip(&s, &lenient).unwrap();
+            if parse(&s).is_ok() {
+                accepted += 1;
+            }
+            testing::check_round_trip(&s, &ParseOptions::default()).unwrap();
+            testing::check_round_trip(&s, &dialects).unwrap();
+            let s = generator.well_formed(12);
+            assert!(parse(&s).is_ok(), "{:?}", s);
+            testing::check_round_trip(&s, &ParseOptions::default()).unwrap();
+            testing::check_round_trip(&s, &trim).unwrap();
+            testing::check_round_trip(&s, &dialects).unwrap();
+        }
+        assert!(accepted > 100, "{}", accepted);
+    }
+
+    #[test]
+    fn round_trip_crlf() {
+        let s = "a {{ x }}\r\n{#\r\n  note\r\n#}\r\n{%\r\nif y\r\n%}\\\r\n${z}\r\n";
+        let elements = parse(s).unwrap();
+        let displayed: String = elements.iter().map(|el| el.to_string()).collect();
+        assert_eq!(displayed, s);
+        assert_eq!(render(&elements, |_| None::<&str>), s);
+        assert_eq!(
+            wrapped(&elements).map(|item| item.text).collect::<Vec<_>>(),
+            vec![" x ", "\r\n  note\r\n", "\r\nif y\r\n", "z"]
+        );
+        assert_eq!(
+            bytes::render_bytes(&bytes::parse_bytes(s.as_bytes()).unwrap(), |_| None::<&str>),
+            s.as_bytes()
+        );
+    }
+
+    #[test]
+    fn parse_strict_identifiers() {
+        let options = ParseOptions {
+            strict_identifiers: true,
+            ..Default::default()
+        };
+        assert!(parse_with_options(
+            "{{ user.name }} ${HOME_2} {} {# any thing #} {% if a %}",
+            &options
+        )
+        .is_ok());
+
+        let error = |s| match parse_with_options(s, &options).unwrap_err().line_col {
+            pest::error::LineColLocation::Pos((_, col)) => col,
+            _ => unreachable!(),
+        };
+        assert_eq!(error("Hi {user name}"), 9);
+        assert_eq!(error("{{ a..b }}"), 6);
+        assert_eq!(error("{{ .a }}"), 4);
+        assert_eq!(error("{a.}"), 3);
+        assert_eq!(error("{{ name | upper }}"), 8);
+        assert!(parse_with_options(
+            "$(a b)",
+            &ParseOptions {
+                dollar_paren: true,
+                ..options.clone()
+            }
+        )
+        .is_err());
+        assert!(parse("{user name}").is_ok());
+    }
+
+    #[test]
+    fn parse_trim_blocks() {
+        let options = ParseOptions {
+            trim_blocks: true,
+            ..ParseOptions::default()
+        };
+        let s = "{% if a %}\nx: {{ x }}\n{# c #}\r\n\n{% endif %}\n";
+        let spanned = parse_spanned(s, &options).unwrap();
+        assert_eq!(
+            spanned.iter().map(|(el, _)| el.clone()).collect::<Vec<_>>(),
+            vec![
+                Element::Wrapped(Item::new(Wrapper::CurlyPercent, " if a ")),
+                Element::Text("x: "),
+                Element::Wrapped(Item::new(Wrapper::DoubleCurly, " x ")),
+                Element::Text("\n"),
+                Element::Wrapped(Item::new(Wrapper::CurlyHash, " c ")),
+                Element::Text("\n"),
+                Element::Wrapped(Item::new(Wrapper::CurlyPercent, " endif ")),
+            ]
+        );
+        assert_eq!(spanned[1].1, Span::new(10, 14));
+        assert_eq!(spanned.last().unwrap().1, Span::new(s.len() - 12, s.len()));
+        assert_eq!(parse_with_options("{{ x }}\n", &options).unwrap().len(), 2);
+
+        let options = ParseOptions {
+            keep_trailing_newline: false,
+            ..ParseOptions::default()
+        };
+        assert_eq!(
+            parse_with_options("a\n\n", &options).unwrap(),
+            vec![Element::Text("a\n")]
+        );
+        assert_eq!(
+            parse_spanned("{a}\r\n", &options).unwrap(),
+            vec![(
+                Element::Wrapped(Item::new(Wrapper::Curly, "a")),
+                Span::new(0, 5)
+            )]
+        );
+        assert_eq!(
+            parse_with_options("{a}\n", &ParseOptions::default())
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn parse_lstrip_blocks() {
+        let options = ParseOptions {
+            lstrip_blocks: true,
+            ..ParseOptions::default()
+        };
+        let parse = |s| parse_with_options(s, &options).unwrap();
+        assert_eq!(
+            parse("  {% if %}\n\t{# c #} \r\n a {% x %}\n  {% y %} {{b}}\n  {% endif %}"),
+            vec![
+                Element::Wrapped(Item::new(Wrapper::CurlyPercent, " if ")),
+                Element::Text("\n"),
+                Element::Wrapped(Item::new(Wrapper::CurlyHash, " c ")),
+                Element::Text(" \r\n a "),
+                Element::Wrapped(Item::new(Wrapper::CurlyPercent, " x ")),
+                Element::Text("\n  "),
+                Element::Wrapped(Item::new(Wrapper::CurlyPercent, " y ")),
+                Element::Text(" "),
+                Element::Wrapped(Item::new(Wrapper::DoubleCurly, "b")),
+                Element::Text("\n"),
+                Element::Wrapped(Item::new(Wrapper::CurlyPercent, " endif ")),
+            ]
+        );
+        assert_eq!(parse("  {{ x }}\n").len(), 3);
+
+        let options = ParseOptions {
+            trim_blocks: true,
+            ..options
+        };
+        let s = "items:\n  {% for x %}\n  - {{ x }}\n  {% endfor %}\n";
+        let elements = parse_with_options(s, &options).unwrap();
+        assert_eq!(
+            render(&elements, |item| match item.wrapper {
+                Wrapper::DoubleCurly => Some("a"),
+                _ => Some(""),
+            }),
+            "items:\n  - a\n"
+        );
+    }
+
+    #[test]
+    fn reserve_rendered_len() {
+        let s = "a {b} ${c}";
+        let elements = parse(s).unwrap();
+        assert_eq!(estimate_len(&elements), s.len());
+        let rendered = render(&elements, |item| Some(item.key().to_owned()));
+        assert_eq!(rendered, "a b c");
+        assert!(rendered.capacity() >= s.len());
+        assert_eq!(
+            estimate_len(&[Element::Wrapped(Item::new(Wrapper::DoubleCurly, "x"))]),
+            5
+        );
+    }
+
+    #[test]
+    fn render_with_trace() {
+        let elements = parse("a {b} {c}").unwrap();
+        let mut events = vec![];
+        let result = render_traced(
+            &elements,
+            |item| match item.text {
+                "b" => Some("B"),
+                _ => None,
+            },
+            |el, value, offset| events.push((el.to_string(), value.map(str::to_owned), offset)),
+        );
+        assert_eq!(result, "a B {c}");
+        assert_eq!(
+            events,
+            vec![
+                ("a ".to_owned(), Some("a ".to_owned()), 0),
+                ("{b}".to_owned(), Some("B".to_owned()), 2),
+                (" ".to_owned(), Some(" ".to_owned()), 3),
+                ("{c}".to_owned(), None, 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_empty_placeholders() {
+        let s = "{} {{ }} ${} {##} {x}";
+        let options = ParseOptions {
+            empty_placeholders: EmptyPlaceholders::Warn,
+            ..ParseOptions::default()
+        };
+        let (elements, warnings) = parse_with_warnings(s, &options).unwrap();
+        assert_eq!(elements, parse(s).unwrap());
+        assert_eq!(
+            warnings,
+            vec![
+                Warning {
+                    pos: 0,
+                    message: "empty placeholder `{}`".to_owned()
+                },
+                Warning {
+                    pos: 3,
+                    message: "empty placeholder `{{ }}`".to_owned()
+                },
+                Warning {
+                    pos: 9,
+                    message: "empty placeholder `${}`".to_owned()
+                },
+            ]
+        );
+
+        let options = ParseOptions {
+            empty_placeholders: EmptyPlaceholders::Reject,
+            ..ParseOptions::default()
+        };
+        let e = parse_with_options("a\n {{ }}", &options).unwrap_err();
+        assert_eq!(e.line_col, pest::error::LineColLocation::Pos((2, 2)));
+        assert!(parse_with_options("{##} {x}", &options).is_ok());
+    }
+
+    #[test]
+    fn parse_nested_comments() {
+        assert_eq!(
+            parse("a{# off {# note #} {##} #}b").unwrap(),
+            vec![
+                Element::Text("a"),
+                Element::Wrapped(Item::new(Wrapper::CurlyHash, " off {# note #} {##} ")),
+                Element::Text("b"),
+            ]
+        );
+        assert!(parse("{# {# #}").is_err());
+        assert_eq!(
+            parse("{# a # b #}").unwrap(),
+            vec![Element::Wrapped(Item::new(Wrapper::Curly, "# a # b #")),]
+        );
+    }
+
+    #[test]
+    fn parse_nested_dollar_curly() {
+        assert_eq!(
+            parse(r#"x = ${lookup({a = 1, b = {c = 2}}, "a")}{y}"#).unwrap(),
+            vec![
+                Element::Text("x = "),
+                Element::Wrapped(Item::new(
+                    Wrapper::DollarCurly,
+                    r#"lookup({a = 1, b = {c = 2}}, "a")"#
+                )),
+                Element::Wrapped(Item::new(Wrapper::Curly, "y")),
+            ]
+        );
+        assert!(parse("${a{b}").is_err());
+    }
+
+    #[test]
+    fn parse_lenient() {
+        let options = ParseOptions {
+            lenient: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_with_options("broken {%", &options).unwrap(),
+            vec![Element::Text("broken {%")]
+        );
+
+        let (elements, warnings) = parse_with_warnings("${ {{todo.. {a} \\", &options).unwrap();
+        assert_eq!(
+            elements,
+            vec![
+                Element::Text("${ {{todo.. "),
+                Element::Wrapped(Item::new(Wrapper::Curly, "a")),
+                Element::Text(" \\"),
+            ]
+        );
+        assert_eq!(
+            warnings.iter().map(|w| w.pos).collect::<Vec<usize>>(),
+            vec![0, 3, 4, 16]
+        );
+        assert_eq!(warnings[0].message, "unmatched `${` treated as text");
+    }
+
+    #[test]
+    fn parse_dollar_paren() {
+        let options = ParseOptions {
+            dollar_paren: true,
+            ..Default::default()
+        };
+        let s = r#"cmd: $( SHELL ) -c "$$(HOME)/${x}" $(a b $("#;
+        let elements = parse_with_options(s, &options).unwrap();
+        assert_eq!(
+            elements,
+            vec![
+                Element::Text("cmd: "),
+                Element::Wrapped(Item::new(Wrapper::DollarParen, " SHELL ")),
+                Element::Text(" -c \""),
+                Element::Escaped {
+                    text: "$",
+                    source: "$$"
+                },
+                Element::Text("(HOME)/"),
+                Element::Wrapped(Item::new(Wrapper::DollarCurly, "x")),
+                Element::Text("\" $(a b $("),
+            ]
+        );
+        assert_eq!(
+            render(&elements, |item| match item.key() {
+                "SHELL" => Some("/bin/sh"),
+                _ => None,
+            }),
+            r#"cmd: /bin/sh -c "$(HOME)/${x}" $(a b $("#
+        );
+        assert_eq!(unparse(&elements), s);
+
+        let spans: Vec<Span> = parse_spanned(s, &options)
+            .unwrap()
+            .into_iter()
+            .map(|(_, span)| span)
+            .collect();
+        assert_eq!(spans[1], Span::new(5, 15));
+        assert_eq!(spans[3], Span::new(20, 22));
+        assert_eq!(spans.last().unwrap().end, s.len());
+
+        assert_eq!(parse("$(A)").unwrap(), vec![Element::Text("$(A)")]);
+    }
+
+    #[test]
+    fn parse_with_spans() {
+        let options = ParseOptions {
+            quotes: QuoteRule::c_style(),
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_spanned("a'{b}'{{c}}", &options).unwrap(),
+            vec![
+                (Element::Text("a'{b}'"), Span::new(0, 6)),
+                (
+                    Element::Wrapped(Item::new(Wrapper::DoubleCurly, "c")),
+                    Span::new(6, 11)
+                ),
+            ]
+        );
+
+        let formatted = parse_with_ctx("{a}, {a} and {b}", |item, location| {
+            Some(format!(
+                "{}#{}@{}..{}",
+                item.text, location.index, location.span.start, location.span.end
+            ))
+        });
+        assert_eq!(formatted.unwrap(), "a#0@0..3, a#2@5..8 and b#4@13..16");
+    }
+
+    #[test]
+    fn format_with_borrowed() {
+        let name = String::from("world");
+        let formatted = parse_with("{greeting}, {name}!", |item| match item.text {
+            "greeting" => Some(Cow::Borrowed("Hello")),
+            "name" => Some(Cow::Borrowed(name.as_str())),
+            _ => None,
+        });
+        assert_eq!(formatted.unwrap(), "Hello, world!");
+
+        let formatted = parse_with("{a}{b}", |item| match item.text {
+            "a" => Some("static"),
+            _ => None,
+        });
+        assert_eq!(formatted.unwrap(), "static{b}");
+    }
+
+    #[test]
+    fn format_partial() {
+        let residual = render_partial("${env} {{request}} {{ spaced }}", |item| match item.text {
+            "env" => Some("prod{x}\\"),
+            _ => None,
+        })
+        .unwrap();
+        assert_eq!(residual, "prod\\{x}\\\\ {{request}} {{ spaced }}");
+
+        let formatted = parse_with(&residual, |item| match item.text {
+            "request" => Some("42"),
+            "x" => Some("oops"),
+            _ => None,
+        });
+        assert_eq!(formatted.unwrap(), "prod\\{x}\\\\ 42 {{ spaced }}");
+        assert_eq!(escape("plain"), Cow::Borrowed("plain"));
+    }
+
+    #[test]
+    fn format_unresolved_from_source() {
+        let options = ParseOptions {
+            trim: true,
+            ..Default::default()
+        };
+        let elements = parse_with_options("{{  a }} {b}", &options).unwrap();
+        assert_eq!(
+            elements[0],
+            Element::Wrapped(Item::new(Wrapper::DoubleCurly, "a"))
+        );
+        assert_eq!(render(&elements, |_| None::<String>), "{{  a }} {b}");
+    }
+
+    #[test]
+    fn item_raw_and_key() {
+        let elements = parse("{{ user.name }}").unwrap();
+        let item = match &elements[0] {
+            Element::Wrapped(item) => item,
+            _ => unreachable!(),
+        };
+        assert_eq!(item.raw(), Cow::Borrowed("{{ user.name }}"));
+        assert_eq!(item.key(), "user.name");
+
+        let item = Item::new(Wrapper::CurlyPercent, " if x ");
+        assert_eq!(item.raw(), "{% if x %}");
+        assert_eq!(item.key(), "if x");
+    }
+
+    #[test]
+    fn display_source_form() {
+        assert_eq!(Wrapper::CurlyPercent.to_string(), "{% %}");
+        assert_eq!(Wrapper::DollarCurly.to_string(), "${ }");
+
+        let s = "Hi {{ name }}, {#}{% x %}\\{";
+        let displayed: String = parse(s).unwrap().iter().map(|el| el.to_string()).collect();
+        assert_eq!(displayed, s);
+        assert_eq!(Item::new(Wrapper::Curly, "a").to_string(), "{a}");
+    }
+
+    #[test]
+    fn wrapper_from_str() {
+        assert_eq!(
+            Wrapper::from_delimiters("{{", "}}"),
+            Some(Wrapper::DoubleCurly)
+        );
+        assert_eq!(Wrapper::from_delimiters("{{", "}"), None);
+
+        for wrapper in Wrapper::ALL.iter() {
+            assert_eq!(wrapper.name().parse(), Ok(*wrapper));
+            assert_eq!(wrapper.to_string().parse(), Ok(*wrapper));
+        }
+        assert_eq!("DollarCurly".parse(), Ok(Wrapper::DollarCurly));
+        assert_eq!("curly-percent".parse(), Ok(Wrapper::CurlyPercent));
+        assert_eq!("{##}".parse(), Ok(Wrapper::CurlyHash));
+        assert_eq!(
+            "angle".parse::<Wrapper>(),
+            Err(ParseWrapperError("angle".to_owned()))
+        );
+    }
+
+    #[test]
+    fn iterate_wrapped() {
+        let elements = parse("a {b} c {{d}}").unwrap();
+        let texts: Vec<&str> = wrapped(&elements).map(|item| item.text).collect();
+        assert_eq!(texts, vec!["b", "d"]);
+
+        let items: Vec<Item> = into_wrapped(elements).collect();
+        assert_eq!(
+            items,
+            vec![
+                Item::new(Wrapper::Curly, "b"),
+                Item::new(Wrapper::DoubleCurly, "d")
+            ]
+        );
+    }
 }
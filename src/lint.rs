@@ -0,0 +1,204 @@
+//! Checks of templates against the contexts they are rendered with.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use pest::error::Error;
+
+use crate::{filters, parse, wrapped, Element, Rule, Wrapper};
+
+#[derive(Debug)]
+pub enum LintError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        error: Box<Error<Rule>>,
+    },
+}
+
+impl fmt::Display for LintError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LintError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            LintError::Parse { path, error } => write!(f, "{}: {}", path.display(), error),
+        }
+    }
+}
+
+impl std::error::Error for LintError {}
+
+/// The distinct variables referenced by `elements`, in order of first
+/// appearance.
+///
+/// Variables are the keys of placeholders which are neither `{# #}` comments
+/// nor `{% %}` statements, without any filter pipeline.
+pub fn variables<'e>(elements: &[Element<'e>]) -> Vec<&'e str> {
+    let mut result: Vec<&str> = vec![];
+    for item in wrapped(elements) {
+        if let Wrapper::CurlyHash | Wrapper::CurlyPercent = item.wrapper {
+            continue;
+        }
+        let key = filters::pipeline(item.text).0;
+        if !result.contains(&key) {
+            result.push(key);
+        }
+    }
+    result
+}
+
+/// Whether the context key `key` is used by a reference to `variable`,
+/// either directly or through a path below it like `key.name` or `key[0]`.
+pub(crate) fn references(variable: &str, key: &str) -> bool {
+    match variable.strip_prefix(key) {
+        Some(rest) => rest.is_empty() || rest.starts_with(['.', '[']),
+        None => false,
+    }
+}
+
+/// A context type checked against the templates it is rendered with,
+/// implemented with `#[derive(TemplateContext)]` from the `spongy-derive`
+/// crate, which also warns about the unused fields at compile time.
+pub trait TemplateContext {
+    /// Names of the fields, in order of declaration.
+    const FIELDS: &'static [&'static str];
+    /// Fields which none of the templates uses.
+    const UNUSED_FIELDS: &'static [&'static str];
+}
+
+/// Reports the context keys which the template `s` never references.
+pub fn unused_keys<'k, I>(s: &str, keys: I) -> Result<Vec<&'k str>, Error<Rule>>
+where
+    I: IntoIterator<Item = &'k str>,
+{
+    let elements = parse(s)?;
+    let variables = variables(&elements);
+    Ok(keys
+        .into_iter()
+        .filter(|key| !variables.iter().any(|v| references(v, key)))
+        .collect())
+}
+
+/// Reports the context keys which none of the `templates` references, as
+/// for a context shared by several templates.
+pub fn unused_keys_in_all<'k, 't, T, I>(templates: T, keys: I) -> Result<Vec<&'k str>, Error<Rule>>
+where
+    T: IntoIterator<Item = &'t str>,
+    I: IntoIterator<Item = &'k str>,
+{
+    let mut unused: Vec<&str> = keys.into_iter().collect();
+    for s in templates {
+        let elements = parse(s)?;
+        let variables = variables(&elements);
+        unused.retain(|key| !variables.iter().any(|v| references(v, key)));
+    }
+    Ok(unused)
+}
+
+/// Warnings for the `fields` of the context type `context` which none of
+/// the template files at `paths` uses, for a build script to print as
+/// `cargo:warning=` lines:
+///
+/// ```no_run
+/// // build.rs
+/// let warnings = spongy::lint::unused_field_warnings(
+///     "Greeting",
+///     &["name", "email"],
+///     &["templates/greeting.txt"],
+/// )?;
+/// for warning in warnings {
+///     println!("cargo:warning={}", warning);
+/// }
+/// println!("cargo:rerun-if-changed=templates");
+/// # Ok::<(), spongy::lint::LintError>(())
+/// ```
+///
+/// Here `fields` are written out by hand, and a field missing from the list
+/// is never warned about. For a struct, `#[derive(TemplateContext)]` from
+/// the `spongy-derive` crate reads the fields itself and warns without a
+/// build script; see [`TemplateContext`].
+pub fn unused_field_warnings<P>(
+    context: &str,
+    fields: &[&str],
+    paths: &[P],
+) -> Result<Vec<String>, LintError>
+where
+    P: AsRef<Path>,
+{
+    let mut unused = fields.to_vec();
+    for path in paths {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|error| LintError::Io {
+            path: path.to_owned(),
+            error,
+        })?;
+        let elements = parse(&source).map_err(|error| LintError::Parse {
+            path: path.to_owned(),
+            error: Box::new(error),
+        })?;
+        let variables = variables(&elements);
+        unused.retain(|key| !variables.iter().any(|v| references(v, key)));
+    }
+    Ok(unused
+        .into_iter()
+        .map(|field| {
+            format!(
+                "field `{}` of `{}` is not used by any of its templates",
+                field, context
+            )
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_variables() {
+        let elements = parse("{{ b }} {a|upper} {# c #} {% d %} {{b}}").unwrap();
+        assert_eq!(variables(&elements), vec!["b", "a"]);
+    }
+
+    #[test]
+    fn lint_unused_keys() {
+        let s = "Hi {{ user.name }}, {items[0]} {# stale #}";
+        let keys = vec!["user", "items", "stale", "user.name", "username", "other"];
+        assert_eq!(
+            unused_keys(s, keys.clone()).unwrap(),
+            vec!["stale", "username", "other"]
+        );
+        assert_eq!(
+            unused_keys_in_all(vec![s, "{other}"], keys).unwrap(),
+            vec!["stale", "username"]
+        );
+    }
+
+    #[test]
+    fn warn_unused_fields() {
+        let dir = std::env::temp_dir().join(format!("spongy-lint-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.txt"), "Hi {{ user.name }}").unwrap();
+        fs::write(dir.join("b.txt"), "{id} {# email #}").unwrap();
+        fs::write(dir.join("broken.txt"), "{{").unwrap();
+
+        let paths = [dir.join("a.txt"), dir.join("b.txt")];
+        assert_eq!(
+            unused_field_warnings("Context", &["user", "id", "email"], &paths).unwrap(),
+            vec!["field `email` of `Context` is not used by any of its templates"]
+        );
+        assert!(matches!(
+            unused_field_warnings("Context", &["id"], &[dir.join("broken.txt")]),
+            Err(LintError::Parse { path, .. }) if path.ends_with("broken.txt")
+        ));
+        assert!(matches!(
+            unused_field_warnings("Context", &["id"], &[dir.join("missing.txt")]),
+            Err(LintError::Io { .. })
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
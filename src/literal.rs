@@ -0,0 +1,287 @@
+//! Scanning for parts of the input which are text regardless of the grammar,
+//! as configured in [`ParseOptions`].
+
+use std::ops::Range;
+
+use crate::bytes::Scanner;
+use crate::quote::closing;
+use crate::{ParseOptions, QuoteRule, Wrapper};
+
+/// Finds the closing brace of a block opened just before `s`, skipping
+/// double-quoted strings. Returns the offset of the brace.
+fn block_end(s: &str) -> Option<usize> {
+    let string = QuoteRule::new('"').escape('\\');
+    let mut depth = 0;
+    let mut pos = 0;
+    while let Some(&b) = s.as_bytes().get(pos) {
+        match b {
+            b'\\' => pos += 1,
+            b'"' => pos += closing(&s[pos + 1..], &string)?,
+            b'{' => depth += 1,
+            b'}' if depth == 0 => return Some(pos),
+            b'}' => depth -= 1,
+            _ => {}
+        }
+        pos += 1;
+    }
+    None
+}
+
+fn is_block(content: &str) -> bool {
+    content.trim().is_empty() || content.contains([':', '\n'])
+}
+
+/// Marker starting a region left as text, see
+/// [`ParseOptions::region_markers`].
+pub(crate) const OFF: &str = "spongy:off";
+/// Marker ending a region left as text.
+pub(crate) const ON: &str = "spongy:on";
+
+/// Finds the end of a region opened just before `s`, which is the start of
+/// the next `spongy:on` marker or of the `{# #}` comment holding it.
+fn region_end(s: &str) -> usize {
+    let i = match s.find(ON) {
+        Some(i) => i,
+        None => return s.len(),
+    };
+    let before = s[..i].trim_end();
+    if before.ends_with("{#") && s[i + ON.len()..].trim_start().starts_with("#}") {
+        before.len() - 2
+    } else {
+        i
+    }
+}
+
+/// Splits a Markdown code fence like ```` ```rust ```` off the line `line`,
+/// returning its character and length.
+fn fence(line: &str) -> Option<(char, usize)> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    let c = line[indent..]
+        .chars()
+        .next()
+        .filter(|&c| c == '`' || c == '~')?;
+    let len = line[indent..].len() - line[indent..].trim_start_matches(c).len();
+    if indent > 3 || len < 3 || (c == '`' && line[indent + len..].contains('`')) {
+        return None;
+    }
+    Some((c, len))
+}
+
+/// Finds the end of a fenced code block whose opening fence starts `s`,
+/// which is the end of the closing fence line, or of `s` if the block is
+/// never closed.
+fn fenced_block_end(s: &str) -> Option<usize> {
+    let (c, len) = fence(s.lines().next()?)?;
+    let mut pos = s.find('\n').map_or(s.len(), |i| i + 1);
+    while pos < s.len() {
+        let line_end = s[pos..].find('\n').map_or(s.len(), |i| pos + i + 1);
+        let line = s[pos..line_end].trim_end();
+        let closes = fence(line).is_some_and(|(close, n)| {
+            close == c && n >= len && line.trim_start_matches(' ').len() == n
+        });
+        if closes {
+            return Some(line_end);
+        }
+        pos = line_end;
+    }
+    Some(s.len())
+}
+
+/// Finds the end of a Markdown code span opened by the backticks starting
+/// `s`, which is closed by a run of as many backticks.
+fn code_span_end(s: &str) -> Option<usize> {
+    let len = s.len() - s.trim_start_matches('`').len();
+    let mut pos = len;
+    while let Some(start) = s[pos..].find('`').map(|i| pos + i) {
+        let run = s[start..].len() - s[start..].trim_start_matches('`').len();
+        if run == len {
+            return Some(start + run);
+        }
+        pos = start + run;
+    }
+    None
+}
+
+/// Returns the sorted, disjoint ranges of `s` which must be parsed as text.
+///
+/// Placeholders and escapes of the template itself are skipped, so quotes
+/// inside them do not count.
+pub(crate) fn literal_ranges(s: &str, options: &ParseOptions) -> Vec<Range<usize>> {
+    let mut result = vec![];
+    let markers = options.region_markers && s.contains(OFF);
+    if options.quotes.is_empty() && !options.literal_blocks && !options.markdown && !markers {
+        return result;
+    }
+
+    let mut scanner = Scanner::new(s.as_bytes());
+    let mut pos = 0;
+    while let Some(c) = s[pos..].chars().next() {
+        let next = pos + c.len_utf8();
+        if c == options.escape {
+            pos = next + s[next..].chars().next().map_or(0, char::len_utf8);
+            continue;
+        }
+
+        if options.markdown {
+            let line_start = pos == 0 || s.as_bytes()[pos - 1] == b'\n';
+            let code = match c {
+                ' ' | '`' | '~' if line_start => fenced_block_end(&s[pos..]),
+                _ => None,
+            };
+            let code = match (code, c) {
+                (None, '`') => Some(
+                    code_span_end(&s[pos..])
+                        .unwrap_or_else(|| s[pos..].len() - s[pos..].trim_start_matches('`').len()),
+                ),
+                (code, _) => code,
+            };
+            if let Some(len) = code {
+                result.push(pos..pos + len);
+                pos += len;
+                continue;
+            }
+        }
+
+        let wrapper = scanner.match_at(pos);
+        if markers {
+            let off = match wrapper {
+                Some((Wrapper::CurlyHash, start, end, len))
+                    if s[pos + start..pos + end].trim() == OFF =>
+                {
+                    Some(pos + len)
+                }
+                _ if s[pos..].starts_with(OFF) => Some(pos + OFF.len()),
+                _ => None,
+            };
+            if let Some(start) = off {
+                let end = start + region_end(&s[start..]);
+                if end > start {
+                    result.push(start..end);
+                }
+                pos = end;
+                continue;
+            }
+        }
+
+        if c == '{' && options.literal_blocks {
+            if let None | Some((Wrapper::Curly, ..)) = wrapper {
+                if let Some(end) = block_end(&s[next..]).map(|len| next + len) {
+                    if is_block(&s[next..end]) {
+                        result.push(pos..next);
+                        result.push(end..end + 1);
+                        pos = next;
+                        continue;
+                    }
+                }
+            }
+        }
+
+        if let Some((_, _, _, len)) = wrapper {
+            pos += len;
+        } else if let Some(rule) = options.quotes.iter().find(|rule| rule.delimiter == c) {
+            match closing(&s[next..], rule) {
+                Some(len) => {
+                    result.push(pos..next + len);
+                    pos = next + len;
+                }
+                None => pos = next,
+            }
+        } else {
+            pos = next;
+        }
+    }
+
+    result.sort_by_key(|range| range.start);
+    let mut end = 0;
+    result.retain(|range| {
+        let keep = range.start >= end;
+        if keep {
+            end = range.end;
+        }
+        keep
+    });
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quoted(s: &str, quotes: Vec<QuoteRule>) -> Vec<Range<usize>> {
+        literal_ranges(
+            s,
+            &ParseOptions {
+                quotes,
+                ..Default::default()
+            },
+        )
+    }
+
+    fn blocks(s: &str) -> Vec<Range<usize>> {
+        literal_ranges(
+            s,
+            &ParseOptions {
+                literal_blocks: true,
+                ..Default::default()
+            },
+        )
+    }
+
+    fn markdown(s: &str) -> Vec<Range<usize>> {
+        literal_ranges(
+            s,
+            &ParseOptions {
+                markdown: true,
+                ..Default::default()
+            },
+        )
+    }
+
+    #[test]
+    fn find_markdown_code() {
+        assert_eq!(markdown("a `{b}` {c} ``{`d`}``"), vec![2..7, 12..21]);
+        assert_eq!(markdown("a ``{b}` {c}"), vec![2..4, 7..8]);
+        assert_eq!(markdown("\\`{a}`"), vec![5..6]);
+        let s = "{a}\n```js\n{b}\n```\n{c}\n  ~~~~\n{d}\n~~~\n~~~~\n{e}";
+        assert_eq!(markdown(s), vec![4..18, 22..42]);
+        assert_eq!(markdown("a ```{b}```"), vec![2..11]);
+        assert_eq!(markdown("    ```\n{a}"), vec![4..7]);
+    }
+
+    #[test]
+    fn find_regions() {
+        let ranges = |s| literal_ranges(s, &ParseOptions::default());
+        assert_eq!(
+            ranges("{a} # spongy:off\n{b}\n# spongy:on\n{c}"),
+            vec![16..23]
+        );
+        assert_eq!(ranges("{# spongy:off #}{a}{#spongy:on#}{b}"), vec![16..19]);
+        assert_eq!(ranges("{a} spongy:off {b}"), vec![14..18]);
+        assert_eq!(ranges("{a} {b: spongy:off} spongy:on"), vec![]);
+        let options = ParseOptions {
+            region_markers: false,
+            ..Default::default()
+        };
+        assert_eq!(literal_ranges("spongy:off {a}", &options), vec![]);
+    }
+
+    #[test]
+    fn find_quoted_ranges() {
+        let rules = QuoteRule::c_style;
+        assert_eq!(quoted(r#"a "b" 'c"' d"#, rules()), vec![2..5, 6..10]);
+        assert_eq!(quoted(r#""a\"b" "c"#, rules()), vec![0..6]);
+        assert_eq!(quoted(r#"{"a"} "b""#, rules()), vec![6..9]);
+        assert_eq!(quoted(r#"\"a" "b""#, rules()), vec![3..6]);
+        assert_eq!(quoted(r#""a" "b""#, vec![]), vec![]);
+        assert_eq!(quoted(r"'it''s' {x}", QuoteRule::sql()), vec![0..4, 4..7]);
+    }
+
+    #[test]
+    fn find_block_braces() {
+        assert_eq!(blocks("{a: 1}"), vec![0..1, 5..6]);
+        assert_eq!(blocks("{a} {{b: c}} {}"), vec![13..14, 14..15]);
+        assert_eq!(blocks(r#"{"}": 1}"#), vec![0..1, 7..8]);
+        assert_eq!(blocks(r#"{"\"}": {x}}"#), vec![0..1, 11..12]);
+        assert_eq!(blocks("{a: 1"), vec![]);
+    }
+}
@@ -0,0 +1,435 @@
+//! Manifests declaring the variables of a template, as a contract between
+//! its authors and the services rendering it.
+//!
+//! [`Manifest::coverage`] compares a template against its manifest:
+//!
+//! ```
+//! use spongy::manifest::Manifest;
+//! use spongy::TypeHint;
+//!
+//! let manifest = Manifest::new()
+//!     .declare("user", None)
+//!     .declare("count", Some(TypeHint::Int))
+//!     .declare("footer", None);
+//! let coverage = manifest.check("{{ user.name }}: {count:int} {{ missing }}")?;
+//! assert_eq!(coverage.undeclared, vec!["missing"]);
+//! assert_eq!(coverage.unused, vec!["footer"]);
+//! assert!(!coverage.is_complete());
+//! # Ok::<(), pest::error::Error<spongy::Rule>>(())
+//! ```
+//!
+//! Manifests are written in a small subset of TOML, with a table for each
+//! variable, quoted so that paths stay whole:
+//!
+//! ```toml
+//! ["user.name"]
+//! description = "Full name, as shown in the greeting"
+//! type = "str"
+//! default = "there"
+//! ```
+//!
+//! [`Manifest::skeleton`] extracts one from a template, for its authors to
+//! fill in.
+
+use std::fmt;
+
+use pest::error::Error;
+
+use crate::lint::{references, variables};
+use crate::{parse, types, Element, Rule, TypeHint};
+
+/// A variable declared in a [`Manifest`].
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Declaration {
+    /// Name of the variable, which may be a path like `user.name`.
+    pub name: String,
+    /// The type values must have, if any.
+    pub hint: Option<TypeHint>,
+    pub description: Option<String>,
+    /// The value used when the context has none.
+    pub default: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ManifestError {
+    /// Line `line` of the manifest, counting from 1, is not understood.
+    Syntax { line: usize, message: String },
+    /// The default of the variable `name` does not conform to its type.
+    Default { name: String, hint: TypeHint },
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ManifestError::Syntax { line, message } => {
+                write!(f, "manifest line {}: {}", line, message)
+            }
+            ManifestError::Default { name, hint } => {
+                write!(f, "default of {:?} is not a valid {}", name, hint)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ManifestError {}
+
+/// Writes `s` as a TOML basic string.
+fn quote(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04X}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Whether `rest` of a line is blank or a comment.
+fn ends_line(rest: &str) -> bool {
+    let rest = rest.trim_start();
+    rest.is_empty() || rest.starts_with('#')
+}
+
+/// Reads the TOML basic string starting `s`, returning it and the rest of
+/// `s` after it.
+fn unquote(s: &str) -> Result<(String, &str), &'static str> {
+    let mut chars = s.strip_prefix('"').ok_or("expected a string")?.chars();
+    let mut result = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return Ok((result, chars.as_str())),
+            '\\' => match chars.next() {
+                Some('"') => result.push('"'),
+                Some('\\') => result.push('\\'),
+                Some('n') => result.push('\n'),
+                Some('r') => result.push('\r'),
+                Some('t') => result.push('\t'),
+                Some('u') => {
+                    let hex = chars.as_str().get(..4).ok_or("invalid escape")?;
+                    let c = u32::from_str_radix(hex, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .ok_or("invalid escape")?;
+                    result.push(c);
+                    chars.nth(3);
+                }
+                _ => return Err("invalid escape"),
+            },
+            c => result.push(c),
+        }
+    }
+    Err("unterminated string")
+}
+
+/// The variables a template may use.
+///
+/// Declaring a variable also declares the paths below it, so `user` covers
+/// `{{ user.name }}` and `{{ user.roles[0] }}`.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Manifest {
+    pub declarations: Vec<Declaration>,
+}
+
+/// A variable used with another type than it is declared with.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TypeConflict<'a> {
+    pub name: &'a str,
+    pub declared: TypeHint,
+    pub used: TypeHint,
+}
+
+/// How a template and a [`Manifest`] agree, as reported by
+/// [`Manifest::coverage`].
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Coverage<'a> {
+    /// Variables the template uses without a declaration, in order of first
+    /// appearance.
+    pub undeclared: Vec<&'a str>,
+    /// Declared variables the template never uses, in order of declaration.
+    pub unused: Vec<&'a str>,
+    /// Variables used with a type hint other than the declared one.
+    pub conflicts: Vec<TypeConflict<'a>>,
+}
+
+impl Coverage<'_> {
+    /// Whether the template uses exactly the declared variables, with their
+    /// declared types.
+    pub fn is_complete(&self) -> bool {
+        self.undeclared.is_empty() && self.unused.is_empty() && self.conflicts.is_empty()
+    }
+}
+
+impl Manifest {
+    pub fn new() -> Manifest {
+        Manifest::default()
+    }
+
+    pub fn declare(mut self, name: &str, hint: Option<TypeHint>) -> Self {
+        self.declarations.push(Declaration {
+            name: name.to_owned(),
+            hint,
+            ..Default::default()
+        });
+        self
+    }
+
+    /// A manifest declaring the variables of the template `s` with their
+    /// type hints, in order of first use.
+    pub fn skeleton(s: &str) -> Result<Manifest, Error<Rule>> {
+        let mut manifest = Manifest::new();
+        for variable in variables(&parse(s)?) {
+            let (name, hint) = types::split(variable);
+            match manifest.declarations.iter_mut().find(|d| d.name == name) {
+                Some(declaration) => declaration.hint = declaration.hint.or(hint),
+                None if name.is_empty() => {}
+                None => manifest = manifest.declare(name, hint),
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// Reads a manifest in its TOML form, checking that defaults conform to
+    /// their types.
+    pub fn from_toml(s: &str) -> Result<Manifest, ManifestError> {
+        let mut manifest = Manifest::new();
+        for (i, line) in s.lines().enumerate() {
+            let syntax = |message: &str| ManifestError::Syntax {
+                line: i + 1,
+                message: message.to_owned(),
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(header) = line.strip_prefix('[') {
+                let (name, rest) = if header.starts_with('"') {
+                    unquote(header).map_err(syntax)?
+                } else {
+                    let end = header.find(']').ok_or_else(|| syntax("expected `]`"))?;
+                    (header[..end].trim().to_owned(), &header[end..])
+                };
+                let rest = rest.trim_start().strip_prefix(']');
+                if !rest.is_some_and(ends_line) {
+                    return Err(syntax("expected `]` ending the line"));
+                }
+                if manifest.declarations.iter().any(|d| d.name == name) {
+                    return Err(syntax("variable declared twice"));
+                }
+                manifest = manifest.declare(&name, None);
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| syntax("expected a key-value pair"))?;
+            let (value, rest) = unquote(value.trim_start()).map_err(syntax)?;
+            if !ends_line(rest) {
+                return Err(syntax("unexpected text after the value"));
+            }
+            let declaration = manifest
+                .declarations
+                .last_mut()
+                .ok_or_else(|| syntax("expected a variable first"))?;
+            match key.trim() {
+                "description" => declaration.description = Some(value),
+                "default" => declaration.default = Some(value),
+                "type" => {
+                    declaration.hint = Some(value.parse().map_err(|_| syntax("unknown type"))?)
+                }
+                _ => return Err(syntax("unknown key")),
+            }
+        }
+
+        for declaration in &manifest.declarations {
+            if let (Some(hint), Some(default)) = (declaration.hint, &declaration.default) {
+                if !hint.matches(default) {
+                    return Err(ManifestError::Default {
+                        name: declaration.name.clone(),
+                        hint,
+                    });
+                }
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// Writes the manifest in its TOML form.
+    pub fn to_toml(&self) -> String {
+        let mut result = String::new();
+        for (i, declaration) in self.declarations.iter().enumerate() {
+            if i > 0 {
+                result.push('\n');
+            }
+            result.push('[');
+            quote(&declaration.name, &mut result);
+            result.push_str("]\n");
+            if let Some(description) = &declaration.description {
+                result.push_str("description = ");
+                quote(description, &mut result);
+                result.push('\n');
+            }
+            if let Some(hint) = declaration.hint {
+                result.push_str("type = ");
+                quote(hint.name(), &mut result);
+                result.push('\n');
+            }
+            if let Some(default) = &declaration.default {
+                result.push_str("default = ");
+                quote(default, &mut result);
+                result.push('\n');
+            }
+        }
+        result
+    }
+
+    /// The declaration covering `variable`, the most specific one if several
+    /// do.
+    pub fn find(&self, variable: &str) -> Option<&Declaration> {
+        self.declarations
+            .iter()
+            .filter(|declaration| references(variable, &declaration.name))
+            .max_by_key(|declaration| declaration.name.len())
+    }
+
+    /// Compares the variables used by `elements` with the declared ones.
+    ///
+    /// Variables are named as by [`lint::variables`](crate::lint::variables),
+    /// without their type hints.
+    pub fn coverage<'a>(&'a self, elements: &[Element<'a>]) -> Coverage<'a> {
+        let mut coverage = Coverage::default();
+        let mut used = vec![false; self.declarations.len()];
+        for variable in variables(elements) {
+            let (name, hint) = types::split(variable);
+            let declaration = match self.find(name) {
+                Some(declaration) => declaration,
+                None => {
+                    if !coverage.undeclared.contains(&name) {
+                        coverage.undeclared.push(name);
+                    }
+                    continue;
+                }
+            };
+            for (declared, used) in self.declarations.iter().zip(&mut used) {
+                if references(name, &declared.name) {
+                    *used = true;
+                }
+            }
+            if let (Some(declared), Some(used)) = (declaration.hint, hint) {
+                let conflict = TypeConflict {
+                    name,
+                    declared,
+                    used,
+                };
+                if declared != used && !coverage.conflicts.contains(&conflict) {
+                    coverage.conflicts.push(conflict);
+                }
+            }
+        }
+        coverage.unused = self
+            .declarations
+            .iter()
+            .zip(used)
+            .filter(|(_, used)| !used)
+            .map(|(declaration, _)| declaration.name.as_str())
+            .collect();
+        coverage
+    }
+
+    /// Parses `s` and compares it with the manifest like
+    /// [`coverage`](Manifest::coverage).
+    pub fn check<'a>(&'a self, s: &'a str) -> Result<Coverage<'a>, Error<Rule>> {
+        Ok(self.coverage(&parse(s)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_coverage() {
+        let manifest = Manifest::new()
+            .declare("user", None)
+            .declare("user.age", Some(TypeHint::Int))
+            .declare("title", Some(TypeHint::Str))
+            .declare("items", None);
+        let coverage = manifest
+            .check("{{ user.name }} {user.age:float} {title} {items[0]} {{ title | upper }}")
+            .unwrap();
+        assert!(!coverage.is_complete());
+        assert_eq!(coverage.undeclared, Vec::<&str>::new());
+        assert_eq!(coverage.unused, Vec::<&str>::new());
+        assert_eq!(
+            coverage.conflicts,
+            vec![TypeConflict {
+                name: "user.age",
+                declared: TypeHint::Int,
+                used: TypeHint::Float,
+            }]
+        );
+
+        let coverage = manifest
+            .check("{user.age:int} {other} {other.x} {# items #}")
+            .unwrap();
+        assert_eq!(coverage.undeclared, vec!["other", "other.x"]);
+        assert_eq!(coverage.unused, vec!["title", "items"]);
+        assert!(coverage.conflicts.is_empty());
+
+        let coverage = manifest
+            .check("{user} {user.age} {title:str} {items}")
+            .unwrap();
+        assert!(coverage.is_complete());
+        assert!(Manifest::new().check("").unwrap().is_complete());
+    }
+
+    #[test]
+    fn read_and_write_toml() {
+        let mut manifest =
+            Manifest::skeleton("{{ user.name }} {age:int} {{ age }} {n[0]} {}").unwrap();
+        assert_eq!(
+            manifest.to_toml(),
+            "[\"user.name\"]\n\n[\"age\"]\ntype = \"int\"\n\n[\"n[0]\"]\n"
+        );
+        manifest.declarations[0].description = Some("Full \"name\"\n\\ \u{1}".to_owned());
+        manifest.declarations[0].default = Some("there".to_owned());
+        let toml = manifest.to_toml();
+        assert!(toml.contains("description = \"Full \\\"name\\\"\\n\\\\ \\u0001\"\n"));
+        assert_eq!(Manifest::from_toml(&toml).unwrap(), manifest);
+
+        let manifest = Manifest::from_toml(
+            "# Greeting\n[user]  # who\ntype = \"str\"\n\n[\"a b\"]\ndefault=\"1\" # one\n",
+        )
+        .unwrap();
+        assert_eq!(manifest.declarations[0].name, "user");
+        assert_eq!(manifest.declarations[1].default.as_deref(), Some("1"));
+
+        let error = |s| Manifest::from_toml(s).unwrap_err();
+        let syntax = |line, message: &str| ManifestError::Syntax {
+            line,
+            message: message.to_owned(),
+        };
+        assert_eq!(
+            error("type = \"int\""),
+            syntax(1, "expected a variable first")
+        );
+        assert_eq!(error("[a]\n\ntype = \"date\""), syntax(3, "unknown type"));
+        assert_eq!(error("[a]\nother = \"x\""), syntax(2, "unknown key"));
+        assert_eq!(error("[a]\n[\"a\"]"), syntax(2, "variable declared twice"));
+        assert_eq!(error("[\"a]"), syntax(1, "unterminated string"));
+        assert_eq!(error("[a]\ntype = int"), syntax(2, "expected a string"));
+        assert_eq!(
+            error("[a]\ntype = \"int\"\ndefault = \"x\""),
+            ManifestError::Default {
+                name: "a".to_owned(),
+                hint: TypeHint::Int
+            }
+        );
+    }
+}
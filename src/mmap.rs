@@ -0,0 +1,201 @@
+//! Parsing template files in place through a memory map.
+//!
+//! Elements of a parsed template borrow from their source, so a template
+//! parsed from a [`Mapped`] file is never copied into memory as a whole: text
+//! elements point into the map. Parsing still reads every page, once to
+//! check that the contents are UTF-8 and once to scan them, but the pages
+//! belong to the page cache, which the kernel can reclaim, rather than to the
+//! heap.
+//!
+//! ```no_run
+//! use spongy::mmap::Mapped;
+//!
+//! // SAFETY: nothing modifies the template while it is rendered.
+//! let map = unsafe { Mapped::open("huge.conf.tmpl")? };
+//! let elements = map.parse()?;
+//! # Ok::<(), spongy::mmap::MapError>(())
+//! ```
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::ptr;
+use std::slice;
+use std::str::{self, Utf8Error};
+
+use pest::error::Error;
+
+use crate::{parse, Elements, Rule};
+
+#[derive(Debug)]
+pub enum MapError {
+    Io(io::Error),
+    InvalidUtf8(Utf8Error),
+    Parse(Error<Rule>),
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapError::Io(e) => e.fmt(f),
+            MapError::InvalidUtf8(e) => e.fmt(f),
+            MapError::Parse(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+impl From<io::Error> for MapError {
+    fn from(e: io::Error) -> Self {
+        MapError::Io(e)
+    }
+}
+
+impl From<Utf8Error> for MapError {
+    fn from(e: Utf8Error) -> Self {
+        MapError::InvalidUtf8(e)
+    }
+}
+
+impl From<Error<Rule>> for MapError {
+    fn from(e: Error<Rule>) -> Self {
+        MapError::Parse(e)
+    }
+}
+
+/// A file mapped read-only into memory.
+pub struct Mapped {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+// The map is read-only and owned, like a `Box<[u8]>`.
+unsafe impl Send for Mapped {}
+unsafe impl Sync for Mapped {}
+
+impl Mapped {
+    /// Maps the file at `path`.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be truncated or modified while it is mapped, by
+    /// this process or another. The map is private, but changes to the file
+    /// may still show through it, breaking the immutability of the `&str`
+    /// borrowed from it, and reading past the end of a truncated file kills
+    /// the process with `SIGBUS`.
+    pub unsafe fn open<P: AsRef<Path>>(path: P) -> io::Result<Mapped> {
+        let file = File::open(path)?;
+        let len = usize::try_from(file.metadata()?.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "file too large to map"))?;
+        if len == 0 {
+            // Empty maps are not allowed, and not needed either.
+            return Ok(Mapped {
+                ptr: ptr::null_mut(),
+                len,
+            });
+        }
+
+        // SAFETY: a fresh read-only private mapping of a whole open file.
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        // Templates are parsed front to back. This is only a hint, so its
+        // failure does not matter.
+        // SAFETY: the range is the mapping just created.
+        unsafe { libc::madvise(ptr, len, libc::MADV_SEQUENTIAL) };
+        Ok(Mapped { ptr, len })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        // SAFETY: the mapping is `len` readable bytes living as long as
+        // `self`.
+        unsafe { slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+
+    /// The contents as a string, if it is valid UTF-8.
+    pub fn as_str(&self) -> Result<&str, Utf8Error> {
+        str::from_utf8(self.as_bytes())
+    }
+
+    /// Parses the contents like [`parse`], borrowing the elements from the
+    /// map.
+    pub fn parse(&self) -> Result<Elements<'_>, MapError> {
+        Ok(parse(self.as_str()?)?)
+    }
+}
+
+impl Drop for Mapped {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            // SAFETY: the mapping is not borrowed anymore.
+            unsafe { libc::munmap(self.ptr, self.len) };
+        }
+    }
+}
+
+impl fmt::Debug for Mapped {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Mapped").field("len", &self.len).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Element, Item, Wrapper};
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn parse_mapped_file() {
+        let dir = env::temp_dir().join(format!("spongy-mmap-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("template");
+        fs::write(&path, "Hello, {name}!").unwrap();
+
+        // SAFETY: the files of this test are only written while unmapped.
+        let open = |path: &Path| unsafe { Mapped::open(path) };
+        {
+            let map = open(&path).unwrap();
+            let elements = map.parse().unwrap();
+            assert_eq!(
+                elements[1],
+                Element::Wrapped(Item::new(Wrapper::Curly, "name"))
+            );
+            match elements[0] {
+                Element::Text(t) => assert_eq!(t.as_ptr(), map.as_bytes().as_ptr()),
+                _ => unreachable!(),
+            }
+        }
+
+        fs::write(&path, "").unwrap();
+        assert!(open(&path).unwrap().parse().unwrap().is_empty());
+        fs::write(&path, b"\xff {a}").unwrap();
+        assert!(matches!(
+            open(&path).unwrap().parse(),
+            Err(MapError::InvalidUtf8(_))
+        ));
+        assert!(matches!(
+            open(&dir.join("missing")),
+            Err(e) if e.kind() == io::ErrorKind::NotFound
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
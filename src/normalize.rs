@@ -0,0 +1,381 @@
+//! Rewriting templates into a canonical form.
+
+use std::borrow::Cow;
+
+use pest::error::Error;
+
+use crate::{parse, Element, Item, OwnedElement, Rule, Wrapper};
+
+/// How [`normalize`] rewrites placeholders.
+#[derive(Clone, Debug, Default)]
+pub struct NormalizePolicy {
+    /// Pad non-empty placeholder contents with a single space on each side,
+    /// as in `{{ name }}`. Otherwise they are written trimmed.
+    pub padding: bool,
+    /// Wrapper replacements, e.g. `(Wrapper::TripleCurly, Wrapper::DoubleCurly)`
+    /// to use `{{ }}` everywhere. A placeholder whose content cannot be
+    /// written with the replacement wrapper keeps its own.
+    pub wrappers: Vec<(Wrapper, Wrapper)>,
+}
+
+/// Whether `interior` can be written inside `wrapper` without changing the
+/// parse.
+fn fits(wrapper: Wrapper, interior: &str) -> bool {
+    match wrapper {
+        Wrapper::CurlyHash => !interior.contains('#'),
+        Wrapper::CurlyPercent => !interior.contains('%'),
+        // `{#x#}` would become a comment.
+        Wrapper::Curly => !interior.starts_with(['#', '%']),
+        Wrapper::DollarParen => !interior.contains(['(', ')', '{', '}']),
+        _ => true,
+    }
+}
+
+/// Rewrites `s` into the canonical form described by `policy`.
+///
+/// Text is kept as-is, so normalizing twice gives the same result.
+pub fn normalize(s: &str, policy: &NormalizePolicy) -> Result<String, Error<Rule>> {
+    let mut result = String::with_capacity(s.len());
+    for el in parse(s)? {
+        match el {
+            Element::Text(t) | Element::Escaped { source: t, .. } => result.push_str(t),
+            Element::Wrapped(item) => {
+                let text = item.key();
+                let interior = if policy.padding && !text.is_empty() {
+                    format!(" {} ", text)
+                } else {
+                    text.to_owned()
+                };
+                let wrapper = policy
+                    .wrappers
+                    .iter()
+                    .find(|(from, _)| *from == item.wrapper)
+                    .map_or(item.wrapper, |&(_, to)| to);
+                let wrapper = if fits(wrapper, &interior) {
+                    wrapper
+                } else {
+                    item.wrapper
+                };
+                Item::new(wrapper, &interior).push_source(&mut result);
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Line ending to [normalize](normalize_newlines) output to.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+}
+
+/// Rewrites every `\n` and `\r\n` in `s` to `ending`, e.g. to keep
+/// rendered output of a Windows-authored template from mixing line endings
+/// with those of substituted values. A lone `\r` is kept.
+pub fn normalize_newlines(s: &str, ending: LineEnding) -> Cow<'_, str> {
+    let consistent = match ending {
+        LineEnding::Lf => !s.contains("\r\n"),
+        LineEnding::CrLf => s.match_indices('\n').all(|(i, _)| s[..i].ends_with('\r')),
+    };
+    if consistent {
+        return Cow::Borrowed(s);
+    }
+
+    let mut result = String::with_capacity(s.len() + s.len() / 16);
+    for line in s.split_inclusive('\n') {
+        match line.strip_suffix('\n') {
+            Some(line) => {
+                result.push_str(line.strip_suffix('\r').unwrap_or(line));
+                result.push_str(ending.as_str());
+            }
+            None => result.push_str(line),
+        }
+    }
+    Cow::Owned(result)
+}
+
+/// How [`minify`] collapses whitespace.
+#[derive(Clone, Debug, Default)]
+pub struct MinifyPolicy {
+    /// Collapse runs of spaces and tabs into a single space and drop those
+    /// ending a line. Indentation is kept, as formats like YAML depend on it.
+    pub spaces: bool,
+    /// Drop lines which are empty or only contain whitespace.
+    pub blank_lines: bool,
+    /// Start and end delimiters of regions kept as-is, like
+    /// `("<pre>", "</pre>")`. A region without an end lasts until the end of
+    /// the input.
+    pub protected: Vec<(String, String)>,
+}
+
+/// Collapses the whitespace in rendered output `s` as described by
+/// `policy`, e.g. to shrink generated HTML or config files.
+pub fn minify(s: &str, policy: &MinifyPolicy) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut at_line_start = true;
+    let mut rest = s;
+
+    while !rest.is_empty() {
+        let region = policy
+            .protected
+            .iter()
+            .filter_map(|(start, end)| rest.find(start.as_str()).map(|pos| (pos, start, end)))
+            .min_by_key(|&(pos, _, _)| pos);
+        let (chunk, protected) = match region {
+            Some((pos, start, end)) => {
+                let content = pos + start.len();
+                let region_end = rest[content..]
+                    .find(end.as_str())
+                    .map_or(rest.len(), |i| content + i + end.len());
+                (&rest[..pos], &rest[pos..region_end])
+            }
+            None => (rest, ""),
+        };
+
+        let last = protected.is_empty() && chunk.len() == rest.len();
+        for piece in chunk.split_inclusive('\n') {
+            let complete = piece.ends_with('\n');
+            if policy.blank_lines && at_line_start && complete && piece.trim().is_empty() {
+                continue;
+            }
+            if policy.spaces {
+                collapse_spaces(piece, at_line_start, complete || last, &mut result);
+            } else {
+                result.push_str(piece);
+            }
+            at_line_start = complete;
+        }
+        if !protected.is_empty() {
+            result.push_str(protected);
+            at_line_start = protected.ends_with('\n');
+        }
+        rest = &rest[chunk.len() + protected.len()..];
+    }
+    result
+}
+
+/// Writes `piece`, a line or part of one, with its runs of spaces and tabs
+/// collapsed. Leading ones are kept if the piece starts a line, trailing ones
+/// are dropped if it ends one.
+fn collapse_spaces(piece: &str, at_line_start: bool, line_end: bool, out: &mut String) {
+    let is_space = |c: char| c == ' ' || c == '\t';
+    let (line, newline) = match piece.strip_suffix('\n') {
+        Some(line) => match line.strip_suffix('\r') {
+            Some(line) => (line, "\r\n"),
+            None => (line, "\n"),
+        },
+        None => (piece, ""),
+    };
+    let mut rest = line;
+    if at_line_start {
+        rest = line.trim_start_matches(is_space);
+        out.push_str(&line[..line.len() - rest.len()]);
+    }
+    if line_end {
+        rest = rest.trim_end_matches(is_space);
+    }
+
+    let mut in_run = false;
+    for c in rest.chars() {
+        if is_space(c) {
+            if !in_run {
+                out.push(' ');
+            }
+            in_run = true;
+        } else {
+            out.push(c);
+            in_run = false;
+        }
+    }
+    out.push_str(newline);
+}
+
+/// Merges consecutive text elements and drops empty ones.
+///
+/// Stripping text can leave it in elements of its own, and concatenating
+/// elements puts their text side by side, so the same source can come in
+/// several shapes. Merged, elements which unparse into the same source
+/// compare equal. [Escapes](Element::Escaped) are kept as they are.
+pub fn merge_text(elements: &mut Vec<OwnedElement>) {
+    let mut merged: Vec<OwnedElement> = Vec::with_capacity(elements.len());
+    for el in elements.drain(..) {
+        match (merged.last_mut(), el) {
+            (_, OwnedElement::Text(t)) if t.is_empty() => {}
+            (Some(OwnedElement::Text(last)), OwnedElement::Text(t)) => last.push_str(&t),
+            (_, el) => merged.push(el),
+        }
+    }
+    *elements = merged;
+}
+
+#[derive(PartialEq, Debug)]
+enum Canonical<'a> {
+    Text(String),
+    Item(Wrapper, &'a str),
+}
+
+/// Removes backslash escapes from template text.
+fn unescape(text: &str, out: &mut String) {
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.extend(chars.next()),
+            c => out.push(c),
+        }
+    }
+}
+
+fn canonical(s: &str) -> Result<Vec<Canonical<'_>>, Error<Rule>> {
+    let mut result = vec![];
+    for el in parse(s)? {
+        match el {
+            Element::Text(t) => {
+                if let Some(Canonical::Text(last)) = result.last_mut() {
+                    unescape(t, last);
+                } else {
+                    let mut text = String::new();
+                    unescape(t, &mut text);
+                    result.push(Canonical::Text(text));
+                }
+            }
+            Element::Wrapped(item) => result.push(Canonical::Item(item.wrapper, item.key())),
+            Element::Escaped { text, .. } => match result.last_mut() {
+                Some(Canonical::Text(last)) => last.push_str(text),
+                _ => result.push(Canonical::Text(text.to_owned())),
+            },
+        }
+    }
+    Ok(result)
+}
+
+/// Compares two templates ignoring whitespace around placeholder contents and
+/// redundant escapes in text, so `{{ a }}\}` equals `{{a}}}`.
+pub fn semantically_equal(a: &str, b: &str) -> Result<bool, Error<Rule>> {
+    Ok(canonical(a)? == canonical(b)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_trimmed() {
+        let policy = NormalizePolicy::default();
+        assert_eq!(
+            normalize("{{ a }} {{b}}  {  c} {%  if x %}{ }", &policy).unwrap(),
+            "{{a}} {{b}}  {c} {%if x%}{}"
+        );
+    }
+
+    #[test]
+    fn normalize_padded_wrappers() {
+        let policy = NormalizePolicy {
+            padding: true,
+            wrappers: vec![
+                (Wrapper::TripleCurly, Wrapper::DoubleCurly),
+                (Wrapper::Curly, Wrapper::CurlyHash),
+            ],
+        };
+        let normalized = normalize("{{{a}}} {{b }} {c} {#d} {}", &policy).unwrap();
+        assert_eq!(normalized, "{{ a }} {{ b }} {# c #} { #d } {##}");
+        assert_eq!(normalize(&normalized, &policy).unwrap(), normalized);
+
+        let policy = NormalizePolicy {
+            padding: false,
+            wrappers: vec![(Wrapper::DoubleCurly, Wrapper::Curly)],
+        };
+        assert_eq!(
+            normalize("{{ a }} {{#b#}}", &policy).unwrap(),
+            "{a} {{#b#}}"
+        );
+    }
+
+    #[test]
+    fn normalize_line_endings() {
+        let s = "a\r\nb\nc\rd\r\n";
+        assert_eq!(normalize_newlines(s, LineEnding::Lf), "a\nb\nc\rd\n");
+        assert_eq!(
+            normalize_newlines(s, LineEnding::CrLf),
+            "a\r\nb\r\nc\rd\r\n"
+        );
+        assert!(matches!(
+            normalize_newlines("a\r\nb", LineEnding::CrLf),
+            Cow::Borrowed(_)
+        ));
+        assert!(matches!(
+            normalize_newlines("a\nb\r", LineEnding::Lf),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn minify_output() {
+        let policy = MinifyPolicy {
+            spaces: true,
+            blank_lines: true,
+            protected: vec![("<pre>".to_owned(), "</pre>".to_owned())],
+        };
+        let s =
+            "<ul>\n\n    <li>a   b</li>  \r\n \t\n<pre>  x\n\n</pre>   <b> c </b>\n\n<pre>\n  y";
+        assert_eq!(
+            minify(s, &policy),
+            "<ul>\n    <li>a b</li>\r\n<pre>  x\n\n</pre> <b> c </b>\n<pre>\n  y"
+        );
+        assert_eq!(minify("a  \n\n b ", &MinifyPolicy::default()), "a  \n\n b ");
+        let policy = MinifyPolicy {
+            spaces: true,
+            ..MinifyPolicy::default()
+        };
+        assert_eq!(minify("a  \n\n b  c ", &policy), "a\n\n b c");
+    }
+
+    #[test]
+    fn compare_semantically() {
+        let eq = |a, b| semantically_equal(a, b).unwrap();
+        assert!(eq("Hi {{ name }}!", "Hi {{name}}!"));
+        assert!(eq("a\\}b {x}", "a}b { x }"));
+        assert!(eq("", ""));
+        assert!(!eq("{{name}}", "{name}"));
+        assert!(!eq("{{ a b }}", "{{ab}}"));
+        assert!(!eq("\\{a}", "{a}"));
+        assert!(semantically_equal("{{", "").is_err());
+    }
+
+    #[test]
+    fn merge_text_elements() {
+        let options = crate::ParseOptions {
+            dollar_escape: true,
+            ..Default::default()
+        };
+        let mut elements: Vec<OwnedElement> = crate::parse_with_options("a $${b} c{d}", &options)
+            .unwrap()
+            .iter()
+            .map(Into::into)
+            .collect();
+        assert_eq!(elements.len(), 4);
+        elements.insert(0, OwnedElement::Text(String::new()));
+        merge_text(&mut elements);
+        assert_eq!(
+            elements,
+            vec![
+                OwnedElement::Text("a ".to_owned()),
+                OwnedElement::Escaped {
+                    text: "${b}".to_owned(),
+                    source: "$${b}".to_owned(),
+                },
+                OwnedElement::Text(" c".to_owned()),
+                OwnedElement::Wrapped(crate::OwnedItem::new(Wrapper::Curly, "d")),
+            ]
+        );
+    }
+}
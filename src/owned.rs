@@ -0,0 +1,154 @@
+//! Owned counterparts of [`Item`] and [`Element`], for storing parsed
+//! templates beyond the lifetime of their input.
+
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use crate::{Element, Item, Wrapper};
+
+/// An [`Item`] owning its text.
+#[derive(Clone, Debug)]
+pub struct OwnedItem {
+    pub wrapper: Wrapper,
+    pub text: String,
+    source: Option<String>,
+}
+
+impl OwnedItem {
+    pub fn new<T: Into<String>>(wrapper: Wrapper, text: T) -> OwnedItem {
+        OwnedItem {
+            wrapper,
+            text: text.into(),
+            source: None,
+        }
+    }
+
+    /// Borrows this item as an [`Item`].
+    pub fn as_item(&self) -> Item<'_> {
+        Item {
+            wrapper: self.wrapper,
+            text: &self.text,
+            source: self.source.as_deref(),
+        }
+    }
+}
+
+impl PartialEq for OwnedItem {
+    fn eq(&self, other: &Self) -> bool {
+        self.wrapper == other.wrapper && self.text == other.text
+    }
+}
+
+impl Eq for OwnedItem {}
+
+impl Hash for OwnedItem {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.wrapper.hash(state);
+        self.text.hash(state);
+    }
+}
+
+impl From<&Item<'_>> for OwnedItem {
+    fn from(item: &Item) -> Self {
+        OwnedItem {
+            wrapper: item.wrapper,
+            text: item.text.to_owned(),
+            source: item.source.map(str::to_owned),
+        }
+    }
+}
+
+impl From<Item<'_>> for OwnedItem {
+    fn from(item: Item) -> Self {
+        OwnedItem::from(&item)
+    }
+}
+
+impl fmt::Display for OwnedItem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.as_item().fmt(f)
+    }
+}
+
+/// An [`Element`] owning its text.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum OwnedElement {
+    Text(String),
+    Wrapped(OwnedItem),
+    Escaped { text: String, source: String },
+}
+
+impl OwnedElement {
+    /// Borrows this element as an [`Element`].
+    pub fn as_element(&self) -> Element<'_> {
+        match self {
+            OwnedElement::Text(t) => Element::Text(t),
+            OwnedElement::Wrapped(item) => Element::Wrapped(item.as_item()),
+            OwnedElement::Escaped { text, source } => Element::Escaped { text, source },
+        }
+    }
+}
+
+impl From<&Element<'_>> for OwnedElement {
+    fn from(el: &Element) -> Self {
+        match el {
+            Element::Text(t) => OwnedElement::Text((*t).to_owned()),
+            Element::Wrapped(item) => OwnedElement::Wrapped(item.into()),
+            Element::Escaped { text, source } => OwnedElement::Escaped {
+                text: (*text).to_owned(),
+                source: (*source).to_owned(),
+            },
+        }
+    }
+}
+
+impl From<Element<'_>> for OwnedElement {
+    fn from(el: Element) -> Self {
+        OwnedElement::from(&el)
+    }
+}
+
+impl fmt::Display for OwnedElement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.as_element().fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, render};
+    use std::collections::{HashMap, HashSet};
+
+    #[test]
+    fn owned_round_trip() {
+        let owned: Vec<OwnedElement> = parse("a {{ b }} {c}")
+            .unwrap()
+            .into_iter()
+            .map(OwnedElement::from)
+            .collect();
+        assert_eq!(
+            owned[1],
+            OwnedElement::Wrapped(OwnedItem::new(Wrapper::DoubleCurly, " b "))
+        );
+
+        let elements: Vec<Element> = owned.iter().map(OwnedElement::as_element).collect();
+        assert_eq!(render(&elements, |_| None::<&str>), "a {{ b }} {c}");
+    }
+
+    #[test]
+    fn items_as_keys() {
+        let elements = parse("{a} {{a}} {a} {b}").unwrap();
+        let mut counts: HashMap<&Item, usize> = HashMap::new();
+        for el in &elements {
+            if let Element::Wrapped(item) = el {
+                *counts.entry(item).or_default() += 1;
+            }
+        }
+        assert_eq!(counts[&Item::new(Wrapper::Curly, "a")], 2);
+        assert_eq!(counts.len(), 3);
+
+        let set: HashSet<OwnedElement> = elements.iter().map(OwnedElement::from).collect();
+        assert_eq!(set.len(), 4);
+    }
+}
@@ -0,0 +1,288 @@
+//! Rendering of templates destined for file names and paths.
+//!
+//! Separators in the template itself are kept, so `out/{name}.rs` renders to
+//! a nested path, but a substituted value may not introduce new components:
+//! separators, `.`/`..` and NUL bytes are handled according to a
+//! [`PathPolicy`]. So are components which substitutions turn into `.` or
+//! `..`, like `out/.{ext}` with an `ext` of `.`.
+
+use std::borrow::Cow;
+use std::ffi::OsString;
+use std::fmt;
+use std::iter;
+use std::ops::Range;
+use std::path::{self, PathBuf};
+
+use pest::error::Error;
+
+use crate::{parse, policy, Element, Item, Rule};
+
+/// What to do with a substitution that would be hostile in a path.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum PathPolicy {
+    /// Fail with [`PathError::Hostile`].
+    #[default]
+    Reject,
+    /// Replace separators and NUL bytes with the given character, and a value
+    /// or component of `.` or `..` with it entirely.
+    Sanitize(char),
+    /// Substitute the value unchanged.
+    Allow,
+}
+
+#[derive(Debug)]
+pub enum PathError {
+    Parse(Box<Error<Rule>>),
+    /// The value substituted for the placeholder `text` is path-hostile.
+    Hostile {
+        text: String,
+        value: String,
+    },
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PathError::Parse(e) => e.fmt(f),
+            PathError::Hostile { text, value } => write!(
+                f,
+                "value {:?} of placeholder {:?} is not a valid path component",
+                value, text
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PathError {}
+
+impl From<Error<Rule>> for PathError {
+    fn from(e: Error<Rule>) -> Self {
+        PathError::Parse(Box::new(e))
+    }
+}
+
+fn is_hostile_char(c: char) -> bool {
+    path::is_separator(c) || c == '/' || c == '\\' || c == '\0'
+}
+
+/// Whether `value` could change the structure of a path it is inserted into.
+pub fn is_hostile(value: &str) -> bool {
+    value == "." || value == ".." || value.contains(is_hostile_char)
+}
+
+/// Applies `policy` to the characters of a substituted value. Whether it
+/// makes a component `.` or `..` depends on the text around it, which
+/// [`check_components`] checks.
+fn check<'v>(item: &Item, value: &'v str, policy: PathPolicy) -> Result<Cow<'v, str>, PathError> {
+    if !value.contains(is_hostile_char) {
+        return Ok(Cow::Borrowed(value));
+    }
+
+    match policy {
+        PathPolicy::Allow => Ok(Cow::Borrowed(value)),
+        PathPolicy::Sanitize(c) => Ok(Cow::Owned(value.replace(is_hostile_char, &c.to_string()))),
+        PathPolicy::Reject => Err(PathError::Hostile {
+            text: item.text.to_owned(),
+            value: value.to_owned(),
+        }),
+    }
+}
+
+/// A substituted value, where it was written in the path and the text of its
+/// placeholder.
+struct Substitution {
+    range: Range<usize>,
+    text: String,
+    value: String,
+}
+
+/// Applies `policy` to the components of `path` containing substitutions.
+fn check_components(
+    path: &str,
+    substitutions: &[Substitution],
+    policy: PathPolicy,
+) -> Result<String, PathError> {
+    let mut result = String::with_capacity(path.len());
+    let mut start = 0;
+    let separators = path.match_indices(is_hostile_char);
+    for (end, separator) in separators.chain(iter::once((path.len(), ""))) {
+        let component = &path[start..end];
+        let substitution = substitutions
+            .iter()
+            .find(|sub| sub.range.start >= start && sub.range.end <= end);
+        match substitution {
+            Some(sub) if component == "." || component == ".." => match policy {
+                PathPolicy::Sanitize(c) => result.push(c),
+                _ => {
+                    return Err(PathError::Hostile {
+                        text: sub.text.clone(),
+                        value: sub.value.clone(),
+                    })
+                }
+            },
+            _ => result.push_str(component),
+        }
+        result.push_str(separator);
+        start = end + separator.len();
+    }
+    Ok(result)
+}
+
+/// Renders `s` as a path, checking every substitution against `policy`.
+pub fn render_path<M, V>(s: &str, mapper: M, policy: PathPolicy) -> Result<PathBuf, PathError>
+where
+    M: Fn(&Item) -> Option<V>,
+    V: AsRef<str>,
+{
+    render_os_string(s, mapper, policy).map(PathBuf::from)
+}
+
+/// Renders `s` like [`render_path`], e.g. for a single file name.
+pub fn render_os_string<M, V>(s: &str, mapper: M, policy: PathPolicy) -> Result<OsString, PathError>
+where
+    M: Fn(&Item) -> Option<V>,
+    V: AsRef<str>,
+{
+    let mut result = String::new();
+    let mut substitutions = vec![];
+    for el in parse(s)? {
+        match el {
+            Element::Text(t) | Element::Escaped { text: t, .. } => result.push_str(t),
+            Element::Wrapped(item) => match mapper(&item) {
+                Some(value) => {
+                    let value = check(&item, value.as_ref(), policy)?;
+                    let start = result.len();
+                    result.push_str(&value);
+                    substitutions.push(Substitution {
+                        range: start..result.len(),
+                        text: item.text.to_owned(),
+                        value: value.into_owned(),
+                    });
+                }
+                None => item.push_source(&mut result),
+            },
+        }
+    }
+    if policy != PathPolicy::Allow {
+        result = check_components(&result, &substitutions, policy)?;
+    }
+    Ok(OsString::from(result))
+}
+
+/// Matches a `/`-separated `path` against a glob `pattern` like
+/// `templates/**/*.tmpl`.
+///
+/// Within a component, `*` stands for any run of characters and `?` for a
+/// single one. A `**` component stands for any number of components.
+pub fn matches_glob(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<&str> = pattern.split('/').filter(|c| !c.is_empty()).collect();
+    let path: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+    matches_components(&pattern, &path)
+}
+
+fn matches_components(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|i| matches_components(rest, &path[i..])),
+        Some((first, rest)) => match path.split_first() {
+            Some((component, path)) => {
+                policy::glob(first, component) && matches_components(rest, path)
+            }
+            None => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapper(value: &'static str) -> impl Fn(&Item) -> Option<String> {
+        move |item| match item.text {
+            "name" => Some(value.to_owned()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn render_plain_path() {
+        assert_eq!(
+            render_path("out/{name}/mod.rs", mapper("user"), PathPolicy::Reject).unwrap(),
+            PathBuf::from("out/user/mod.rs")
+        );
+    }
+
+    #[test]
+    fn match_path_globs() {
+        assert!(matches_glob("templates/**/*.tmpl", "templates/a.tmpl"));
+        assert!(matches_glob("templates/**/*.tmpl", "templates/a/b/c.tmpl"));
+        assert!(!matches_glob("templates/**/*.tmpl", "templates/a/b.txt"));
+        assert!(!matches_glob("templates/*.tmpl", "templates/a/b.tmpl"));
+        assert!(matches_glob("**", "a/b"));
+        assert!(matches_glob("a/?/c", "a/b/c"));
+        assert!(!matches_glob("a/?/c", "a/bb/c"));
+    }
+
+    #[test]
+    fn reject_hostile() {
+        for value in &["..", ".", "a/b", "a\\b", "a\0b"] {
+            let value: &'static str = value;
+            assert!(matches!(
+                render_path("out/{name}", mapper(value), PathPolicy::Reject),
+                Err(PathError::Hostile { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn reject_hostile_components() {
+        let dot = |value: &'static str| move |_: &Item| Some(value);
+        for s in &["out/{a}{b}", "out/.{a}", "{a}./x"] {
+            assert!(
+                matches!(
+                    render_path(s, dot("."), PathPolicy::Reject),
+                    Err(PathError::Hostile { .. })
+                ),
+                "{}",
+                s
+            );
+        }
+        assert!(matches!(
+            render_path("out/.{a}./x", dot(""), PathPolicy::Reject),
+            Err(PathError::Hostile { .. })
+        ));
+        assert_eq!(
+            render_path("../{a}.{b}/x", dot("."), PathPolicy::Reject).unwrap(),
+            PathBuf::from("../.../x")
+        );
+        assert_eq!(
+            render_path("out/.{a}./x", dot(""), PathPolicy::Sanitize('_')).unwrap(),
+            PathBuf::from("out/_/x")
+        );
+        assert_eq!(
+            render_path("out/{a}{b}", dot("."), PathPolicy::Allow).unwrap(),
+            PathBuf::from("out/..")
+        );
+        assert_eq!(
+            render_os_string("{name}.txt", mapper("notes"), PathPolicy::Reject).unwrap(),
+            OsString::from("notes.txt")
+        );
+    }
+
+    #[test]
+    fn sanitize_hostile() {
+        let policy = PathPolicy::Sanitize('_');
+        assert_eq!(
+            render_path("out/{name}", mapper("../etc/passwd"), policy).unwrap(),
+            PathBuf::from("out/.._etc_passwd")
+        );
+        assert_eq!(
+            render_path("out/{name}", mapper(".."), policy).unwrap(),
+            PathBuf::from("out/_")
+        );
+        assert_eq!(
+            render_path("out/{name}", mapper("a/b"), PathPolicy::Allow).unwrap(),
+            PathBuf::from("out/a/b")
+        );
+    }
+}
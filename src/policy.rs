@@ -0,0 +1,298 @@
+//! Enforcing which placeholder names templates may use.
+//!
+//! A [`Policy`] is checked against templates from untrusted authors before
+//! rendering them, reporting every offending placeholder as a
+//! [`Violation`]. An [`Allowlist`] restricts what a resolver may be asked
+//! for while rendering.
+
+use std::borrow::Cow;
+use std::fmt;
+
+use pest::error::Error;
+
+use crate::resolve::{Outcome, Resolver};
+use crate::value::{segments, Segment};
+use crate::{filters, parse_spanned, Element, Item, ParseOptions, Rule, Span, Wrapper};
+
+/// Why a placeholder violates a [`Policy`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Reason {
+    /// The name matches the given denied pattern.
+    Denied(String),
+    /// The name contains a forbidden character.
+    Character(char),
+}
+
+/// A placeholder rejected by a [`Policy`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Violation {
+    /// The [key](crate::Item::key) of the placeholder.
+    pub key: String,
+    pub span: Span,
+    pub reason: Reason,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.reason {
+            Reason::Denied(pattern) => {
+                write!(f, "`{}` matches denied pattern `{}`", self.key, pattern)
+            }
+            Reason::Character(c) => write!(f, "`{}` contains forbidden {:?}", self.key, c),
+        }
+    }
+}
+
+/// Rules on the placeholders of a template.
+///
+/// Names are the placeholder keys without any filter pipeline, and are
+/// matched against glob patterns where `*` stands for any run of characters
+/// and `?` for a single one, so `secret.*` denies every path below `secret`.
+/// Comments and statements are not checked.
+#[derive(Clone, Debug, Default)]
+pub struct Policy {
+    denied: Vec<String>,
+    chars: Vec<char>,
+}
+
+impl Policy {
+    pub fn new() -> Policy {
+        Policy::default()
+    }
+
+    /// Rejects names matching the glob `pattern`.
+    pub fn deny<S: Into<String>>(mut self, pattern: S) -> Self {
+        self.denied.push(pattern.into());
+        self
+    }
+
+    /// Rejects names containing any of `chars`, e.g. the shell
+    /// metacharacters `` ;&|`$ ``.
+    pub fn deny_chars(mut self, chars: &str) -> Self {
+        self.chars.extend(chars.chars());
+        self
+    }
+
+    /// Checks the placeholders of `s`, parsed with the default options.
+    pub fn check(&self, s: &str) -> Result<Vec<Violation>, Error<Rule>> {
+        Ok(self.violations(&parse_spanned(s, &ParseOptions::default())?))
+    }
+
+    /// The violations among parsed elements, in order of appearance.
+    pub fn violations(&self, nodes: &[(Element, Span)]) -> Vec<Violation> {
+        let mut result = vec![];
+        for (el, span) in nodes {
+            let item = match el {
+                Element::Wrapped(item) => item,
+                Element::Text(_) | Element::Escaped { .. } => continue,
+            };
+            if let Wrapper::CurlyHash | Wrapper::CurlyPercent = item.wrapper {
+                continue;
+            }
+
+            let key = item.key();
+            let violation = |reason| Violation {
+                key: key.to_owned(),
+                span: *span,
+                reason,
+            };
+            let name = filters::pipeline(key).0;
+            if let Some(c) = name.chars().find(|c| self.chars.contains(c)) {
+                result.push(violation(Reason::Character(c)));
+                continue;
+            }
+            if let Some(pattern) = self.denied.iter().find(|p| glob(p, name)) {
+                result.push(violation(Reason::Denied(pattern.clone())));
+            }
+        }
+        result
+    }
+}
+
+/// Only passes placeholders whose names match one of a set of glob
+/// patterns on to a resolver, as described for [`Policy`].
+///
+/// Other placeholders are left unresolved, so a template rendered for one
+/// tenant cannot read context keys meant for another. So are names which are
+/// not plain [paths](crate::Value::lookup) like `user.name` or `items[0]`,
+/// since a resolver evaluating expressions could read other keys through
+/// them, as in `tenant_a.x or tenant_b.password`.
+pub struct Allowlist<R> {
+    resolver: R,
+    patterns: Vec<String>,
+}
+
+impl<R: Resolver> Allowlist<R> {
+    pub fn new<I, S>(resolver: R, patterns: I) -> Allowlist<R>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Allowlist {
+            resolver,
+            patterns: patterns.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    pub fn allows(&self, item: &Item) -> bool {
+        let name = filters::pipeline(item.key()).0;
+        is_path(name) && self.patterns.iter().any(|p| glob(p, name))
+    }
+}
+
+/// Whether `name` is a path of identifiers and indices.
+fn is_path(name: &str) -> bool {
+    segments(name).is_some_and(|segments| {
+        segments.iter().all(|segment| match segment {
+            Segment::Key(key) => {
+                !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || c == '_')
+            }
+            Segment::Index(_) | Segment::All => true,
+        })
+    })
+}
+
+impl<R: Resolver> Resolver for Allowlist<R> {
+    fn resolve<'r>(&'r self, item: &Item) -> Option<Cow<'r, str>> {
+        if self.allows(item) {
+            self.resolver.resolve(item)
+        } else {
+            None
+        }
+    }
+
+    fn resolve_with_outcome<'r>(&'r self, item: &Item) -> (Option<Cow<'r, str>>, Outcome) {
+        if self.allows(item) {
+            self.resolver.resolve_with_outcome(item)
+        } else {
+            (None, Outcome::Unresolved)
+        }
+    }
+}
+
+/// Matches `name` against a glob `pattern` of `*` and `?` wildcards.
+pub(crate) fn glob(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Position of the last `*` and the name position it was tried at.
+    let mut star = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((sp, sn)) => {
+                    p = sp + 1;
+                    n = sn + 1;
+                    star = Some((sp, sn + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn match_globs() {
+        assert!(glob("secret.*", "secret.key"));
+        assert!(!glob("secret.*", "secret"));
+        assert!(!glob("secret.*", "secrets.key"));
+        assert!(glob("*.password", "db.password"));
+        assert!(glob("a?c*", "abc"));
+        assert!(glob("*a*b", "xaxxab"));
+        assert!(!glob("*a*b", "xaxxa"));
+        assert!(glob("", ""));
+        assert!(glob("*", ""));
+    }
+
+    #[test]
+    fn resolve_allowed_names() {
+        let resolver = Allowlist::new(
+            |item: &Item| Some(item.key().to_uppercase()),
+            vec!["tenant_a.*", "name"],
+        );
+        assert_eq!(
+            crate::parse_with("{tenant_a.key} {tenant_b.key} {{ name }} {names}", |item| {
+                resolver.resolve(item)
+            })
+            .unwrap(),
+            "TENANT_A.KEY {tenant_b.key} NAME {names}"
+        );
+
+        let mut tenant_a = std::collections::BTreeMap::new();
+        tenant_a.insert("x".to_owned(), crate::Value::Bool(false));
+        let mut tenant_b = std::collections::BTreeMap::new();
+        tenant_b.insert("password".to_owned(), crate::Value::from("hunter2"));
+        let mut root = std::collections::BTreeMap::new();
+        root.insert("tenant_a".to_owned(), crate::Value::Map(tenant_a));
+        root.insert("tenant_b".to_owned(), crate::Value::Map(tenant_b));
+        let root = crate::Value::Map(root);
+        let expressions = crate::expr::ExprResolver::new(&root);
+        let s = "{tenant_a.x or tenant_b.password}";
+        let render = |resolver: &dyn Resolver| crate::parse_with(s, |item| resolver.resolve(item));
+        assert_eq!(render(&expressions).unwrap(), "hunter2");
+        let resolver = Allowlist::new(expressions, vec!["tenant_a.*"]);
+        assert_eq!(render(&resolver).unwrap(), s);
+        for key in &[
+            "tenant_a.x or tenant_b.password",
+            "tenant_a.x*tenant_b.y",
+            "tenant_a.x[tenant_b.y]",
+            "tenant_a.(x)",
+        ] {
+            assert!(!resolver.allows(&Item::new(Wrapper::Curly, key)), "{}", key);
+        }
+        for key in &[
+            "tenant_a.x",
+            "tenant_a.items[0].y | upper",
+            "tenant_a.items[*]",
+        ] {
+            assert!(resolver.allows(&Item::new(Wrapper::Curly, key)), "{}", key);
+        }
+    }
+
+    #[test]
+    fn deny_names_and_chars() {
+        let policy = Policy::new()
+            .deny("secret.*")
+            .deny("internal")
+            .deny_chars(";&|`$");
+        let violations = policy
+            .check("{{ user }} {{secret.key | upper}} {internal} {a;b} {# secret.x #}")
+            .unwrap();
+        assert_eq!(
+            violations,
+            vec![
+                Violation {
+                    key: "secret.key | upper".to_owned(),
+                    span: Span::new(11, 33),
+                    reason: Reason::Denied("secret.*".to_owned()),
+                },
+                Violation {
+                    key: "internal".to_owned(),
+                    span: Span::new(34, 44),
+                    reason: Reason::Denied("internal".to_owned()),
+                },
+                Violation {
+                    key: "a;b".to_owned(),
+                    span: Span::new(45, 50),
+                    reason: Reason::Character(';'),
+                },
+            ]
+        );
+        assert_eq!(violations[2].to_string(), "`a;b` contains forbidden ';'");
+        assert!(Policy::new().check("{a;b}").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,54 @@
+//! Quote rules, used to leave string literals of the templated language
+//! alone.
+
+/// A kind of quoted span inside which placeholders are not recognized.
+///
+/// A quote is closed by the next unescaped `delimiter`. An opening delimiter
+/// without a matching close is ordinary text.
+#[derive(Clone, PartialEq, Debug)]
+pub struct QuoteRule {
+    pub delimiter: char,
+    /// Character escaping the one following it inside the quote.
+    pub escape: Option<char>,
+}
+
+impl QuoteRule {
+    pub fn new(delimiter: char) -> QuoteRule {
+        QuoteRule {
+            delimiter,
+            escape: None,
+        }
+    }
+
+    pub fn escape(mut self, escape: char) -> Self {
+        self.escape = Some(escape);
+        self
+    }
+
+    /// Single and double quotes with backslash escapes, as in C or JavaScript.
+    pub fn c_style() -> Vec<QuoteRule> {
+        vec![
+            QuoteRule::new('"').escape('\\'),
+            QuoteRule::new('\'').escape('\\'),
+        ]
+    }
+
+    /// Single and double quotes without escapes, as in SQL, where a doubled
+    /// quote simply closes and reopens the span.
+    pub fn sql() -> Vec<QuoteRule> {
+        vec![QuoteRule::new('\''), QuoteRule::new('"')]
+    }
+}
+
+/// Finds the end of a quote opened by `rule` just before `s`.
+pub(crate) fn closing(s: &str, rule: &QuoteRule) -> Option<usize> {
+    let mut chars = s.char_indices();
+    while let Some((pos, c)) = chars.next() {
+        if Some(c) == rule.escape {
+            chars.next();
+        } else if c == rule.delimiter {
+            return Some(pos + c.len_utf8());
+        }
+    }
+    None
+}
@@ -0,0 +1,1196 @@
+//! Ready-made resolvers for common sources of values.
+//!
+//! A [`Resolver`] maps an [`Item`] to its substitution. Any mapper closure
+//! accepted by [`parse_with`](crate::parse_with) is a resolver, and resolvers
+//! can be handed to it with `|item| resolver.resolve(item)`.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::io::{self, IoSlice, Write};
+use std::marker::PhantomData;
+use std::slice;
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use pest::error::Error;
+
+use crate::{parse, Element, Item, OwnedItem, Rule, Value, Wrapper};
+
+/// Produces substitutions for placeholders, or `None` to leave them as-is.
+///
+/// Values borrowed from the resolver itself, like those of a map, can be
+/// returned without allocating.
+pub trait Resolver {
+    fn resolve<'r>(&'r self, item: &Item) -> Option<Cow<'r, str>>;
+
+    /// Resolves `item` like [`resolve`](Resolver::resolve), also telling how
+    /// the value was arrived at.
+    ///
+    /// By default, any value counts as [`Outcome::Resolved`]. Resolvers
+    /// applying defaults or failing on some placeholders override this.
+    fn resolve_with_outcome<'r>(&'r self, item: &Item) -> (Option<Cow<'r, str>>, Outcome) {
+        let value = self.resolve(item);
+        let outcome = if value.is_some() {
+            Outcome::Resolved
+        } else {
+            Outcome::Unresolved
+        };
+        (value, outcome)
+    }
+}
+
+/// How a placeholder was handled, as recorded in a [`RenderReport`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Outcome {
+    Resolved,
+    /// Substituted with a default, such as a fallback value or the empty
+    /// string for a null.
+    Defaulted,
+    /// Left as-is.
+    Unresolved,
+    /// Left as-is because resolving it failed.
+    Errored,
+}
+
+/// Keys of the placeholders of a rendered template by [`Outcome`], in order
+/// of appearance.
+///
+/// Comparing these across renders helps detecting templates drifting out of
+/// sync with their contexts.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct RenderReport {
+    pub resolved: Vec<String>,
+    pub defaulted: Vec<String>,
+    pub unresolved: Vec<String>,
+    pub errored: Vec<String>,
+}
+
+impl RenderReport {
+    pub fn record(&mut self, item: &Item, outcome: Outcome) {
+        let keys = match outcome {
+            Outcome::Resolved => &mut self.resolved,
+            Outcome::Defaulted => &mut self.defaulted,
+            Outcome::Unresolved => &mut self.unresolved,
+            Outcome::Errored => &mut self.errored,
+        };
+        keys.push(item.key().to_owned());
+    }
+
+    /// Total number of placeholders.
+    pub fn total(&self) -> usize {
+        self.resolved.len() + self.defaulted.len() + self.unresolved.len() + self.errored.len()
+    }
+}
+
+/// Renders `elements` with `resolver`, reporting how each placeholder was
+/// handled.
+pub fn render_with_report<R>(elements: &[Element], resolver: &R) -> (String, RenderReport)
+where
+    R: Resolver + ?Sized,
+{
+    let mut result = String::new();
+    let mut report = RenderReport::default();
+    for el in elements {
+        match el {
+            Element::Text(t) | Element::Escaped { text: t, .. } => result.push_str(t),
+            Element::Wrapped(item) => {
+                let (value, outcome) = resolver.resolve_with_outcome(item);
+                match value {
+                    Some(value) => result.push_str(&value),
+                    None => item.push_source(&mut result),
+                }
+                report.record(item, outcome);
+            }
+        }
+    }
+    (result, report)
+}
+
+/// Renders `elements` with `resolver` as the pieces of the output, without
+/// joining them into a `String`.
+///
+/// Text and unresolved placeholders are borrowed from the template, and
+/// values from the resolver if it returns them borrowed.
+///
+/// ```
+/// use spongy::resolve::segments;
+///
+/// let name = String::from("world");
+/// let elements = spongy::parse("Hello, {name}!").unwrap();
+/// let resolver = |_: &spongy::Item| Some(name.clone());
+/// let pieces: Vec<_> = segments(&elements, &resolver).collect();
+/// assert_eq!(pieces, ["Hello, ", "world", "!"]);
+/// ```
+pub fn segments<'a, 'e, R>(elements: &'a [Element<'e>], resolver: &'a R) -> Segments<'a, 'e, R>
+where
+    R: Resolver + ?Sized,
+{
+    Segments {
+        elements: elements.iter(),
+        resolver,
+    }
+}
+
+/// Iterator returned by [`segments`].
+pub struct Segments<'a, 'e, R: ?Sized> {
+    elements: slice::Iter<'a, Element<'e>>,
+    resolver: &'a R,
+}
+
+impl<'a, R: Resolver + ?Sized> Iterator for Segments<'a, '_, R> {
+    type Item = Cow<'a, str>;
+
+    fn next(&mut self) -> Option<Cow<'a, str>> {
+        Some(match self.elements.next()? {
+            Element::Text(t) | Element::Escaped { text: t, .. } => Cow::Borrowed(*t),
+            Element::Wrapped(item) => match self.resolver.resolve(item) {
+                Some(value) => value,
+                None => item.raw(),
+            },
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.elements.size_hint()
+    }
+}
+
+/// Writes the [`segments`] of `elements` to `out` with vectored writes.
+pub fn write_segments<W, R>(out: &mut W, elements: &[Element], resolver: &R) -> io::Result<()>
+where
+    W: Write + ?Sized,
+    R: Resolver + ?Sized,
+{
+    /// Segments handed to a single vectored write.
+    const BATCH: usize = 64;
+
+    let mut segments = segments(elements, resolver).peekable();
+    let mut batch = Vec::with_capacity(BATCH);
+    while segments.peek().is_some() {
+        batch.clear();
+        batch.extend(segments.by_ref().take(BATCH));
+        let mut slices: Vec<IoSlice> = batch.iter().map(|s| IoSlice::new(s.as_bytes())).collect();
+        let mut slices = &mut slices[..];
+        while !slices.is_empty() {
+            match out.write_vectored(slices) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => IoSlice::advance_slices(&mut slices, n),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Binds `elements` to `resolver`, rendering them when formatted, so a
+/// template can be used in `write!`, `println!` or logging macros without
+/// being rendered into a `String` first.
+///
+/// ```
+/// use spongy::resolve::display;
+///
+/// let elements = spongy::parse("user {name} logged in").unwrap();
+/// let resolver = |_: &spongy::Item| Some("ann");
+/// assert_eq!(format!("[auth] {}", display(&elements, &resolver)), "[auth] user ann logged in");
+/// ```
+///
+/// Width and precision are not applied, as the output is written piece by
+/// piece.
+pub fn display<'a, 'e, R>(elements: &'a [Element<'e>], resolver: &'a R) -> Display<'a, 'e, R>
+where
+    R: Resolver + ?Sized,
+{
+    Display { elements, resolver }
+}
+
+/// Value returned by [`display`].
+pub struct Display<'a, 'e, R: ?Sized> {
+    elements: &'a [Element<'e>],
+    resolver: &'a R,
+}
+
+impl<R: Resolver + ?Sized> fmt::Display for Display<'_, '_, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for segment in segments(self.elements, self.resolver) {
+            f.write_str(&segment)?;
+        }
+        Ok(())
+    }
+}
+
+/// Closures returning owned or `'static` values are resolvers. Those
+/// returning values borrowed from elsewhere, like
+/// `|item| map.get(item.text).map(String::as_str)`, are wrapped in
+/// [`borrowing`] instead.
+impl<F, V> Resolver for F
+where
+    F: Fn(&Item) -> Option<V>,
+    V: Into<Cow<'static, str>>,
+{
+    fn resolve<'r>(&'r self, item: &Item) -> Option<Cow<'r, str>> {
+        self(item).map(Into::into)
+    }
+}
+
+/// Makes a resolver of a closure returning values which live for `'v`, such
+/// as ones borrowed from a map.
+///
+/// ```
+/// use std::collections::HashMap;
+/// use spongy::resolve::{borrowing, display};
+///
+/// let mut map = HashMap::new();
+/// map.insert("name".to_owned(), "Ann".to_owned());
+/// let resolver = borrowing(|item: &spongy::Item| map.get(item.text).map(String::as_str));
+/// let elements = spongy::parse("Hi {name}").unwrap();
+/// assert_eq!(display(&elements, &resolver).to_string(), "Hi Ann");
+/// ```
+pub fn borrowing<'v, F, V>(f: F) -> Borrowing<'v, F>
+where
+    F: Fn(&Item) -> Option<V>,
+    V: Into<Cow<'v, str>>,
+{
+    Borrowing {
+        f,
+        values: PhantomData,
+    }
+}
+
+/// Resolver returned by [`borrowing`].
+pub struct Borrowing<'v, F> {
+    f: F,
+    /// Ties values to `'v`, which outlives any borrow of the resolver.
+    values: PhantomData<&'v str>,
+}
+
+impl<'v, F, V> Resolver for Borrowing<'v, F>
+where
+    F: Fn(&Item) -> Option<V>,
+    V: Into<Cow<'v, str>>,
+{
+    fn resolve<'r>(&'r self, item: &Item) -> Option<Cow<'r, str>> {
+        (self.f)(item).map(Into::into)
+    }
+}
+
+/// Resolves placeholders by looking their text up in a map.
+pub struct MapResolver<'m, V> {
+    map: &'m HashMap<String, V>,
+    folded: Option<HashMap<String, &'m V>>,
+}
+
+impl<'m, V> MapResolver<'m, V>
+where
+    V: AsRef<str>,
+{
+    pub fn new(map: &'m HashMap<String, V>) -> MapResolver<'m, V> {
+        MapResolver { map, folded: None }
+    }
+
+    /// Match keys case-insensitively, so `${path}` finds `PATH`.
+    ///
+    /// If several keys fold to the same name, which one wins is unspecified.
+    pub fn ignore_case(mut self, ignore_case: bool) -> Self {
+        self.folded = if ignore_case {
+            Some(
+                self.map
+                    .iter()
+                    .map(|(k, v)| (k.to_lowercase(), v))
+                    .collect(),
+            )
+        } else {
+            None
+        };
+        self
+    }
+}
+
+impl<V> Resolver for MapResolver<'_, V>
+where
+    V: AsRef<str>,
+{
+    fn resolve<'r>(&'r self, item: &Item) -> Option<Cow<'r, str>> {
+        let value = match &self.folded {
+            Some(folded) => folded.get(&item.text.to_lowercase()).copied(),
+            None => self.map.get(item.text),
+        };
+        value.map(|v| Cow::Borrowed(v.as_ref()))
+    }
+}
+
+/// How a [`ValueResolver`] treats a path that is missing or null, or a
+/// [`Timeout`] a lookup that took too long.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Absent {
+    /// Leave the placeholder unresolved.
+    Keep,
+    /// Substitute an empty string.
+    Empty,
+    /// Substitute the given value.
+    Default(String),
+    /// Fail with a [`LookupError`] in [`ValueResolver::try_render`], and
+    /// leave the placeholder unresolved otherwise, reporting it as
+    /// [`Outcome::Errored`].
+    Error,
+}
+
+#[derive(Debug)]
+pub enum LookupError {
+    Parse(Box<Error<Rule>>),
+    /// Nothing was found at `path`.
+    Missing {
+        path: String,
+    },
+    /// The value at `path` is null.
+    Null {
+        path: String,
+    },
+}
+
+impl fmt::Display for LookupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LookupError::Parse(e) => e.fmt(f),
+            LookupError::Missing { path } => write!(f, "no value for {:?}", path),
+            LookupError::Null { path } => write!(f, "value of {:?} is null", path),
+        }
+    }
+}
+
+impl std::error::Error for LookupError {}
+
+impl From<Error<Rule>> for LookupError {
+    fn from(e: Error<Rule>) -> Self {
+        LookupError::Parse(Box::new(e))
+    }
+}
+
+/// Resolves placeholders as [paths](Value::lookup) into a structured value.
+///
+/// Paths with [wildcards](Value::lookup_all) resolve to all their matches,
+/// joined with a separator, and count as missing if nothing matches.
+/// Missing paths are left unresolved and null values substituted as empty
+/// strings, unless configured otherwise.
+pub struct ValueResolver<'v> {
+    root: &'v Value,
+    separator: Cow<'v, str>,
+    missing: Absent,
+    null: Absent,
+}
+
+impl<'v> ValueResolver<'v> {
+    pub fn new(root: &'v Value) -> ValueResolver<'v> {
+        ValueResolver {
+            root,
+            separator: Cow::Borrowed(", "),
+            missing: Absent::Keep,
+            null: Absent::Empty,
+        }
+    }
+
+    /// Separator between the matches of a wildcard path, `", "` by default.
+    pub fn separator<S: Into<Cow<'v, str>>>(mut self, separator: S) -> Self {
+        self.separator = separator.into();
+        self
+    }
+
+    pub fn on_missing(mut self, policy: Absent) -> Self {
+        self.missing = policy;
+        self
+    }
+
+    pub fn on_null(mut self, policy: Absent) -> Self {
+        self.null = policy;
+        self
+    }
+
+    /// Resolves `item`, failing where the policies say so.
+    pub fn try_resolve(&self, item: &Item) -> Result<Option<Cow<'_, str>>, LookupError> {
+        Ok(self.try_resolve_with_outcome(item)?.0)
+    }
+
+    fn try_resolve_with_outcome(
+        &self,
+        item: &Item,
+    ) -> Result<(Option<Cow<'_, str>>, Outcome), LookupError> {
+        let path = item.key();
+        let absent = |policy: &Absent, error: LookupError| match policy {
+            Absent::Keep => Ok((None, Outcome::Unresolved)),
+            Absent::Empty => Ok((Some(Cow::Borrowed("")), Outcome::Defaulted)),
+            Absent::Default(value) => Ok((Some(Cow::Owned(value.clone())), Outcome::Defaulted)),
+            Absent::Error => Err(error),
+        };
+
+        if !path.contains("[*]") {
+            return match self.root.lookup(path) {
+                None => absent(
+                    &self.missing,
+                    LookupError::Missing {
+                        path: path.to_owned(),
+                    },
+                ),
+                Some(Value::Null) => absent(
+                    &self.null,
+                    LookupError::Null {
+                        path: path.to_owned(),
+                    },
+                ),
+                Some(value) => Ok((Some(value.to_text()), Outcome::Resolved)),
+            };
+        }
+
+        let values = self.root.lookup_all(path);
+        if values.is_empty() {
+            return absent(
+                &self.missing,
+                LookupError::Missing {
+                    path: path.to_owned(),
+                },
+            );
+        }
+        let texts: Vec<Cow<str>> = values.into_iter().map(Value::to_text).collect();
+        Ok((
+            Some(Cow::Owned(texts.join(&*self.separator))),
+            Outcome::Resolved,
+        ))
+    }
+
+    /// Renders `s`, failing on the first placeholder whose policy is
+    /// [`Absent::Error`].
+    pub fn try_render(&self, s: &str) -> Result<String, LookupError> {
+        let mut result = String::with_capacity(s.len());
+        for el in parse(s)? {
+            match el {
+                Element::Text(t) | Element::Escaped { text: t, .. } => result.push_str(t),
+                Element::Wrapped(item) => match self.try_resolve(&item)? {
+                    Some(value) => result.push_str(&value),
+                    None => item.push_source(&mut result),
+                },
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl Resolver for ValueResolver<'_> {
+    fn resolve<'r>(&'r self, item: &Item) -> Option<Cow<'r, str>> {
+        self.try_resolve(item).ok().flatten()
+    }
+
+    fn resolve_with_outcome<'r>(&'r self, item: &Item) -> (Option<Cow<'r, str>>, Outcome) {
+        self.try_resolve_with_outcome(item)
+            .unwrap_or((None, Outcome::Errored))
+    }
+}
+
+/// Falls back to a table of defaults for variables another resolver leaves
+/// unresolved, so templates need not spell defaults out.
+///
+/// Defaults are looked up by the [key](Item::key) of the placeholder.
+pub struct Defaults<'d, R, V> {
+    resolver: R,
+    defaults: &'d HashMap<String, V>,
+}
+
+impl<'d, R, V> Defaults<'d, R, V>
+where
+    R: Resolver,
+    V: AsRef<str>,
+{
+    pub fn new(resolver: R, defaults: &'d HashMap<String, V>) -> Defaults<'d, R, V> {
+        Defaults { resolver, defaults }
+    }
+}
+
+impl<R, V> Resolver for Defaults<'_, R, V>
+where
+    R: Resolver,
+    V: AsRef<str>,
+{
+    fn resolve<'r>(&'r self, item: &Item) -> Option<Cow<'r, str>> {
+        self.resolver.resolve(item).or_else(|| {
+            self.defaults
+                .get(item.key())
+                .map(|v| Cow::Borrowed(v.as_ref()))
+        })
+    }
+
+    fn resolve_with_outcome<'r>(&'r self, item: &Item) -> (Option<Cow<'r, str>>, Outcome) {
+        match self.resolver.resolve_with_outcome(item) {
+            (None, outcome) => match self.defaults.get(item.key()) {
+                Some(v) => (Some(Cow::Borrowed(v.as_ref())), Outcome::Defaulted),
+                None => (None, outcome),
+            },
+            resolved => resolved,
+        }
+    }
+}
+
+/// Bounds the time another resolver may take per placeholder, e.g. one
+/// fetching values over the network.
+///
+/// Each lookup runs on its own thread. One which does not finish in time is
+/// treated as the [`Absent`] policy set with
+/// [`on_timeout`](Timeout::on_timeout) says, leaving the placeholder
+/// unresolved by default.
+///
+/// A lookup which timed out is abandoned rather than cancelled: its thread
+/// keeps running until the inner resolver returns, and its result is
+/// dropped. At most [`max_in_flight`](Timeout::max_in_flight) lookups run at
+/// once, abandoned ones included, so a resolver which hangs ties up a
+/// bounded number of threads; once they are all taken, further lookups wait
+/// for one to finish within their own timeout, and time out otherwise.
+pub struct Timeout<R> {
+    resolver: Arc<R>,
+    timeout: Duration,
+    on_timeout: Absent,
+    max_in_flight: usize,
+    in_flight: Arc<InFlight>,
+}
+
+/// Number of lookups running on behalf of a [`Timeout`].
+#[derive(Default)]
+struct InFlight {
+    count: Mutex<usize>,
+    finished: Condvar,
+}
+
+/// A lookup counted in its [`InFlight`] until dropped.
+struct Slot(Arc<InFlight>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        *self.0.count.lock().unwrap_or_else(|e| e.into_inner()) -= 1;
+        self.0.finished.notify_one();
+    }
+}
+
+impl<R> Timeout<R>
+where
+    R: Resolver + Send + Sync + 'static,
+{
+    pub fn new(resolver: R, timeout: Duration) -> Timeout<R> {
+        Timeout {
+            resolver: Arc::new(resolver),
+            timeout,
+            on_timeout: Absent::Keep,
+            max_in_flight: 16,
+            in_flight: Arc::default(),
+        }
+    }
+
+    pub fn on_timeout(mut self, policy: Absent) -> Self {
+        self.on_timeout = policy;
+        self
+    }
+
+    /// Runs at most `max` lookups at once, or 1 if `max` is 0. Defaults to
+    /// 16.
+    pub fn max_in_flight(mut self, max: usize) -> Self {
+        self.max_in_flight = max.max(1);
+        self
+    }
+
+    /// Waits until a lookup may start before `deadline`.
+    fn acquire(&self, deadline: Instant) -> Option<Slot> {
+        let mut count = self
+            .in_flight
+            .count
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        while *count >= self.max_in_flight {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return None;
+            }
+            count = match self.in_flight.finished.wait_timeout(count, remaining) {
+                Ok((count, _)) => count,
+                Err(e) => e.into_inner().0,
+            };
+        }
+        *count += 1;
+        Some(Slot(Arc::clone(&self.in_flight)))
+    }
+
+    fn timed_out(&self) -> (Option<Cow<'_, str>>, Outcome) {
+        match &self.on_timeout {
+            Absent::Keep => (None, Outcome::Unresolved),
+            Absent::Empty => (Some(Cow::Borrowed("")), Outcome::Defaulted),
+            Absent::Default(value) => (Some(Cow::Borrowed(value)), Outcome::Defaulted),
+            Absent::Error => (None, Outcome::Errored),
+        }
+    }
+}
+
+impl<R> Resolver for Timeout<R>
+where
+    R: Resolver + Send + Sync + 'static,
+{
+    fn resolve<'r>(&'r self, item: &Item) -> Option<Cow<'r, str>> {
+        self.resolve_with_outcome(item).0
+    }
+
+    fn resolve_with_outcome<'r>(&'r self, item: &Item) -> (Option<Cow<'r, str>>, Outcome) {
+        let deadline = Instant::now() + self.timeout;
+        let slot = match self.acquire(deadline) {
+            Some(slot) => slot,
+            None => return self.timed_out(),
+        };
+        let (sender, receiver) = mpsc::channel();
+        let resolver = Arc::clone(&self.resolver);
+        let item = OwnedItem::from(item);
+        thread::spawn(move || {
+            let (value, outcome) = resolver.resolve_with_outcome(&item.as_item());
+            drop(slot);
+            // The receiver is gone if the lookup timed out.
+            let _ = sender.send((value.map(Cow::into_owned), outcome));
+        });
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match receiver.recv_timeout(remaining) {
+            Ok((value, outcome)) => (value.map(Cow::Owned), outcome),
+            Err(_) => self.timed_out(),
+        }
+    }
+}
+
+/// Cached result of a lookup.
+struct Memo {
+    value: Option<String>,
+    outcome: Outcome,
+    at: Instant,
+}
+
+/// Caches the lookups of another resolver by wrapper and text, so repeated
+/// placeholders cost a single lookup.
+///
+/// Entries are kept for the lifetime of the cache, so a cache created per
+/// render caches within that render. To share one across renders, set a
+/// [`ttl`](Memoized::ttl) or [`clear`](Memoized::clear) it when values may
+/// have changed. Failed lookups are not cached, so they are tried again.
+///
+/// The cache is not locked during lookups, so threads sharing it look up
+/// different placeholders in parallel, and the same placeholder looked up
+/// on several threads at once may reach the resolver more than once.
+pub struct Memoized<R> {
+    resolver: R,
+    ttl: Option<Duration>,
+    cache: Mutex<HashMap<(Wrapper, String), Memo>>,
+}
+
+impl<R: Resolver> Memoized<R> {
+    pub fn new(resolver: R) -> Memoized<R> {
+        Memoized {
+            resolver,
+            ttl: None,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Looks values up again once their entry is older than `ttl`.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Forgets all cached lookups.
+    pub fn clear(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+}
+
+impl<R: Resolver> Resolver for Memoized<R> {
+    fn resolve<'r>(&'r self, item: &Item) -> Option<Cow<'r, str>> {
+        self.resolve_with_outcome(item).0
+    }
+
+    fn resolve_with_outcome<'r>(&'r self, item: &Item) -> (Option<Cow<'r, str>>, Outcome) {
+        let key = (item.wrapper, item.text.to_owned());
+        if let Some(memo) = self.cache.lock().unwrap().get(&key) {
+            if self.ttl.is_none_or(|ttl| memo.at.elapsed() < ttl) {
+                return (memo.value.clone().map(Cow::Owned), memo.outcome);
+            }
+        }
+
+        let (value, outcome) = self.resolver.resolve_with_outcome(item);
+        if outcome == Outcome::Errored {
+            return (value, outcome);
+        }
+        let value = value.map(Cow::into_owned);
+        self.cache.lock().unwrap().insert(
+            key,
+            Memo {
+                value: value.clone(),
+                outcome,
+                at: Instant::now(),
+            },
+        );
+        (value.map(Cow::Owned), outcome)
+    }
+}
+
+/// Resolves placeholders from the process environment.
+#[derive(Default)]
+pub struct EnvResolver {
+    ignore_case: bool,
+}
+
+impl EnvResolver {
+    pub fn new() -> EnvResolver {
+        EnvResolver::default()
+    }
+
+    /// Match variable names case-insensitively, as Windows does.
+    pub fn ignore_case(mut self, ignore_case: bool) -> Self {
+        self.ignore_case = ignore_case;
+        self
+    }
+}
+
+impl Resolver for EnvResolver {
+    fn resolve<'r>(&'r self, item: &Item) -> Option<Cow<'r, str>> {
+        if item.text.is_empty() {
+            return None;
+        }
+
+        if let Ok(value) = env::var(item.text) {
+            return Some(Cow::Owned(value));
+        }
+
+        if self.ignore_case {
+            // `env::vars` panics on variables which are not UTF-8, which
+            // cannot match anyway.
+            let key = item.text.to_lowercase();
+            env::vars_os()
+                .filter(|(k, _)| k.to_str().is_some_and(|k| k.to_lowercase() == key))
+                .find_map(|(_, v)| v.into_string().ok())
+                .map(Cow::Owned)
+        } else {
+            None
+        }
+    }
+}
+
+/// Dispatches to a separate resolver per wrapper kind.
+///
+/// Placeholders of a kind without a resolver are left unresolved. Several
+/// resolvers registered for the same kind are tried in order until one
+/// resolves the placeholder.
+#[derive(Default)]
+pub struct ByWrapper<'r> {
+    resolvers: Vec<(Wrapper, Box<dyn Resolver + 'r>)>,
+}
+
+impl<'r> ByWrapper<'r> {
+    pub fn new() -> ByWrapper<'r> {
+        ByWrapper::default()
+    }
+
+    pub fn on<F, V>(self, wrapper: Wrapper, mapper: F) -> Self
+    where
+        F: Fn(&Item) -> Option<V> + 'r,
+        V: Into<Cow<'static, str>>,
+    {
+        self.on_resolver(wrapper, mapper)
+    }
+
+    pub fn on_resolver<R>(mut self, wrapper: Wrapper, resolver: R) -> Self
+    where
+        R: Resolver + 'r,
+    {
+        self.resolvers.push((wrapper, Box::new(resolver)));
+        self
+    }
+}
+
+impl Resolver for ByWrapper<'_> {
+    fn resolve<'r>(&'r self, item: &Item) -> Option<Cow<'r, str>> {
+        self.resolvers
+            .iter()
+            .filter(|(wrapper, _)| *wrapper == item.wrapper)
+            .find_map(|(_, resolver)| resolver.resolve(item))
+    }
+
+    /// The outcome of the first resolver producing a value, or
+    /// [`Outcome::Errored`] if none does and one of them failed.
+    fn resolve_with_outcome<'r>(&'r self, item: &Item) -> (Option<Cow<'r, str>>, Outcome) {
+        let mut outcome = Outcome::Unresolved;
+        for (wrapper, resolver) in &self.resolvers {
+            if *wrapper != item.wrapper {
+                continue;
+            }
+            match resolver.resolve_with_outcome(item) {
+                (None, Outcome::Errored) => outcome = Outcome::Errored,
+                (None, _) => {}
+                resolved => return resolved,
+            }
+        }
+        (None, outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_with, Wrapper};
+
+    #[test]
+    fn render_segments() {
+        use std::fmt::Write as _;
+
+        let mut map = HashMap::new();
+        map.insert("name".to_owned(), "Ann".to_owned());
+        let resolver = MapResolver::new(&map);
+        let elements = parse("Hi {name}, {{x}}{name}").unwrap();
+        let pieces: Vec<_> = segments(&elements, &resolver).collect();
+        assert_eq!(pieces, ["Hi ", "Ann", ", ", "{{x}}", "Ann"]);
+        assert!(pieces.iter().all(|s| matches!(s, Cow::Borrowed(_))));
+
+        let s = "{a}-".repeat(100);
+        let elements = parse(&s).unwrap();
+        let mut out = vec![];
+        write_segments(&mut out, &elements, &|_: &Item| Some("b")).unwrap();
+        assert_eq!(out, "b-".repeat(100).as_bytes());
+
+        let elements = parse("Hi {name}, {{x}}!").unwrap();
+        let mut line = String::new();
+        writeln!(line, "> {}", display(&elements, &resolver)).unwrap();
+        assert_eq!(line, "> Hi Ann, {{x}}!\n");
+        assert_eq!(display(&[], &resolver).to_string(), "");
+
+        let resolver = borrowing(|item: &Item| map.get(item.text).map(String::as_str));
+        let pieces: Vec<_> = segments(&elements, &resolver).collect();
+        assert_eq!(pieces, ["Hi ", "Ann", ", ", "{{x}}", "!"]);
+        assert!(pieces.iter().all(|s| matches!(s, Cow::Borrowed(_))));
+    }
+
+    #[test]
+    fn resolve_from_map() {
+        let mut map = HashMap::new();
+        map.insert("PATH".to_owned(), "/bin");
+
+        let resolver = MapResolver::new(&map);
+        assert_eq!(
+            resolver.resolve(&Item::new(Wrapper::DollarCurly, "PATH")),
+            Some(Cow::Borrowed("/bin"))
+        );
+        assert_eq!(
+            resolver.resolve(&Item::new(Wrapper::DollarCurly, "path")),
+            None
+        );
+
+        let resolver = resolver.ignore_case(true);
+        assert_eq!(
+            parse_with("${path}:${Path}:${HOME}", |item| resolver.resolve(item)).unwrap(),
+            "/bin:/bin:${HOME}"
+        );
+    }
+
+    #[test]
+    fn resolve_from_value() {
+        let mut map = HashMap::new();
+        map.insert("n".to_owned(), Value::from(vec![1, 2]));
+        let root = Value::from(map);
+
+        let resolver = ValueResolver::new(&root);
+        assert_eq!(
+            parse_with("{{ n }} {{n.0}} {{n.5}}", |item| resolver.resolve(item)).unwrap(),
+            "1, 2 1 {{n.5}}"
+        );
+
+        let resolver = resolver.separator("/");
+        assert_eq!(
+            parse_with("{{n[*]}} {{n[1]}} {{m[*]}}", |item| resolver.resolve(item)).unwrap(),
+            "1/2 2 {{m[*]}}"
+        );
+    }
+
+    #[test]
+    fn missing_and_null() {
+        let mut map = HashMap::new();
+        map.insert("a".to_owned(), Value::Null);
+        map.insert("b".to_owned(), Value::from("B"));
+        let root = Value::from(map);
+        let s = "{{a}} {{b}} {{c}} {{c[*]}}";
+
+        assert_eq!(
+            ValueResolver::new(&root).try_render(s).unwrap(),
+            " B {{c}} {{c[*]}}"
+        );
+
+        let resolver = ValueResolver::new(&root)
+            .on_missing(Absent::Empty)
+            .on_null(Absent::Default("-".to_owned()));
+        assert_eq!(resolver.try_render(s).unwrap(), "- B  ");
+
+        let resolver = ValueResolver::new(&root).on_null(Absent::Keep);
+        assert_eq!(
+            parse_with(s, |item| resolver.resolve(item)).unwrap(),
+            "{{a}} B {{c}} {{c[*]}}"
+        );
+
+        let resolver = ValueResolver::new(&root).on_missing(Absent::Error);
+        assert!(matches!(
+            resolver.try_render(s),
+            Err(LookupError::Missing { path }) if path == "c"
+        ));
+        assert_eq!(
+            parse_with(s, |item| resolver.resolve(item)).unwrap(),
+            " B {{c}} {{c[*]}}"
+        );
+
+        let resolver = ValueResolver::new(&root).on_null(Absent::Error);
+        assert!(matches!(
+            resolver.try_render(s),
+            Err(LookupError::Null { path }) if path == "a"
+        ));
+    }
+
+    #[test]
+    fn fall_back_to_defaults() {
+        let mut defaults = HashMap::new();
+        defaults.insert("port".to_owned(), "8080");
+        defaults.insert("host".to_owned(), "localhost");
+
+        let resolver = Defaults::new(
+            |item: &Item| match item.key() {
+                "host" => Some("example.com"),
+                _ => None,
+            },
+            &defaults,
+        );
+        assert_eq!(
+            parse_with("{{ host }}:{{ port }}/{{path}}", |item| resolver
+                .resolve(item))
+            .unwrap(),
+            "example.com:8080/{{path}}"
+        );
+    }
+
+    #[test]
+    fn report_outcomes() {
+        let mut map = HashMap::new();
+        map.insert("a".to_owned(), Value::Null);
+        map.insert("b".to_owned(), Value::from("B"));
+        let root = Value::from(map);
+        let mut defaults = HashMap::new();
+        defaults.insert("d".to_owned(), "D");
+
+        let resolver = Defaults::new(
+            ValueResolver::new(&root).on_missing(Absent::Error),
+            &defaults,
+        );
+        let elements = parse("{{a}} {{ b }} {{c}} {{d}} {{b}}").unwrap();
+        let (result, report) = render_with_report(&elements, &resolver);
+        assert_eq!(result, " B {{c}} D B");
+        assert_eq!(report.resolved, vec!["b", "b"]);
+        assert_eq!(report.defaulted, vec!["a", "d"]);
+        assert_eq!(report.errored, vec!["c"]);
+        assert!(report.unresolved.is_empty());
+        assert_eq!(report.total(), 5);
+
+        let resolver = ByWrapper::new()
+            .on_resolver(
+                Wrapper::DoubleCurly,
+                ValueResolver::new(&root).on_missing(Absent::Error),
+            )
+            .on(Wrapper::DoubleCurly, |item| match item.text {
+                "d" => Some("D"),
+                _ => None,
+            });
+        let (_, report) = render_with_report(&parse("{{d}} {{c}} {x}").unwrap(), &resolver);
+        assert_eq!(report.resolved, vec!["d"]);
+        assert_eq!(report.errored, vec!["c"]);
+        assert_eq!(report.unresolved, vec!["x"]);
+    }
+
+    #[test]
+    fn time_out_slow_lookups() {
+        let slow = |item: &Item| {
+            if item.key() == "slow" {
+                thread::sleep(Duration::from_millis(200));
+            }
+            Some(item.key().to_uppercase())
+        };
+        let resolver = Timeout::new(slow, Duration::from_millis(50));
+        assert_eq!(
+            parse_with("{fast} {slow}", |item| resolver.resolve(item)).unwrap(),
+            "FAST {slow}"
+        );
+
+        let resolver = resolver.on_timeout(Absent::Default("?".to_owned()));
+        assert_eq!(
+            parse_with("{fast} {slow}", |item| resolver.resolve(item)).unwrap(),
+            "FAST ?"
+        );
+
+        let resolver = Timeout::new(slow, Duration::from_millis(50)).on_timeout(Absent::Error);
+        let (_, report) = render_with_report(&parse("{slow}").unwrap(), &resolver);
+        assert_eq!(report.errored, vec!["slow"]);
+    }
+
+    #[test]
+    fn bound_lookups_in_flight() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&calls);
+        let hanging = move |item: &Item| {
+            counted.fetch_add(1, Ordering::SeqCst);
+            thread::sleep(Duration::from_secs(1));
+            Some(item.key().to_owned())
+        };
+        let resolver = Timeout::new(hanging, Duration::from_millis(50)).max_in_flight(2);
+        let start = Instant::now();
+        assert_eq!(
+            parse_with("{a} {b} {c} {d}", |item| resolver.resolve(item)).unwrap(),
+            "{a} {b} {c} {d}"
+        );
+        // The first two lookups hold both slots for a second, so the others
+        // time out waiting for one without reaching the resolver.
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        thread::sleep(Duration::from_millis(1200));
+        assert_eq!(resolver.resolve(&Item::new(Wrapper::Curly, "e")), None);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn memoize_lookups() {
+        use std::cell::Cell;
+
+        let calls = Cell::new(0);
+        let counting = |item: &Item| {
+            calls.set(calls.get() + 1);
+            match item.key() {
+                "name" => Some("Ann"),
+                _ => None,
+            }
+        };
+        let resolver = Memoized::new(counting);
+        assert_eq!(
+            parse_with("{{ name }} {{name}} {name} {x} {x}", |item| resolver
+                .resolve(item))
+            .unwrap(),
+            "Ann Ann Ann {x} {x}"
+        );
+        assert_eq!(calls.get(), 4);
+
+        resolver.clear();
+        resolver.resolve(&Item::new(Wrapper::Curly, "name"));
+        assert_eq!(calls.get(), 5);
+
+        let resolver = Memoized::new(counting).ttl(Duration::from_millis(20));
+        let item = Item::new(Wrapper::Curly, "name");
+        resolver.resolve(&item);
+        resolver.resolve(&item);
+        assert_eq!(calls.get(), 6);
+        thread::sleep(Duration::from_millis(30));
+        resolver.resolve(&item);
+        assert_eq!(calls.get(), 7);
+    }
+
+    #[test]
+    fn memoize_without_errors_or_locking() {
+        use std::cell::Cell;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        struct Failing(Cell<usize>);
+
+        impl Resolver for Failing {
+            fn resolve<'r>(&'r self, item: &Item) -> Option<Cow<'r, str>> {
+                self.resolve_with_outcome(item).0
+            }
+
+            fn resolve_with_outcome<'r>(&'r self, _: &Item) -> (Option<Cow<'r, str>>, Outcome) {
+                self.0.set(self.0.get() + 1);
+                (None, Outcome::Errored)
+            }
+        }
+
+        let resolver = Memoized::new(Failing(Cell::new(0)));
+        let (_, report) = render_with_report(&parse("{a} {a}").unwrap(), &resolver);
+        assert_eq!(report.errored, vec!["a", "a"]);
+        assert_eq!(resolver.resolver.0.get(), 2);
+
+        let (running, most) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        let slow = |item: &Item| {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            most.fetch_max(now, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(50));
+            running.fetch_sub(1, Ordering::SeqCst);
+            Some(item.key().to_owned())
+        };
+        let resolver = Memoized::new(slow);
+        thread::scope(|scope| {
+            for key in &["a", "b"] {
+                let resolver = &resolver;
+                scope.spawn(move || resolver.resolve(&Item::new(Wrapper::Curly, key)));
+            }
+        });
+        assert_eq!(most.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn resolve_from_env() {
+        env::set_var("SPONGY_TEST_VAR", "value");
+
+        let resolver = EnvResolver::new();
+        assert_eq!(
+            parse_with("${SPONGY_TEST_VAR} ${spongy_test_var}", |item| resolver
+                .resolve(item))
+            .unwrap(),
+            "value ${spongy_test_var}"
+        );
+
+        let resolver = resolver.ignore_case(true);
+        assert_eq!(
+            parse_with("${spongy_test_var}", |item| resolver.resolve(item)).unwrap(),
+            "value"
+        );
+
+        #[cfg(unix)]
+        {
+            use std::ffi::OsStr;
+            use std::os::unix::ffi::OsStrExt;
+
+            env::set_var("SPONGY_TEST_BYTES", OsStr::from_bytes(b"\xff"));
+            env::set_var(OsStr::from_bytes(b"SPONGY_TEST_\xff"), "value");
+            assert_eq!(
+                parse_with("${spongy_test_bytes} ${spongy_test_missing}", |item| {
+                    resolver.resolve(item)
+                })
+                .unwrap(),
+                "${spongy_test_bytes} ${spongy_test_missing}"
+            );
+        }
+    }
+
+    #[test]
+    fn resolve_by_wrapper() {
+        let mut map = HashMap::new();
+        map.insert("name".to_owned(), "world");
+        map.insert("HOME".to_owned(), "~");
+
+        let resolver = ByWrapper::new()
+            .on_resolver(Wrapper::DoubleCurly, MapResolver::new(&map))
+            .on(Wrapper::DoubleCurly, |item| match item.text {
+                "greeting" => Some("Hello".to_owned()),
+                _ => None,
+            })
+            .on(Wrapper::CurlyHash, |_| Some(String::new()));
+        assert_eq!(
+            parse_with(
+                "{{greeting}}, {{name}}!{# hidden #} {name} {{HOME}}",
+                |item| { resolver.resolve(item) }
+            )
+            .unwrap(),
+            "Hello, world! {name} ~"
+        );
+    }
+}
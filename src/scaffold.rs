@@ -0,0 +1,509 @@
+//! Rendering whole directory trees, cookiecutter-style.
+//!
+//! Every file below a template directory is rendered into the output
+//! directory, and placeholders in file and directory names are resolved as
+//! well, so `{{project}}/README.md` becomes `demo/README.md`. Values which
+//! would change the structure of a path are handled by a [`PathPolicy`].
+//!
+//! Binary files, like images, are copied untouched. Symbolic links are not
+//! followed: a template directory containing one is rejected, so that a
+//! link cannot pull files from elsewhere into the output or loop.
+//!
+//! A template starting with a `{% skip_if condition %}` statement is only
+//! rendered if the condition does not hold. The condition is a key,
+//! optionally preceded by `not`, which holds if its value is resolved and
+//! is not empty, `0` or `false`. The statement is left out of the output,
+//! along with the newline after it.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use pest::error::Error;
+
+use crate::path::{render_path, PathError, PathPolicy};
+use crate::resolve::Resolver;
+use crate::{parse_with_options, render, Element, Item, ParseOptions, Rule, Wrapper};
+
+#[derive(Debug)]
+pub enum ScaffoldError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    /// The name of the template file at `path` did not render to a path.
+    Path {
+        path: PathBuf,
+        error: PathError,
+    },
+    /// The name of the template file at `path` renders to `target`, which is
+    /// not below the output directory.
+    Outside {
+        path: PathBuf,
+        target: PathBuf,
+    },
+    /// The template file at `path` did not parse.
+    Parse {
+        path: PathBuf,
+        error: Box<Error<Rule>>,
+    },
+    /// The template directory contains a symbolic link at `path`.
+    Symlink {
+        path: PathBuf,
+    },
+}
+
+impl fmt::Display for ScaffoldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScaffoldError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            ScaffoldError::Path { path, error } => write!(f, "{}: {}", path.display(), error),
+            ScaffoldError::Outside { path, target } => write!(
+                f,
+                "{}: renders to {}, outside the output directory",
+                path.display(),
+                target.display()
+            ),
+            ScaffoldError::Parse { path, error } => write!(f, "{}: {}", path.display(), error),
+            ScaffoldError::Symlink { path } => {
+                write!(f, "{}: symbolic links are not followed", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ScaffoldError {}
+
+/// Extensions of files [`Scaffold`] copies without rendering by default.
+pub const BINARY_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "gif", "ico", "webp", "pdf", "zip", "gz", "tar", "woff", "woff2", "ttf",
+    "otf", "exe", "dll", "so",
+];
+
+/// Whether `bytes` look like the contents of a binary file, i.e. they are
+/// not UTF-8 or contain a NUL byte within the first 8 KiB.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(8192)].contains(&0) || std::str::from_utf8(bytes).is_err()
+}
+
+/// Statement which skips a template when it starts it, see the
+/// [module docs](self).
+pub const SKIP_IF: &str = "skip_if";
+
+/// Splits a leading `{% skip_if %}` statement off `elements`, returning its
+/// condition and the elements after it.
+fn skip_header<'e>(elements: &[Element<'e>]) -> Option<(&'e str, Vec<Element<'e>>)> {
+    let condition = match elements.first()? {
+        Element::Wrapped(item) if item.wrapper == Wrapper::CurlyPercent => {
+            item.key().strip_prefix(SKIP_IF)?
+        }
+        _ => return None,
+    };
+    if !condition.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let mut rest = elements[1..].to_vec();
+    if let Some(Element::Text(t)) = rest.first_mut() {
+        *t = t
+            .strip_prefix("\r\n")
+            .or_else(|| t.strip_prefix('\n'))
+            .unwrap_or(t);
+    }
+    Some((condition.trim(), rest))
+}
+
+/// Whether a `skip_if` condition holds.
+fn holds<R>(condition: &str, resolver: &R) -> bool
+where
+    R: Resolver + ?Sized,
+{
+    let (negated, key) = match condition.strip_prefix("not") {
+        Some(key) if key.starts_with(char::is_whitespace) => (true, key.trim()),
+        _ => (false, condition),
+    };
+    let value = resolver.resolve(&Item::new(Wrapper::DoubleCurly, key));
+    let truthy = value.is_some_and(|value| !matches!(value.as_ref(), "" | "0" | "false"));
+    truthy != negated
+}
+
+fn io_error(path: &Path) -> impl FnOnce(io::Error) -> ScaffoldError + '_ {
+    move |error| ScaffoldError::Io {
+        path: path.to_owned(),
+        error,
+    }
+}
+
+/// Renders a template directory into an output directory.
+pub struct Scaffold {
+    template_dir: PathBuf,
+    out_dir: PathBuf,
+    options: ParseOptions,
+    policy: PathPolicy,
+    binary_extensions: Vec<String>,
+}
+
+impl Scaffold {
+    pub fn new<T, O>(template_dir: T, out_dir: O) -> Scaffold
+    where
+        T: Into<PathBuf>,
+        O: Into<PathBuf>,
+    {
+        Scaffold {
+            template_dir: template_dir.into(),
+            out_dir: out_dir.into(),
+            options: ParseOptions::default(),
+            policy: PathPolicy::default(),
+            binary_extensions: BINARY_EXTENSIONS
+                .iter()
+                .map(|ext| ext.to_string())
+                .collect(),
+        }
+    }
+
+    /// Options to parse file contents with.
+    pub fn options(mut self, options: ParseOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// How to treat values substituted into file and directory names, which
+    /// are rejected by default if they contain separators or are `.` or
+    /// `..`.
+    pub fn path_policy(mut self, policy: PathPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Extensions of files to copy without rendering, compared
+    /// case-insensitively, [`BINARY_EXTENSIONS`] by default. Files which
+    /// look [binary](is_binary) are always copied.
+    pub fn binary_extensions<I, S>(mut self, extensions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.binary_extensions = extensions.into_iter().map(Into::into).collect();
+        self
+    }
+
+    fn has_binary_extension(&self, path: &Path) -> bool {
+        let ext = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase());
+        ext.is_some_and(|ext| {
+            self.binary_extensions
+                .iter()
+                .any(|b| b.to_lowercase() == ext)
+        })
+    }
+
+    /// Templates below the template directory, relative to it and sorted.
+    ///
+    /// Fails on the first symbolic link found, without following it.
+    pub fn files(&self) -> Result<Vec<PathBuf>, ScaffoldError> {
+        let mut files = vec![];
+        walk(&self.template_dir, Path::new(""), &mut files)?;
+        Ok(files)
+    }
+
+    /// Where the template at `relative` is rendered to, relative to the
+    /// output directory.
+    ///
+    /// Even with [`PathPolicy::Allow`], or when an empty value leaves a
+    /// leading separator, the target may not leave the output directory.
+    pub fn target<R>(&self, relative: &Path, resolver: &R) -> Result<PathBuf, ScaffoldError>
+    where
+        R: Resolver + ?Sized,
+    {
+        // Backslashes would be taken as escapes.
+        let name = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let target =
+            render_path(&name, |item| resolver.resolve(item), self.policy).map_err(|error| {
+                ScaffoldError::Path {
+                    path: relative.to_owned(),
+                    error,
+                }
+            })?;
+
+        let mut depth = 0usize;
+        for component in target.components() {
+            depth = match component {
+                Component::Normal(_) => depth + 1,
+                Component::CurDir => depth,
+                Component::ParentDir if depth > 0 => depth - 1,
+                _ => 0,
+            };
+            if depth == 0 && !matches!(component, Component::CurDir) {
+                return Err(ScaffoldError::Outside {
+                    path: relative.to_owned(),
+                    target,
+                });
+            }
+        }
+        Ok(target)
+    }
+
+    /// Renders every template and copies every binary file, returning the
+    /// paths written to relative to the output directory. Skipped templates
+    /// are not written, and their names need not render. Stops at the first
+    /// failure.
+    pub fn render<R>(&self, resolver: &R) -> Result<Vec<PathBuf>, ScaffoldError>
+    where
+        R: Resolver + ?Sized,
+    {
+        let mut written = vec![];
+        for relative in self.files()? {
+            let source = self.template_dir.join(&relative);
+            let bytes = fs::read(&source).map_err(io_error(&source))?;
+            let output = match String::from_utf8(bytes) {
+                Ok(s) if !self.has_binary_extension(&relative) && !is_binary(s.as_bytes()) => {
+                    let elements = parse_with_options(&s, &self.options).map_err(|error| {
+                        ScaffoldError::Parse {
+                            path: relative.clone(),
+                            error: Box::new(error),
+                        }
+                    })?;
+                    match skip_header(&elements) {
+                        Some((condition, _)) if holds(condition, resolver) => continue,
+                        Some((_, rest)) => render(&rest, |item| resolver.resolve(item)),
+                        None => render(&elements, |item| resolver.resolve(item)),
+                    }
+                    .into_bytes()
+                }
+                Ok(s) => s.into_bytes(),
+                Err(e) => e.into_bytes(),
+            };
+            let target = self.target(&relative, resolver)?;
+
+            let out = self.out_dir.join(&target);
+            if let Some(parent) = out.parent() {
+                fs::create_dir_all(parent).map_err(io_error(parent))?;
+            }
+            fs::write(&out, output).map_err(io_error(&out))?;
+            written.push(target);
+        }
+        Ok(written)
+    }
+}
+
+/// Collects the files below `root.join(dir)` relative to `root`, sorted,
+/// failing on symbolic links.
+fn walk(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), ScaffoldError> {
+    let path = root.join(dir);
+    let mut entries = fs::read_dir(&path)
+        .and_then(|entries| entries.collect::<io::Result<Vec<_>>>())
+        .map_err(io_error(&path))?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let relative = dir.join(entry.file_name());
+        // Unlike `Path::is_dir`, this does not follow links.
+        let file_type = entry.file_type().map_err(io_error(&entry.path()))?;
+        if file_type.is_symlink() {
+            return Err(ScaffoldError::Symlink { path: relative });
+        } else if file_type.is_dir() {
+            walk(root, &relative, files)?;
+        } else {
+            files.push(relative);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Item;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("spongy-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn write(root: &Path, file: &str, content: &str) {
+        let path = root.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn render_tree() {
+        let root = temp_dir("scaffold");
+        let templates = root.join("templates");
+        let out = root.join("out");
+        write(&templates, "README.md", "# {{project}}\n");
+        write(&templates, "{{project}}/{{module}}.rs", "mod {{module}};");
+
+        let resolver = |item: &Item| match item.text {
+            "project" => Some("demo"),
+            "module" => Some("core"),
+            _ => None,
+        };
+        let written = Scaffold::new(&templates, &out).render(&resolver).unwrap();
+        assert_eq!(
+            written,
+            vec![PathBuf::from("README.md"), PathBuf::from("demo/core.rs")]
+        );
+        assert_eq!(
+            fs::read_to_string(out.join("README.md")).unwrap(),
+            "# demo\n"
+        );
+        assert_eq!(
+            fs::read_to_string(out.join("demo/core.rs")).unwrap(),
+            "mod core;"
+        );
+
+        write(&templates, "bad.txt", "{{");
+        assert!(matches!(
+            Scaffold::new(&templates, &out).render(&resolver),
+            Err(ScaffoldError::Parse { path, .. }) if path == Path::new("bad.txt")
+        ));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn skip_files() {
+        let root = temp_dir("skip");
+        let templates = root.join("templates");
+        let out = root.join("out");
+        write(
+            &templates,
+            "Dockerfile",
+            "{% skip_if not use_docker %}\nFROM {{image}}\n",
+        );
+        write(&templates, "{{ci}}.yml", "{% skip_if no_ci %}ci");
+        write(&templates, "a.txt", "{% skip_iffy %}");
+
+        let render = |docker: &'static str, ci: Option<&'static str>| {
+            let _ = fs::remove_dir_all(&out);
+            let resolver = move |item: &Item| match item.key() {
+                "use_docker" => Some(docker),
+                "no_ci" => ci,
+                "ci" => Some("github"),
+                "image" => Some("rust"),
+                _ => None,
+            };
+            Scaffold::new(&templates, &out).render(&resolver).unwrap()
+        };
+        assert_eq!(
+            render("yes", None),
+            vec![
+                PathBuf::from("Dockerfile"),
+                PathBuf::from("a.txt"),
+                PathBuf::from("github.yml"),
+            ]
+        );
+        assert_eq!(
+            fs::read_to_string(out.join("Dockerfile")).unwrap(),
+            "FROM rust\n"
+        );
+        assert_eq!(fs::read_to_string(out.join("github.yml")).unwrap(), "ci");
+        assert_eq!(render("false", Some("1")), vec![PathBuf::from("a.txt")]);
+        assert!(!out.join("Dockerfile").exists());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn copy_binary_files() {
+        assert!(is_binary(b"\x89PNG\r\n\x1a\n\0\0"));
+        assert!(is_binary(b"\xff\xfe{{a}}"));
+        assert!(!is_binary("{{ a }} é".as_bytes()));
+
+        let root = temp_dir("binary");
+        let templates = root.join("templates");
+        let out = root.join("out");
+        let png = b"\x89PNG\r\n{{\0";
+        fs::create_dir_all(&templates).unwrap();
+        fs::write(templates.join("logo.png"), png).unwrap();
+        write(&templates, "icon.SVG", "{{");
+        write(&templates, "a.txt", "{{x}}");
+
+        let resolver = |_: &Item| Some("X");
+        let scaffold = Scaffold::new(&templates, &out).binary_extensions(vec!["svg"]);
+        assert_eq!(scaffold.render(&resolver).unwrap().len(), 3);
+        assert_eq!(fs::read(out.join("logo.png")).unwrap(), png);
+        assert_eq!(fs::read_to_string(out.join("icon.SVG")).unwrap(), "{{");
+        assert_eq!(fs::read_to_string(out.join("a.txt")).unwrap(), "X");
+
+        assert!(matches!(
+            Scaffold::new(&templates, &out).render(&resolver),
+            Err(ScaffoldError::Parse { path, .. }) if path == Path::new("icon.SVG")
+        ));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reject_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let root = temp_dir("symlink");
+        let templates = root.join("templates");
+        let out = root.join("out");
+        write(&root, "secret.txt", "hunter2");
+        write(&templates, "a.txt", "a");
+        symlink(root.join("secret.txt"), templates.join("leak.txt")).unwrap();
+
+        let resolver = |_: &Item| None::<&str>;
+        let scaffold = Scaffold::new(&templates, &out);
+        assert!(matches!(
+            scaffold.render(&resolver),
+            Err(ScaffoldError::Symlink { path }) if path == Path::new("leak.txt")
+        ));
+        assert!(!out.join("leak.txt").exists());
+
+        fs::remove_file(templates.join("leak.txt")).unwrap();
+        write(&templates, "sub/b.txt", "b");
+        symlink(".", templates.join("sub/loop")).unwrap();
+        assert!(matches!(
+            scaffold.files(),
+            Err(ScaffoldError::Symlink { path }) if path == Path::new("sub/loop")
+        ));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn check_targets() {
+        let resolver = |item: &Item| match item.text {
+            "name" => Some("../etc"),
+            "empty" => Some(""),
+            "sub" => Some("a/b"),
+            _ => None,
+        };
+        let target = |scaffold: &Scaffold, path: &str| scaffold.target(Path::new(path), &resolver);
+
+        let scaffold = Scaffold::new("templates", "out");
+        assert!(matches!(
+            target(&scaffold, "{{name}}/x"),
+            Err(ScaffoldError::Path { .. })
+        ));
+        assert!(matches!(
+            target(&scaffold, "{{empty}}/x"),
+            Err(ScaffoldError::Outside { .. })
+        ));
+        assert_eq!(target(&scaffold, "x/{{empty}}y").unwrap(), Path::new("x/y"));
+
+        let scaffold = scaffold.path_policy(PathPolicy::Sanitize('_'));
+        assert_eq!(
+            target(&scaffold, "{{name}}/x").unwrap(),
+            Path::new(".._etc/x")
+        );
+
+        let scaffold = scaffold.path_policy(PathPolicy::Allow);
+        assert_eq!(target(&scaffold, "{{sub}}/x").unwrap(), Path::new("a/b/x"));
+        assert!(matches!(
+            target(&scaffold, "{{name}}/x"),
+            Err(ScaffoldError::Outside { .. })
+        ));
+        assert!(matches!(
+            target(&scaffold, "a/../{{name}}"),
+            Err(ScaffoldError::Outside { .. })
+        ));
+    }
+}
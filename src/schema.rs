@@ -0,0 +1,177 @@
+//! JSON Schemas of the contexts templates are rendered with, with the
+//! `serde` feature, e.g. to generate input forms.
+//!
+//! Every variable becomes a required property: dotted paths like
+//! `user.name` nest objects, and indexed ones like `items[0]` arrays.
+//!
+//! ```
+//! use serde_json::json;
+//! use spongy::schema::context_schema;
+//!
+//! let schema = context_schema("{{ user.name }}: {count:int}")?;
+//! assert_eq!(schema["properties"]["user"]["properties"]["name"], json!({"type": "string"}));
+//! assert_eq!(schema["properties"]["count"], json!({"type": "integer"}));
+//! assert_eq!(schema["required"], json!(["user", "count"]));
+//! # Ok::<(), pest::error::Error<spongy::Rule>>(())
+//! ```
+
+use pest::error::Error;
+use serde_json::{json, Map, Value as Json};
+
+use crate::lint::variables;
+use crate::value::{segments, Segment};
+use crate::{parse, types, Element, Rule, TypeHint};
+
+/// The shape a context value must have.
+enum Shape {
+    Scalar(Option<TypeHint>),
+    /// Properties in order of first use.
+    Object(Vec<(String, Shape)>),
+    /// Elements, and how many there must be at least.
+    Array(Box<Shape>, usize),
+}
+
+impl Shape {
+    /// Makes room for a value at `path` below this one.
+    fn insert(&mut self, path: &[Segment], hint: Option<TypeHint>) {
+        let (segment, rest) = match path.split_first() {
+            Some(split) => split,
+            None => {
+                // A value used both whole and through its parts is only
+                // described by its parts.
+                if let Shape::Scalar(None) = self {
+                    *self = Shape::Scalar(hint);
+                }
+                return;
+            }
+        };
+        match segment {
+            Segment::Key(key) => {
+                if !matches!(self, Shape::Object(_)) {
+                    *self = Shape::Object(vec![]);
+                }
+                if let Shape::Object(properties) = self {
+                    let index = match properties.iter().position(|(name, _)| name == key) {
+                        Some(index) => index,
+                        None => {
+                            properties.push(((*key).to_owned(), Shape::Scalar(None)));
+                            properties.len() - 1
+                        }
+                    };
+                    properties[index].1.insert(rest, hint);
+                }
+            }
+            Segment::Index(_) | Segment::All => {
+                let min_len = match segment {
+                    Segment::Index(i) => i + 1,
+                    _ => 0,
+                };
+                match self {
+                    Shape::Array(_, len) => *len = (*len).max(min_len),
+                    _ => *self = Shape::Array(Box::new(Shape::Scalar(None)), min_len),
+                }
+                if let Shape::Array(items, _) = self {
+                    items.insert(rest, hint);
+                }
+            }
+        }
+    }
+
+    fn to_json(&self) -> Json {
+        match self {
+            Shape::Scalar(hint) => match hint {
+                Some(TypeHint::Int) => json!({"type": "integer"}),
+                Some(TypeHint::Float) => json!({"type": "number"}),
+                Some(TypeHint::Bool) => json!({"type": "boolean"}),
+                Some(TypeHint::Uuid) => json!({"type": "string", "format": "uuid"}),
+                Some(TypeHint::Str) | None => json!({"type": "string"}),
+            },
+            Shape::Object(properties) => {
+                let required: Vec<&str> =
+                    properties.iter().map(|(name, _)| name.as_str()).collect();
+                let properties: Map<String, Json> = properties
+                    .iter()
+                    .map(|(name, shape)| (name.clone(), shape.to_json()))
+                    .collect();
+                json!({"type": "object", "properties": properties, "required": required})
+            }
+            Shape::Array(items, 0) => json!({"type": "array", "items": items.to_json()}),
+            Shape::Array(items, min_len) => {
+                json!({"type": "array", "items": items.to_json(), "minItems": min_len})
+            }
+        }
+    }
+}
+
+/// Describes the context needed by `elements`.
+///
+/// Variables are those of [`lint::variables`](crate::lint::variables), typed
+/// by their [type hints](crate::types) and strings otherwise. Paths with
+/// malformed brackets are taken as a single property name.
+pub fn schema(elements: &[Element]) -> Json {
+    let mut root = Shape::Object(vec![]);
+    for variable in variables(elements) {
+        let (name, hint) = types::split(variable);
+        if name.is_empty() {
+            continue;
+        }
+        match segments(name) {
+            Some(path) => root.insert(&path, hint),
+            None => root.insert(&[Segment::Key(name)], hint),
+        }
+    }
+    let mut schema = json!({"$schema": "https://json-schema.org/draft/2020-12/schema"});
+    if let (Json::Object(schema), Json::Object(root)) = (&mut schema, root.to_json()) {
+        schema.extend(root);
+    }
+    schema
+}
+
+/// Parses `s` and describes the context it needs like [`schema`].
+pub fn context_schema(s: &str) -> Result<Json, Error<Rule>> {
+    Ok(schema(&parse(s)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describe_context() {
+        let s = "{{ user.name }} {user.id:uuid} {items[2].price:float} {items[*].tags[*]} \
+                 {{ user | upper }} {ok:bool} {# x #} {} {a[b}";
+        let schema = context_schema(s).unwrap();
+        assert_eq!(
+            schema,
+            json!({
+                "$schema": "https://json-schema.org/draft/2020-12/schema",
+                "type": "object",
+                "properties": {
+                    "user": {
+                        "type": "object",
+                        "properties": {
+                            "name": {"type": "string"},
+                            "id": {"type": "string", "format": "uuid"},
+                        },
+                        "required": ["name", "id"],
+                    },
+                    "items": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "price": {"type": "number"},
+                                "tags": {"type": "array", "items": {"type": "string"}},
+                            },
+                            "required": ["price", "tags"],
+                        },
+                        "minItems": 3,
+                    },
+                    "ok": {"type": "boolean"},
+                    "a[b": {"type": "string"},
+                },
+                "required": ["user", "items", "ok", "a[b"],
+            })
+        );
+    }
+}
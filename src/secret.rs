@@ -0,0 +1,184 @@
+//! Keeping sensitive values out of diagnostics.
+//!
+//! [`Secrets`] marks placeholder names as sensitive with glob patterns, as
+//! described for [`Policy`](crate::policy::Policy). Rendering through
+//! [`Masked`] remembers the values substituted for them, which
+//! [`redact`](Secrets::redact) then replaces with [`REDACTED`] in error
+//! messages, reports or diffs meant for humans. The rendered output itself is
+//! left alone.
+//!
+//! ```
+//! use spongy::secret::{Masked, Secrets};
+//! use spongy::{parse_with, Item, Resolver};
+//!
+//! let secrets = Secrets::new().mark("*password");
+//! let resolver = Masked::new(|item: &Item| Some(format!("<{}>", item.key())), &secrets);
+//! let output = parse_with("{user}:{db_password}", |item| resolver.resolve(item)).unwrap();
+//! assert_eq!(output, "<user>:<db_password>");
+//! assert_eq!(secrets.redact(&output), "<user>:[redacted]");
+//! ```
+
+use std::borrow::Cow;
+use std::sync::Mutex;
+
+use crate::policy::glob;
+use crate::resolve::{Outcome, Resolver};
+use crate::{filters, Item};
+
+/// What redacted values are replaced with.
+pub const REDACTED: &str = "[redacted]";
+
+/// Sensitive placeholder names, and the values seen for them.
+#[derive(Debug, Default)]
+pub struct Secrets {
+    patterns: Vec<String>,
+    /// Distinct values recorded for sensitive placeholders.
+    values: Mutex<Vec<String>>,
+}
+
+impl Secrets {
+    pub fn new() -> Secrets {
+        Secrets::default()
+    }
+
+    /// Marks names matching the glob `pattern` as sensitive.
+    pub fn mark<S: Into<String>>(mut self, pattern: S) -> Self {
+        self.patterns.push(pattern.into());
+        self
+    }
+
+    /// Whether `name`, a placeholder key without filters, is sensitive.
+    pub fn is_secret(&self, name: &str) -> bool {
+        self.patterns.iter().any(|p| glob(p, name))
+    }
+
+    /// Whether the placeholder `item` is sensitive.
+    pub fn covers(&self, item: &Item) -> bool {
+        self.is_secret(filters::pipeline(item.key()).0)
+    }
+
+    /// Remembers `value` for redaction if `item` is sensitive.
+    pub fn record(&self, item: &Item, value: &str) {
+        if self.covers(item) {
+            self.remember(value);
+        }
+    }
+
+    /// Remembers `value` for redaction, whatever it was substituted for.
+    pub fn remember(&self, value: &str) {
+        if value.is_empty() {
+            return;
+        }
+        let mut values = self.values.lock().unwrap();
+        if !values.iter().any(|v| v == value) {
+            values.push(value.to_owned());
+        }
+    }
+
+    /// `value` if `item` is not sensitive, [`REDACTED`] otherwise, for
+    /// tracing what [`render_traced`](crate::render_traced) substitutes.
+    pub fn mask<'v>(&self, item: &Item, value: &'v str) -> &'v str {
+        if self.covers(item) {
+            REDACTED
+        } else {
+            value
+        }
+    }
+
+    /// Replaces the recorded values in `s` with [`REDACTED`], longest first
+    /// so a value containing another is redacted as a whole.
+    pub fn redact<'s>(&self, s: &'s str) -> Cow<'s, str> {
+        let mut values = self.values.lock().unwrap().clone();
+        values.retain(|v| s.contains(v.as_str()));
+        if values.is_empty() {
+            return Cow::Borrowed(s);
+        }
+        values.sort_by_key(|v| std::cmp::Reverse(v.len()));
+        let mut result = s.to_owned();
+        for value in values {
+            result = result.replace(&value, REDACTED);
+        }
+        Cow::Owned(result)
+    }
+}
+
+/// Records the values another resolver substitutes for sensitive
+/// placeholders in a [`Secrets`].
+pub struct Masked<'s, R> {
+    resolver: R,
+    secrets: &'s Secrets,
+}
+
+impl<'s, R: Resolver> Masked<'s, R> {
+    pub fn new(resolver: R, secrets: &'s Secrets) -> Masked<'s, R> {
+        Masked { resolver, secrets }
+    }
+}
+
+impl<R: Resolver> Resolver for Masked<'_, R> {
+    fn resolve<'r>(&'r self, item: &Item) -> Option<Cow<'r, str>> {
+        let value = self.resolver.resolve(item);
+        if let Some(value) = &value {
+            self.secrets.record(item, value);
+        }
+        value
+    }
+
+    fn resolve_with_outcome<'r>(&'r self, item: &Item) -> (Option<Cow<'r, str>>, Outcome) {
+        let (value, outcome) = self.resolver.resolve_with_outcome(item);
+        if let Some(value) = &value {
+            self.secrets.record(item, value);
+        }
+        (value, outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, render_traced, Element, Wrapper};
+
+    #[test]
+    fn redact_recorded_values() {
+        let secrets = Secrets::new().mark("*.password").mark("token");
+        assert!(secrets.is_secret("db.password"));
+        assert!(secrets.covers(&Item::new(Wrapper::DoubleCurly, " token | upper ")));
+        assert!(!secrets.is_secret("password"));
+
+        let resolver = Masked::new(
+            |item: &Item| match item.key() {
+                "db.password" => Some("hunter2"),
+                "token" => Some("hunter"),
+                "empty.password" => Some(""),
+                _ => Some("user"),
+            },
+            &secrets,
+        );
+        let output = crate::parse_with("{user} {db.password} {token} {empty.password}", |item| {
+            resolver.resolve(item)
+        })
+        .unwrap();
+        assert_eq!(output, "user hunter2 hunter ");
+        assert_eq!(
+            secrets.redact("failed: hunter2, hunter"),
+            "failed: [redacted], [redacted]"
+        );
+        assert!(matches!(secrets.redact("user"), Cow::Borrowed("user")));
+    }
+
+    #[test]
+    fn mask_traced_values() {
+        let secrets = Secrets::new().mark("key");
+        let mut traced = vec![];
+        render_traced(
+            &parse("{name}={key}").unwrap(),
+            |item| Some(item.key().to_uppercase()),
+            |el, value, _| {
+                if let (Element::Wrapped(item), Some(value)) = (el, value) {
+                    traced.push(secrets.mask(item, value).to_owned());
+                }
+            },
+        );
+        assert_eq!(traced, vec!["NAME", REDACTED]);
+    }
+}
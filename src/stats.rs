@@ -0,0 +1,111 @@
+//! Complexity metrics of templates.
+
+use std::collections::HashMap;
+
+use pest::error::Error;
+
+use crate::{lint, parse, wrapped, Element, Item, Rule, Wrapper};
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct TemplateStats {
+    /// Total length of the text elements.
+    pub text_bytes: usize,
+    /// Number of placeholders of each wrapper kind.
+    pub placeholders: HashMap<Wrapper, usize>,
+    /// Number of distinct [variables](crate::lint::variables).
+    pub unique_variables: usize,
+    /// Deepest nesting of `{% name %}` ... `{% endname %}` blocks.
+    pub max_depth: usize,
+}
+
+impl TemplateStats {
+    /// Total number of placeholders.
+    pub fn placeholder_count(&self) -> usize {
+        self.placeholders.values().sum()
+    }
+}
+
+/// The first word of a `{% %}` statement.
+fn keyword<'a>(item: &Item<'a>) -> Option<&'a str> {
+    match item.wrapper {
+        Wrapper::CurlyPercent => item.key().split_whitespace().next(),
+        _ => None,
+    }
+}
+
+/// Computes the nesting depth of statement blocks. A statement only opens a
+/// block if a matching `end` statement follows it.
+fn max_depth(items: &[&Item]) -> usize {
+    let mut opens = vec![false; items.len()];
+    let mut closes = vec![false; items.len()];
+    let mut stack: Vec<(&str, usize)> = vec![];
+
+    for (i, item) in items.iter().enumerate() {
+        let word = match keyword(item) {
+            Some(word) => word,
+            None => continue,
+        };
+        match word.strip_prefix("end") {
+            Some(name) => {
+                if let Some(pos) = stack.iter().rposition(|&(w, _)| w == name) {
+                    opens[stack[pos].1] = true;
+                    closes[i] = true;
+                    stack.truncate(pos);
+                }
+            }
+            None => stack.push((word, i)),
+        }
+    }
+
+    let mut depth = 0;
+    let mut max = 0;
+    for i in 0..items.len() {
+        if opens[i] {
+            depth += 1;
+            max = max.max(depth);
+        } else if closes[i] {
+            depth -= 1;
+        }
+    }
+    max
+}
+
+pub fn stats(s: &str) -> Result<TemplateStats, Error<Rule>> {
+    let elements = parse(s)?;
+    let mut stats = TemplateStats::default();
+
+    for el in &elements {
+        match el {
+            Element::Text(t) | Element::Escaped { text: t, .. } => stats.text_bytes += t.len(),
+            Element::Wrapped(item) => *stats.placeholders.entry(item.wrapper).or_default() += 1,
+        }
+    }
+
+    stats.unique_variables = lint::variables(&elements).len();
+    stats.max_depth = max_depth(&wrapped(&elements).collect::<Vec<&Item>>());
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count_elements() {
+        let stats = stats("Hi {{ name }}, {name|upper} {{name}} ${HOME} {# c #}!").unwrap();
+        assert_eq!(stats.text_bytes, 9);
+        assert_eq!(stats.placeholders[&Wrapper::DoubleCurly], 2);
+        assert_eq!(stats.placeholders[&Wrapper::Curly], 1);
+        assert_eq!(stats.placeholder_count(), 5);
+        assert_eq!(stats.unique_variables, 2);
+        assert_eq!(stats.max_depth, 0);
+    }
+
+    #[test]
+    fn nesting_depth() {
+        let s = "{% if a %}{% for x %}{% if b %}{% else %}{% endif %}{% endfor %}\
+                 {% set y %}{% endif %}{% block c %}{% endblock %}";
+        assert_eq!(stats(s).unwrap().max_depth, 3);
+        assert_eq!(stats("{% endif %}{% if %}").unwrap().max_depth, 0);
+    }
+}
@@ -0,0 +1,264 @@
+//! Pulling placeholder values from secret managers at render time.
+//!
+//! A [`SecretStore`] adapts a secret manager, like a vault or a cloud key
+//! store, to a single lookup by name. [`StoreResolver`] turns it into a
+//! [`Resolver`], caching what it fetched and recording every value in a
+//! [`Secrets`] so it can be [redacted](Secrets::redact) from diagnostics.
+//!
+//! See `examples/http_store.rs` for a store backed by a key-value HTTP API.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::resolve::{Outcome, Resolver};
+use crate::secret::Secrets;
+use crate::{filters, Item};
+
+#[derive(Debug)]
+pub enum StoreError {
+    Io(io::Error),
+    /// The store refused or failed the lookup, as described.
+    Backend(String),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StoreError::Io(e) => e.fmt(f),
+            StoreError::Backend(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<io::Error> for StoreError {
+    fn from(e: io::Error) -> Self {
+        StoreError::Io(e)
+    }
+}
+
+/// A source of secret values.
+pub trait SecretStore {
+    /// Fetches the secret `name`, or `None` if the store has no such secret.
+    fn fetch(&self, name: &str) -> Result<Option<String>, StoreError>;
+}
+
+impl<F> SecretStore for F
+where
+    F: Fn(&str) -> Result<Option<String>, StoreError>,
+{
+    fn fetch(&self, name: &str) -> Result<Option<String>, StoreError> {
+        self(name)
+    }
+}
+
+/// A fixed set of secrets, e.g. for tests.
+impl SecretStore for HashMap<String, String> {
+    fn fetch(&self, name: &str) -> Result<Option<String>, StoreError> {
+        Ok(self.get(name).cloned())
+    }
+}
+
+/// Cached result of a successful fetch.
+struct Fetched {
+    value: Option<String>,
+    at: Instant,
+}
+
+/// Resolves placeholders from a [`SecretStore`].
+///
+/// Placeholders are looked up by their [key](Item::key) without filters,
+/// optionally only those starting with a [`prefix`](StoreResolver::prefix).
+/// Successful fetches are cached for the lifetime of the resolver unless a
+/// [`ttl`](StoreResolver::ttl) is set, while failed ones are tried again
+/// and reported as [`Outcome::Errored`].
+///
+/// The cache is not locked during fetches, so threads sharing the resolver
+/// fetch different secrets in parallel, and the same secret fetched on
+/// several threads at once may reach the store more than once.
+pub struct StoreResolver<'s, S> {
+    store: S,
+    secrets: &'s Secrets,
+    prefix: Option<String>,
+    ttl: Option<Duration>,
+    cache: Mutex<HashMap<String, Fetched>>,
+}
+
+impl<'s, S: SecretStore> StoreResolver<'s, S> {
+    /// Resolves from `store`, recording the values in `secrets`.
+    pub fn new(store: S, secrets: &'s Secrets) -> StoreResolver<'s, S> {
+        StoreResolver {
+            store,
+            secrets,
+            prefix: None,
+            ttl: None,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Only resolves names starting with `prefix`, like `vault.`, looking
+    /// them up without it.
+    pub fn prefix<P: Into<String>>(mut self, prefix: P) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Fetches values again once they are older than `ttl`.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// The name `item` is looked up by in the store, if any.
+    fn name<'i>(&self, item: &Item<'i>) -> Option<&'i str> {
+        let name = filters::pipeline(item.key()).0;
+        match &self.prefix {
+            Some(prefix) => name.strip_prefix(prefix.as_str()),
+            None => Some(name),
+        }
+        .filter(|name| !name.is_empty())
+    }
+
+    /// Resolves `item`, failing if the store does.
+    pub fn try_resolve(&self, item: &Item) -> Result<Option<String>, StoreError> {
+        let name = match self.name(item) {
+            Some(name) => name,
+            None => return Ok(None),
+        };
+        if let Some(fetched) = self.cache.lock().unwrap().get(name) {
+            if self.ttl.is_none_or(|ttl| fetched.at.elapsed() < ttl) {
+                return Ok(fetched.value.clone());
+            }
+        }
+
+        // Not locked while fetching, so that renders on other threads are
+        // not held up by a slow store, nor is the lock poisoned if it panics.
+        let value = self.store.fetch(name)?;
+        if let Some(value) = &value {
+            self.secrets.remember(value);
+        }
+        self.cache.lock().unwrap().insert(
+            name.to_owned(),
+            Fetched {
+                value: value.clone(),
+                at: Instant::now(),
+            },
+        );
+        Ok(value)
+    }
+}
+
+impl<S: SecretStore> Resolver for StoreResolver<'_, S> {
+    fn resolve<'r>(&'r self, item: &Item) -> Option<Cow<'r, str>> {
+        self.try_resolve(item).ok().flatten().map(Cow::Owned)
+    }
+
+    fn resolve_with_outcome<'r>(&'r self, item: &Item) -> (Option<Cow<'r, str>>, Outcome) {
+        match self.try_resolve(item) {
+            Ok(Some(value)) => (Some(Cow::Owned(value)), Outcome::Resolved),
+            Ok(None) => (None, Outcome::Unresolved),
+            Err(_) => (None, Outcome::Errored),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolve::render_with_report;
+    use crate::{parse, parse_with, Wrapper};
+    use std::cell::Cell;
+
+    #[test]
+    fn resolve_from_store() {
+        let mut store = HashMap::new();
+        store.insert("db/password".to_owned(), "hunter2".to_owned());
+        let secrets = Secrets::new();
+        let resolver = StoreResolver::new(store, &secrets).prefix("vault:");
+        assert_eq!(
+            parse_with(
+                "{{vault:db/password}} {{db/password}} {{vault:x}}",
+                |item| { resolver.resolve(item) }
+            )
+            .unwrap(),
+            "hunter2 {{db/password}} {{vault:x}}"
+        );
+        assert_eq!(secrets.redact("pw=hunter2"), "pw=[redacted]");
+    }
+
+    #[test]
+    fn cache_fetches() {
+        let calls = Cell::new(0);
+        let store = |name: &str| {
+            calls.set(calls.get() + 1);
+            match name {
+                "down" => Err(StoreError::Backend("unavailable".to_owned())),
+                "none" => Ok(None),
+                name => Ok(Some(name.to_uppercase())),
+            }
+        };
+        let secrets = Secrets::new();
+        let resolver = StoreResolver::new(store, &secrets);
+        let (result, report) = render_with_report(
+            &parse("{a} {a} {none} {none} {down} {down}").unwrap(),
+            &resolver,
+        );
+        assert_eq!(result, "A A {none} {none} {down} {down}");
+        assert_eq!(report.errored, vec!["down", "down"]);
+        assert_eq!(calls.get(), 4);
+
+        let resolver = StoreResolver::new(store, &secrets).ttl(Duration::ZERO);
+        let item = Item::new(Wrapper::Curly, "a | upper");
+        resolver.resolve(&item);
+        resolver.resolve(&item);
+        assert_eq!(calls.get(), 6);
+        assert!(matches!(
+            resolver.try_resolve(&Item::new(Wrapper::Curly, "down")),
+            Err(StoreError::Backend(_))
+        ));
+    }
+
+    #[test]
+    fn fetch_without_locking() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::thread;
+
+        let (running, most) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        let store = |name: &str| {
+            if name == "panic" {
+                panic!("store failed");
+            }
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            most.fetch_max(now, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(50));
+            running.fetch_sub(1, Ordering::SeqCst);
+            Ok(Some(name.to_uppercase()))
+        };
+        let secrets = Secrets::new();
+        let resolver = StoreResolver::new(store, &secrets);
+        thread::scope(|scope| {
+            for name in &["a", "b"] {
+                let resolver = &resolver;
+                scope.spawn(move || resolver.resolve(&Item::new(Wrapper::Curly, name)));
+            }
+        });
+        assert_eq!(most.load(Ordering::SeqCst), 2);
+
+        let panicked = catch_unwind(AssertUnwindSafe(|| {
+            resolver.resolve(&Item::new(Wrapper::Curly, "panic"))
+        }));
+        assert!(panicked.is_err());
+        assert_eq!(
+            resolver
+                .try_resolve(&Item::new(Wrapper::Curly, "c"))
+                .unwrap(),
+            Some("C".to_owned())
+        );
+    }
+}
@@ -0,0 +1,508 @@
+//! Parsed templates which can be composed at the element level.
+
+use std::thread;
+
+use pest::error::Error;
+
+use crate::backend::ParserBackend;
+use crate::resolve::Resolver;
+use crate::{
+    parse_spanned, render_iter, render_traced_iter, unparse, Element, Item, OwnedElement,
+    ParseOptions, Rule, Span,
+};
+
+/// An element of a template, or text merged from the parts of a composed
+/// one.
+#[derive(Clone, Debug)]
+enum Node<'a> {
+    Element(Element<'a>),
+    Text(String),
+}
+
+impl Node<'_> {
+    fn as_element(&self) -> Element<'_> {
+        match self {
+            Node::Element(el) => el.clone(),
+            Node::Text(text) => Element::Text(text),
+        }
+    }
+
+    fn text(&self) -> Option<&str> {
+        match self {
+            Node::Element(Element::Text(text)) => Some(text),
+            Node::Text(text) => Some(text),
+            Node::Element(_) => None,
+        }
+    }
+}
+
+impl PartialEq for Node<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_element() == other.as_element()
+    }
+}
+
+fn from_elements(nodes: Vec<(Element, Span)>) -> Vec<(Node, Span)> {
+    nodes
+        .into_iter()
+        .map(|(el, span)| (Node::Element(el), span))
+        .collect()
+}
+
+/// Merges consecutive text nodes and drops empty ones, so that a composed
+/// template has the nodes parsing its source would give. Escapes stay nodes
+/// of their own.
+fn merge_text(nodes: Vec<(Node, Span)>) -> Vec<(Node, Span)> {
+    let mut merged: Vec<(Node, Span)> = Vec::with_capacity(nodes.len());
+    for (node, span) in nodes {
+        let text = match node.text() {
+            Some("") => continue,
+            text => text,
+        };
+        if let (Some(text), Some((last, last_span))) = (text, merged.last_mut()) {
+            if let Some(last_text) = last.text() {
+                *last = Node::Text(format!("{}{}", last_text, text));
+                last_span.end = span.end;
+                continue;
+            }
+        }
+        merged.push((node, span));
+    }
+    merged
+}
+
+/// A parsed template, keeping the span of each element.
+///
+/// Spans always refer to the source form of the template, which for a
+/// composed template is the concatenation of its parts' sources. Composing
+/// templates merges the text where they meet, so a composed template equals
+/// the template parsed from its source.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Template<'a> {
+    nodes: Vec<(Node<'a>, Span)>,
+}
+
+impl<'a> Template<'a> {
+    pub fn parse(s: &'a str) -> Result<Template<'a>, Error<Rule>> {
+        Template::parse_with_options(s, &ParseOptions::default())
+    }
+
+    pub fn parse_with_options(
+        s: &'a str,
+        options: &ParseOptions,
+    ) -> Result<Template<'a>, Error<Rule>> {
+        Ok(Template {
+            nodes: from_elements(parse_spanned(s, options)?),
+        })
+    }
+
+    /// Parses `s` with an alternative parser.
+    pub fn parse_with_backend<B>(s: &'a str, backend: &B) -> Result<Template<'a>, Error<Rule>>
+    where
+        B: ParserBackend + ?Sized,
+    {
+        Ok(Template {
+            nodes: from_elements(backend.parse(s)?),
+        })
+    }
+
+    /// The elements with their spans.
+    pub fn nodes(&self) -> impl Iterator<Item = (Element<'_>, Span)> + '_ {
+        self.nodes
+            .iter()
+            .map(|(node, span)| (node.as_element(), *span))
+    }
+
+    pub fn elements(&self) -> impl Iterator<Item = Element<'_>> + '_ {
+        self.nodes.iter().map(|(node, _)| node.as_element())
+    }
+
+    /// The elements in owned form.
+    pub fn to_owned_elements(&self) -> Vec<OwnedElement> {
+        self.elements().map(OwnedElement::from).collect()
+    }
+
+    /// Writes the template back in its source form, like [`unparse`].
+    pub fn unparse(&self) -> String {
+        let elements: Vec<Element> = self.elements().collect();
+        unparse(&elements)
+    }
+
+    /// Length of the template source.
+    pub fn source_len(&self) -> usize {
+        self.nodes.last().map_or(0, |(_, span)| span.end)
+    }
+
+    fn shifted(nodes: Vec<(Node<'a>, Span)>, offset: usize) -> Vec<(Node<'a>, Span)> {
+        nodes
+            .into_iter()
+            .map(|(el, span)| (el, Span::new(span.start + offset, span.end + offset)))
+            .collect()
+    }
+
+    /// Appends `other` after this template.
+    pub fn append(&mut self, other: Template<'a>) {
+        let offset = self.source_len();
+        let mut nodes = std::mem::take(&mut self.nodes);
+        nodes.extend(Template::shifted(other.nodes, offset));
+        self.nodes = merge_text(nodes);
+    }
+
+    pub fn concat(mut self, other: Template<'a>) -> Template<'a> {
+        self.append(other);
+        self
+    }
+
+    /// Replaces every item matching `predicate` with the elements of
+    /// `other`, returning how many items were replaced.
+    pub fn substitute<P>(&mut self, predicate: P, other: &Template<'a>) -> usize
+    where
+        P: Fn(&Item) -> bool,
+    {
+        let mut nodes = Vec::with_capacity(self.nodes.len());
+        let mut count = 0;
+        let mut offset = 0isize;
+
+        for (node, span) in self.nodes.drain(..) {
+            let start = (span.start as isize + offset) as usize;
+            match node {
+                Node::Element(Element::Wrapped(ref item)) if predicate(item) => {
+                    nodes.extend(Template::shifted(other.nodes.clone(), start));
+                    offset += other.source_len() as isize - span.len() as isize;
+                    count += 1;
+                }
+                node => nodes.push((node, Span::new(start, start + span.len()))),
+            }
+        }
+
+        self.nodes = merge_text(nodes);
+        count
+    }
+
+    /// Renders the template like [`render`](crate::render).
+    pub fn render<M, V>(&self, mapper: M) -> String
+    where
+        M: Fn(&Item) -> Option<V>,
+        V: AsRef<str>,
+    {
+        render_iter(self.elements(), mapper, self.source_len())
+    }
+
+    /// Renders the template once per context, as in a mail merge, reusing
+    /// the parsed elements.
+    pub fn render_many<I>(&self, contexts: I) -> Vec<String>
+    where
+        I: IntoIterator,
+        I::Item: Resolver,
+    {
+        contexts
+            .into_iter()
+            .map(|context| self.render(|item| context.resolve(item)))
+            .collect()
+    }
+
+    /// Renders the template once per context like
+    /// [`render_many`](Template::render_many), spreading the contexts over
+    /// as many threads as there are CPUs. Outputs are in the order of the
+    /// contexts.
+    pub fn render_many_parallel<C>(&self, contexts: &[C]) -> Vec<String>
+    where
+        C: Resolver + Sync,
+    {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        let chunk = contexts.len().div_ceil(threads).max(1);
+        thread::scope(|scope| {
+            let handles: Vec<_> = contexts
+                .chunks(chunk)
+                .map(|contexts| {
+                    scope.spawn(move || {
+                        contexts
+                            .iter()
+                            .map(|context| self.render(|item| context.resolve(item)))
+                            .collect::<Vec<String>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        })
+    }
+
+    /// Renders the template like [`render_traced`](crate::render_traced).
+    pub fn render_traced<M, V, T>(&self, mapper: M, trace: T) -> String
+    where
+        M: Fn(&Item) -> Option<V>,
+        V: AsRef<str>,
+        T: FnMut(&Element, Option<&str>, usize),
+    {
+        render_traced_iter(self.elements(), mapper, trace, self.source_len())
+    }
+
+    /// Renders the template like [`render`](Template::render), calling
+    /// `on_unresolved` with each item the mapper declined and its span.
+    ///
+    /// Declined items are still written back in their source form, so this
+    /// allows logging them without changing the output.
+    pub fn render_on_unresolved<M, V, U>(&self, mapper: M, mut on_unresolved: U) -> String
+    where
+        M: Fn(&Item) -> Option<V>,
+        V: AsRef<str>,
+        U: FnMut(&Item, Span),
+    {
+        let mut index = 0;
+        self.render_traced(mapper, |el, value, _| {
+            if let (Element::Wrapped(item), None) = (el, value) {
+                on_unresolved(item, self.nodes[index].1);
+            }
+            index += 1;
+        })
+    }
+
+    /// Renders the template like [`render`](Template::render), also
+    /// recording which element each part of the output was rendered from.
+    pub fn render_with_source_map<M, V>(&self, mapper: M) -> (String, SourceMap)
+    where
+        M: Fn(&Item) -> Option<V>,
+        V: AsRef<str>,
+    {
+        let mut mappings: Vec<Mapping> = Vec::with_capacity(self.nodes.len());
+        // Each element's output ends where the next one starts.
+        let result = self.render_traced(mapper, |_, _, offset| {
+            if let Some(last) = mappings.last_mut() {
+                last.output.end = offset;
+            }
+            let index = mappings.len();
+            mappings.push(Mapping {
+                output: Span::new(offset, offset),
+                source: self.nodes[index].1,
+                index,
+            });
+        });
+        if let Some(last) = mappings.last_mut() {
+            last.output.end = result.len();
+        }
+        (result, SourceMap { mappings })
+    }
+}
+
+/// A part of rendered output and the template element it came from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Mapping {
+    /// Byte range in the output.
+    pub output: Span,
+    /// Span of the element in the template source.
+    pub source: Span,
+    /// Index of the element among the [nodes](Template::nodes).
+    pub index: usize,
+}
+
+/// Maps rendered output back to template spans, as produced by
+/// [`Template::render_with_source_map`].
+///
+/// This allows pointing at the template location responsible when a
+/// downstream tool rejects part of the output.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct SourceMap {
+    mappings: Vec<Mapping>,
+}
+
+impl SourceMap {
+    /// The mappings of all elements in output order, including those which
+    /// rendered to nothing.
+    pub fn mappings(&self) -> &[Mapping] {
+        &self.mappings
+    }
+
+    /// The mapping of the element rendered at byte `offset` of the output.
+    pub fn find(&self, offset: usize) -> Option<&Mapping> {
+        let i = self
+            .mappings
+            .partition_point(|mapping| mapping.output.end <= offset);
+        self.mappings
+            .get(i)
+            .filter(|mapping| mapping.output.start <= offset)
+    }
+
+    /// The mappings of the elements rendered on `line` of `output`, counting
+    /// from 1, as reported by most validators.
+    pub fn find_line<'m>(&'m self, output: &str, line: usize) -> impl Iterator<Item = &'m Mapping> {
+        let start = match line {
+            0 => None,
+            1 => Some(0),
+            _ => output
+                .match_indices('\n')
+                .nth(line - 2)
+                .map(|(pos, _)| pos + 1),
+        };
+        let range = start.map_or(0..0, |start| {
+            let end = output[start..]
+                .find('\n')
+                .map_or(output.len(), |i| start + i);
+            start..end
+        });
+        let found = start.is_some();
+        self.mappings.iter().filter(move |mapping| {
+            found
+                && !mapping.output.is_empty()
+                && mapping.output.start <= range.end
+                && mapping.output.end > range.start
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Wrapper;
+
+    fn source(template: &Template) -> String {
+        let mut result = String::new();
+        for el in template.elements() {
+            match el {
+                Element::Text(t) | Element::Escaped { source: t, .. } => result.push_str(t),
+                Element::Wrapped(item) => item.push_source(&mut result),
+            }
+        }
+        result
+    }
+
+    fn assert_spans_consistent(template: &Template) {
+        let source = source(template);
+        for (el, span) in template.nodes() {
+            let text = &source[span.start..span.end];
+            match el {
+                Element::Text(t) | Element::Escaped { source: t, .. } => assert_eq!(text, t),
+                Element::Wrapped(item) => assert!(text.contains(item.text)),
+            }
+        }
+    }
+
+    #[test]
+    fn concat_templates() {
+        let header = Template::parse("# {title}\n").unwrap();
+        let body = Template::parse("{{body}}!").unwrap();
+        let page = header.concat(body);
+
+        assert_eq!(source(&page), "# {title}\n{{body}}!");
+        assert_eq!(
+            page.nodes().nth(3).unwrap(),
+            (
+                Element::Wrapped(Item::new(Wrapper::DoubleCurly, "body")),
+                Span::new(10, 18)
+            )
+        );
+        assert_spans_consistent(&page);
+
+        let joined = Template::parse("{a} ")
+            .unwrap()
+            .concat(Template::parse("b").unwrap());
+        assert_eq!(joined, Template::parse("{a} b").unwrap());
+        assert_spans_consistent(&joined);
+    }
+
+    #[test]
+    fn map_output_to_source() {
+        let template = Template::parse("a: {{a}}\nb: {{b}}\n{{c}}").unwrap();
+        let (output, map) = template.render_with_source_map(|item| match item.text {
+            "a" => Some("1\n2"),
+            "b" => Some(""),
+            _ => None,
+        });
+        assert_eq!(output, "a: 1\n2\nb: \n{{c}}");
+
+        let mapping = map.find(5).unwrap();
+        assert_eq!(mapping.source, Span::new(3, 8));
+        assert_eq!(
+            template.nodes().nth(mapping.index).unwrap().0,
+            Element::Wrapped(Item::new(Wrapper::DoubleCurly, "a"))
+        );
+        assert_eq!(map.find(0).unwrap().index, 0);
+        assert_eq!(map.find(output.len()), None);
+        assert_eq!(map.mappings()[3].output, Span::new(10, 10));
+
+        let line = |n| {
+            map.find_line(&output, n)
+                .map(|m| m.index)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(line(2), vec![1, 2]);
+        assert_eq!(line(3), vec![2, 4]);
+        assert_eq!(line(4), vec![5]);
+        assert!(line(5).is_empty());
+    }
+
+    #[test]
+    fn report_unresolved() {
+        let template = Template::parse("{a} {{b}} {c}").unwrap();
+        let mut unresolved = vec![];
+        let result = template.render_on_unresolved(
+            |item| match item.text {
+                "a" => Some("A"),
+                _ => None,
+            },
+            |item, span| unresolved.push((item.text.to_owned(), span)),
+        );
+        assert_eq!(result, "A {{b}} {c}");
+        assert_eq!(
+            unresolved,
+            vec![
+                ("b".to_owned(), Span::new(4, 9)),
+                ("c".to_owned(), Span::new(10, 13)),
+            ]
+        );
+    }
+
+    #[test]
+    fn render_many_contexts() {
+        use std::collections::HashMap;
+
+        use crate::resolve::MapResolver;
+
+        let template = Template::parse("Dear {{name}},").unwrap();
+        let maps: Vec<HashMap<String, String>> = (0..10)
+            .map(|i| {
+                let mut map = HashMap::new();
+                map.insert("name".to_owned(), format!("user{}", i));
+                map
+            })
+            .collect();
+        let expected: Vec<String> = (0..10).map(|i| format!("Dear user{},", i)).collect();
+
+        assert_eq!(
+            template.render_many(maps.iter().map(MapResolver::new)),
+            expected
+        );
+        assert_eq!(
+            template.render_many_parallel(&maps.iter().map(MapResolver::new).collect::<Vec<_>>()),
+            expected
+        );
+        assert!(template
+            .render_many_parallel::<MapResolver<String>>(&[])
+            .is_empty());
+    }
+
+    #[test]
+    fn substitute_templates() {
+        let mut layout = Template::parse("<{{content}}> {{content}} {x}").unwrap();
+        let content = Template::parse("a {b} c").unwrap();
+
+        let count = layout.substitute(|item| item.text == "content", &content);
+        assert_eq!(count, 2);
+        assert_eq!(source(&layout), "<a {b} c> a {b} c {x}");
+        assert_eq!(layout.unparse(), source(&layout));
+        assert_spans_consistent(&layout);
+        let expected = Template::parse("<a {b} c> a {b} c {x}").unwrap();
+        assert_eq!(layout, expected);
+        assert_eq!(layout.nodes().count(), 6);
+
+        assert_eq!(
+            layout.render(|item| match item.text {
+                "b" => Some("B"),
+                _ => None,
+            }),
+            "<a B c> a B c {x}"
+        );
+    }
+}
@@ -0,0 +1,140 @@
+//! Generated templates for property tests, with the `testing` feature.
+//!
+//! [`Generator`] produces random templates from a seed, either
+//! [anything](Generator::template) built from delimiters and text, most of
+//! which does not parse, or [well-formed](Generator::well_formed) ones, which
+//! always do. Tools relying on [`unparse`] giving back their input can check
+//! it for their options with [`check_round_trip`]:
+//!
+//! ```
+//! use spongy::testing::{check_round_trip, Generator};
+//! use spongy::ParseOptions;
+//!
+//! let mut generator = Generator::new(42);
+//! for _ in 0..100 {
+//!     check_round_trip(&generator.template(16), &ParseOptions::default()).unwrap();
+//! }
+//! ```
+
+use crate::{parse_with_options, unparse, ParseOptions};
+
+/// Pieces of arbitrary templates, weighted towards delimiters.
+const PIECES: &[&str] = &[
+    "{", "}", "{{", "}}", "{{{", "}}}", "${", "{#", "#}", "{%", "%}", "#", "%", "$", "\\", "\\{",
+    "\r\n", "\n", " ", "\t", "a", "name", "ü", "€", "-", ".", "|", ":", "(", ")", "=",
+];
+
+/// Characters of text and placeholder contents in well-formed templates.
+const CHARS: &[char] = &['a', 'b', '_', '.', ' ', '\n', 'ü', '|', ':', '-'];
+
+/// A deterministic generator of templates.
+#[derive(Clone, Debug)]
+pub struct Generator {
+    state: u64,
+}
+
+impl Generator {
+    /// A generator producing the same templates for the same `seed`.
+    pub fn new(seed: u64) -> Generator {
+        Generator {
+            // Xorshift never leaves zero.
+            state: seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1,
+        }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    /// A number below `n`, which must not be 0.
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+
+    /// Up to `max_pieces` delimiters and bits of text in any order.
+    pub fn template(&mut self, max_pieces: usize) -> String {
+        let pieces = self.below(max_pieces + 1);
+        (0..pieces).map(|_| *self.pick(PIECES)).collect()
+    }
+
+    /// Random text without delimiters, from 0 to 3 characters long.
+    fn plain(&mut self) -> String {
+        let len = self.below(4);
+        (0..len).map(|_| *self.pick(CHARS)).collect()
+    }
+
+    /// Up to `max_pieces` text runs, escapes and placeholders of every kind
+    /// with plain contents, which always parse with the default options.
+    pub fn well_formed(&mut self, max_pieces: usize) -> String {
+        let pieces = self.below(max_pieces + 1);
+        let mut result = String::new();
+        for _ in 0..pieces {
+            let content = self.plain();
+            match self.below(9) {
+                0 => result.push_str(&format!("{{{}}}", content)),
+                1 => result.push_str(&format!("{{{{{}}}}}", content)),
+                2 => result.push_str(&format!("{{{{{{{}}}}}}}", content)),
+                3 => result.push_str(&format!("${{{}}}", content)),
+                4 => result.push_str(&format!("${{{{{}}}}}", content)),
+                5 => result.push_str(&format!("{{#{}#}}", content)),
+                6 => result.push_str(&format!("{{%{}%}}", content)),
+                7 => result.push_str(&format!("\\{}", self.pick(&["{", "$", "\\", "\r\n"]))),
+                _ => result.push_str(&content),
+            }
+        }
+        result
+    }
+}
+
+/// Checks that `s`, if it parses with `options`, [unparses](unparse) back
+/// into itself, describing the difference otherwise.
+pub fn check_round_trip(s: &str, options: &ParseOptions) -> Result<(), String> {
+    let elements = match parse_with_options(s, options) {
+        Ok(elements) => elements,
+        Err(_) => return Ok(()),
+    };
+    let unparsed = unparse(&elements);
+    if unparsed == s {
+        Ok(())
+    } else {
+        Err(format!("{:?} unparses into {:?}", s, unparsed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generate_deterministically() {
+        let mut a = Generator::new(1);
+        let mut b = Generator::new(1);
+        for _ in 0..10 {
+            assert_eq!(a.template(8), b.template(8));
+        }
+        assert_ne!(
+            Generator::new(2).template(50),
+            Generator::new(3).template(50)
+        );
+    }
+
+    #[test]
+    fn report_differences() {
+        let options = ParseOptions {
+            trim_blocks: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            check_round_trip("{% a %}\nb", &options),
+            Err("\"{% a %}\\nb\" unparses into \"{% a %}b\"".to_owned())
+        );
+        assert!(check_round_trip("{", &options).is_ok());
+    }
+}
@@ -0,0 +1,245 @@
+//! Tokenizing templates which arrive in chunks, like frames of a network
+//! stream, without waiting for the whole input.
+//!
+//! [`Tokenizer`] follows the grammar of [`bytes`](crate::bytes) as a state
+//! machine: [`feed`](Tokenizer::feed) hands it the next chunk and returns the
+//! events complete so far, keeping only an unfinished placeholder or escape
+//! for the next chunk, and [`finish`](Tokenizer::finish) ends the input.
+//!
+//! ```
+//! use spongy::tokenizer::{Event, Tokenizer};
+//!
+//! let mut tokenizer = Tokenizer::new();
+//! let mut events = tokenizer.feed(b"Hello, {na").unwrap();
+//! events.extend(tokenizer.feed(b"me}!").unwrap());
+//! events.extend(tokenizer.finish().unwrap());
+//! assert_eq!(events.len(), 3);
+//! assert!(matches!(&events[1], Event::Wrapped(item) if item.text == "name"));
+//! ```
+
+use std::str;
+
+use crate::bytes::{match_wrapper_partial, ByteError, WrapperMatch};
+use crate::{Item, OwnedItem};
+
+/// Length up to which an unfinished placeholder is matched again on every
+/// chunk. Longer ones are only matched again once they have doubled, so that
+/// feeding one in small chunks still takes linear time.
+const EAGER_LEN: usize = 256;
+
+/// A piece of a tokenized template.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Event {
+    /// Text, which may be split over several events.
+    Text(Vec<u8>),
+    Wrapped(OwnedItem),
+}
+
+/// How far the text at the start of some input reaches.
+enum TextEnd {
+    /// Up to a placeholder, or the end of the input.
+    At(usize),
+    /// Up to a `$` or `\` at the end, which the next chunk decides about.
+    Held(usize),
+}
+
+/// Returns the length of the text run at the start of `input`, or `None` if
+/// a trailing escape has nothing to escape in a `complete` input.
+fn text_end(input: &[u8], complete: bool) -> Option<TextEnd> {
+    let mut pos = 0;
+    while pos < input.len() {
+        match input[pos] {
+            b'{' => break,
+            b'$' | b'\\' if pos + 1 == input.len() && !complete => {
+                return Some(TextEnd::Held(pos));
+            }
+            b'$' if input.get(pos + 1) == Some(&b'{') => break,
+            b'\\' if pos + 1 == input.len() => return None,
+            b'\\' => pos += 2,
+            _ => pos += 1,
+        }
+    }
+    Some(TextEnd::At(pos))
+}
+
+/// An incremental tokenizer.
+///
+/// Input it is done with is dropped, so memory use is bounded by the
+/// longest placeholder rather than by the template. Positions in errors are
+/// counted from the start of the first chunk.
+#[derive(Clone, Debug, Default)]
+pub struct Tokenizer {
+    /// Input not tokenized yet.
+    pending: Vec<u8>,
+    /// Position of `pending` in the whole input.
+    offset: usize,
+    /// Length of `pending` when it was last found to be incomplete.
+    incomplete: usize,
+}
+
+impl Tokenizer {
+    pub fn new() -> Tokenizer {
+        Tokenizer::default()
+    }
+
+    /// Tokenizes the next `chunk` of input, returning the events it
+    /// completes.
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<Vec<Event>, ByteError> {
+        self.pending.extend_from_slice(chunk);
+        if self.pending.len() > EAGER_LEN && self.pending.len() < 2 * self.incomplete {
+            return Ok(vec![]);
+        }
+        self.drain(false)
+    }
+
+    /// Ends the input, returning the last events, or an error if it ends
+    /// inside a placeholder or an escape.
+    pub fn finish(mut self) -> Result<Vec<Event>, ByteError> {
+        self.drain(true)
+    }
+
+    fn drain(&mut self, complete: bool) -> Result<Vec<Event>, ByteError> {
+        let mut events = vec![];
+        let mut pos = 0;
+        let input = &self.pending;
+        self.incomplete = 0;
+
+        while pos < input.len() {
+            let rest = &input[pos..];
+            let (len, held) = match text_end(rest, complete) {
+                Some(TextEnd::At(len)) => (len, false),
+                Some(TextEnd::Held(len)) => (len, true),
+                None => {
+                    return Err(ByteError::Unexpected {
+                        pos: self.offset + input.len(),
+                    })
+                }
+            };
+            if len > 0 {
+                events.push(Event::Text(rest[..len].to_vec()));
+                pos += len;
+            }
+            if held || pos == input.len() {
+                break;
+            }
+
+            let rest = &input[pos..];
+            let (wrapper, start, end, len) = match match_wrapper_partial(rest, complete) {
+                WrapperMatch::Matched(wrapper, start, end, len) => (wrapper, start, end, len),
+                WrapperMatch::Incomplete => {
+                    self.incomplete = input.len() - pos;
+                    break;
+                }
+                WrapperMatch::Failed => {
+                    return Err(ByteError::Unexpected {
+                        pos: self.offset + pos,
+                    })
+                }
+            };
+            let text =
+                str::from_utf8(&rest[start..end]).map_err(|error| ByteError::InvalidUtf8 {
+                    pos: self.offset + pos + start,
+                    error,
+                })?;
+            let source = str::from_utf8(&rest[..len]).unwrap();
+            events.push(Event::Wrapped(
+                Item::with_source(wrapper, text, source).into(),
+            ));
+            pos += len;
+        }
+
+        self.pending.drain(..pos);
+        self.offset += pos;
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytes::{parse_bytes, ByteElement};
+
+    /// Tokenizes `s` in chunks of `size` bytes, merging consecutive text.
+    fn tokenize(s: &[u8], size: usize) -> Result<Vec<Event>, ByteError> {
+        let mut tokenizer = Tokenizer::new();
+        let mut events = vec![];
+        for chunk in s.chunks(size) {
+            events.extend(tokenizer.feed(chunk)?);
+        }
+        events.extend(tokenizer.finish()?);
+
+        let mut merged: Vec<Event> = vec![];
+        for event in events {
+            match (merged.last_mut(), event) {
+                (Some(Event::Text(text)), Event::Text(more)) => text.extend(more),
+                (_, event) => merged.push(event),
+            }
+        }
+        Ok(merged)
+    }
+
+    #[test]
+    fn tokenize_like_parse_bytes() {
+        for s in &[
+            &b"Hello, {name}!"[..],
+            b"{{{a}}} ${b} {{ c }} {## #} {% d %} {}",
+            b"{#} {%} $ a } \\{x} \\${y} $",
+            b"${lookup({a = {b = 1}}, \"a\")} ${}}",
+            b"{# a {# b {##} #} c #}{#}#} x {##}",
+            b"\xff\xfe{name}\x80 {{a}}} {#a}",
+        ] {
+            let expected: Vec<Event> = parse_bytes(s)
+                .unwrap()
+                .into_iter()
+                .map(|el| match el {
+                    ByteElement::Text(t) => Event::Text(t.to_vec()),
+                    ByteElement::Wrapped(item) => Event::Wrapped(item.into()),
+                })
+                .collect();
+            for size in 1..=s.len() {
+                assert_eq!(tokenize(s, size).unwrap(), expected, "{:?} by {}", s, size);
+            }
+        }
+    }
+
+    #[test]
+    fn tokenize_broken() {
+        for size in 1..5 {
+            assert_eq!(
+                tokenize(b"ab {{todo..", size),
+                Err(ByteError::Unexpected { pos: 3 })
+            );
+            assert_eq!(
+                tokenize(b"ab\\", size),
+                Err(ByteError::Unexpected { pos: 3 })
+            );
+            assert_eq!(
+                tokenize(b"ab {a{", size),
+                Err(ByteError::Unexpected { pos: 3 })
+            );
+        }
+
+        let mut tokenizer = Tokenizer::new();
+        assert_eq!(tokenizer.feed(b"text {{a}").unwrap().len(), 1);
+        assert_eq!(tokenizer.feed(b"x"), Err(ByteError::Unexpected { pos: 5 }));
+    }
+
+    #[test]
+    fn tokenize_long_placeholders() {
+        // Matching again on every byte would take minutes.
+        let mut tokenizer = Tokenizer::new();
+        tokenizer.feed(b"${").unwrap();
+        for _ in 0..100_000 {
+            assert!(tokenizer.feed(b"a").unwrap().is_empty());
+        }
+        assert_eq!(tokenizer.finish(), Err(ByteError::Unexpected { pos: 0 }));
+
+        let mut tokenizer = Tokenizer::new();
+        tokenizer.feed(b"{").unwrap();
+        tokenizer.feed(&[b'a'; 1000]).unwrap();
+        assert!(tokenizer.feed(b"} ").unwrap().is_empty());
+        let events = tokenizer.finish().unwrap();
+        assert!(matches!(&events[0], Event::Wrapped(item) if item.text.len() == 1000));
+        assert_eq!(events[1], Event::Text(b" ".to_vec()));
+    }
+}
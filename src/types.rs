@@ -0,0 +1,211 @@
+//! Type annotations on placeholders, like `{age:int}`.
+//!
+//! A placeholder whose text ends in `:` and a known type name carries a
+//! [`TypeHint`]. [`render_checked`] resolves the name before the colon and
+//! checks the value conforms to the hint.
+
+use std::fmt;
+use std::str::FromStr;
+
+use pest::error::Error;
+
+use crate::{parse, Element, Item, Rule};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum TypeHint {
+    Str,
+    Int,
+    Float,
+    Bool,
+    Uuid,
+}
+
+impl TypeHint {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TypeHint::Str => "str",
+            TypeHint::Int => "int",
+            TypeHint::Float => "float",
+            TypeHint::Bool => "bool",
+            TypeHint::Uuid => "uuid",
+        }
+    }
+
+    /// Whether `value` is a valid rendering of this type.
+    pub fn matches(&self, value: &str) -> bool {
+        match self {
+            TypeHint::Str => true,
+            TypeHint::Int => {
+                let digits = value.strip_prefix(['-', '+']).unwrap_or(value);
+                !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+            }
+            TypeHint::Float => value.trim() == value && value.parse::<f64>().is_ok(),
+            TypeHint::Bool => value == "true" || value == "false",
+            TypeHint::Uuid => {
+                let groups: Vec<&str> = value.split('-').collect();
+                groups.iter().map(|g| g.len()).eq([8, 4, 4, 4, 12])
+                    && groups
+                        .iter()
+                        .all(|g| g.bytes().all(|b| b.is_ascii_hexdigit()))
+            }
+        }
+    }
+}
+
+impl fmt::Display for TypeHint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for TypeHint {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "str" => Ok(TypeHint::Str),
+            "int" => Ok(TypeHint::Int),
+            "float" => Ok(TypeHint::Float),
+            "bool" => Ok(TypeHint::Bool),
+            "uuid" => Ok(TypeHint::Uuid),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Splits a placeholder text into its name and type hint.
+///
+/// `" age:int "` gives `("age", Some(TypeHint::Int))`, while a suffix which
+/// is not a type name is kept as part of the name.
+pub fn split(text: &str) -> (&str, Option<TypeHint>) {
+    let text = text.trim();
+    if let Some(pos) = text.rfind(':') {
+        if let Ok(hint) = text[pos + 1..].trim().parse() {
+            return (text[..pos].trim_end(), Some(hint));
+        }
+    }
+    (text, None)
+}
+
+#[derive(Debug)]
+pub enum TypeError {
+    Parse(Box<Error<Rule>>),
+    /// The value resolved for the placeholder `text` does not conform to its
+    /// type hint.
+    Mismatch {
+        text: String,
+        hint: TypeHint,
+        value: String,
+    },
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TypeError::Parse(e) => e.fmt(f),
+            TypeError::Mismatch { text, hint, value } => write!(
+                f,
+                "value {:?} of placeholder {:?} is not a valid {}",
+                value, text, hint
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TypeError {}
+
+impl From<Error<Rule>> for TypeError {
+    fn from(e: Error<Rule>) -> Self {
+        TypeError::Parse(Box::new(e))
+    }
+}
+
+/// Renders `s`, calling the mapper with type hints stripped from item texts
+/// and failing if a resolved value does not conform to its hint.
+pub fn render_checked<M, V>(s: &str, mapper: M) -> Result<String, TypeError>
+where
+    M: Fn(&Item) -> Option<V>,
+    V: AsRef<str>,
+{
+    let mut result = String::new();
+    for el in parse(s)? {
+        match el {
+            Element::Text(t) | Element::Escaped { text: t, .. } => result.push_str(t),
+            Element::Wrapped(item) => {
+                let (name, hint) = split(item.text);
+                let value = match hint {
+                    Some(_) => mapper(&Item {
+                        text: name,
+                        ..item.clone()
+                    }),
+                    None => mapper(&item),
+                };
+                match (value, hint) {
+                    (Some(value), Some(hint)) if !hint.matches(value.as_ref()) => {
+                        return Err(TypeError::Mismatch {
+                            text: item.text.to_owned(),
+                            hint,
+                            value: value.as_ref().to_owned(),
+                        })
+                    }
+                    (Some(value), _) => result.push_str(value.as_ref()),
+                    (None, _) => item.push_source(&mut result),
+                }
+            }
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_hints() {
+        assert_eq!(split(" age : int "), ("age", Some(TypeHint::Int)));
+        assert_eq!(split("id:uuid"), ("id", Some(TypeHint::Uuid)));
+        assert_eq!(split("name:<20"), ("name:<20", None));
+        assert_eq!(split("name"), ("name", None));
+
+        let elements = parse("{age:float}").unwrap();
+        assert_eq!(
+            crate::wrapped(&elements).next().unwrap().type_hint(),
+            Some(TypeHint::Float)
+        );
+    }
+
+    #[test]
+    fn match_values() {
+        assert!(TypeHint::Int.matches("-42"));
+        assert!(!TypeHint::Int.matches("4.2"));
+        assert!(!TypeHint::Int.matches("-"));
+        assert!(TypeHint::Float.matches("4.2e3"));
+        assert!(!TypeHint::Float.matches(" 4"));
+        assert!(TypeHint::Bool.matches("false"));
+        assert!(!TypeHint::Bool.matches("yes"));
+        assert!(TypeHint::Uuid.matches("123e4567-e89b-12d3-a456-426614174000"));
+        assert!(!TypeHint::Uuid.matches("123e4567-e89b-12d3-a456-42661417400g"));
+        assert!(TypeHint::Str.matches(""));
+    }
+
+    #[test]
+    fn render_with_hints() {
+        let mapper = |item: &Item| match item.text {
+            "age" => Some("42"),
+            "name" => Some("Alice"),
+            _ => None,
+        };
+        assert_eq!(
+            render_checked("{name:str} is {age:int}, {other:bool}", mapper).unwrap(),
+            "Alice is 42, {other:bool}"
+        );
+        assert!(matches!(
+            render_checked("{name:int}", mapper),
+            Err(TypeError::Mismatch {
+                hint: TypeHint::Int,
+                ..
+            })
+        ));
+    }
+}
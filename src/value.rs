@@ -0,0 +1,469 @@
+//! Typed values for substitutions.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use pest::error::Error;
+
+#[cfg(feature = "serde")]
+use crate::resolve::{Resolver, ValueResolver};
+use crate::{parse, render, Item, Rule};
+
+/// A typed substitution, formatted to text only at render time.
+///
+/// Null renders as an empty string, lists as their elements separated by
+/// `, ` and maps as `key: value` pairs separated by `, `, in key order.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub enum Value {
+    #[default]
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    List(Vec<Value>),
+    Map(BTreeMap<String, Value>),
+}
+
+impl Value {
+    /// Formats the value, borrowing it if it is a string.
+    pub fn to_text(&self) -> Cow<'_, str> {
+        match self {
+            Value::Str(s) => Cow::Borrowed(s),
+            Value::Null => Cow::Borrowed(""),
+            value => Cow::Owned(value.to_string()),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// The value as a float, converting integers.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Int(i) => Some(*i as f64),
+            Value::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// Whether the value counts as true in a condition. Null, `false`, zero,
+    /// NaN and empty strings, lists and maps are false.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Null => false,
+            Value::Bool(b) => *b,
+            Value::Int(i) => *i != 0,
+            Value::Float(f) => *f != 0.0 && !f.is_nan(),
+            Value::Str(s) => !s.is_empty(),
+            Value::List(list) => !list.is_empty(),
+            Value::Map(map) => !map.is_empty(),
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == Value::Null
+    }
+
+    /// Looks up a path like `user.address.city` or `items[0].name`. Dotted
+    /// segments index into maps by key and into lists by position, brackets
+    /// into lists by position.
+    pub fn lookup(&self, path: &str) -> Option<&Value> {
+        segments(path)?
+            .into_iter()
+            .try_fold(self, |value, segment| match segment {
+                Segment::All => None,
+                segment => value.child(segment),
+            })
+    }
+
+    /// Looks up a path which may contain `[*]` wildcards, like
+    /// `items[*].id`, returning every match in order. Elements of a list
+    /// without the rest of the path are skipped.
+    pub fn lookup_all(&self, path: &str) -> Vec<&Value> {
+        let segments = match segments(path) {
+            Some(segments) => segments,
+            None => return vec![],
+        };
+        let mut values = vec![self];
+        for segment in segments {
+            values = match segment {
+                Segment::All => values
+                    .into_iter()
+                    .filter_map(|value| match value {
+                        Value::List(list) => Some(list),
+                        _ => None,
+                    })
+                    .flatten()
+                    .collect(),
+                segment => values
+                    .into_iter()
+                    .filter_map(|value| value.child(segment))
+                    .collect(),
+            };
+        }
+        values
+    }
+
+    fn child(&self, segment: Segment) -> Option<&Value> {
+        match (self, segment) {
+            (Value::Map(map), Segment::Key(key)) => map.get(key),
+            (Value::List(list), Segment::Key(key)) => list.get(key.parse::<usize>().ok()?),
+            (Value::List(list), Segment::Index(i)) => list.get(i),
+            _ => None,
+        }
+    }
+
+    /// Name of the variant, for error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "null",
+            Value::Bool(_) => "bool",
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+            Value::Str(_) => "str",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum Segment<'a> {
+    Key(&'a str),
+    Index(usize),
+    All,
+}
+
+/// Splits a lookup path into segments, or `None` if a bracket is malformed.
+pub(crate) fn segments(path: &str) -> Option<Vec<Segment<'_>>> {
+    let mut result = vec![];
+    for part in path.split('.') {
+        let (key, mut rest) = part.split_at(part.find('[').unwrap_or(part.len()));
+        if !key.is_empty() || rest.is_empty() {
+            result.push(Segment::Key(key));
+        }
+        while !rest.is_empty() {
+            let end = rest.find(']')?;
+            let index = rest.get(1..end).filter(|_| rest.starts_with('['))?;
+            result.push(match index {
+                "*" => Segment::All,
+                index => Segment::Index(index.parse().ok()?),
+            });
+            rest = &rest[end + 1..];
+        }
+    }
+    Some(result)
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Null => Ok(()),
+            Value::Bool(b) => b.fmt(f),
+            Value::Int(i) => i.fmt(f),
+            Value::Float(x) => x.fmt(f),
+            Value::Str(s) => f.write_str(s),
+            Value::List(list) => {
+                for (i, value) in list.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    value.fmt(f)?;
+                }
+                Ok(())
+            }
+            Value::Map(map) => {
+                for (i, (key, value)) in map.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}: {}", key, value)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(i: i32) -> Self {
+        Value::Int(i.into())
+    }
+}
+
+impl From<i64> for Value {
+    fn from(i: i64) -> Self {
+        Value::Int(i)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(f: f64) -> Self {
+        Value::Float(f)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::Str(s.to_owned())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::Str(s)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::Null, Into::into)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(list: Vec<T>) -> Self {
+        Value::List(list.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<Value>> From<BTreeMap<String, T>> for Value {
+    fn from(map: BTreeMap<String, T>) -> Self {
+        Value::Map(map.into_iter().map(|(k, v)| (k, v.into())).collect())
+    }
+}
+
+impl<T: Into<Value>> From<HashMap<String, T>> for Value {
+    fn from(map: HashMap<String, T>) -> Self {
+        Value::Map(map.into_iter().map(|(k, v)| (k, v.into())).collect())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<serde_json::Value> for Value {
+    fn from(value: serde_json::Value) -> Self {
+        use serde_json::Value as Json;
+
+        match value {
+            Json::Null => Value::Null,
+            Json::Bool(b) => Value::Bool(b),
+            Json::Number(n) => n
+                .as_i64()
+                .map(Value::Int)
+                .or_else(|| n.as_f64().map(Value::Float))
+                .unwrap_or(Value::Null),
+            Json::String(s) => Value::Str(s),
+            Json::Array(list) => Value::List(list.into_iter().map(Into::into).collect()),
+            Json::Object(map) => Value::Map(map.into_iter().map(|(k, v)| (k, v.into())).collect()),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+pub enum SerializeError {
+    Parse(Box<Error<Rule>>),
+    Serialize(serde_json::Error),
+}
+
+#[cfg(feature = "serde")]
+impl fmt::Display for SerializeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SerializeError::Parse(e) => e.fmt(f),
+            SerializeError::Serialize(e) => write!(f, "cannot serialize context: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl std::error::Error for SerializeError {}
+
+#[cfg(feature = "serde")]
+impl From<Error<Rule>> for SerializeError {
+    fn from(e: Error<Rule>) -> Self {
+        SerializeError::Parse(Box::new(e))
+    }
+}
+
+/// Serializes `context` into a [`Value`] and renders `s` against it,
+/// resolving placeholders as dotted paths like `{{ user.name }}`.
+#[cfg(feature = "serde")]
+pub fn render_serialize<T>(s: &str, context: &T) -> Result<String, SerializeError>
+where
+    T: serde::Serialize + ?Sized,
+{
+    let root = Value::from(serde_json::to_value(context).map_err(SerializeError::Serialize)?);
+    let resolver = ValueResolver::new(&root);
+    Ok(render(&parse(s)?, |item| resolver.resolve(item)))
+}
+
+/// Like [`parse_with`](crate::parse_with), with a mapper resolving
+/// placeholders to typed values.
+pub fn parse_with_values<M>(s: &str, mapper: M) -> Result<String, Error<Rule>>
+where
+    M: Fn(&Item) -> Option<Value>,
+{
+    Ok(render(&parse(s)?, |item| {
+        mapper(item).map(|value| value.to_text().into_owned())
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_values() {
+        assert_eq!(Value::Null.to_string(), "");
+        assert_eq!(Value::from(true).to_string(), "true");
+        assert_eq!(Value::from(-3).to_string(), "-3");
+        assert_eq!(Value::from(1.5).to_string(), "1.5");
+        assert_eq!(Value::from(vec![1, 2, 3]).to_string(), "1, 2, 3");
+        assert_eq!(Value::from(None::<i64>), Value::Null);
+
+        let mut map = BTreeMap::new();
+        map.insert("b".to_owned(), Value::from("x"));
+        map.insert(
+            "a".to_owned(),
+            Value::from(vec![Value::from(1), Value::Null]),
+        );
+        assert_eq!(Value::from(map).to_string(), "a: 1, , b: x");
+
+        assert!(matches!(Value::from("s").to_text(), Cow::Borrowed("s")));
+    }
+
+    #[test]
+    fn truthiness() {
+        for value in &[Value::Null, false.into(), 0.into(), 0.0.into(), "".into()] {
+            assert!(!value.is_truthy());
+        }
+        assert!(!Value::List(vec![]).is_truthy());
+        assert!(Value::from("0").is_truthy());
+        assert!(Value::from(-1).is_truthy());
+        assert!(Value::from(vec![Value::Null]).is_truthy());
+    }
+
+    #[test]
+    fn lookup_paths() {
+        let mut user = BTreeMap::new();
+        user.insert("name".to_owned(), Value::from("Ann"));
+        user.insert("tags".to_owned(), Value::from(vec!["a", "b"]));
+        let mut root = BTreeMap::new();
+        root.insert("user".to_owned(), Value::from(user));
+        let root = Value::from(root);
+
+        assert_eq!(root.lookup("user.name"), Some(&Value::from("Ann")));
+        assert_eq!(root.lookup("user.tags.1"), Some(&Value::from("b")));
+        assert_eq!(root.lookup("user.tags.2"), None);
+        assert_eq!(root.lookup("user.name.first"), None);
+        assert_eq!(root.lookup("nobody"), None);
+        assert_eq!(root.lookup("user.tags[0]"), Some(&Value::from("a")));
+        assert_eq!(root.lookup("user.tags[x]"), None);
+        assert_eq!(root.lookup("user.tags[0"), None);
+        assert_eq!(root.lookup("user.tags[*]"), None);
+    }
+
+    #[test]
+    fn lookup_wildcards() {
+        let item = |id: i64| {
+            let mut map = BTreeMap::new();
+            map.insert("id".to_owned(), Value::from(id));
+            Value::from(map)
+        };
+        let mut root = BTreeMap::new();
+        root.insert(
+            "items".to_owned(),
+            Value::List(vec![item(1), Value::Null, item(2)]),
+        );
+        root.insert("grid".to_owned(), Value::from(vec![vec![1, 2], vec![3]]));
+        let root = Value::from(root);
+
+        assert_eq!(
+            root.lookup_all("items[*].id"),
+            vec![&Value::from(1), &Value::from(2)]
+        );
+        assert_eq!(root.lookup("items[2].id"), Some(&Value::from(2)));
+        assert_eq!(root.lookup("grid[1][0]"), Some(&Value::from(3)));
+        assert_eq!(root.lookup_all("grid[*][*]").len(), 3);
+        assert_eq!(root.lookup_all("grid[*][1]"), vec![&Value::from(2)]);
+        assert!(root.lookup_all("items.id").is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn render_serialized() {
+        #[derive(serde::Serialize)]
+        struct User {
+            name: &'static str,
+            age: u32,
+            admin: bool,
+        }
+        #[derive(serde::Serialize)]
+        struct Context {
+            user: User,
+            score: f64,
+        }
+
+        let context = Context {
+            user: User {
+                name: "Ann",
+                age: 30,
+                admin: false,
+            },
+            score: 0.5,
+        };
+        assert_eq!(
+            render_serialize(
+                "{{ user.name }} ({{user.age}}, {{user.admin}}) {{score}} {{other}}",
+                &context
+            )
+            .unwrap(),
+            "Ann (30, false) 0.5 {{other}}"
+        );
+        assert!(matches!(
+            render_serialize("{{", &context),
+            Err(SerializeError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn render_values() {
+        let formatted =
+            parse_with_values("{n} items at {price}, {ok} {missing}", |item| {
+                match item.text {
+                    "n" => Some(3.into()),
+                    "price" => Some(9.99.into()),
+                    "ok" => Some(false.into()),
+                    _ => None,
+                }
+            });
+        assert_eq!(formatted.unwrap(), "3 items at 9.99, false {missing}");
+    }
+}